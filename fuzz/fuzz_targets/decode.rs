@@ -0,0 +1,56 @@
+//! Reads arbitrary input through the decoding paths of the reader, which
+//! must report errors instead of panicking.
+//!
+//! Seed the corpus with the regression inputs of the reader tests:
+//!
+//! ```sh
+//! cargo +nightly fuzz run decode corpus/decode ../reader/tests/corpus
+//! ```
+//!
+//! Minimized inputs of fixed crashes belong in `reader/tests/corpus`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use osm_pbf_reader::data::primitives::PrimitiveType;
+use osm_pbf_reader::wire::{self, Fields};
+use osm_pbf_reader::{Blobs, FlatBlock, Limits};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut blobs) = Blobs::from_bytes(data.to_vec()) {
+        while let Ok(Some(block)) = blobs.next_primitive_block_decoded() {
+            for primitive in block.primitives() {
+                let _ = primitive.to_element();
+            }
+        }
+    }
+    let Ok(mut blobs) = Blobs::from_bytes(data.to_vec()) else {
+        return;
+    };
+    let mut block = FlatBlock::new();
+    let mut out = Vec::new();
+    while let Ok(Some((_, blob))) = blobs.next_blob() {
+        if block.decode_blob(&blob, &Limits::new()).is_ok() {
+            let ways = block.ways();
+            for i in 0..ways.len() {
+                let _ = (ways.tags(i), ways.refs(i));
+            }
+            let relations = block.relations();
+            for i in 0..relations.len() {
+                let _ = relations.members(i);
+            }
+        }
+        if let Ok(payload) = wire::payload(&blob, &Limits::new()) {
+            for field in Fields::new(&payload).map_while(Result::ok) {
+                let _ = field.message().map(Iterator::count);
+            }
+            for types in [
+                PrimitiveType::NODE,
+                PrimitiveType::WAY | PrimitiveType::RELATION,
+            ] {
+                out.clear();
+                let _ = wire::retain_types(&payload, types, &mut out);
+            }
+        }
+    }
+});
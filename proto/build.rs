@@ -1,16 +1,34 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
 use protobuf_codegen::Customize;
 
-const PROTOS: &[&str] = &["src/protos/fileformat.proto", "src/protos/osmformat.proto"];
+const PROTOS: &[&str] = &["fileformat.proto", "osmformat.proto"];
+
+/// Messages that replace the `ChangeSet` message of `osmformat.proto`.
+const CHANGESET_PROTO: &str = "src/changeset.proto";
 
 fn main() {
     for proto in PROTOS {
-        println!("cargo:rerun-if-changed={}", proto);
+        println!("cargo:rerun-if-changed=src/protos/{}", proto);
+    }
+    println!("cargo:rerun-if-changed={}", CHANGESET_PROTO);
+
+    let dir = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("protos");
+    fs::create_dir_all(&dir).unwrap();
+    for proto in PROTOS {
+        let mut schema = fs::read_to_string(format!("src/protos/{}", proto)).unwrap();
+        if *proto == "osmformat.proto" {
+            schema = replace_changeset(&schema);
+        }
+        fs::write(dir.join(proto), schema).unwrap();
     }
+
     protobuf_codegen::Codegen::new()
         .pure()
         .cargo_out_dir("protos-gen")
-        .includes(["src/protos"])
-        .inputs(PROTOS)
+        .includes([&dir])
+        .inputs(PROTOS.iter().map(|proto| dir.join(proto)))
         .customize(
             Customize::default()
                 .lite_runtime(true)
@@ -19,3 +37,21 @@ fn main() {
         )
         .run_from_script();
 }
+
+/// Replaces the `ChangeSet` message of the vendored schema with the messages
+/// of [`CHANGESET_PROTO`].
+fn replace_changeset(schema: &str) -> String {
+    let start = schema
+        .find("message ChangeSet {")
+        .expect("no ChangeSet message in osmformat.proto");
+    let len = schema[start..]
+        .find('}')
+        .expect("unterminated ChangeSet message in osmformat.proto");
+    let messages = fs::read_to_string(CHANGESET_PROTO).unwrap();
+    format!(
+        "{}{}{}",
+        &schema[..start],
+        messages.trim(),
+        &schema[start + len + 1..]
+    )
+}
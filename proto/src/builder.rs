@@ -0,0 +1,860 @@
+use bytes::Bytes;
+use protobuf::MessageField;
+use std::collections::HashMap;
+
+use crate::element::{Element, OwnedChangeSet, OwnedInfo, OwnedNode, OwnedRelation, OwnedWay};
+use crate::osmformat::{
+    ChangeSet, ChangeSetComment, DenseNodes, Info, Node, PrimitiveBlock, PrimitiveGroup, Relation,
+    StringTable, Way,
+};
+
+const GRANULARITY: i64 = 100;
+const DATE_GRANULARITY: i64 = 1000;
+
+/// Builds a deduplicated [`StringTable`].
+///
+/// Index `0` is reserved as delimiter and always contains the empty string;
+/// interned strings (including the empty string) get indices starting at `1`.
+#[derive(Clone, Debug)]
+pub struct StringTableBuilder {
+    indices: HashMap<Bytes, u32>,
+    strings: Vec<Bytes>,
+    counts: Vec<u64>,
+}
+
+impl Default for StringTableBuilder {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::new(),
+            strings: vec![Bytes::new()],
+            counts: vec![0],
+        }
+    }
+}
+
+impl StringTableBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of entries, including the reserved entry at index `0`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// `true` when nothing was interned yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strings.len() <= 1
+    }
+
+    /// Returns the index of `s`, adding it to the table if necessary.
+    #[inline]
+    pub fn intern(&mut self, s: &str) -> u32 {
+        self.intern_bytes(s.as_bytes())
+    }
+
+    pub fn intern_bytes(&mut self, s: &[u8]) -> u32 {
+        if let Some(&i) = self.indices.get(s) {
+            self.counts[i as usize] += 1;
+            return i;
+        }
+        let i = self.strings.len() as u32;
+        let s = Bytes::copy_from_slice(s);
+        self.strings.push(s.clone());
+        self.counts.push(1);
+        self.indices.insert(s, i);
+        i
+    }
+
+    /// Number of times `index` was returned by `intern`.
+    #[inline]
+    pub fn count(&self, index: u32) -> u64 {
+        self.counts.get(index as usize).copied().unwrap_or(0)
+    }
+
+    #[inline]
+    pub fn get(&self, index: u32) -> Option<&[u8]> {
+        self.strings.get(index as usize).map(|b| &b[..])
+    }
+
+    pub fn build(self) -> StringTable {
+        let mut table = StringTable::new();
+        table.s = self.strings;
+        table
+    }
+
+    /// Builds a table ordered by descending use count, so frequent strings
+    /// get small (1-byte varint) indices.
+    ///
+    /// Returns the table and the mapping from the interned indices to the new
+    /// indices, to be applied with [`remap_string_indices`].
+    pub fn build_by_frequency(self) -> (StringTable, Vec<u32>) {
+        let mut order: Vec<u32> = (1..self.strings.len() as u32).collect();
+        // stable: ties keep the first-seen order
+        order.sort_by(|&a, &b| self.counts[b as usize].cmp(&self.counts[a as usize]));
+        let mut mapping = vec![0; self.strings.len()];
+        let mut strings = Vec::with_capacity(self.strings.len());
+        strings.push(Bytes::new());
+        for (new, &old) in order.iter().enumerate() {
+            mapping[old as usize] = new as u32 + 1;
+            strings.push(self.strings[old as usize].clone());
+        }
+        let mut table = StringTable::new();
+        table.s = strings;
+        (table, mapping)
+    }
+}
+
+/// Replaces all string-table indices in `block` using `mapping` (old index
+/// to new index); see [`StringTableBuilder::build_by_frequency`].
+///
+/// Indices outside of `mapping` are left unchanged.
+pub fn remap_string_indices(block: &mut PrimitiveBlock, mapping: &[u32]) {
+    map_string_indices(block, &mut |i| {
+        mapping.get(i as usize).copied().unwrap_or(i)
+    });
+}
+
+/// Reorders the string table of an existing block by descending use count
+/// and remaps all indices accordingly; strings that are not used at all are
+/// moved to the end.
+pub fn sort_string_table(block: &mut PrimitiveBlock) {
+    let len = block.stringtable.s.len();
+    if len <= 2 {
+        return;
+    }
+    let mut counts = vec![0u64; len];
+    map_string_indices(block, &mut |i| {
+        if let Some(c) = counts.get_mut(i as usize) {
+            *c += 1;
+        }
+        i
+    });
+    let mut order: Vec<u32> = (1..len as u32).collect();
+    // stable: ties keep the original order
+    order.sort_by(|&a, &b| counts[b as usize].cmp(&counts[a as usize]));
+    if order
+        .iter()
+        .enumerate()
+        .all(|(new, &old)| new as u32 + 1 == old)
+    {
+        return;
+    }
+    let mut mapping = vec![0; len];
+    let old = std::mem::take(&mut block.stringtable.mut_or_insert_default().s);
+    let mut strings = Vec::with_capacity(len);
+    strings.push(old[0].clone());
+    for (new, &i) in order.iter().enumerate() {
+        mapping[i as usize] = new as u32 + 1;
+        strings.push(old[i as usize].clone());
+    }
+    block.stringtable.mut_or_insert_default().s = strings;
+    remap_string_indices(block, &mapping);
+}
+
+/// Re-encodes the coordinates of `block` with the coarsest granularity and
+/// the offsets that represent all of its coordinates exactly.
+///
+/// Offsets are moved to the south-west corner of the block, so the first
+/// (absolute) values become small; the granularity is raised to the greatest
+/// common divisor of all coordinates relative to that corner. Returns `false`
+/// when the block was left unchanged, e.g. for a granularity `<= 0` or when
+/// the new offsets would overflow (corrupt blocks).
+pub fn optimize_encoding(block: &mut PrimitiveBlock) -> bool {
+    let granularity = block.granularity() as i64;
+    if granularity <= 0 {
+        return false;
+    }
+    let mut lat = AxisStats::default();
+    let mut lon = AxisStats::default();
+    map_coordinates(block, &mut |v| lat.add(v), &mut |v| lon.add(v));
+    let (Some(min_lat), Some(min_lon)) = (lat.min, lon.min) else {
+        return false;
+    };
+    // the coordinates relative to the corner must fit as well
+    if lat.span().is_none() || lon.span().is_none() {
+        return false;
+    }
+    // the gcd of the distances to the first value is also the gcd of the
+    // distances to the minimum; `0` when all values are equal
+    let divisor = gcd(lat.gcd, lon.gcd).clamp(1, i32::MAX as u64) as i64;
+    let (divisor, new_granularity) = match granularity.checked_mul(divisor) {
+        Some(g) if g <= i32::MAX as i64 => (divisor, g),
+        _ => (1, granularity),
+    };
+    if divisor == 1 && min_lat == 0 && min_lon == 0 {
+        return false;
+    }
+    let offset = |offset: i64, min: i64| min.checked_mul(granularity)?.checked_add(offset);
+    let (Some(lat_offset), Some(lon_offset)) = (
+        offset(block.lat_offset(), min_lat),
+        offset(block.lon_offset(), min_lon),
+    ) else {
+        return false;
+    };
+    map_coordinates(block, &mut |v| (v - min_lat) / divisor, &mut |v| {
+        (v - min_lon) / divisor
+    });
+    block.granularity = (new_granularity != GRANULARITY).then_some(new_granularity as i32);
+    block.lat_offset = (lat_offset != 0).then_some(lat_offset);
+    block.lon_offset = (lon_offset != 0).then_some(lon_offset);
+    true
+}
+
+#[derive(Default)]
+struct AxisStats {
+    min: Option<i64>,
+    max: Option<i64>,
+    first: Option<i64>,
+    /// gcd of the distances to `first`
+    gcd: u64,
+}
+
+impl AxisStats {
+    fn add(&mut self, v: i64) -> i64 {
+        let first = *self.first.get_or_insert(v);
+        self.gcd = gcd(self.gcd, v.abs_diff(first));
+        self.min = Some(self.min.map_or(v, |m| m.min(v)));
+        self.max = Some(self.max.map_or(v, |m| m.max(v)));
+        v
+    }
+
+    /// `max - min`; `None` on overflow.
+    fn span(&self) -> Option<i64> {
+        self.max?.checked_sub(self.min?)
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Calls `map_lat`/`map_lon` for every coordinate (in block units) of the
+/// nodes and way locations in `block` and replaces it with the result.
+fn map_coordinates(
+    block: &mut PrimitiveBlock,
+    map_lat: &mut dyn FnMut(i64) -> i64,
+    map_lon: &mut dyn FnMut(i64) -> i64,
+) {
+    fn map_deltas(values: &mut [i64], map: &mut dyn FnMut(i64) -> i64) {
+        let (mut last_old, mut last_new) = (0i64, 0i64);
+        for v in values {
+            last_old = last_old.wrapping_add(*v);
+            let new = map(last_old);
+            *v = new.wrapping_sub(last_new);
+            last_new = new;
+        }
+    }
+    for group in &mut block.primitivegroup {
+        for node in &mut group.nodes {
+            node.set_lat(map_lat(node.lat()));
+            node.set_lon(map_lon(node.lon()));
+        }
+        if let Some(dense) = group.dense.as_mut() {
+            map_deltas(&mut dense.lat, map_lat);
+            map_deltas(&mut dense.lon, map_lon);
+        }
+        for way in &mut group.ways {
+            map_deltas(&mut way.lat, map_lat);
+            map_deltas(&mut way.lon, map_lon);
+        }
+    }
+}
+
+/// Calls `map` for every string-table index in `block` and replaces the
+/// index with the result.
+fn map_string_indices(block: &mut PrimitiveBlock, map: &mut dyn FnMut(u32) -> u32) {
+    fn map_all(v: &mut [u32], map: &mut dyn FnMut(u32) -> u32) {
+        v.iter_mut().for_each(|i| *i = map(*i));
+    }
+    fn map_info(info: &mut Info, map: &mut dyn FnMut(u32) -> u32) {
+        if let Some(sid) = &mut info.user_sid {
+            *sid = map(*sid);
+        }
+    }
+    for group in &mut block.primitivegroup {
+        for node in &mut group.nodes {
+            map_all(&mut node.keys, map);
+            map_all(&mut node.vals, map);
+            if let Some(info) = node.info.as_mut() {
+                map_info(info, map);
+            }
+        }
+        if let Some(dense) = group.dense.as_mut() {
+            for kv in &mut dense.keys_vals {
+                // 0 is the delimiter and maps to itself
+                if *kv != 0 {
+                    *kv = map(*kv as u32) as i32;
+                }
+            }
+            if let Some(denseinfo) = dense.denseinfo.as_mut() {
+                let (mut last_old, mut last_new) = (0i32, 0i32);
+                for sid in &mut denseinfo.user_sid {
+                    last_old = last_old.wrapping_add(*sid);
+                    let new = map(last_old as u32) as i32;
+                    *sid = new.wrapping_sub(last_new);
+                    last_new = new;
+                }
+            }
+        }
+        for way in &mut group.ways {
+            map_all(&mut way.keys, map);
+            map_all(&mut way.vals, map);
+            if let Some(info) = way.info.as_mut() {
+                map_info(info, map);
+            }
+        }
+        for relation in &mut group.relations {
+            map_all(&mut relation.keys, map);
+            map_all(&mut relation.vals, map);
+            if let Some(info) = relation.info.as_mut() {
+                map_info(info, map);
+            }
+            for role in &mut relation.roles_sid {
+                *role = map(*role as u32) as i32;
+            }
+        }
+        for changeset in &mut group.changesets {
+            map_all(&mut changeset.keys, map);
+            map_all(&mut changeset.vals, map);
+            if let Some(info) = changeset.info.as_mut() {
+                map_info(info, map);
+            }
+            for comment in &mut changeset.discussion {
+                comment.user_sid = comment.user_sid.map(&mut *map);
+                comment.text_sid = comment.text_sid.map(&mut *map);
+            }
+        }
+    }
+}
+
+/// Builds a delta-encoded [`DenseNodes`] group.
+///
+/// Coordinates are expected in units of the block granularity (with the
+/// block offsets already subtracted) and tags as string-table indices.
+#[derive(Clone, Debug, Default)]
+pub struct DenseNodesBuilder {
+    dense: DenseNodes,
+    len: usize,
+    has_tags: bool,
+    has_info: bool,
+    has_visible: bool,
+    last_id: i64,
+    last_lat: i64,
+    last_lon: i64,
+    last_timestamp: i64,
+    last_changeset: i64,
+    last_uid: i32,
+    last_user_sid: i32,
+}
+
+impl DenseNodesBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(
+        &mut self,
+        id: i64,
+        lat: i64,
+        lon: i64,
+        tags: impl IntoIterator<Item = (u32, u32)>,
+        info: Option<&Info>,
+    ) {
+        let dense = &mut self.dense;
+        dense.id.push(id.wrapping_sub(self.last_id));
+        dense.lat.push(lat.wrapping_sub(self.last_lat));
+        dense.lon.push(lon.wrapping_sub(self.last_lon));
+        self.last_id = id;
+        self.last_lat = lat;
+        self.last_lon = lon;
+
+        let mut tags = tags.into_iter().peekable();
+        if tags.peek().is_some() && !self.has_tags {
+            // previous nodes had no tags: add their delimiters
+            dense.keys_vals.resize(self.len, 0);
+            self.has_tags = true;
+        }
+        if self.has_tags {
+            for (k, v) in tags {
+                dense.keys_vals.push(k as i32);
+                dense.keys_vals.push(v as i32);
+            }
+            dense.keys_vals.push(0);
+        }
+
+        if info.is_some() && !self.has_info {
+            // previous nodes had no info: fill with defaults
+            let denseinfo = dense.denseinfo.mut_or_insert_default();
+            denseinfo.version.resize(self.len, -1);
+            denseinfo.timestamp.resize(self.len, 0);
+            denseinfo.changeset.resize(self.len, 0);
+            denseinfo.uid.resize(self.len, 0);
+            denseinfo.user_sid.resize(self.len, 0);
+            self.has_info = true;
+        }
+        if self.has_info {
+            let default_info = Info::new();
+            let info = info.unwrap_or(&default_info);
+            let denseinfo = dense.denseinfo.mut_or_insert_default();
+            denseinfo.version.push(info.version());
+            let timestamp = info.timestamp();
+            denseinfo
+                .timestamp
+                .push(timestamp.wrapping_sub(self.last_timestamp));
+            self.last_timestamp = timestamp;
+            let changeset = info.changeset();
+            denseinfo
+                .changeset
+                .push(changeset.wrapping_sub(self.last_changeset));
+            self.last_changeset = changeset;
+            let uid = info.uid();
+            denseinfo.uid.push(uid.wrapping_sub(self.last_uid));
+            self.last_uid = uid;
+            let user_sid = info.user_sid() as i32;
+            denseinfo
+                .user_sid
+                .push(user_sid.wrapping_sub(self.last_user_sid));
+            self.last_user_sid = user_sid;
+
+            if info.visible.is_some() && !self.has_visible {
+                denseinfo.visible.resize(self.len, true);
+                self.has_visible = true;
+            }
+            if self.has_visible {
+                denseinfo.visible.push(info.visible.unwrap_or(true));
+            }
+        }
+
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn build(self) -> DenseNodes {
+        self.dense
+    }
+}
+
+/// Quantization of coordinates and timestamps in a [`PrimitiveBlock`].
+///
+/// Coordinates are stored as `(nano_degrees - offset) / granularity`,
+/// timestamps in units of `date_granularity` milliseconds. Coarser values
+/// produce smaller files with lower precision.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockEncoding {
+    /// Coordinate resolution in nanodegrees (default `100`).
+    pub granularity: i32,
+    /// Latitude offset in nanodegrees.
+    pub lat_offset: i64,
+    /// Longitude offset in nanodegrees.
+    pub lon_offset: i64,
+    /// Timestamp resolution in milliseconds (default `1000`).
+    pub date_granularity: i32,
+}
+
+impl Default for BlockEncoding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockEncoding {
+    /// The encoding used by an existing block.
+    pub fn of(block: &PrimitiveBlock) -> Self {
+        Self {
+            granularity: block.granularity(),
+            lat_offset: block.lat_offset(),
+            lon_offset: block.lon_offset(),
+            date_granularity: block.date_granularity(),
+        }
+    }
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            granularity: GRANULARITY as i32,
+            lat_offset: 0,
+            lon_offset: 0,
+            date_granularity: DATE_GRANULARITY as i32,
+        }
+    }
+
+    /// Values below `1` are treated as `1`.
+    #[inline]
+    pub const fn granularity(mut self, granularity: i32) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    #[inline]
+    pub const fn offsets(mut self, lat_offset: i64, lon_offset: i64) -> Self {
+        self.lat_offset = lat_offset;
+        self.lon_offset = lon_offset;
+        self
+    }
+
+    /// Values below `1` are treated as `1`.
+    #[inline]
+    pub const fn date_granularity(mut self, date_granularity: i32) -> Self {
+        self.date_granularity = date_granularity;
+        self
+    }
+
+    /// Quantizes a latitude in nanodegrees to block units.
+    #[inline]
+    pub fn encode_lat(&self, nano_lat: i64) -> i64 {
+        div_round(nano_lat - self.lat_offset, self.granularity.max(1) as i64)
+    }
+
+    /// Quantizes a longitude in nanodegrees to block units.
+    #[inline]
+    pub fn encode_lon(&self, nano_lon: i64) -> i64 {
+        div_round(nano_lon - self.lon_offset, self.granularity.max(1) as i64)
+    }
+
+    /// Converts a timestamp in milliseconds to block units.
+    #[inline]
+    pub fn encode_timestamp(&self, millis: i64) -> i64 {
+        millis / self.date_granularity.max(1) as i64
+    }
+
+    /// Converts a latitude in block units to nanodegrees.
+    #[inline]
+    pub fn decode_lat(&self, lat: i64) -> i64 {
+        self.lat_offset + lat * self.granularity.max(1) as i64
+    }
+
+    /// Converts a longitude in block units to nanodegrees.
+    #[inline]
+    pub fn decode_lon(&self, lon: i64) -> i64 {
+        self.lon_offset + lon * self.granularity.max(1) as i64
+    }
+
+    /// Converts a timestamp in block units (e.g. `Info::timestamp`) to
+    /// milliseconds.
+    #[inline]
+    pub fn decode_timestamp(&self, timestamp: i64) -> i64 {
+        timestamp * self.date_granularity.max(1) as i64
+    }
+
+    /// Sets the non-default encoding fields of `block`.
+    pub fn apply_to(&self, block: &mut PrimitiveBlock) {
+        if self.granularity != GRANULARITY as i32 {
+            block.set_granularity(self.granularity.max(1));
+        }
+        if self.lat_offset != 0 {
+            block.set_lat_offset(self.lat_offset);
+        }
+        if self.lon_offset != 0 {
+            block.set_lon_offset(self.lon_offset);
+        }
+        if self.date_granularity != DATE_GRANULARITY as i32 {
+            block.set_date_granularity(self.date_granularity.max(1));
+        }
+    }
+}
+
+/// Order of the strings in the string table of each block.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StringTableStrategy {
+    /// Strings are stored in the order they are first used (fastest).
+    #[default]
+    FirstSeen,
+    /// Strings are ordered by descending use count, so frequent strings get
+    /// short indices. This usually improves compression of tag-heavy data.
+    ///
+    /// Complete blocks passed through a writer are reordered with
+    /// [`sort_string_table`].
+    Frequency,
+}
+
+#[derive(Clone, Debug)]
+enum GroupEncoder {
+    Nodes(Vec<Node>),
+    Dense(DenseNodesBuilder),
+    Ways(Vec<Way>),
+    Relations(Vec<Relation>),
+    ChangeSets(Vec<ChangeSet>),
+}
+
+/// Builds a [`PrimitiveBlock`] from owned elements.
+///
+/// Strings are interned, ids, coordinates and metadata delta encoded and
+/// consecutive elements of the same type are collected into one group.
+/// Nodes are written as [`DenseNodes`] unless they carry unknown fields.
+#[derive(Clone, Debug, Default)]
+pub struct PrimitiveBlockBuilder {
+    encoding: BlockEncoding,
+    strings: StringTableBuilder,
+    groups: Vec<PrimitiveGroup>,
+    current: Option<GroupEncoder>,
+    len: usize,
+}
+
+impl PrimitiveBlockBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_encoding(BlockEncoding::new())
+    }
+
+    #[inline]
+    pub fn with_encoding(encoding: BlockEncoding) -> Self {
+        Self {
+            encoding,
+            strings: StringTableBuilder::new(),
+            groups: Vec::new(),
+            current: None,
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn encoding(&self) -> &BlockEncoding {
+        &self.encoding
+    }
+
+    /// Number of pushed elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, element: &Element) {
+        match element {
+            Element::Node(n) => self.push_node(n),
+            Element::Way(w) => self.push_way(w),
+            Element::Relation(r) => self.push_relation(r),
+        }
+    }
+
+    #[inline]
+    fn string(&mut self, s: &str) -> u32 {
+        self.strings.intern(s)
+    }
+
+    fn tags(&mut self, tags: &[(String, String)]) -> (Vec<u32>, Vec<u32>) {
+        tags.iter()
+            .map(|(k, v)| (self.string(k), self.string(v)))
+            .unzip()
+    }
+
+    fn info(&mut self, info: &OwnedInfo) -> Info {
+        let mut result = Info::new();
+        result.version = info.version;
+        result.timestamp = info.timestamp.map(|t| self.encoding.encode_timestamp(t));
+        result.changeset = info.changeset;
+        result.uid = info.uid;
+        result.user_sid = info.user.as_deref().map(|u| self.string(u));
+        result.visible = info.visible;
+        result
+    }
+
+    fn finish_group(&mut self) {
+        let mut group = PrimitiveGroup::new();
+        match self.current.take() {
+            None => return,
+            Some(GroupEncoder::Nodes(nodes)) => group.nodes = nodes,
+            Some(GroupEncoder::Dense(dense)) => group.dense = MessageField::some(dense.build()),
+            Some(GroupEncoder::Ways(ways)) => group.ways = ways,
+            Some(GroupEncoder::Relations(relations)) => group.relations = relations,
+            Some(GroupEncoder::ChangeSets(changesets)) => group.changesets = changesets,
+        }
+        self.groups.push(group);
+    }
+
+    pub fn push_node(&mut self, node: &OwnedNode) {
+        let kv: Vec<(u32, u32)> = node
+            .tags
+            .iter()
+            .map(|(k, v)| (self.string(k), self.string(v)))
+            .collect();
+        let info = node.info.as_ref().map(|i| self.info(i));
+        if node.unknown_fields.iter().next().is_some() {
+            // `DenseNodes` can't carry unknown fields of single nodes
+            let mut n = Node::new();
+            n.set_id(node.id);
+            n.set_lat(self.encoding.encode_lat(node.nano_lat));
+            n.set_lon(self.encoding.encode_lon(node.nano_lon));
+            (n.keys, n.vals) = kv.into_iter().unzip();
+            n.info = MessageField::from_option(info);
+            *n.special_fields.mut_unknown_fields() = node.unknown_fields.clone();
+            if let Some(GroupEncoder::Nodes(nodes)) = &mut self.current {
+                nodes.push(n);
+            } else {
+                self.finish_group();
+                self.current = Some(GroupEncoder::Nodes(vec![n]));
+            }
+            self.len += 1;
+            return;
+        }
+        let mut dense = match self.current.take() {
+            Some(GroupEncoder::Dense(dense)) => dense,
+            current => {
+                self.current = current;
+                self.finish_group();
+                DenseNodesBuilder::new()
+            }
+        };
+        dense.push(
+            node.id,
+            self.encoding.encode_lat(node.nano_lat),
+            self.encoding.encode_lon(node.nano_lon),
+            kv,
+            info.as_ref(),
+        );
+        self.current = Some(GroupEncoder::Dense(dense));
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn push_way(&mut self, way: &OwnedWay) {
+        self.push_way_inner(way, None);
+    }
+
+    /// Pushes a way with the `(lat, lon)` nanodegree locations of its nodes
+    /// (`LocationsOnWays`).
+    ///
+    /// `locations` must have one entry per node reference.
+    #[inline]
+    pub fn push_way_with_locations(&mut self, way: &OwnedWay, locations: &[(i64, i64)]) {
+        self.push_way_inner(way, Some(locations));
+    }
+
+    fn push_way_inner(&mut self, way: &OwnedWay, locations: Option<&[(i64, i64)]>) {
+        let mut w = Way::new();
+        w.set_id(way.id);
+        (w.keys, w.vals) = self.tags(&way.tags);
+        w.info = MessageField::from_option(way.info.as_ref().map(|i| self.info(i)));
+        w.refs = delta_encode(way.refs.iter().copied());
+        if let Some(locations) = locations {
+            let encoding = self.encoding;
+            w.lat = delta_encode(locations.iter().map(|&(lat, _)| encoding.encode_lat(lat)));
+            w.lon = delta_encode(locations.iter().map(|&(_, lon)| encoding.encode_lon(lon)));
+        }
+        *w.special_fields.mut_unknown_fields() = way.unknown_fields.clone();
+        if let Some(GroupEncoder::Ways(ways)) = &mut self.current {
+            ways.push(w);
+        } else {
+            self.finish_group();
+            self.current = Some(GroupEncoder::Ways(vec![w]));
+        }
+        self.len += 1;
+    }
+
+    pub fn push_relation(&mut self, relation: &OwnedRelation) {
+        let mut r = Relation::new();
+        r.set_id(relation.id);
+        (r.keys, r.vals) = self.tags(&relation.tags);
+        r.info = MessageField::from_option(relation.info.as_ref().map(|i| self.info(i)));
+        for m in &relation.members {
+            let role = self.string(&m.role);
+            r.roles_sid.push(role as i32);
+            r.types.push(m.member_type.into());
+        }
+        r.memids = delta_encode(relation.members.iter().map(|m| m.id));
+        *r.special_fields.mut_unknown_fields() = relation.unknown_fields.clone();
+        if let Some(GroupEncoder::Relations(relations)) = &mut self.current {
+            relations.push(r);
+        } else {
+            self.finish_group();
+            self.current = Some(GroupEncoder::Relations(vec![r]));
+        }
+        self.len += 1;
+    }
+
+    pub fn push_changeset(&mut self, changeset: &OwnedChangeSet) {
+        let encoding = self.encoding;
+        let mut c = ChangeSet::new();
+        c.set_id(changeset.id);
+        (c.keys, c.vals) = self.tags(&changeset.tags);
+        c.info = MessageField::from_option(changeset.info.as_ref().map(|i| self.info(i)));
+        c.created_at = changeset.created_at.map(|t| encoding.encode_timestamp(t));
+        if let (Some(created_at), Some(closed_at)) = (c.created_at, changeset.closed_at) {
+            c.closetime_delta = Some(encoding.encode_timestamp(closed_at) - created_at);
+        }
+        c.open = changeset.open;
+        c.bbox = MessageField::from_option(changeset.bbox.clone());
+        c.num_changes = changeset.num_changes;
+        c.comments_count = changeset.comments_count;
+        for comment in &changeset.discussion {
+            let mut d = ChangeSetComment::new();
+            d.date = comment.timestamp.map(|t| encoding.encode_timestamp(t));
+            d.uid = comment.uid;
+            d.user_sid = Some(self.string(&comment.user));
+            d.text_sid = Some(self.string(&comment.text));
+            c.discussion.push(d);
+        }
+        *c.special_fields.mut_unknown_fields() = changeset.unknown_fields.clone();
+        if let Some(GroupEncoder::ChangeSets(changesets)) = &mut self.current {
+            changesets.push(c);
+        } else {
+            self.finish_group();
+            self.current = Some(GroupEncoder::ChangeSets(vec![c]));
+        }
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn build(self) -> PrimitiveBlock {
+        self.build_with(StringTableStrategy::FirstSeen)
+    }
+
+    pub fn build_with(mut self, strategy: StringTableStrategy) -> PrimitiveBlock {
+        self.finish_group();
+        let mut block = PrimitiveBlock::new();
+        self.encoding.apply_to(&mut block);
+        block.primitivegroup = self.groups;
+        let strings = match strategy {
+            StringTableStrategy::FirstSeen => self.strings.build(),
+            StringTableStrategy::Frequency => {
+                let (strings, mapping) = self.strings.build_by_frequency();
+                remap_string_indices(&mut block, &mapping);
+                strings
+            }
+        };
+        block.stringtable = MessageField::some(strings);
+        block
+    }
+}
+
+fn delta_encode(values: impl Iterator<Item = i64>) -> Vec<i64> {
+    let mut last = 0i64;
+    values
+        .map(|v| {
+            let d = v.wrapping_sub(last);
+            last = v;
+            d
+        })
+        .collect()
+}
+
+#[inline]
+fn div_round(value: i64, divisor: i64) -> i64 {
+    if value >= 0 {
+        (value + divisor / 2) / divisor
+    } else {
+        (value - divisor / 2) / divisor
+    }
+}
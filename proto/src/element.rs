@@ -0,0 +1,514 @@
+pub use crate::osmformat::relation::MemberType;
+use crate::osmformat::{HeaderBBox, Info, PrimitiveBlock};
+use crate::primitives::{ChangeSetRef, NodeRef, Primitive, PrimitiveType, RelationRef, WayRef};
+use protobuf::UnknownFields;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Owned metadata of an element.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnedInfo {
+    pub version: Option<i32>,
+    /// Milliseconds since the unix epoch.
+    pub timestamp: Option<i64>,
+    pub changeset: Option<i64>,
+    pub uid: Option<i32>,
+    pub user: Option<String>,
+    pub visible: Option<bool>,
+}
+
+impl OwnedInfo {
+    pub fn from_info(info: &Info, block: &PrimitiveBlock) -> Self {
+        Self {
+            version: info.version,
+            timestamp: info.timestamp.map(|t| block.millis(t)),
+            changeset: info.changeset,
+            uid: info.uid,
+            user: info
+                .user_sid
+                .and_then(|sid| block.string(sid as usize))
+                .filter(|user| !user.is_empty())
+                .map(str::to_string),
+            visible: info.visible,
+        }
+    }
+
+    /// The timestamp as [`SystemTime`].
+    pub fn system_time(&self) -> Option<SystemTime> {
+        let millis = self.timestamp?;
+        let offset = Duration::from_millis(millis.unsigned_abs());
+        if millis >= 0 {
+            UNIX_EPOCH.checked_add(offset)
+        } else {
+            UNIX_EPOCH.checked_sub(offset)
+        }
+    }
+
+    /// Sets the timestamp (with millisecond precision). The writer quantizes
+    /// it to the `date_granularity` of the block.
+    pub fn set_system_time(&mut self, time: SystemTime) {
+        self.timestamp = Some(match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as i64,
+            Err(e) => -(e.duration().as_millis() as i64),
+        });
+    }
+}
+
+pub type OwnedTags = Vec<(String, String)>;
+
+/// Node references of an [`OwnedWay`]; stored inline for short ways with the
+/// `smallvec` feature.
+#[cfg(not(feature = "smallvec"))]
+pub type OwnedRefs = Vec<i64>;
+/// Node references of an [`OwnedWay`]; stored inline for short ways with the
+/// `smallvec` feature.
+#[cfg(feature = "smallvec")]
+pub type OwnedRefs = smallvec::SmallVec<[i64; 8]>;
+
+/// Members of an [`OwnedRelation`]; stored inline for small relations with
+/// the `smallvec` feature.
+#[cfg(not(feature = "smallvec"))]
+pub type OwnedMembers = Vec<OwnedMember>;
+/// Members of an [`OwnedRelation`]; stored inline for small relations with
+/// the `smallvec` feature.
+#[cfg(feature = "smallvec")]
+pub type OwnedMembers = smallvec::SmallVec<[OwnedMember; 4]>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnedNode {
+    pub id: i64,
+    pub nano_lat: i64,
+    pub nano_lon: i64,
+    pub tags: OwnedTags,
+    pub info: Option<OwnedInfo>,
+    /// Fields unknown to this crate (vendor extensions); written back by the
+    /// writer.
+    pub unknown_fields: UnknownFields,
+}
+
+/// Size of a varint-encoded value.
+#[inline]
+const fn varint_size(v: u64) -> usize {
+    if v == 0 {
+        1
+    } else {
+        (70 - v.leading_zeros() as usize) / 7
+    }
+}
+
+#[inline]
+const fn zigzag_size(v: i64) -> usize {
+    varint_size(((v << 1) ^ (v >> 63)) as u64)
+}
+
+fn deltas_size(values: impl Iterator<Item = i64>) -> usize {
+    let mut last = 0i64;
+    values
+        .map(|v| {
+            let size = zigzag_size(v.wrapping_sub(last));
+            last = v;
+            size
+        })
+        .sum()
+}
+
+/// Size of a string in the string table plus the size of its index.
+#[inline]
+fn string_size(s: &str) -> usize {
+    // index (assuming < 2^14 entries) + tag + length prefix + data
+    2 + 1 + varint_size(s.len() as u64) + s.len()
+}
+
+#[inline]
+fn unknown_size(fields: &UnknownFields) -> usize {
+    protobuf::rt::unknown_fields_size(fields) as usize
+}
+
+fn tags_size(tags: &OwnedTags) -> usize {
+    tags.iter()
+        .map(|(k, v)| string_size(k) + string_size(v))
+        .sum()
+}
+
+impl OwnedInfo {
+    pub fn encoded_size_estimate(&self) -> usize {
+        // field tags + values; the timestamp is assumed in seconds
+        let mut size = 2;
+        size += self.version.map_or(0, |v| 1 + varint_size(v as u64));
+        size += self
+            .timestamp
+            .map_or(0, |t| 1 + varint_size((t / 1000) as u64));
+        size += self.changeset.map_or(0, |c| 1 + varint_size(c as u64));
+        size += self.uid.map_or(0, |u| 1 + varint_size(u as u64));
+        size += self.user.as_deref().map_or(0, string_size);
+        size += self.visible.map_or(0, |_| 2);
+        size
+    }
+}
+
+impl OwnedNode {
+    /// Latitude in degrees.
+    #[inline]
+    pub fn lat(&self) -> f64 {
+        self.nano_lat as f64 * 1e-9
+    }
+
+    /// Longitude in degrees.
+    #[inline]
+    pub fn lon(&self) -> f64 {
+        self.nano_lon as f64 * 1e-9
+    }
+}
+
+impl OwnedNode {
+    /// Estimated number of bytes this node occupies in an uncompressed
+    /// `PrimitiveBlock` (as part of `DenseNodes`, granularity 100).
+    ///
+    /// This is an upper bound: delta coding between nodes and deduplication
+    /// of strings are not taken into account.
+    pub fn encoded_size_estimate(&self) -> usize {
+        zigzag_size(self.id)
+            + zigzag_size(self.nano_lat / 100)
+            + zigzag_size(self.nano_lon / 100)
+            + 1 // keys_vals delimiter
+            + tags_size(&self.tags)
+            + unknown_size(&self.unknown_fields)
+            + self
+                .info
+                .as_ref()
+                .map_or(0, OwnedInfo::encoded_size_estimate)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnedWay {
+    pub id: i64,
+    pub refs: OwnedRefs,
+    pub tags: OwnedTags,
+    pub info: Option<OwnedInfo>,
+    /// Fields unknown to this crate (vendor extensions); written back by the
+    /// writer.
+    pub unknown_fields: UnknownFields,
+}
+
+impl OwnedWay {
+    /// Estimated number of bytes this way occupies in an uncompressed
+    /// `PrimitiveBlock`; see [`OwnedNode::encoded_size_estimate`].
+    pub fn encoded_size_estimate(&self) -> usize {
+        // message tag + length + id + packed refs
+        4 + varint_size(self.id as u64)
+            + 3
+            + deltas_size(self.refs.iter().copied())
+            + tags_size(&self.tags)
+            + unknown_size(&self.unknown_fields)
+            + self
+                .info
+                .as_ref()
+                .map_or(0, OwnedInfo::encoded_size_estimate)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedMember {
+    pub member_type: MemberType,
+    pub id: i64,
+    pub role: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnedRelation {
+    pub id: i64,
+    pub members: OwnedMembers,
+    pub tags: OwnedTags,
+    pub info: Option<OwnedInfo>,
+    /// Fields unknown to this crate (vendor extensions); written back by the
+    /// writer.
+    pub unknown_fields: UnknownFields,
+}
+
+impl OwnedRelation {
+    /// Estimated number of bytes this relation occupies in an uncompressed
+    /// `PrimitiveBlock`; see [`OwnedNode::encoded_size_estimate`].
+    pub fn encoded_size_estimate(&self) -> usize {
+        // message tag + length + id + 3 packed member arrays
+        4 + varint_size(self.id as u64)
+            + 9
+            + deltas_size(self.members.iter().map(|m| m.id))
+            + self
+                .members
+                .iter()
+                .map(|m| 1 + string_size(&m.role))
+                .sum::<usize>()
+            + tags_size(&self.tags)
+            + unknown_size(&self.unknown_fields)
+            + self
+                .info
+                .as_ref()
+                .map_or(0, OwnedInfo::encoded_size_estimate)
+    }
+}
+
+/// An owned comment in the discussion of a changeset.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnedComment {
+    /// Milliseconds since the unix epoch.
+    pub timestamp: Option<i64>,
+    pub uid: Option<i32>,
+    pub user: String,
+    pub text: String,
+}
+
+/// An owned changeset, as found in changeset dumps.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OwnedChangeSet {
+    pub id: i64,
+    pub tags: OwnedTags,
+    pub info: Option<OwnedInfo>,
+    /// Milliseconds since the unix epoch.
+    pub created_at: Option<i64>,
+    /// Milliseconds since the unix epoch.
+    pub closed_at: Option<i64>,
+    pub open: Option<bool>,
+    pub bbox: Option<HeaderBBox>,
+    pub num_changes: Option<u32>,
+    pub comments_count: Option<u32>,
+    pub discussion: Vec<OwnedComment>,
+    /// Fields unknown to this crate (vendor extensions); written back by the
+    /// writer.
+    pub unknown_fields: UnknownFields,
+}
+
+impl OwnedChangeSet {
+    /// Estimated number of bytes this changeset occupies in an uncompressed
+    /// `PrimitiveBlock`; see [`OwnedNode::encoded_size_estimate`].
+    pub fn encoded_size_estimate(&self) -> usize {
+        // message tag + length + id + times, flags and bbox
+        4 + varint_size(self.id as u64)
+            + self.created_at.map_or(0, |_| 12)
+            + self.bbox.as_ref().map_or(0, |_| 22)
+            + 8
+            + self
+                .discussion
+                .iter()
+                .map(|c| 10 + string_size(&c.user) + string_size(&c.text))
+                .sum::<usize>()
+            + tags_size(&self.tags)
+            + unknown_size(&self.unknown_fields)
+            + self
+                .info
+                .as_ref()
+                .map_or(0, OwnedInfo::encoded_size_estimate)
+    }
+}
+
+/// An owned OSM element, independent of any `PrimitiveBlock`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    Node(OwnedNode),
+    Way(OwnedWay),
+    Relation(OwnedRelation),
+}
+
+impl Element {
+    #[inline]
+    pub fn id(&self) -> i64 {
+        match self {
+            Self::Node(n) => n.id,
+            Self::Way(w) => w.id,
+            Self::Relation(r) => r.id,
+        }
+    }
+
+    #[inline]
+    pub fn primitive_type(&self) -> PrimitiveType {
+        match self {
+            Self::Node(_) => PrimitiveType::NODE,
+            Self::Way(_) => PrimitiveType::WAY,
+            Self::Relation(_) => PrimitiveType::RELATION,
+        }
+    }
+
+    #[inline]
+    pub fn tags(&self) -> &OwnedTags {
+        match self {
+            Self::Node(n) => &n.tags,
+            Self::Way(w) => &w.tags,
+            Self::Relation(r) => &r.tags,
+        }
+    }
+
+    /// Estimated number of bytes this element occupies in an uncompressed
+    /// `PrimitiveBlock`, computed without serializing it.
+    ///
+    /// Useful for cost models, like splitting data into blocks or shards.
+    #[inline]
+    pub fn encoded_size_estimate(&self) -> usize {
+        match self {
+            Self::Node(n) => n.encoded_size_estimate(),
+            Self::Way(w) => w.encoded_size_estimate(),
+            Self::Relation(r) => r.encoded_size_estimate(),
+        }
+    }
+
+    #[inline]
+    pub fn info(&self) -> Option<&OwnedInfo> {
+        match self {
+            Self::Node(n) => n.info.as_ref(),
+            Self::Way(w) => w.info.as_ref(),
+            Self::Relation(r) => r.info.as_ref(),
+        }
+    }
+
+    #[inline]
+    pub fn info_mut(&mut self) -> &mut Option<OwnedInfo> {
+        match self {
+            Self::Node(n) => &mut n.info,
+            Self::Way(w) => &mut w.info,
+            Self::Relation(r) => &mut r.info,
+        }
+    }
+
+    #[inline]
+    pub fn tags_mut(&mut self) -> &mut OwnedTags {
+        match self {
+            Self::Node(n) => &mut n.tags,
+            Self::Way(w) => &mut w.tags,
+            Self::Relation(r) => &mut r.tags,
+        }
+    }
+}
+
+impl From<OwnedNode> for Element {
+    #[inline]
+    fn from(n: OwnedNode) -> Self {
+        Self::Node(n)
+    }
+}
+
+impl From<OwnedWay> for Element {
+    #[inline]
+    fn from(w: OwnedWay) -> Self {
+        Self::Way(w)
+    }
+}
+
+impl From<OwnedRelation> for Element {
+    #[inline]
+    fn from(r: OwnedRelation) -> Self {
+        Self::Relation(r)
+    }
+}
+
+/// `true` when `info` carries no values (or only the defaults that are
+/// filled in for nodes without metadata in `DenseInfo`).
+fn is_empty_info(info: &Info) -> bool {
+    info.version.unwrap_or(-1) == -1
+        && info.timestamp.unwrap_or(0) == 0
+        && info.changeset.unwrap_or(0) == 0
+        && info.uid.unwrap_or(0) == 0
+        && info.user_sid.unwrap_or(0) == 0
+        && info.visible.unwrap_or(true)
+}
+
+fn owned_tags<'l>(tags: impl Iterator<Item = (&'l str, &'l str)>) -> OwnedTags {
+    tags.map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+impl From<&NodeRef<'_>> for OwnedNode {
+    fn from(n: &NodeRef<'_>) -> Self {
+        let info = n.info();
+        Self {
+            id: n.id,
+            nano_lat: n.nano_lat,
+            nano_lon: n.nano_lon,
+            tags: owned_tags(n.tags()),
+            info: (!is_empty_info(&info)).then(|| OwnedInfo::from_info(&info, n.block())),
+            unknown_fields: n.unknown_fields().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&WayRef<'_>> for OwnedWay {
+    fn from(w: &WayRef<'_>) -> Self {
+        Self {
+            id: w.id(),
+            refs: w.refs().collect(),
+            tags: owned_tags(w.tags()),
+            info: w
+                .info
+                .as_ref()
+                .map(|info| OwnedInfo::from_info(info, w.block())),
+            unknown_fields: w.unknown_fields().clone(),
+        }
+    }
+}
+
+impl From<&RelationRef<'_>> for OwnedRelation {
+    fn from(r: &RelationRef<'_>) -> Self {
+        Self {
+            id: r.id(),
+            members: r
+                .members()
+                .map(|m| OwnedMember {
+                    member_type: m.member_type,
+                    id: m.id,
+                    role: m.role.to_string(),
+                })
+                .collect(),
+            tags: owned_tags(r.tags()),
+            info: r
+                .info
+                .as_ref()
+                .map(|info| OwnedInfo::from_info(info, r.block())),
+            unknown_fields: r.unknown_fields().clone(),
+        }
+    }
+}
+
+impl From<&ChangeSetRef<'_>> for OwnedChangeSet {
+    fn from(c: &ChangeSetRef<'_>) -> Self {
+        Self {
+            id: c.id(),
+            tags: owned_tags(c.tags()),
+            info: c
+                .info
+                .as_ref()
+                .map(|info| OwnedInfo::from_info(info, c.block())),
+            created_at: c.created_at(),
+            closed_at: c.closed_at(),
+            open: c.open,
+            bbox: c.bbox.as_ref().cloned(),
+            num_changes: c.num_changes,
+            comments_count: c.comments_count,
+            discussion: c
+                .discussion()
+                .map(|comment| OwnedComment {
+                    timestamp: comment.timestamp,
+                    uid: comment.uid,
+                    user: comment.user.to_string(),
+                    text: comment.text.to_string(),
+                })
+                .collect(),
+            unknown_fields: c.unknown_fields().clone(),
+        }
+    }
+}
+
+impl Primitive<'_> {
+    /// Converts the primitive into an owned [`Element`] (`None` for changesets).
+    pub fn to_element(&self) -> Option<Element> {
+        match self {
+            Self::Node(n) => Some(Element::Node(n.into())),
+            Self::Way(w) => Some(Element::Way(w.into())),
+            Self::Relation(r) => Some(Element::Relation(r.into())),
+            Self::ChangeSet(_) => None,
+        }
+    }
+
+    /// Converts a changeset primitive into an [`OwnedChangeSet`].
+    pub fn to_changeset(&self) -> Option<OwnedChangeSet> {
+        match self {
+            Self::ChangeSet(c) => Some(c.into()),
+            _ => None,
+        }
+    }
+}
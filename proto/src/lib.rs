@@ -1,6 +1,10 @@
 // re-export of protobuf
 pub use protobuf;
+#[cfg(feature = "smallvec")]
+pub use smallvec;
 
 include!(concat!(env!("OUT_DIR"), "/protos-gen/mod.rs"));
 
+pub mod builder;
+pub mod element;
 pub mod primitives;
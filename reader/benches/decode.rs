@@ -0,0 +1,49 @@
+//! Decoding of a generated file (see [`SyntheticPlanet`]) with the protobuf
+//! runtime and with [`FlatBlock`].
+//!
+//! The blobs are not compressed, so only the decoders are measured.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::{Blobs, Codec, FlatBlock, PbfWriter, SyntheticPlanet, WriterOptions};
+
+fn sample() -> Vec<u8> {
+    let options = WriterOptions::new().codec(Codec::Raw);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    SyntheticPlanet::new()
+        .nodes(200_000)
+        .write_to(&mut writer)
+        .unwrap();
+    writer.finish().unwrap()
+}
+
+fn decode(c: &mut Criterion) {
+    let data = bytes::Bytes::from(sample());
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("protobuf", |b| {
+        b.iter(|| {
+            let mut blobs = Blobs::from_bytes(data.clone()).unwrap();
+            let mut elements = 0;
+            while let Some(block) = blobs.next_primitive_block_decoded().unwrap() {
+                elements += block.primitives().count();
+            }
+            elements
+        })
+    });
+    group.bench_function("flat", |b| {
+        b.iter(|| {
+            let mut blobs = Blobs::from_bytes(data.clone()).unwrap();
+            let mut block = FlatBlock::new();
+            let mut elements = 0;
+            while blobs.next_flat_block(&mut block).unwrap() {
+                elements += block.nodes().len() + block.ways().len() + block.relations().len();
+            }
+            elements
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+//! Delta decoding of the columns of dense nodes, by the columnar view and by
+//! the primitive iterator.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use osm_pbf_proto::osmformat::DenseNodes;
+use osm_pbf_proto::protobuf::MessageField;
+use osm_pbf_reader::data::primitives::Primitive;
+use osm_pbf_reader::data::{PrimitiveBlock, PrimitiveGroup};
+
+/// Nodes per block of the reference writers.
+const NODES: usize = 8000;
+
+fn block() -> PrimitiveBlock {
+    let mut state = 1u64;
+    let mut delta = |max: u64| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        ((state >> 33) % max) as i64 - (max / 2) as i64
+    };
+    let mut dense = DenseNodes::new();
+    dense.id = (0..NODES).map(|_| delta(4) + 3).collect();
+    dense.lat = (0..NODES).map(|_| delta(20_000)).collect();
+    dense.lon = (0..NODES).map(|_| delta(20_000)).collect();
+    let mut group = PrimitiveGroup::new();
+    group.dense = MessageField::some(dense);
+    let mut block = PrimitiveBlock::new();
+    block.primitivegroup = vec![group];
+    block
+}
+
+fn dense(c: &mut Criterion) {
+    let block = block();
+    let mut group = c.benchmark_group("dense");
+    group.throughput(Throughput::Elements(NODES as u64));
+    group.bench_function("columns", |b| {
+        b.iter(|| block.dense_nodes().nano_lats.len())
+    });
+    group.bench_function("primitives", |b| {
+        b.iter(|| {
+            block
+                .primitives()
+                .map(|p| match p {
+                    Primitive::Node(n) => n.nano_lat,
+                    _ => 0,
+                })
+                .fold(0i64, i64::wrapping_add)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dense);
+criterion_main!(benches);
@@ -0,0 +1,130 @@
+//! Cuts the area of a bounding box out of a file.
+//!
+//! The extract contains the nodes inside the bbox, the ways that reference
+//! at least one of them (with all their nodes) and the relations that have
+//! one of the extracted elements as a member.
+//!
+//! ```sh
+//! cargo run --release --example bbox-extract -- input.osm.pbf output.osm.pbf --bbox=13.0,52.3,13.8,52.7
+//! ```
+
+use std::collections::HashSet;
+use std::process::ExitCode;
+
+use osm_pbf_reader::cli::{Args, BBox, Progress};
+use osm_pbf_reader::data::{Element, MemberType};
+use osm_pbf_reader::error::{Error, Result};
+use osm_pbf_reader::header::HeaderBBox;
+use osm_pbf_reader::{Blobs, PbfWriter, WriterOptions};
+
+/// Ids of the extracted elements.
+#[derive(Default)]
+struct Selection {
+    nodes: HashSet<i64>,
+    ways: HashSet<i64>,
+    relations: HashSet<i64>,
+}
+
+impl Selection {
+    fn contains(&self, element: &Element) -> bool {
+        match element {
+            Element::Node(n) => self.nodes.contains(&n.id),
+            Element::Way(w) => self.ways.contains(&w.id),
+            Element::Relation(r) => self.relations.contains(&r.id),
+        }
+    }
+}
+
+/// First pass: selects the elements of the extract.
+fn select(input: &str, bbox: &BBox) -> Result<Selection> {
+    let mut selection = Selection::default();
+    let mut way_nodes = Vec::new();
+    let mut progress = Progress::new("select");
+    let mut blocks = 0u64;
+    let mut blobs = Blobs::from_path(input)?;
+    while let Some(block) = blobs.next_primitive_block_decoded()? {
+        for element in block.primitives().filter_map(|p| p.to_element()) {
+            match element {
+                Element::Node(n) => {
+                    if bbox.contains(n.lat(), n.lon()) {
+                        selection.nodes.insert(n.id);
+                    }
+                }
+                Element::Way(w) => {
+                    if w.refs.iter().any(|id| selection.nodes.contains(id)) {
+                        selection.ways.insert(w.id);
+                        way_nodes.extend(w.refs.iter().copied());
+                    }
+                }
+                Element::Relation(r) => {
+                    let selected = r.members.iter().any(|m| match m.member_type {
+                        MemberType::NODE => selection.nodes.contains(&m.id),
+                        MemberType::WAY => selection.ways.contains(&m.id),
+                        MemberType::RELATION => selection.relations.contains(&m.id),
+                    });
+                    if selected {
+                        selection.relations.insert(r.id);
+                    }
+                }
+            }
+        }
+        blocks += 1;
+        progress.update(format_args!("{blocks} blocks"));
+    }
+    // complete the ways, after all of them were selected
+    selection.nodes.extend(way_nodes);
+    progress.finish(format_args!(
+        "{} nodes, {} ways, {} relations",
+        selection.nodes.len(),
+        selection.ways.len(),
+        selection.relations.len()
+    ));
+    Ok(selection)
+}
+
+fn run(args: &Args) -> Result<()> {
+    args.ensure_known(&["bbox"])?;
+    let input = args.required(0, "input")?;
+    let output = args.required(1, "output")?;
+    let bbox: BBox = args
+        .value("bbox")?
+        .ok_or_else(|| Error::InvalidArgument("missing --bbox".to_owned()))?;
+
+    let total = Progress::new("bbox-extract");
+    let selection = select(input, &bbox)?;
+
+    // second pass: copy the selected elements
+    let mut blobs = Blobs::from_path(input)?;
+    let mut header = blobs.header().clone();
+    let mut header_bbox = HeaderBBox::new();
+    header_bbox.set_left((bbox.left * 1e9) as i64);
+    header_bbox.set_right((bbox.right * 1e9) as i64);
+    header_bbox.set_top((bbox.top * 1e9) as i64);
+    header_bbox.set_bottom((bbox.bottom * 1e9) as i64);
+    header.bbox = Some(header_bbox).into();
+
+    let mut writer = PbfWriter::create_with_options(output, header, WriterOptions::default())?;
+    writer.set_progress(Progress::new("write").writer());
+    let mut written = 0u64;
+    while let Some(block) = blobs.next_primitive_block_decoded()? {
+        for element in block.primitives().filter_map(|p| p.to_element()) {
+            if selection.contains(&element) {
+                writer.write_element(&element)?;
+                written += 1;
+            }
+        }
+    }
+    writer.finish_and_commit()?;
+    total.finish(format_args!("{written} elements written"));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(&Args::from_env()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("bbox-extract: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
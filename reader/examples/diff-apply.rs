@@ -0,0 +1,49 @@
+//! Applies change files to a snapshot sorted by type and id.
+//!
+//! The changes are read from PBF files; elements with `visible=false` are
+//! deleted. Later change files take precedence.
+//!
+//! ```sh
+//! cargo run --release --example diff-apply -- base.osm.pbf output.osm.pbf changes-1.osm.pbf [changes-2.osm.pbf ...]
+//! ```
+
+use std::process::ExitCode;
+
+use osm_pbf_reader::cli::{Args, Progress};
+use osm_pbf_reader::error::{Error, Result};
+use osm_pbf_reader::writer::FileSink;
+use osm_pbf_reader::{apply_changes, Blobs, Changes, WriterOptions};
+
+fn run(args: &Args) -> Result<()> {
+    args.ensure_known(&["atomic"])?;
+    let base = args.required(0, "base")?;
+    let output = args.required(1, "output")?;
+    let change_files = args.positional().get(2..).unwrap_or_default();
+    if change_files.is_empty() {
+        return Err(Error::InvalidArgument("missing <changes>".to_owned()));
+    }
+
+    let mut progress = Progress::new("diff-apply");
+    let mut changes = Vec::with_capacity(change_files.len());
+    for path in change_files {
+        let c = Changes::from_blobs(&mut Blobs::from_path(path)?)?;
+        progress.update(format_args!("{path}: {} changes", c.len()));
+        changes.push(c);
+    }
+
+    let sink = FileSink::create(output, args.flag("atomic"))?;
+    let mut base = Blobs::from_path(base)?;
+    apply_changes(&mut base, &changes, sink, WriterOptions::default())?.commit()?;
+    progress.finish(format_args!("{} change files applied", changes.len()));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(&Args::from_env()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("diff-apply: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
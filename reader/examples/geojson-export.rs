@@ -0,0 +1,152 @@
+//! Exports the tagged nodes and the ways of a file as GeoJSON.
+//!
+//! Nodes become `Point`s, ways `LineString`s. With `--key`, only elements
+//! with that tag are exported. The output is written to stdout when no
+//! output path is given.
+//!
+//! ```sh
+//! cargo run --release --example geojson-export -- input.osm.pbf [output.geojson] [--key=amenity]
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+use osm_pbf_reader::cli::{Args, Progress};
+use osm_pbf_reader::data::{Element, OwnedTags};
+use osm_pbf_reader::error::Result;
+use osm_pbf_reader::Blobs;
+
+/// Writes `s` as a JSON string.
+fn write_json_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+struct FeatureWriter<W> {
+    out: W,
+    features: u64,
+}
+
+impl<W: Write> FeatureWriter<W> {
+    fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(b"{\"type\":\"FeatureCollection\",\"features\":[\n")?;
+        Ok(Self { out, features: 0 })
+    }
+
+    /// Writes a feature; `coordinates` is the JSON of the coordinates.
+    fn feature(
+        &mut self,
+        id: &str,
+        tags: &OwnedTags,
+        geometry: &str,
+        coordinates: &str,
+    ) -> io::Result<()> {
+        if self.features > 0 {
+            self.out.write_all(b",\n")?;
+        }
+        self.features += 1;
+        write!(
+            self.out,
+            "{{\"type\":\"Feature\",\"id\":\"{id}\",\"properties\":{{"
+        )?;
+        for (i, (k, v)) in tags.iter().enumerate() {
+            if i > 0 {
+                self.out.write_all(b",")?;
+            }
+            write_json_string(&mut self.out, k)?;
+            self.out.write_all(b":")?;
+            write_json_string(&mut self.out, v)?;
+        }
+        write!(
+            self.out,
+            "}},\"geometry\":{{\"type\":\"{geometry}\",\"coordinates\":{coordinates}}}}}"
+        )
+    }
+
+    fn finish(mut self) -> io::Result<u64> {
+        self.out.write_all(b"\n]}\n")?;
+        self.out.flush()?;
+        Ok(self.features)
+    }
+}
+
+fn export(input: &str, key: Option<&str>, out: impl Write) -> Result<u64> {
+    let mut features = FeatureWriter::new(out)?;
+    // locations of all nodes, in degrees
+    let mut locations = HashMap::new();
+    let mut progress = Progress::new("geojson-export");
+    let mut blobs = Blobs::from_path(input)?;
+    let selected = |tags: &OwnedTags| match key {
+        Some(key) => tags.iter().any(|(k, _)| k == key),
+        None => !tags.is_empty(),
+    };
+    while let Some(block) = blobs.next_primitive_block_decoded()? {
+        for element in block.primitives().filter_map(|p| p.to_element()) {
+            match &element {
+                Element::Node(n) => {
+                    locations.insert(n.id, (n.lon(), n.lat()));
+                    if selected(&n.tags) {
+                        let point = format!("[{:.7},{:.7}]", n.lon(), n.lat());
+                        features.feature(&format!("node/{}", n.id), &n.tags, "Point", &point)?;
+                    }
+                }
+                Element::Way(w) => {
+                    if !selected(&w.tags) {
+                        continue;
+                    }
+                    let points: Vec<_> = w
+                        .refs
+                        .iter()
+                        .filter_map(|id| locations.get(id))
+                        .map(|(lon, lat)| format!("[{lon:.7},{lat:.7}]"))
+                        .collect();
+                    // incomplete ways (e.g. at the border of an extract)
+                    // are skipped
+                    if points.len() == w.refs.len() && points.len() >= 2 {
+                        let line = format!("[{}]", points.join(","));
+                        features.feature(&format!("way/{}", w.id), &w.tags, "LineString", &line)?;
+                    }
+                }
+                Element::Relation(_) => {}
+            }
+        }
+        progress.update(format_args!("{} features", features.features));
+    }
+    let count = features.finish()?;
+    progress.finish(format_args!("{count} features"));
+    Ok(count)
+}
+
+fn run(args: &Args) -> Result<()> {
+    args.ensure_known(&["key"])?;
+    let input = args.required(0, "input")?;
+    let key = args.option("key");
+    match args.positional().get(1) {
+        Some(output) => export(input, key, BufWriter::new(File::create(output)?))?,
+        None => export(input, key, io::stdout().lock())?,
+    };
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(&Args::from_env()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("geojson-export: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Counts the tag keys of a file, or the values of one key.
+//!
+//! ```sh
+//! cargo run --release --example tag-count -- input.osm.pbf [--key=highway] [--top=20]
+//! ```
+
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+use osm_pbf_reader::cli::{Args, Progress};
+use osm_pbf_reader::data::primitives::{NodeRef, RelationRef, WayRef};
+use osm_pbf_reader::data::PrimitiveBlock;
+use osm_pbf_reader::error::Result;
+use osm_pbf_reader::{Blobs, Visitor};
+
+struct TagCounter<'a> {
+    key: Option<&'a str>,
+    counts: HashMap<String, u64>,
+    progress: Progress,
+    elements: u64,
+}
+
+impl TagCounter<'_> {
+    fn count<'t>(&mut self, tags: impl Iterator<Item = (&'t str, &'t str)>) {
+        self.elements += 1;
+        for (k, v) in tags {
+            let counted = match self.key {
+                Some(key) if key == k => v,
+                Some(_) => continue,
+                None => k,
+            };
+            // avoid allocating for strings that were already seen
+            match self.counts.get_mut(counted) {
+                Some(count) => *count += 1,
+                None => {
+                    self.counts.insert(counted.to_owned(), 1);
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for TagCounter<'_> {
+    fn node(&mut self, node: &NodeRef<'_>) {
+        self.count(node.tags());
+    }
+
+    fn way(&mut self, way: &WayRef<'_>) {
+        self.count(way.tags());
+    }
+
+    fn relation(&mut self, relation: &RelationRef<'_>) {
+        self.count(relation.tags());
+    }
+
+    fn block_will_drop(&mut self, _block: &PrimitiveBlock) {
+        self.progress
+            .update(format_args!("{} elements", self.elements));
+    }
+}
+
+fn run(args: &Args) -> Result<()> {
+    args.ensure_known(&["key", "top"])?;
+    let input = args.required(0, "input")?;
+    let top = args.value::<usize>("top")?.unwrap_or(20);
+    let mut counter = TagCounter {
+        key: args.option("key"),
+        counts: HashMap::new(),
+        progress: Progress::new("tag-count"),
+        elements: 0,
+    };
+    Blobs::from_path(input)?.visit(&mut counter)?;
+
+    let mut counts: Vec<_> = counter.counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    for (string, count) in counts.iter().take(top) {
+        println!("{count:>12} {string}");
+    }
+    counter.progress.finish(format_args!(
+        "{} elements, {} distinct",
+        counter.elements,
+        counts.len()
+    ));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(&Args::from_env()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("tag-count: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
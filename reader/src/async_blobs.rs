@@ -0,0 +1,421 @@
+//! Async reading of blobs.
+//!
+//! The reader is independent of the async runtime: it reads from any
+//! [`AsyncInput`], which is implemented for tokio's `AsyncBufRead` (`tokio`
+//! feature) and for `futures::io::AsyncBufRead` through [`FuturesIo`]
+//! (`futures-io` feature, e.g. for async-std or smol).
+
+use bytes::Bytes;
+use futures_core::{FusedStream, Stream};
+use osm_pbf_proto::osmformat::{HeaderBlock, PrimitiveBlock as PbfPrimitiveBlock};
+use osm_pbf_proto::protobuf::CodedInputStream;
+use std::fmt;
+use std::future::{self, Future};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::blob::{merge_lenient, merge_payload, Blob, PbfBlob, PbfBlobHeader};
+use crate::cancel::CancellationToken;
+use crate::codec::{BlobCodec, Codecs};
+use crate::data::OSMDataBlob;
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+
+mod offload;
+
+#[cfg(feature = "tokio")]
+pub use self::offload::TokioBlocking;
+pub use self::offload::{BlockingSpawner, DecodedBlocks};
+
+/// A buffered async input of [`AsyncBlobs`].
+///
+/// Mirrors the `AsyncBufRead` traits of tokio and `futures-io`.
+pub trait AsyncInput {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>>;
+
+    fn consume(self: Pin<&mut Self>, amt: usize);
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead> AsyncInput for R {
+    #[inline]
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        tokio::io::AsyncBufRead::poll_fill_buf(self, cx)
+    }
+
+    #[inline]
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        tokio::io::AsyncBufRead::consume(self, amt);
+    }
+}
+
+/// Adapts a `futures::io::AsyncBufRead` (e.g. of async-std or smol) to an
+/// [`AsyncInput`].
+#[cfg(feature = "futures-io")]
+#[derive(Clone, Debug, Default)]
+pub struct FuturesIo<R>(pub R);
+
+#[cfg(feature = "futures-io")]
+impl<R: futures_io::AsyncBufRead + Unpin> AsyncInput for FuturesIo<R> {
+    #[inline]
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().0).poll_fill_buf(cx)
+    }
+
+    #[inline]
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.get_mut().0).consume(amt);
+    }
+}
+
+/// Reads from `input` into `buf` until it is full or the input ends;
+/// returns the number of bytes read.
+async fn read_full<R: AsyncInput + Unpin>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = future::poll_fn(|cx| {
+            let mut input = Pin::new(&mut *input);
+            let available = match input.as_mut().poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let n = available.len().min(buf.len() - filled);
+            buf[filled..filled + n].copy_from_slice(&available[..n]);
+            input.consume(n);
+            Poll::Ready(Ok(n))
+        })
+        .await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// Skips up to `len` bytes of `input`; returns the number of skipped bytes.
+async fn skip<R: AsyncInput + Unpin>(input: &mut R, len: u64) -> io::Result<u64> {
+    let mut skipped = 0;
+    while skipped < len {
+        let n = future::poll_fn(|cx| {
+            let mut input = Pin::new(&mut *input);
+            let available = match input.as_mut().poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available.len(),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let n = available.min(usize::try_from(len - skipped).unwrap_or(usize::MAX));
+            input.consume(n);
+            Poll::Ready(Ok(n))
+        })
+        .await?;
+        if n == 0 {
+            break;
+        }
+        skipped += n as u64;
+    }
+    Ok(skipped)
+}
+
+/// The async counterpart of [`Blobs`](crate::Blobs).
+///
+/// Only the framing is async: blobs are read without blocking, decoding
+/// (see [`AsyncBlobs::next_primitive_block_decoded`]) runs on the calling
+/// task.
+#[derive(Debug)]
+pub struct AsyncBlobs<R> {
+    header: HeaderBlock,
+    reader: R,
+    limits: Limits,
+    blob_count: u64,
+    /// Position in the input, relative to its start.
+    position: u64,
+    /// Offset of the last read blob.
+    blob_offset: u64,
+    codecs: Codecs,
+    cancel: Option<CancellationToken>,
+}
+
+impl<R> AsyncBlobs<R> {
+    #[inline]
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+
+    #[inline]
+    pub fn header(&self) -> &HeaderBlock {
+        &self.header
+    }
+
+    #[inline]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    #[inline]
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    #[inline]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// See [`Blobs::register_codec`](crate::Blobs::register_codec).
+    #[inline]
+    pub fn register_codec(&mut self, field: u32, codec: impl BlobCodec + 'static) {
+        self.codecs.register(field, codec);
+    }
+
+    #[inline]
+    pub fn with_codec(mut self, field: u32, codec: impl BlobCodec + 'static) -> Self {
+        self.register_codec(field, codec);
+        self
+    }
+
+    /// See [`Blobs::with_cancellation`](crate::Blobs::with_cancellation).
+    #[inline]
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = Some(token);
+    }
+
+    #[inline]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Offset of the last read blob in the input.
+    #[inline]
+    pub fn blob_offset(&self) -> u64 {
+        self.blob_offset
+    }
+}
+
+impl<R: AsyncInput + Unpin> AsyncBlobs<R> {
+    /// Reads the header block of the input.
+    pub async fn new(reader: R) -> Result<Self> {
+        let mut r = Self {
+            header: HeaderBlock::new(),
+            reader,
+            limits: Limits::default(),
+            blob_count: 0,
+            position: 0,
+            blob_offset: 0,
+            codecs: Codecs::new(),
+            cancel: None,
+        };
+        r.read_header_block().await?;
+        Ok(r)
+    }
+
+    /// Reads the next `len` bytes of the input.
+    async fn read_bytes(&mut self, len: usize) -> Result<Bytes> {
+        let mut buf = vec![0; len];
+        let read = read_full(&mut self.reader, &mut buf).await?;
+        self.position += read as u64;
+        if read < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(Bytes::from(buf))
+    }
+
+    async fn skip_payload(&mut self, header: &PbfBlobHeader) -> Result<()> {
+        let size = header.datasize() as u64;
+        let skipped = skip(&mut self.reader, size).await?;
+        self.position += skipped;
+        if skipped < size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+
+    async fn read_blob_header(&mut self) -> Result<Option<PbfBlobHeader>> {
+        if let Some(cancel) = &self.cancel {
+            cancel.check()?;
+        }
+        let blob_offset = self.position;
+        let mut size = [0; 4];
+        let header_size = match read_full(&mut self.reader, &mut size).await? {
+            4 => u32::from_be_bytes(size),
+            _ => return Ok(None), // Expected EOF
+        };
+        if header_size > self.limits.max_header_size {
+            return Err(Error::BlobHeaderToLarge);
+        }
+        let header_size = header_size as usize;
+        self.position += 4;
+
+        self.blob_count += 1;
+        self.limits.check_blob_count(self.blob_count)?;
+
+        let bytes = self.read_bytes(header_size).await?;
+        let header: PbfBlobHeader = merge_lenient(&mut CodedInputStream::from_tokio_bytes(&bytes))?;
+        let data_size = header.datasize() as usize;
+        if data_size > self.limits.max_blob_size {
+            return Err(self.limits.size_error());
+        }
+        self.blob_offset = blob_offset;
+        Ok(Some(header))
+    }
+
+    async fn read_blob(&mut self, header: &PbfBlobHeader) -> Result<PbfBlob> {
+        let bytes = self.read_bytes(header.datasize() as usize).await?;
+        let blob: PbfBlob = merge_lenient(&mut CodedInputStream::from_tokio_bytes(&bytes))?;
+        self.limits.check_blob(&blob)?;
+        Ok(blob)
+    }
+
+    async fn read_header_block(&mut self) -> Result<()> {
+        let Some(header) = self.read_blob_header().await? else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        if header.type_() != "OSMHeader" {
+            return Err(Error::UnexpectedBlobType(header.type_().to_string()));
+        }
+        let blob = self.read_blob(&header).await?;
+        merge_payload(&blob, &mut self.header, self.limits.max_decompressed_size)
+            .map_err(|e| self.limits.map_size_error(e))
+    }
+
+    pub async fn next_blob(&mut self) -> Result<Option<(PbfBlobHeader, PbfBlob)>> {
+        let Some(header) = self.read_blob_header().await? else {
+            return Ok(None);
+        };
+        let blob = self.read_blob(&header).await?;
+        Ok(Some((header, blob)))
+    }
+
+    /// Reads the next `OSMData` blob without decoding it. Blobs of unknown
+    /// types are skipped.
+    pub async fn next_primitive_block(&mut self) -> Result<Option<OSMDataBlob>> {
+        loop {
+            let Some(header) = self.read_blob_header().await? else {
+                return Ok(None);
+            };
+            match header.type_() {
+                "OSMData" => {
+                    let mut blob = self.read_blob(&header).await?;
+                    self.codecs
+                        .decompress_in_place(&mut blob, self.limits.max_decompressed_size)
+                        .map_err(|e| self.limits.map_size_error(e))?;
+                    return Ok(Some(Blob::new(blob)));
+                }
+                "OSMHeader" => return Err(Error::UnexpectedBlobType(header.type_().to_string())),
+                _ => self.skip_payload(&header).await?,
+            }
+        }
+    }
+
+    /// The next block, decoded and checked against the limits of the
+    /// reader.
+    pub async fn next_primitive_block_decoded(&mut self) -> Result<Option<PbfPrimitiveBlock>> {
+        let Some(mut blob) = self.next_primitive_block().await? else {
+            return Ok(None);
+        };
+        blob.decode_with_limits(&self.limits)?;
+        blob.decode_into().map(Some)
+    }
+}
+
+impl<R: AsyncInput + Unpin + Send + 'static> AsyncBlobs<R> {
+    /// A [`Stream`] of the `OSMData` blobs; see
+    /// [`AsyncBlobs::next_primitive_block`].
+    #[inline]
+    pub fn into_stream(self) -> BlobStream<R, OSMDataBlob> {
+        BlobStream::new(self, |mut blobs| {
+            Box::pin(async move {
+                let next = blobs.next_primitive_block().await;
+                (blobs, next)
+            })
+        })
+    }
+
+    /// A [`Stream`] of the decoded blocks; see
+    /// [`AsyncBlobs::next_primitive_block_decoded`].
+    #[inline]
+    pub fn into_decoded_stream(self) -> BlobStream<R, PbfPrimitiveBlock> {
+        BlobStream::new(self, |mut blobs| {
+            Box::pin(async move {
+                let next = blobs.next_primitive_block_decoded().await;
+                (blobs, next)
+            })
+        })
+    }
+}
+
+type Step<R, T> = Pin<Box<dyn Future<Output = (Box<AsyncBlobs<R>>, Result<Option<T>>)> + Send>>;
+
+enum StreamState<R, T> {
+    Idle(Box<AsyncBlobs<R>>),
+    Reading(Step<R, T>),
+    Done,
+}
+
+/// A [`Stream`] over the blobs of an [`AsyncBlobs`]; see
+/// [`AsyncBlobs::into_stream`] and [`AsyncBlobs::into_decoded_stream`].
+///
+/// The stream ends after the first error.
+pub struct BlobStream<R, T> {
+    state: StreamState<R, T>,
+    next: fn(Box<AsyncBlobs<R>>) -> Step<R, T>,
+}
+
+impl<R, T> BlobStream<R, T> {
+    #[inline]
+    fn new(blobs: AsyncBlobs<R>, next: fn(Box<AsyncBlobs<R>>) -> Step<R, T>) -> Self {
+        Self {
+            state: StreamState::Idle(Box::new(blobs)),
+            next,
+        }
+    }
+}
+
+impl<R, T> fmt::Debug for BlobStream<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            StreamState::Idle(_) => "Idle",
+            StreamState::Reading(_) => "Reading",
+            StreamState::Done => "Done",
+        };
+        f.debug_struct("BlobStream").field("state", &state).finish()
+    }
+}
+
+// the pending step is boxed, nothing is pinned in place
+impl<R, T> Unpin for BlobStream<R, T> {}
+
+impl<R, T> Stream for BlobStream<R, T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut step = match std::mem::replace(&mut self.state, StreamState::Done) {
+            StreamState::Idle(blobs) => (self.next)(blobs),
+            StreamState::Reading(step) => step,
+            StreamState::Done => return Poll::Ready(None),
+        };
+        match step.as_mut().poll(cx) {
+            Poll::Pending => {
+                self.state = StreamState::Reading(step);
+                Poll::Pending
+            }
+            Poll::Ready((blobs, Ok(Some(item)))) => {
+                self.state = StreamState::Idle(blobs);
+                Poll::Ready(Some(Ok(item)))
+            }
+            Poll::Ready((_, Ok(None))) => Poll::Ready(None),
+            Poll::Ready((_, Err(e))) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+impl<R, T> FusedStream for BlobStream<R, T> {
+    #[inline]
+    fn is_terminated(&self) -> bool {
+        matches!(self.state, StreamState::Done)
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use futures_core::{FusedStream, Stream};
+use osm_pbf_proto::osmformat::PrimitiveBlock as PbfPrimitiveBlock;
+
+use super::{AsyncBlobs, AsyncInput, BlobStream};
+use crate::data::OSMDataBlob;
+use crate::error::Result;
+use crate::limits::Limits;
+
+/// Runs blocking tasks off the async executor; see
+/// [`AsyncBlobs::decode_offloaded`].
+///
+/// Implemented for closures, e.g. `|task| rayon::spawn(task)`.
+pub trait BlockingSpawner {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>);
+}
+
+impl<F: Fn(Box<dyn FnOnce() + Send>)> BlockingSpawner for F {
+    #[inline]
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        self(task)
+    }
+}
+
+/// Spawns on the blocking pool of the current tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TokioBlocking;
+
+#[cfg(feature = "tokio")]
+impl BlockingSpawner for TokioBlocking {
+    #[inline]
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        tokio::task::spawn_blocking(task);
+    }
+}
+
+type Decoded = thread::Result<Result<PbfPrimitiveBlock>>;
+
+/// The result of a decoding task.
+#[derive(Default)]
+struct Slot {
+    decoded: Option<Decoded>,
+    waker: Option<Waker>,
+}
+
+fn fill(slot: &Mutex<Slot>, decoded: Decoded) {
+    let mut slot = slot.lock().unwrap_or_else(PoisonError::into_inner);
+    slot.decoded = Some(decoded);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+/// Blocks that are read asynchronously and decoded by a
+/// [`BlockingSpawner`], in the order of the input; see
+/// [`AsyncBlobs::decode_offloaded`].
+///
+/// The stream ends after the first error. A panic of a decoding task is
+/// resumed when its block is due.
+pub struct DecodedBlocks<R, S> {
+    blobs: BlobStream<R, OSMDataBlob>,
+    spawner: S,
+    limits: Limits,
+    window: usize,
+    pending: VecDeque<Arc<Mutex<Slot>>>,
+    reading: bool,
+    done: bool,
+}
+
+impl<R: AsyncInput + Unpin + Send + 'static> AsyncBlobs<R> {
+    /// Reads blobs on the calling task, but decodes them with `spawner`,
+    /// with up to `n` blocks in flight (capped by
+    /// [`Limits::max_blocks_in_flight`]).
+    ///
+    /// Decompression and parsing don't block the executor, and up to `n`
+    /// blocks are decoded in parallel.
+    pub fn decode_offloaded<S: BlockingSpawner>(self, spawner: S, n: usize) -> DecodedBlocks<R, S> {
+        let limits = self.limits().clone();
+        DecodedBlocks {
+            window: limits.blocks_in_flight(n),
+            blobs: self.into_stream(),
+            spawner,
+            limits,
+            pending: VecDeque::new(),
+            reading: true,
+            done: false,
+        }
+    }
+
+    /// Like [`AsyncBlobs::decode_offloaded`], on the blocking pool of the
+    /// current tokio runtime.
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn decode_on_blocking_pool(self, n: usize) -> DecodedBlocks<R, TokioBlocking> {
+        self.decode_offloaded(TokioBlocking, n)
+    }
+}
+
+impl<R, S: BlockingSpawner> DecodedBlocks<R, S> {
+    fn spawn(&mut self, mut blob: OSMDataBlob) {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let limits = self.limits.clone();
+        let task_slot = slot.clone();
+        self.spawner.spawn_blocking(Box::new(move || {
+            let decoded = panic::catch_unwind(AssertUnwindSafe(|| {
+                blob.decode_with_limits(&limits)?;
+                blob.decode_into()
+            }));
+            fill(&task_slot, decoded);
+        }));
+        self.pending.push_back(slot);
+    }
+}
+
+impl<R, S> fmt::Debug for DecodedBlocks<R, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecodedBlocks")
+            .field("window", &self.window)
+            .field("pending", &self.pending.len())
+            .field("reading", &self.reading)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<R, S> Unpin for DecodedBlocks<R, S> {}
+
+impl<R, S: BlockingSpawner> Stream for DecodedBlocks<R, S> {
+    type Item = Result<PbfPrimitiveBlock>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        while self.reading && self.pending.len() < self.window {
+            match Pin::new(&mut self.blobs).poll_next(cx) {
+                Poll::Ready(Some(Ok(blob))) => self.spawn(blob),
+                Poll::Ready(Some(Err(e))) => {
+                    // reported after the blocks before it
+                    self.reading = false;
+                    let slot = Slot {
+                        decoded: Some(Ok(Err(e))),
+                        waker: None,
+                    };
+                    self.pending.push_back(Arc::new(Mutex::new(slot)));
+                }
+                Poll::Ready(None) => self.reading = false,
+                Poll::Pending => break,
+            }
+        }
+        let Some(front) = self.pending.front() else {
+            self.done = !self.reading;
+            return if self.done {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        };
+        let decoded = {
+            let mut slot = front.lock().unwrap_or_else(PoisonError::into_inner);
+            match slot.decoded.take() {
+                Some(decoded) => decoded,
+                None => {
+                    slot.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        };
+        self.pending.pop_front();
+        match decoded {
+            Ok(Ok(block)) => Poll::Ready(Some(Ok(block))),
+            Ok(Err(e)) => {
+                self.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<R, S: BlockingSpawner> FusedStream for DecodedBlocks<R, S> {
+    #[inline]
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::Result;
+use crate::writer::Durable;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A file that is written to a temporary location in the target directory
+/// and only moved into place by [`AtomicFile::commit`].
+///
+/// When dropped without being committed, the temporary file is removed, so
+/// downstream watchers never observe a partially written output.
+#[derive(Debug)]
+#[must_use = "the file is removed unless it is committed"]
+pub struct AtomicFile {
+    file: Option<File>,
+    temp_path: PathBuf,
+    target_path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let target_path = path.as_ref().to_path_buf();
+        let dir = match target_path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = target_path
+            .file_name()
+            .ok_or(io::ErrorKind::InvalidInput)?
+            .to_string_lossy()
+            .into_owned();
+        loop {
+            let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+            let temp_path = dir.join(format!(".{file_name}.{}.{counter}.tmp", std::process::id()));
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)
+            {
+                Ok(file) => {
+                    return Ok(Self {
+                        file: Some(file),
+                        temp_path,
+                        target_path,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn temp_path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    #[inline]
+    pub fn target_path(&self) -> &Path {
+        &self.target_path
+    }
+
+    /// Flushes and fsyncs the temporary file and renames it to the target path.
+    ///
+    /// The temporary file is removed when this fails.
+    pub fn commit(mut self) -> Result<()> {
+        let file = self.file.take().expect("file already committed");
+        let synced = file.sync_all();
+        drop(file);
+        if let Err(e) = synced.and_then(|()| fs::rename(&self.temp_path, &self.target_path)) {
+            let _ = fs::remove_file(&self.temp_path);
+            return Err(e.into());
+        }
+        // persist the rename itself
+        if let Some(dir) = self.target_path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+
+    fn file_mut(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for AtomicFile {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file_mut()?.write(buf)
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.file_mut()?.write_all(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.file_mut()?.flush()
+    }
+}
+
+impl Durable for AtomicFile {
+    #[inline]
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.file_mut()?.sync_data()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
@@ -1,19 +1,241 @@
 use byteorder::{BigEndian, ReadBytesExt};
-use bytes::Buf;
+use bytes::Bytes;
 use osm_pbf_proto::fileformat::blob::Data;
 pub use osm_pbf_proto::fileformat::{Blob as PbfBlob, BlobHeader as PbfBlobHeader};
-use osm_pbf_proto::osmformat::{HeaderBlock, PrimitiveBlock as PbfPrimitiveBlock};
+use osm_pbf_proto::osmformat::{HeaderBlock, PrimitiveBlock as PbfPrimitiveBlock, PrimitiveGroup};
+use osm_pbf_proto::primitives::PrimitiveType;
 use osm_pbf_proto::protobuf::{self as pb, CodedInputStream, Message};
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::iter;
 use std::path::Path;
 
+use crate::cancel::CancellationToken;
+use crate::codec::Codecs;
 use crate::data::OSMDataBlob;
 use crate::error::{Error, Result};
+use crate::limits::Limits;
+use crate::wire;
+use crate::writer::type_name;
 
-const MAX_HEADER_SIZE: u32 = 64 * 1024;
-const MAX_UNCOMPRESSED_DATA_SIZE: usize = 32 * 1024 * 1024;
+pub(crate) const MAX_HEADER_SIZE: u32 = 64 * 1024;
+pub(crate) const MAX_UNCOMPRESSED_DATA_SIZE: usize = 32 * 1024 * 1024;
+
+/// Compression codec for blob payloads.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum Codec {
+    Raw,
+    Zlib,
+    Lzma,
+    Zstd,
+    Lz4,
+}
+
+impl Default for Codec {
+    #[inline]
+    fn default() -> Self {
+        if cfg!(feature = "zlib") {
+            Self::Zlib
+        } else {
+            Self::Raw
+        }
+    }
+}
+
+impl Codec {
+    /// Codec of an encoded payload (`None` for unknown encodings).
+    pub fn of(data: &Data) -> Option<Self> {
+        match data {
+            Data::Raw(_) => Some(Self::Raw),
+            Data::ZlibData(_) => Some(Self::Zlib),
+            Data::LzmaData(_) => Some(Self::Lzma),
+            Data::ZstdData(_) => Some(Self::Zstd),
+            Data::Lz4Data(_) => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+
+    /// The level used by [`Codec::compress`] when no level is given (`None`
+    /// for codecs without levels).
+    pub const fn default_level(self) -> Option<u32> {
+        match self {
+            Self::Zlib | Self::Lzma => Some(6),
+            Self::Zstd => Some(3),
+            _ => None,
+        }
+    }
+
+    /// `true` when encoding with this codec is enabled by crate features.
+    pub const fn is_supported(self) -> bool {
+        match self {
+            Self::Raw => true,
+            Self::Zlib => cfg!(feature = "zlib"),
+            Self::Lzma => cfg!(feature = "lzma"),
+            Self::Zstd => cfg!(feature = "zstd"),
+            Self::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// Number of bytes of an encoded payload.
+    pub fn encoded_len(data: &Data) -> usize {
+        match data {
+            Data::Raw(d)
+            | Data::ZlibData(d)
+            | Data::LzmaData(d)
+            | Data::OBSOLETEBzip2Data(d)
+            | Data::Lz4Data(d)
+            | Data::ZstdData(d) => d.len(),
+            _ => 0,
+        }
+    }
+
+    /// Decompresses an encoded payload.
+    pub fn decompress(data: &Data) -> Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        Self::decompress_into(data, &mut raw, usize::MAX)?;
+        Ok(raw)
+    }
+
+    /// Decompresses an encoded payload into `raw` (which is cleared before,
+    /// so its allocation can be reused); fails when the payload is larger
+    /// than `max_size`.
+    ///
+    /// Prefer [`Codec::decompress_blob_into`]: lz4 payloads don't store their
+    /// size, it is taken from the `raw_size` of the blob.
+    #[inline]
+    pub fn decompress_into(data: &Data, raw: &mut Vec<u8>, max_size: usize) -> Result<()> {
+        Self::decompress_sized(data, None, raw, max_size)
+    }
+
+    /// Decompresses the payload of `blob`.
+    pub fn decompress_blob(blob: &PbfBlob) -> Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        Self::decompress_blob_into(blob, &mut raw, usize::MAX)?;
+        Ok(raw)
+    }
+
+    /// Like [`Codec::decompress_into`], but for the payload of `blob`.
+    pub fn decompress_blob_into(blob: &PbfBlob, raw: &mut Vec<u8>, max_size: usize) -> Result<()> {
+        match &blob.data {
+            Some(data) => Self::decompress_sized(data, blob.raw_size, raw, max_size),
+            None => {
+                raw.clear();
+                Ok(())
+            }
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn decompress_sized(
+        data: &Data,
+        raw_size: Option<i32>,
+        raw: &mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<()> {
+        raw.clear();
+        let limit = (max_size as u64).saturating_add(1);
+        match data {
+            Data::Raw(r) => raw.extend_from_slice(r),
+            #[cfg(feature = "zlib")]
+            Data::ZlibData(z) => {
+                flate2::bufread::ZlibDecoder::new(io::Cursor::new(z))
+                    .take(limit)
+                    .read_to_end(raw)?;
+            }
+            #[cfg(feature = "lzma")]
+            Data::LzmaData(z) => {
+                xz2::bufread::XzDecoder::new(io::Cursor::new(z))
+                    .take(limit)
+                    .read_to_end(raw)?;
+            }
+            #[cfg(feature = "zstd")]
+            Data::ZstdData(z) => {
+                zstd::stream::read::Decoder::with_buffer(io::Cursor::new(z))?
+                    .take(limit)
+                    .read_to_end(raw)?;
+            }
+            #[cfg(feature = "lz4")]
+            Data::Lz4Data(z) => lz4_decompress(z, raw_size, raw, max_size)?,
+            #[cfg(feature = "bzip2")]
+            Data::OBSOLETEBzip2Data(z) => {
+                bzip2::bufread::BzDecoder::new(io::Cursor::new(z))
+                    .take(limit)
+                    .read_to_end(raw)?;
+            }
+            _ => return Err(Error::UnsupportedEncoding),
+        }
+        if raw.len() > max_size {
+            return Err(Error::BlobDataToLarge);
+        }
+        Ok(())
+    }
+
+    /// Compresses `raw` into the matching `Data` variant.
+    ///
+    /// `level` is interpreted by the codec (`0..=9` for zlib and lzma,
+    /// `1..=22` for zstd, ignored for lz4); `None` selects the default level.
+    pub fn compress(self, raw: Vec<u8>, level: Option<u32>) -> Result<Data> {
+        Ok(match self {
+            Self::Raw => Data::Raw(Bytes::from(raw)),
+            #[cfg(feature = "zlib")]
+            Self::Zlib => {
+                let level = level.map_or(flate2::Compression::default(), |l| {
+                    flate2::Compression::new(l.min(9))
+                });
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(&raw)?;
+                Data::ZlibData(Bytes::from(encoder.finish()?))
+            }
+            #[cfg(feature = "lzma")]
+            Self::Lzma => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), level.unwrap_or(6).min(9));
+                encoder.write_all(&raw)?;
+                Data::LzmaData(Bytes::from(encoder.finish()?))
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => {
+                let level = level.map_or(0, |l| l.min(22) as i32);
+                Data::ZstdData(Bytes::from(zstd::bulk::compress(&raw, level)?))
+            }
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Data::Lz4Data(Bytes::from(lz4_flex::block::compress(&raw))),
+            #[allow(unreachable_patterns)]
+            _ => return Err(Error::UnsupportedEncoding),
+        })
+    }
+}
+
+/// Tuning of the blob compression.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct CompressionOptions {
+    /// Codec specific compression level (`None` for the default level).
+    pub level: Option<u32>,
+    /// Blobs with less uncompressed bytes are stored raw.
+    pub min_size_to_compress: usize,
+}
+
+impl CompressionOptions {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            level: None,
+            min_size_to_compress: 0,
+        }
+    }
+
+    #[inline]
+    pub const fn level(mut self, level: u32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    #[inline]
+    pub const fn min_size_to_compress(mut self, bytes: usize) -> Self {
+        self.min_size_to_compress = bytes;
+        self
+    }
+}
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Blob<M> {
@@ -22,14 +244,8 @@ pub enum Blob<M> {
 }
 
 impl<M> Blob<M> {
-    const INST: Self = Self::Encoded(PbfBlob {
-        raw_size: None,
-        data: None,
-        special_fields: pb::SpecialFields::new(),
-    });
-
     #[inline]
-    const fn new(blob: PbfBlob) -> Self {
+    pub(crate) const fn new(blob: PbfBlob) -> Self {
         Self::Encoded(blob)
     }
 }
@@ -41,46 +257,141 @@ impl<M> Default for Blob<M> {
 }
 
 impl<M: Message> Blob<M> {
-    pub fn decode_into(mut self) -> Result<M> {
-        self.decode()?;
-        let Self::Decoded(d) = self else {
-            unreachable!();
-        };
-        Ok(d)
+    pub fn decode_into(self) -> Result<M> {
+        match self {
+            Self::Encoded(d) => {
+                let mut r = M::new();
+                merge_payload(&d, &mut r, MAX_UNCOMPRESSED_DATA_SIZE)?;
+                Ok(r)
+            }
+            Self::Decoded(m) => Ok(m),
+        }
     }
 
+    /// Decodes the blob; the decompressed payload may be at most 32 MiB (see
+    /// [`Limits::max_decompressed_size`]).
+    #[inline]
     pub fn decode(&mut self) -> Result<&mut M> {
-        if let Self::Encoded(d) = self {
-            let r = match &d.data {
-                Some(Data::Raw(r)) => M::parse_from_tokio_bytes(r)?,
-                Some(Data::ZlibData(z)) => {
-                    let mut decoder = flate2::bufread::ZlibDecoder::new(io::Cursor::new(z));
-                    M::parse_from_reader(&mut decoder)?
-                }
-                Some(Data::LzmaData(z)) => {
-                    let mut decoder = xz2::bufread::XzDecoder::new(io::Cursor::new(z));
-                    M::parse_from_reader(&mut decoder)?
-                }
-                None => M::new(),
-                _ => {
-                    return Err(Error::UnsupportedEncoding);
-                }
-            };
-            *self = Self::Decoded(r);
+        self.decode_with_max_size(MAX_UNCOMPRESSED_DATA_SIZE)
+    }
+
+    fn decode_with_max_size(&mut self, max_size: usize) -> Result<&mut M> {
+        match self {
+            Self::Decoded(m) => Ok(m),
+            Self::Encoded(d) => {
+                let mut r = M::new();
+                merge_payload(d, &mut r, max_size)?;
+                *self = Self::Decoded(r);
+                // returns through the first arm
+                self.decode_with_max_size(max_size)
+            }
+        }
+    }
+
+    /// Decodes the blob into `buffer`, without changing the blob.
+    ///
+    /// `buffer` is cleared before; the allocations that the protobuf runtime
+    /// keeps on `clear` (e.g. the list of groups of a block) are reused, so
+    /// decoding many blocks into the same buffer allocates less.
+    pub fn decode_into_buffer(&self, buffer: &mut M) -> Result<()> {
+        buffer.clear();
+        match self {
+            Self::Encoded(d) => merge_payload(d, buffer, MAX_UNCOMPRESSED_DATA_SIZE),
+            Self::Decoded(m) => {
+                buffer.clone_from(m);
+                Ok(())
+            }
+        }
+    }
+
+    /// Serializes and compresses `msg` into a blob.
+    #[inline]
+    pub fn encode_message(msg: &M, codec: Codec) -> Result<PbfBlob> {
+        Self::encode_message_with(msg, codec, &CompressionOptions::default())
+    }
+
+    pub fn encode_message_with(
+        msg: &M,
+        codec: Codec,
+        options: &CompressionOptions,
+    ) -> Result<PbfBlob> {
+        let raw = msg.write_to_bytes()?;
+        if raw.len() > MAX_UNCOMPRESSED_DATA_SIZE {
+            return Err(Error::BlobDataToLarge);
         }
-        let Self::Decoded(d) = self else {
-            unreachable!();
+        let mut blob = PbfBlob::new();
+        blob.raw_size = Some(raw.len() as i32);
+        let codec = if raw.len() < options.min_size_to_compress {
+            Codec::Raw
+        } else {
+            codec
         };
-        Ok(d)
+        blob.data = Some(codec.compress(raw, options.level)?);
+        Ok(blob)
     }
 
-    pub fn parse_and_decode(is: &mut CodedInputStream<'_>) -> pb::Result<M> {
+    /// Encodes a decoded blob with `codec`. Already encoded blobs are kept
+    /// as they are.
+    pub fn encode(&mut self, codec: Codec) -> Result<&mut PbfBlob> {
+        match self {
+            Self::Encoded(e) => Ok(e),
+            Self::Decoded(m) => {
+                *self = Self::Encoded(Self::encode_message(m, codec)?);
+                // returns through the first arm
+                self.encode(codec)
+            }
+        }
+    }
+
+    pub fn encode_into(self, codec: Codec) -> Result<PbfBlob> {
+        match self {
+            Self::Encoded(e) => Ok(e),
+            Self::Decoded(m) => Self::encode_message(&m, codec),
+        }
+    }
+
+    pub fn parse_and_decode(is: &mut CodedInputStream<'_>) -> Result<M> {
         let mut data = M::new();
+        Self::parse_and_merge(is, &mut data)?;
+        Ok(data)
+    }
+
+    /// Like [`Blob::parse_and_decode`], but merges the payload into `data`.
+    #[inline]
+    pub fn parse_and_merge(is: &mut CodedInputStream<'_>, data: &mut M) -> Result<()> {
+        Self::parse_with_limit(is, data, MAX_UNCOMPRESSED_DATA_SIZE)
+    }
+
+    /// Parses a blob and merges its payload into `data`; fails when the
+    /// decompressed payload is larger than `max_size`.
+    fn parse_with_limit(
+        is: &mut CodedInputStream<'_>,
+        data: &mut M,
+        max_size: usize,
+    ) -> Result<()> {
+        // needed by lz4, whose payload doesn't store its size
+        #[cfg(feature = "lz4")]
+        let mut raw_size = None;
         while let Some(tag) = is.read_raw_tag_or_eof()? {
             match tag {
+                16 => {
+                    // RawSize (2); fails before decompressing a payload
+                    // that is too large
+                    let size = is.read_int32()?;
+                    if size > 0 && size as usize > max_size {
+                        return Err(Error::BlobDataToLarge);
+                    }
+                    #[cfg(feature = "lz4")]
+                    {
+                        raw_size = Some(size);
+                    }
+                }
                 10 => {
                     // Raw (1)
                     let len = is.read_raw_varint64()?;
+                    if len > max_size as u64 {
+                        return Err(Error::BlobDataToLarge);
+                    }
                     let old_limit = is.push_limit(len)?;
                     data.merge_from(is)?;
                     is.pop_limit(old_limit);
@@ -91,11 +402,7 @@ impl<M: Message> Blob<M> {
                     let len = is.read_raw_varint64()?;
                     let old_limit = is.push_limit(len)?;
                     let read: &mut dyn io::BufRead = is;
-                    {
-                        let mut decoder = flate2::bufread::ZlibDecoder::new(read);
-                        let mut is = CodedInputStream::new(&mut decoder);
-                        data.merge_from(&mut is)?;
-                    }
+                    merge_bounded(flate2::bufread::ZlibDecoder::new(read), data, max_size)?;
                     is.pop_limit(old_limit);
                 }
                 #[cfg(feature = "lzma")]
@@ -104,32 +411,247 @@ impl<M: Message> Blob<M> {
                     let len = is.read_raw_varint64()?;
                     let old_limit = is.push_limit(len)?;
                     let read: &mut dyn io::BufRead = is;
-                    {
-                        let mut decoder = xz2::bufread::XzDecoder::new(read);
-                        let mut is = CodedInputStream::new(&mut decoder);
-                        data.merge_from(&mut is)?;
-                    }
+                    merge_bounded(xz2::bufread::XzDecoder::new(read), data, max_size)?;
+                    is.pop_limit(old_limit);
+                }
+                #[cfg(feature = "zstd")]
+                58 => {
+                    // ZstdData (7)
+                    let len = is.read_raw_varint64()?;
+                    let old_limit = is.push_limit(len)?;
+                    let read: &mut dyn io::BufRead = is;
+                    merge_bounded(
+                        zstd::stream::read::Decoder::with_buffer(read)?,
+                        data,
+                        max_size,
+                    )?;
+                    is.pop_limit(old_limit);
+                }
+                #[cfg(feature = "lz4")]
+                50 => {
+                    // Lz4Data (6)
+                    let compressed = is.read_tokio_bytes()?;
+                    let mut raw = Vec::new();
+                    lz4_decompress(&compressed, raw_size, &mut raw, max_size)?;
+                    merge_lenient_into(&mut CodedInputStream::from_bytes(&raw), data)?;
+                }
+                #[cfg(feature = "bzip2")]
+                42 => {
+                    // OBSOLETE_bzip2_data (5)
+                    let len = is.read_raw_varint64()?;
+                    let old_limit = is.push_limit(len)?;
+                    let read: &mut dyn io::BufRead = is;
+                    merge_bounded(bzip2::bufread::BzDecoder::new(read), data, max_size)?;
                     is.pop_limit(old_limit);
                 }
-                /*
-                42 => { // OBSOLETEzip2Data (5)
-                    todo!()
-                },
-                50 => { // Lz4Data (6)
-                    todo!()
-                },
-                58 => { // ZstdData (
-                        // 7)
-                    todo!()
-                },
-                */
                 tag => {
                     pb::rt::skip_field_for_tag(tag, is)?;
                 }
             };
         }
-        data.check_initialized()?;
-        Ok(data)
+        Ok(())
+    }
+}
+
+/// Decompresses the payload of `blob` and merges it into `msg`; fails when
+/// the decompressed payload is larger than `max_size`.
+pub(crate) fn merge_payload<M: Message>(
+    blob: &PbfBlob,
+    msg: &mut M,
+    max_size: usize,
+) -> Result<()> {
+    match &blob.data {
+        Some(Data::Raw(r)) => {
+            if r.len() > max_size {
+                return Err(Error::BlobDataToLarge);
+            }
+            merge_lenient_into(&mut CodedInputStream::from_tokio_bytes(r), msg)?;
+        }
+        #[cfg(feature = "zlib")]
+        Some(Data::ZlibData(z)) => {
+            merge_bounded(
+                flate2::bufread::ZlibDecoder::new(io::Cursor::new(z)),
+                msg,
+                max_size,
+            )?;
+        }
+        #[cfg(feature = "lzma")]
+        Some(Data::LzmaData(z)) => {
+            merge_bounded(
+                xz2::bufread::XzDecoder::new(io::Cursor::new(z)),
+                msg,
+                max_size,
+            )?;
+        }
+        #[cfg(feature = "zstd")]
+        Some(Data::ZstdData(z)) => {
+            merge_bounded(
+                zstd::stream::read::Decoder::with_buffer(io::Cursor::new(z))?,
+                msg,
+                max_size,
+            )?;
+        }
+        #[cfg(feature = "lz4")]
+        Some(Data::Lz4Data(z)) => {
+            let mut raw = Vec::new();
+            lz4_decompress(z, blob.raw_size, &mut raw, max_size)?;
+            merge_lenient_into(&mut CodedInputStream::from_bytes(&raw), msg)?;
+        }
+        #[cfg(feature = "bzip2")]
+        Some(Data::OBSOLETEBzip2Data(z)) => {
+            merge_bounded(
+                bzip2::bufread::BzDecoder::new(io::Cursor::new(z)),
+                msg,
+                max_size,
+            )?;
+        }
+        None => {}
+        _ => return Err(Error::UnsupportedEncoding),
+    }
+    Ok(())
+}
+
+/// Decompresses an lz4 block into `raw`.
+///
+/// The block format doesn't store the uncompressed size; without `raw_size`
+/// the output buffer is sized for the maximal compression ratio of lz4.
+#[cfg(feature = "lz4")]
+fn lz4_decompress(
+    data: &[u8],
+    raw_size: Option<i32>,
+    raw: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<()> {
+    let size = match raw_size {
+        Some(size) => usize::try_from(size).map_err(|_| Error::BlobDataToLarge)?,
+        None => data
+            .len()
+            .saturating_mul(255)
+            .saturating_add(16)
+            .min(max_size),
+    };
+    if size > max_size {
+        return Err(Error::BlobDataToLarge);
+    }
+    raw.clear();
+    raw.resize(size, 0);
+    let len = lz4_flex::block::decompress_into(data, raw)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    raw.truncate(len);
+    Ok(())
+}
+
+/// Merges the output of a decompressor into `msg`, reading at most
+/// `max_size` bytes.
+fn merge_bounded<M: Message>(decoder: impl Read, msg: &mut M, max_size: usize) -> Result<()> {
+    let mut decoder = decoder.take((max_size as u64).saturating_add(1));
+    let merged = merge_lenient_into(&mut CodedInputStream::new(&mut decoder), msg);
+    if decoder.limit() == 0 {
+        return Err(Error::BlobDataToLarge);
+    }
+    Ok(merged?)
+}
+
+/// Parses a message without checking for `required` fields.
+///
+/// Some producers omit fields that are required by the schema (e.g. the
+/// `stringtable` of empty blocks); missing fields read as their defaults.
+pub(crate) fn merge_lenient<M: Message>(is: &mut CodedInputStream<'_>) -> pb::Result<M> {
+    let mut msg = M::new();
+    merge_lenient_into(is, &mut msg)?;
+    Ok(msg)
+}
+
+fn merge_lenient_into<M: Message>(is: &mut CodedInputStream<'_>, msg: &mut M) -> pb::Result<()> {
+    msg.merge_from(is)?;
+    is.check_eof()
+}
+
+impl Blob<PbfPrimitiveBlock> {
+    /// Decodes the block and verifies it against the given `limits`.
+    pub fn decode_with_limits(&mut self, limits: &Limits) -> Result<&mut PbfPrimitiveBlock> {
+        if let Self::Encoded(blob) = self {
+            limits.check_blob(blob)?;
+        }
+        let block = self
+            .decode_with_max_size(limits.max_decompressed_size)
+            .map_err(|e| limits.map_size_error(e))?;
+        limits.check_primitive_block(block)?;
+        Ok(block)
+    }
+
+    /// Decodes only the primitives of the given `types`, without changing
+    /// the blob, and verifies the block against `limits`.
+    ///
+    /// The primitives of the other types (e.g. the dense nodes when only
+    /// ways are requested) are skipped at the wire level, without being
+    /// parsed; see [`wire::retain_types`].
+    pub fn decode_types(&self, types: PrimitiveType, limits: &Limits) -> Result<PbfPrimitiveBlock> {
+        self.decode_types_unmapped(types, limits)
+            .map_err(|e| limits.map_size_error(e))
+    }
+
+    fn decode_types_unmapped(
+        &self,
+        types: PrimitiveType,
+        limits: &Limits,
+    ) -> Result<PbfPrimitiveBlock> {
+        let max_size = limits.max_decompressed_size;
+        let block = match self {
+            Self::Decoded(block) => {
+                let mut block = block.clone();
+                for group in &mut block.primitivegroup {
+                    retain_group_types(group, types);
+                }
+                block
+            }
+            Self::Encoded(blob) if types.contains(PrimitiveType::all()) => {
+                limits.check_blob(blob)?;
+                let mut block = PbfPrimitiveBlock::new();
+                merge_payload(blob, &mut block, max_size)?;
+                block
+            }
+            Self::Encoded(blob) => {
+                limits.check_blob(blob)?;
+                let mut filtered = Vec::new();
+                match &blob.data {
+                    Some(Data::Raw(raw)) if raw.len() > max_size => {
+                        return Err(Error::BlobDataToLarge)
+                    }
+                    Some(Data::Raw(raw)) => wire::retain_types(raw, types, &mut filtered)?,
+                    Some(_) => {
+                        let mut raw = Vec::new();
+                        Codec::decompress_blob_into(blob, &mut raw, max_size)?;
+                        wire::retain_types(&raw, types, &mut filtered)?;
+                    }
+                    None => {}
+                }
+                let mut block = PbfPrimitiveBlock::new();
+                merge_lenient_into(
+                    &mut CodedInputStream::from_tokio_bytes(&Bytes::from(filtered)),
+                    &mut block,
+                )?;
+                block
+            }
+        };
+        limits.check_primitive_block(&block)?;
+        Ok(block)
+    }
+}
+
+fn retain_group_types(group: &mut PrimitiveGroup, types: PrimitiveType) {
+    if !types.contains(PrimitiveType::NODE) {
+        group.nodes.clear();
+        group.dense.clear();
+    }
+    if !types.contains(PrimitiveType::WAY) {
+        group.ways.clear();
+    }
+    if !types.contains(PrimitiveType::RELATION) {
+        group.relations.clear();
+    }
+    if !types.contains(PrimitiveType::CHANGE_SET) {
+        group.changesets.clear();
     }
 }
 
@@ -137,6 +659,22 @@ impl<M: Message> Blob<M> {
 pub struct Blobs<R> {
     header: HeaderBlock,
     reader: R,
+    limits: Limits,
+    blob_count: u64,
+    /// Position in the input, relative to its start.
+    position: u64,
+    /// The whole input, when it is held in memory (`reader` is then a
+    /// cursor over it). Payloads are sliced out of it without copying.
+    source: Option<Bytes>,
+    /// Offset of the last read blob.
+    blob_offset: u64,
+    /// Offset of the first blob after the header block.
+    data_offset: u64,
+    check_order: bool,
+    /// Type and id of the last checked element and the offset of its blob.
+    last_key: Option<(PrimitiveType, i64, u64)>,
+    pub(crate) codecs: Codecs,
+    cancel: Option<CancellationToken>,
 }
 
 impl<R> Blobs<R> {
@@ -145,16 +683,162 @@ impl<R> Blobs<R> {
         self.reader
     }
 
+    /// Offset of the next blob, relative to the start of the input; reading
+    /// can be resumed there with [`Blobs::seek_to`].
+    #[inline]
+    pub fn current_offset(&self) -> u64 {
+        self.position
+    }
+
     #[inline]
     pub fn header(&self) -> &HeaderBlock {
         &self.header
     }
+
+    #[inline]
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    #[inline]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    #[inline]
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    #[inline]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Verifies that the ids of the decoded blocks never decrease (by type,
+    /// then id), see [`Blobs::with_order_check`].
+    #[inline]
+    pub fn set_order_check(&mut self, check: bool) {
+        self.check_order = check;
+    }
+
+    /// Verifies that the ids never decrease across the blocks returned by
+    /// [`Blobs::next_primitive_block_decoded`] (and the scans based on it).
+    ///
+    /// Only the first and last element of each group are compared, so the
+    /// check is cheap, but not all unsorted elements within a group are
+    /// detected. The first violation is reported as
+    /// [`Error::UnsortedBlob`] with the offsets of the blobs.
+    #[inline]
+    pub fn with_order_check(mut self, check: bool) -> Self {
+        self.check_order = check;
+        self
+    }
+
+    /// Fails the reading of the next blob with [`Error::Cancelled`] once
+    /// `token` is cancelled, see [`Blobs::with_cancellation`].
+    #[inline]
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Stops reading with [`Error::Cancelled`] once `token` is cancelled.
+    ///
+    /// The token is checked before each blob, so the scans that read from
+    /// this reader (e.g. [`Blobs::map_reduce`] or [`Blobs::spawn`]) stop
+    /// after the blocks in flight and return the error.
+    #[inline]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    fn check_block_order(&mut self, block: &PbfPrimitiveBlock) -> Result<()> {
+        for (t, first, last) in block.primitivegroup.iter().flat_map(group_id_ranges) {
+            if let Some((last_t, last_id, last_offset)) = self.last_key {
+                if (t.bits(), first) < (last_t.bits(), last_id) {
+                    return Err(Error::UnsortedBlob(
+                        type_name(t),
+                        first,
+                        self.blob_offset,
+                        type_name(last_t),
+                        last_id,
+                        last_offset,
+                    ));
+                }
+            }
+            if last < first {
+                let offset = self.blob_offset;
+                return Err(Error::UnsortedBlob(
+                    type_name(t),
+                    last,
+                    offset,
+                    type_name(t),
+                    first,
+                    offset,
+                ));
+            }
+            self.last_key = Some((t, last, self.blob_offset));
+        }
+        Ok(())
+    }
 }
 
-impl<R: AsRef<[u8]>> Blobs<io::Cursor<R>> {
+/// Type, first and last id of the elements of a group.
+fn group_id_ranges(group: &PrimitiveGroup) -> impl Iterator<Item = (PrimitiveType, i64, i64)> {
+    fn range(
+        t: PrimitiveType,
+        ids: impl DoubleEndedIterator<Item = i64>,
+    ) -> Option<(PrimitiveType, i64, i64)> {
+        let mut ids = ids;
+        let first = ids.next()?;
+        Some((t, first, ids.next_back().unwrap_or(first)))
+    }
+    let dense = group.dense.as_ref().and_then(|d| {
+        let first = *d.id.first()?;
+        let last = d.id.iter().fold(0i64, |id, delta| id.wrapping_add(*delta));
+        Some((PrimitiveType::NODE, first, last))
+    });
+    [
+        range(PrimitiveType::NODE, group.nodes.iter().map(|n| n.id())),
+        dense,
+        range(PrimitiveType::WAY, group.ways.iter().map(|w| w.id())),
+        range(
+            PrimitiveType::RELATION,
+            group.relations.iter().map(|r| r.id()),
+        ),
+        range(
+            PrimitiveType::CHANGE_SET,
+            group.changesets.iter().map(|c| c.id()),
+        ),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+impl Blobs<io::Cursor<Bytes>> {
+    /// Reads from an in-memory buffer. Blob payloads (and the strings of raw
+    /// blocks) are slices of `bytes` and are not copied.
     #[inline]
-    pub fn from_bytes(bytes: R) -> Result<Self> {
-        Self::from_buf_read(io::Cursor::new(bytes))
+    pub fn from_bytes(bytes: impl Into<Bytes>) -> Result<Self> {
+        let bytes = bytes.into();
+        Self::with_source(io::Cursor::new(bytes.clone()), Some(bytes))
+    }
+
+    /// Maps the file at `path` into memory. Blob payloads (and the strings
+    /// of raw blocks) are sliced out of the mapping without copying.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the mapping is in
+    /// use, i.e. as long as the returned value or any blob or block read
+    /// from it is alive; see [`memmap2::Mmap::map`].
+    #[cfg(feature = "memmap2")]
+    pub unsafe fn from_mmap(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        let mmap = memmap2::Mmap::map(&file)?;
+        Self::from_bytes(Bytes::from_owner(mmap))
     }
 }
 
@@ -174,9 +858,20 @@ impl Blobs<io::BufReader<File>> {
 }
 
 impl<R: io::Seek> Blobs<R> {
+    /// Continues reading at the first blob after the header block.
     #[inline]
     pub fn rewind(&mut self) -> Result<()> {
-        self.reader.rewind()?;
+        self.seek_to(self.data_offset)
+    }
+
+    /// Continues reading at the blob at `offset`, e.g. a
+    /// [`Blobs::current_offset`] saved before. `offset` must be at the start
+    /// of a blob.
+    #[inline]
+    pub fn seek_to(&mut self, offset: u64) -> Result<()> {
+        self.reader.seek(io::SeekFrom::Start(offset))?;
+        self.position = offset;
+        self.last_key = None;
         Ok(())
     }
 }
@@ -184,40 +879,143 @@ impl<R: io::Seek> Blobs<R> {
 impl<R: io::BufRead> Blobs<R> {
     #[inline]
     pub fn from_buf_read(reader: R) -> Result<Self> {
+        Self::with_source(reader, None)
+    }
+
+    fn with_source(reader: R, source: Option<Bytes>) -> Result<Self> {
         let mut r = Self {
             header: HeaderBlock::new(),
             reader,
+            limits: Limits::default(),
+            blob_count: 0,
+            position: 0,
+            source,
+            blob_offset: 0,
+            data_offset: 0,
+            check_order: false,
+            last_key: None,
+            codecs: Codecs::new(),
+            cancel: None,
         };
         r._read_header_block()?;
+        r.data_offset = r.position;
         Ok(r)
     }
 
+    /// Parses the next `len` bytes of the input with `parse`.
+    fn parse_input<T>(
+        &mut self,
+        len: usize,
+        parse: impl FnOnce(&mut CodedInputStream<'_>) -> Result<T>,
+    ) -> Result<T> {
+        let value = match &self.source {
+            Some(source) => {
+                let start = self.position as usize;
+                if source.len() < start + len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let bytes = source.slice(start..start + len);
+                self.reader.consume(len);
+                let mut input = CodedInputStream::from_tokio_bytes(&bytes);
+                let value = parse(&mut input)?;
+                input.check_eof()?;
+                value
+            }
+            None => {
+                let mut input = self.reader.by_ref().take(len as u64);
+                let mut input = CodedInputStream::from_buf_read(&mut input);
+                let value = parse(&mut input)?;
+                input.check_eof()?;
+                value
+            }
+        };
+        self.position += len as u64;
+        Ok(value)
+    }
+
     fn _read_blob_header(&mut self) -> Result<Option<PbfBlobHeader>> {
+        if let Some(cancel) = &self.cancel {
+            cancel.check()?;
+        }
+        let blob_offset = self.position;
         let header_size = match self.reader.read_u32::<BigEndian>() {
             Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
                 return Ok(None); // Expected EOF
             }
             Err(e) => return Err(Error::IoError(e)),
-            Ok(header_size) if header_size > MAX_HEADER_SIZE => {
+            Ok(header_size) if header_size > self.limits.max_header_size => {
                 return Err(Error::BlobHeaderToLarge);
             }
             Ok(header_size) => header_size as usize,
         };
+        self.position += 4;
+
+        self.blob_count += 1;
+        self.limits.check_blob_count(self.blob_count)?;
 
         let header: PbfBlobHeader = self.read_msg_exact(header_size)?;
         let data_size = header.datasize() as usize;
-        if data_size > MAX_UNCOMPRESSED_DATA_SIZE {
-            return Err(Error::BlobDataToLarge);
+        if data_size > self.limits.max_blob_size {
+            return Err(self.limits.size_error());
         }
+        self.blob_offset = blob_offset;
         Ok(Some(header))
     }
 
     fn read_msg_exact<M: Message>(&mut self, exact_size: usize) -> Result<M> {
-        let mut input = self.reader.by_ref().take(exact_size as u64);
-        let mut input = CodedInputStream::from_buf_read(&mut input);
-        let msg = M::parse_from_reader(&mut input)?;
-        input.check_eof()?;
-        Ok(msg)
+        self.parse_input(exact_size, |is| Ok(merge_lenient(is)?))
+    }
+
+    /// Reads the next blob header of type `OSMData`. Blobs of unknown types
+    /// are skipped, as required by the specification.
+    fn _read_data_blob_header(&mut self) -> Result<Option<PbfBlobHeader>> {
+        loop {
+            let Some(header) = self._read_blob_header()? else {
+                return Ok(None);
+            };
+            match header.type_() {
+                "OSMData" => return Ok(Some(header)),
+                "OSMHeader" => return Err(Error::UnexpectedBlobType(header.type_().to_string())),
+                _ => self.skip_payload(&header)?,
+            }
+        }
+    }
+
+    /// Skips the payload of the blob of `header`.
+    fn skip_payload(&mut self, header: &PbfBlobHeader) -> Result<()> {
+        let size = header.datasize() as usize;
+        if let Some(source) = &self.source {
+            if source.len() < self.position as usize + size {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.reader.consume(size);
+            self.position += size as u64;
+            return Ok(());
+        }
+        let skipped = io::copy(&mut self.reader.by_ref().take(size as u64), &mut io::sink())?;
+        self.position += skipped;
+        if skipped < size as u64 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+
+    /// Reads the next `OSMData` blob whose header matches `cond`; the
+    /// payload of the other blobs is skipped.
+    pub(crate) fn next_data_blob_where(
+        &mut self,
+        cond: impl Fn(&PbfBlobHeader) -> bool,
+    ) -> Result<Option<(PbfBlobHeader, OSMDataBlob)>> {
+        loop {
+            let Some(header) = self._read_data_blob_header()? else {
+                return Ok(None);
+            };
+            if cond(&header) {
+                let blob = self.read_data_blob(&header)?;
+                return Ok(Some((header, Blob::new(blob))));
+            }
+            self.skip_payload(&header)?;
+        }
     }
 
     pub fn next_blob(&mut self) -> Result<Option<(PbfBlobHeader, PbfBlob)>> {
@@ -235,55 +1033,171 @@ impl<R: io::BufRead> Blobs<R> {
         if header.type_() != "OSMHeader" {
             return Err(Error::UnexpectedBlobType(header.type_().to_string()));
         }
-        let mut input = self.reader.by_ref().take(header.datasize() as u64);
-        let mut input = CodedInputStream::from_buf_read(&mut input);
-        self.header = Blob::parse_and_decode(&mut input)?;
-        input.check_eof()?;
+        let max_size = self.limits.max_decompressed_size;
+        self.header = self.parse_input(header.datasize() as usize, |is| {
+            let mut block = HeaderBlock::new();
+            Blob::parse_with_limit(is, &mut block, max_size)?;
+            Ok(block)
+        })?;
         Ok(())
     }
 
     pub fn next_primitive_block(&mut self) -> Result<Option<OSMDataBlob>> {
-        let Some(header) = self._read_blob_header()? else {
+        Ok(self.next_data_blob()?.map(Blob::new))
+    }
+
+    /// Reads the next `OSMData` blob without decoding it.
+    pub(crate) fn next_data_blob(&mut self) -> Result<Option<PbfBlob>> {
+        let Some(header) = self._read_data_blob_header()? else {
             return Ok(None);
         };
-        if header.type_() != "OSMData" {
-            return Err(Error::UnexpectedBlobType(header.type_().to_string()));
-        }
-        let blob: PbfBlob = self.read_msg_exact(header.datasize() as usize)?;
-        Ok(Some(Blob::Encoded(blob)))
+        self.read_data_blob(&header).map(Some)
+    }
+
+    /// Reads the payload of the blob of `header`; payloads of registered
+    /// codecs are decompressed.
+    fn read_data_blob(&mut self, header: &PbfBlobHeader) -> Result<PbfBlob> {
+        let mut blob: PbfBlob = self.read_msg_exact(header.datasize() as usize)?;
+        self.limits.check_blob(&blob)?;
+        self.codecs
+            .decompress_in_place(&mut blob, self.limits.max_decompressed_size)
+            .map_err(|e| self.limits.map_size_error(e))?;
+        Ok(blob)
     }
 
     pub fn next_primitive_block_decoded(&mut self) -> Result<Option<PbfPrimitiveBlock>> {
-        let Some(header) = self._read_blob_header()? else {
-            return Ok(None);
+        let max_size = self.limits.max_decompressed_size;
+        let decoded = if self.codecs.is_empty() {
+            let Some(header) = self._read_data_blob_header()? else {
+                return Ok(None);
+            };
+            self.parse_input(header.datasize() as usize, |is| {
+                let mut block = PbfPrimitiveBlock::new();
+                Blob::parse_with_limit(is, &mut block, max_size)?;
+                Ok(block)
+            })
+            .map_err(|e| self.limits.map_size_error(e))?
+        } else {
+            let Some(blob) = self.next_data_blob()? else {
+                return Ok(None);
+            };
+            let mut block = PbfPrimitiveBlock::new();
+            merge_payload(&blob, &mut block, max_size)
+                .map_err(|e| self.limits.map_size_error(e))?;
+            block
         };
-        if header.type_() != "OSMData" {
-            return Err(Error::UnexpectedBlobType(header.type_().to_string()));
+        self.limits.check_primitive_block(&decoded)?;
+        if self.check_order {
+            self.check_block_order(&decoded)?;
         }
-        let mut input = self.reader.by_ref().take(header.datasize() as u64);
-        let mut input = CodedInputStream::from_buf_read(&mut input);
-        let decoded = Blob::parse_and_decode(&mut input)?;
-        input.check_eof()?;
         Ok(Some(decoded))
     }
+
+    /// Like [`Blobs::next_primitive_block_decoded`], but only decodes the
+    /// primitives of the given `types` (see [`Blob::decode_types`]).
+    pub fn next_primitive_block_of_types(
+        &mut self,
+        types: PrimitiveType,
+    ) -> Result<Option<PbfPrimitiveBlock>> {
+        if types.contains(PrimitiveType::all()) {
+            return self.next_primitive_block_decoded();
+        }
+        let Some(blob) = self.next_data_blob()? else {
+            return Ok(None);
+        };
+        let block = OSMDataBlob::new(blob).decode_types(types, &self.limits)?;
+        if self.check_order {
+            self.check_block_order(&block)?;
+        }
+        Ok(Some(block))
+    }
+
+    /// Like [`Blobs::next_primitive_block_decoded`], but decodes the block
+    /// into `block` (see [`Blob::decode_into_buffer`]). Returns `false` at
+    /// the end of the input.
+    pub fn next_primitive_block_into(&mut self, block: &mut PbfPrimitiveBlock) -> Result<bool> {
+        let max_size = self.limits.max_decompressed_size;
+        if self.codecs.is_empty() {
+            let Some(header) = self._read_data_blob_header()? else {
+                return Ok(false);
+            };
+            block.clear();
+            self.parse_input(header.datasize() as usize, |is| {
+                Blob::parse_with_limit(is, block, max_size)
+            })
+            .map_err(|e| self.limits.map_size_error(e))?;
+        } else {
+            let Some(blob) = self.next_data_blob()? else {
+                return Ok(false);
+            };
+            block.clear();
+            merge_payload(&blob, block, max_size).map_err(|e| self.limits.map_size_error(e))?;
+        }
+        self.limits.check_primitive_block(block)?;
+        if self.check_order {
+            self.check_block_order(block)?;
+        }
+        Ok(true)
+    }
+}
+
+/// A blob header and the position of the blob; see [`Blobs::next_blob_with`].
+#[derive(Copy, Clone, Debug)]
+pub struct BlobInfo<'a> {
+    pub header: &'a PbfBlobHeader,
+    /// Offset of the blob (of its header length) in the input.
+    pub offset: u64,
+    /// Number of blobs read or skipped before this one, including the
+    /// header blob.
+    pub blobs_read: u64,
 }
 
 impl<R: io::BufRead + io::Seek> Blobs<R> {
-    fn next_blob_with(
+    /// Reads the next blob (of any type) for which `cond` returns `true`;
+    /// the payloads of the other blobs are skipped by seeking, without
+    /// reading them.
+    pub fn next_blob_with(
         &mut self,
-        cond: impl Fn(&PbfBlobHeader) -> bool,
+        mut cond: impl FnMut(&BlobInfo<'_>) -> bool,
     ) -> Result<Option<(PbfBlobHeader, PbfBlob)>> {
         loop {
             let Some(header) = self._read_blob_header()? else {
                 return Ok(None);
             };
-            if cond(&header) {
+            let info = BlobInfo {
+                header: &header,
+                offset: self.blob_offset,
+                blobs_read: self.blob_count - 1,
+            };
+            if cond(&info) {
                 let blob: PbfBlob = self.read_msg_exact((header.datasize() as u32) as usize)?;
                 return Ok(Some((header, blob)));
             }
-            self.reader
-                .seek(io::SeekFrom::Current((header.datasize() as u32) as i64))?;
+            self.seek_past_payload(&header)?;
+        }
+    }
+
+    fn seek_past_payload(&mut self, header: &PbfBlobHeader) -> Result<()> {
+        self.reader
+            .seek(io::SeekFrom::Current((header.datasize() as u32) as i64))?;
+        self.position += (header.datasize() as u32) as u64;
+        Ok(())
+    }
+
+    /// Offsets of the remaining `OSMData` blobs; only their headers are
+    /// read, and they don't count against [`Limits::max_blob_count`]. The
+    /// reader is left at the end of the input.
+    pub(crate) fn data_blob_offsets(&mut self) -> Result<Vec<u64>> {
+        let blob_count = self.blob_count;
+        let mut offsets = Vec::new();
+        while let Some(header) = self._read_blob_header()? {
+            if header.type_() == "OSMData" {
+                offsets.push(self.blob_offset);
+            }
+            self.seek_past_payload(&header)?;
         }
+        self.blob_count = blob_count;
+        Ok(offsets)
     }
 }
 
@@ -297,3 +1211,47 @@ impl<R: io::BufRead> Iterator for Blobs<R> {
 }
 
 impl<R: io::BufRead> iter::FusedIterator for Blobs<R> {}
+
+/// Iterator over all blobs with their offsets; see [`Blobs::framed_blobs`].
+#[derive(Debug)]
+pub struct FramedBlobs<'a, R> {
+    blobs: &'a mut Blobs<R>,
+    done: bool,
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Iterates over the remaining blobs of any type, as the offset of the
+    /// blob (see [`Blobs::current_offset`]), its header and the blob, which
+    /// is not decompressed. Iteration stops after the first error.
+    #[inline]
+    pub fn framed_blobs(&mut self) -> FramedBlobs<'_, R> {
+        FramedBlobs {
+            blobs: self,
+            done: false,
+        }
+    }
+}
+
+impl<R: io::BufRead> Iterator for FramedBlobs<'_, R> {
+    type Item = Result<(u64, PbfBlobHeader, PbfBlob)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let offset = self.blobs.current_offset();
+        match self.blobs.next_blob() {
+            Ok(Some((header, blob))) => Some(Ok((offset, header, blob))),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R: io::BufRead> iter::FusedIterator for FramedBlobs<'_, R> {}
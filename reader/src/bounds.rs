@@ -0,0 +1,201 @@
+//! Bounding boxes of elements.
+
+use std::collections::HashMap;
+
+use osm_pbf_proto::osmformat::HeaderBBox;
+use osm_pbf_proto::primitives::{RelationRef, WayRef};
+
+use crate::data::MemberType;
+use crate::writer::NodeLocations;
+
+/// A bounding box in nanodegrees (inclusive).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Bounds {
+    pub min_lat: i64,
+    pub min_lon: i64,
+    pub max_lat: i64,
+    pub max_lon: i64,
+}
+
+impl Bounds {
+    /// Bounds of a single location.
+    #[inline]
+    pub const fn point(nano_lat: i64, nano_lon: i64) -> Self {
+        Self {
+            min_lat: nano_lat,
+            min_lon: nano_lon,
+            max_lat: nano_lat,
+            max_lon: nano_lon,
+        }
+    }
+
+    /// Bounds of `(nano_lat, nano_lon)` locations; `None` when there are
+    /// none.
+    pub fn from_locations(locations: impl IntoIterator<Item = (i64, i64)>) -> Option<Self> {
+        let mut locations = locations.into_iter();
+        let (lat, lon) = locations.next()?;
+        let mut bounds = Self::point(lat, lon);
+        for (lat, lon) in locations {
+            bounds.extend(lat, lon);
+        }
+        Some(bounds)
+    }
+
+    #[inline]
+    pub fn extend(&mut self, nano_lat: i64, nano_lon: i64) {
+        self.min_lat = self.min_lat.min(nano_lat);
+        self.min_lon = self.min_lon.min(nano_lon);
+        self.max_lat = self.max_lat.max(nano_lat);
+        self.max_lon = self.max_lon.max(nano_lon);
+    }
+
+    #[inline]
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min_lat: self.min_lat.min(other.min_lat),
+            min_lon: self.min_lon.min(other.min_lon),
+            max_lat: self.max_lat.max(other.max_lat),
+            max_lon: self.max_lon.max(other.max_lon),
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, nano_lat: i64, nano_lon: i64) -> bool {
+        self.min_lat <= nano_lat
+            && nano_lat <= self.max_lat
+            && self.min_lon <= nano_lon
+            && nano_lon <= self.max_lon
+    }
+
+    #[inline]
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min_lat <= other.max_lat
+            && other.min_lat <= self.max_lat
+            && self.min_lon <= other.max_lon
+            && other.min_lon <= self.max_lon
+    }
+
+    pub fn to_header_bbox(&self) -> HeaderBBox {
+        let mut bbox = HeaderBBox::new();
+        bbox.set_left(self.min_lon);
+        bbox.set_right(self.max_lon);
+        bbox.set_top(self.max_lat);
+        bbox.set_bottom(self.min_lat);
+        bbox
+    }
+}
+
+impl From<&HeaderBBox> for Bounds {
+    #[inline]
+    fn from(bbox: &HeaderBBox) -> Self {
+        Self {
+            min_lat: bbox.bottom(),
+            min_lon: bbox.left(),
+            max_lat: bbox.top(),
+            max_lon: bbox.right(),
+        }
+    }
+}
+
+/// Approximate bounds of ways and relations, computed while streaming a
+/// file that is sorted by type and id.
+///
+/// The bounds of a way are computed from a sample of its nodes (the first,
+/// the last and evenly spaced nodes in between), so long ways may extend
+/// slightly beyond them. The locations are taken from the way itself
+/// (`LocationsOnWays`) or from the [`NodeLocations`] source. Relations get
+/// the union of the bounds of their member nodes, ways and (previously
+/// seen) relations. The bounds of all ways and relations are kept in memory.
+#[derive(Debug)]
+pub struct ApproxBounds<L> {
+    locations: L,
+    samples: usize,
+    ways: HashMap<i64, Bounds>,
+    relations: HashMap<i64, Bounds>,
+}
+
+impl<L: NodeLocations> ApproxBounds<L> {
+    pub fn new(locations: L) -> Self {
+        Self {
+            locations,
+            samples: 8,
+            ways: HashMap::new(),
+            relations: HashMap::new(),
+        }
+    }
+
+    /// Number of nodes that are sampled per way (8 by default, at least 2).
+    #[inline]
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(2);
+        self
+    }
+
+    /// Computes and remembers the bounds of `way`; `None` when none of the
+    /// sampled nodes has a location.
+    pub fn way(&mut self, way: &WayRef<'_>) -> Option<Bounds> {
+        let locations = way.locations();
+        let bounds = if locations.len() > 0 {
+            let len = locations.len();
+            Bounds::from_locations(sample(locations, len, self.samples))
+        } else {
+            let refs = way.refs();
+            let len = refs.len();
+            let nodes = sample(refs, len, self.samples);
+            Bounds::from_locations(nodes.filter_map(|id| self.locations.location(id)))
+        };
+        if let Some(bounds) = bounds {
+            self.ways.insert(way.id(), bounds);
+        }
+        bounds
+    }
+
+    /// Computes and remembers the bounds of `relation` from the members seen
+    /// so far.
+    pub fn relation(&mut self, relation: &RelationRef<'_>) -> Option<Bounds> {
+        let bounds = relation
+            .members()
+            .filter_map(|m| match m.member_type {
+                MemberType::NODE => self
+                    .locations
+                    .location(m.id)
+                    .map(|(lat, lon)| Bounds::point(lat, lon)),
+                MemberType::WAY => self.ways.get(&m.id).copied(),
+                MemberType::RELATION => self.relations.get(&m.id).copied(),
+            })
+            .reduce(Bounds::union);
+        if let Some(bounds) = bounds {
+            self.relations.insert(relation.id(), bounds);
+        }
+        bounds
+    }
+
+    /// Bounds of a way that was passed to [`ApproxBounds::way`].
+    #[inline]
+    pub fn get_way(&self, id: i64) -> Option<Bounds> {
+        self.ways.get(&id).copied()
+    }
+
+    /// Bounds of a relation that was passed to [`ApproxBounds::relation`].
+    #[inline]
+    pub fn get_relation(&self, id: i64) -> Option<Bounds> {
+        self.relations.get(&id).copied()
+    }
+}
+
+/// Yields the first, the last and `samples - 2` evenly spaced items in
+/// between of an iterator with `len` items.
+fn sample<T>(
+    items: impl Iterator<Item = T>,
+    len: usize,
+    samples: usize,
+) -> impl Iterator<Item = T> {
+    let mut taken = 0;
+    items.enumerate().filter_map(move |(i, item)| {
+        if len > samples && i != taken * (len - 1) / (samples - 1) {
+            return None;
+        }
+        taken += 1;
+        Some(item)
+    })
+}
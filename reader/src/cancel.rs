@@ -0,0 +1,48 @@
+//! Cooperative cancellation of long scans.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+
+/// A flag that aborts the reading of a [`Blobs`](crate::Blobs) (and of the
+/// scans built on it) from another thread; see
+/// [`Blobs::with_cancellation`](crate::Blobs::with_cancellation).
+///
+/// Clones share the flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns [`Error::Cancelled`] when the token was cancelled.
+    #[inline]
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancellationToken {
+    #[inline]
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        Self(flag)
+    }
+}
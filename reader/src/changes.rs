@@ -0,0 +1,161 @@
+use std::collections::btree_map::{self, BTreeMap};
+use std::io;
+use std::iter::Peekable;
+
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Blobs;
+use crate::data::Element;
+use crate::error::Result;
+use crate::extract::{ChangeKind, Extract};
+use crate::probe::{FileKind, KindProbe, Probe};
+use crate::writer::{ElementOrdering, PbfWriter, WriterOptions};
+
+/// Sort key of an element: type, then id.
+type Key = (u32, i64);
+
+/// Creations, modifications and deletions of elements, e.g. parsed from an
+/// OsmChange file.
+///
+/// Only the last change of an element is kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Changes {
+    /// New state of the changed elements; `None` for deleted elements.
+    elements: BTreeMap<Key, Option<Element>>,
+}
+
+impl Changes {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the elements of a change file. Elements with `visible=false`
+    /// are deletions.
+    ///
+    /// Fails for files that are not detected as change or history files
+    /// (see [`Probe::file_kind`]), e.g. a snapshot passed by mistake.
+    pub fn from_blobs<R: io::BufRead>(blobs: &mut Blobs<R>) -> Result<Self> {
+        const KINDS: &[FileKind] = &[FileKind::Change, FileKind::History];
+        let mut changes = Self::new();
+        let mut probe = KindProbe::new(blobs.header(), Probe::DEFAULT_SAMPLE_BLOCKS);
+        while let Some(block) = blobs.next_primitive_block_decoded()? {
+            if let Some(kind) = probe.sample(&block) {
+                kind.ensure(KINDS)?;
+            }
+            for element in block.primitives().filter_map(|p| p.to_element()) {
+                if element.info().and_then(|i| i.visible) == Some(false) {
+                    changes.delete(element.primitive_type(), element.id());
+                } else {
+                    changes.upsert(element);
+                }
+            }
+        }
+        if let Some(kind) = probe.finish() {
+            kind.ensure(KINDS)?;
+        }
+        Ok(changes)
+    }
+
+    /// The changes tracked by an [`Extract`].
+    pub fn from_extract(extract: &Extract) -> Self {
+        let mut changes = Self::new();
+        for (kind, element) in extract.changes() {
+            changes.push(kind, element.to_element());
+        }
+        changes
+    }
+
+    /// Adds a change. For deletions, only the type and id of `element` are
+    /// used.
+    pub fn push(&mut self, kind: ChangeKind, element: Element) {
+        match kind {
+            ChangeKind::Create | ChangeKind::Modify => self.upsert(element),
+            ChangeKind::Delete => self.delete(element.primitive_type(), element.id()),
+        }
+    }
+
+    /// Adds or replaces an element.
+    pub fn upsert(&mut self, element: Element) {
+        let key = (element.primitive_type().bits(), element.id());
+        self.elements.insert(key, Some(element));
+    }
+
+    pub fn delete(&mut self, t: PrimitiveType, id: i64) {
+        self.elements.insert((t.bits(), id), None);
+    }
+
+    /// Adds all changes of `other`; they take precedence over the changes of
+    /// `self`.
+    pub fn merge(&mut self, other: Self) {
+        self.elements.extend(other.elements);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+}
+
+/// Writes the remaining elements of `base` with `changes` applied (in
+/// order; later changes take precedence) to `output`.
+///
+/// `base` must be a snapshot sorted by type and id; the output is sorted as
+/// well. Returns `output` after the file was finished.
+pub fn apply_changes<'c, R, W>(
+    base: &mut Blobs<R>,
+    changes: impl IntoIterator<Item = &'c Changes>,
+    output: W,
+    options: WriterOptions,
+) -> Result<W>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    Probe::from_blobs(base, 0)?
+        .file_kind()
+        .ensure(&[FileKind::Snapshot])?;
+    let mut merged = Changes::new();
+    for c in changes {
+        merged.merge(c.clone());
+    }
+    let options = options.ordering(ElementOrdering::RequireTypeThenId);
+    let mut writer = PbfWriter::with_options(output, base.header().clone(), options);
+    let mut pending = merged.elements.into_iter().peekable();
+    while let Some(block) = base.next_primitive_block_decoded()? {
+        for element in block.primitives().filter_map(|p| p.to_element()) {
+            let key = (element.primitive_type().bits(), element.id());
+            write_changes_before(&mut writer, &mut pending, key)?;
+            match pending.next_if(|(k, _)| *k == key) {
+                Some((_, Some(changed))) => writer.write_element(&changed)?,
+                Some((_, None)) => {}
+                None => writer.write_element(&element)?,
+            }
+        }
+    }
+    write_changes_before(&mut writer, &mut pending, (u32::MAX, i64::MAX))?;
+    if let Some((_, Some(changed))) = pending.next() {
+        writer.write_element(&changed)?;
+    }
+    writer.finish()
+}
+
+/// Writes the created elements that are sorted before `key`.
+fn write_changes_before<W: io::Write>(
+    writer: &mut PbfWriter<W>,
+    pending: &mut Peekable<btree_map::IntoIter<Key, Option<Element>>>,
+    key: Key,
+) -> Result<()> {
+    while let Some((_, element)) = pending.next_if(|(k, _)| *k < key) {
+        // deletions of elements that are not in the base are ignored
+        if let Some(element) = element {
+            writer.write_element(&element)?;
+        }
+    }
+    Ok(())
+}
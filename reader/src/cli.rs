@@ -0,0 +1,205 @@
+//! Helpers for small command line tools (see the examples of this crate).
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::writer::WriterStats;
+
+/// Parsed command line arguments.
+///
+/// Options have the form `--name=value`, flags the form `--name`; all other
+/// arguments are positional. Arguments after `--` are always positional.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// The arguments of the current process (without the program name).
+    pub fn from_env() -> Self {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I, S>(args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positional.extend(args.by_ref());
+            } else if let Some(option) = arg.strip_prefix("--") {
+                let (name, value) = match option.split_once('=') {
+                    Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+                    None => (option.to_owned(), None),
+                };
+                parsed.options.push((name, value));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        parsed
+    }
+
+    #[inline]
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    /// The positional argument at `index`, named `name` in the error.
+    pub fn required(&self, index: usize, name: &str) -> Result<&str> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| Error::InvalidArgument(format!("missing <{name}>")))
+    }
+
+    /// Whether the flag `--name` is given.
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    /// The value of the last `--name=value` option.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Parses the value of the option `--name`.
+    pub fn value<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.option(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|e| Error::InvalidArgument(format!("--{name}={v}: {e}")))
+            })
+            .transpose()
+    }
+
+    /// Fails on options that are not in `known`, e.g. typos.
+    pub fn ensure_known(&self, known: &[&str]) -> Result<()> {
+        match self
+            .options
+            .iter()
+            .find(|(n, _)| !known.contains(&n.as_str()))
+        {
+            Some((name, _)) => Err(Error::InvalidArgument(format!("unknown option --{name}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A bounding box in degrees, parsed from `left,bottom,right,top`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BBox {
+    pub left: f64,
+    pub bottom: f64,
+    pub right: f64,
+    pub top: f64,
+}
+
+impl BBox {
+    #[inline]
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        self.bottom <= lat && lat <= self.top && self.left <= lon && lon <= self.right
+    }
+}
+
+impl FromStr for BBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let &[left, bottom, right, top] = values.as_slice() else {
+            return Err("expected left,bottom,right,top".to_owned());
+        };
+        if left > right || bottom > top {
+            return Err("empty bbox".to_owned());
+        }
+        Ok(Self {
+            left,
+            bottom,
+            right,
+            top,
+        })
+    }
+}
+
+/// Rate limited progress output on stderr.
+#[derive(Debug)]
+pub struct Progress {
+    label: String,
+    interval: Duration,
+    start: Instant,
+    last: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            interval: Duration::from_secs(1),
+            start: Instant::now(),
+            last: None,
+        }
+    }
+
+    /// Minimum time between two lines (1s by default).
+    #[inline]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Prints `message` unless the last line was printed less than the
+    /// interval ago.
+    pub fn update(&mut self, message: impl fmt::Display) {
+        let now = Instant::now();
+        if self.last.is_some_and(|last| now - last < self.interval) {
+            return;
+        }
+        self.last = Some(now);
+        self.print(message);
+    }
+
+    /// Prints `message` together with the elapsed time.
+    pub fn finish(self, message: impl fmt::Display) {
+        self.print(format_args!(
+            "{message} in {:.1}s",
+            self.start.elapsed().as_secs_f64()
+        ));
+    }
+
+    fn print(&self, message: impl fmt::Display) {
+        // progress is best effort
+        let _ = writeln!(io::stderr().lock(), "{}: {message}", self.label);
+    }
+
+    /// Prints the statistics of a writer; pass the result to
+    /// [`PbfWriter::set_progress`](crate::writer::PbfWriter::set_progress).
+    pub fn writer(mut self) -> impl FnMut(&WriterStats) + Send + 'static {
+        move |stats| {
+            self.update(format_args!(
+                "{} blocks, {} elements, {} KiB written",
+                stats.blocks,
+                stats.elements(),
+                stats.compressed_bytes / 1024
+            ))
+        }
+    }
+}
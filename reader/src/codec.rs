@@ -0,0 +1,169 @@
+//! Decompressors for payload encodings that are not built in.
+//!
+//! A [`BlobCodec`] is registered for the number of the field of the `Blob`
+//! message that holds its payloads: `3` to `7` replace the built-in
+//! decoders (e.g. `5` for bzip2 without the `bzip2` feature), other numbers
+//! are encodings that the schema doesn't know about. Raw payloads (field `1`)
+//! are never passed to a codec.
+
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use osm_pbf_proto::fileformat::blob::Data;
+use osm_pbf_proto::protobuf::UnknownValueRef;
+
+use crate::blob::{Blobs, PbfBlob};
+use crate::error::{Error, Result};
+
+/// A decompressor for blob payloads; see [`Codecs`].
+pub trait BlobCodec: Send + Sync {
+    /// Decompresses `data` into `raw`, which is empty. `raw_size` is the
+    /// uncompressed size stated by the blob; the result may not be larger
+    /// than `max_size`.
+    fn decompress(
+        &self,
+        data: &[u8],
+        raw_size: Option<usize>,
+        raw: &mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<()>;
+}
+
+impl<F> BlobCodec for F
+where
+    F: Fn(&[u8], Option<usize>, &mut Vec<u8>, usize) -> Result<()> + Send + Sync,
+{
+    #[inline]
+    fn decompress(
+        &self,
+        data: &[u8],
+        raw_size: Option<usize>,
+        raw: &mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<()> {
+        self(data, raw_size, raw, max_size)
+    }
+}
+
+/// Registry of [`BlobCodec`]s by payload field number.
+#[derive(Clone, Default)]
+pub struct Codecs {
+    codecs: Vec<(u32, Arc<dyn BlobCodec>)>,
+}
+
+impl fmt::Debug for Codecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.codecs.iter().map(|(field, _)| field))
+            .finish()
+    }
+}
+
+impl Codecs {
+    #[inline]
+    pub const fn new() -> Self {
+        Self { codecs: Vec::new() }
+    }
+
+    /// Registers `codec` for the payloads in `field`, replacing a codec
+    /// registered before.
+    pub fn register(&mut self, field: u32, codec: impl BlobCodec + 'static) {
+        self.codecs.retain(|(f, _)| *f != field);
+        self.codecs.push((field, Arc::new(codec)));
+    }
+
+    #[inline]
+    pub fn with(mut self, field: u32, codec: impl BlobCodec + 'static) -> Self {
+        self.register(field, codec);
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.codecs.is_empty()
+    }
+
+    /// The registered field numbers.
+    pub fn fields(&self) -> impl Iterator<Item = u32> + '_ {
+        self.codecs.iter().map(|(field, _)| *field)
+    }
+
+    /// Decompresses the payload of `blob` into `raw` if it is encoded with a
+    /// registered codec; returns `false` (and leaves `raw` empty) otherwise.
+    pub fn decompress_blob_into(
+        &self,
+        blob: &PbfBlob,
+        raw: &mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<bool> {
+        raw.clear();
+        for (field, codec) in &self.codecs {
+            let Some(data) = payload(blob, *field) else {
+                continue;
+            };
+            let raw_size = blob.raw_size.and_then(|s| usize::try_from(s).ok());
+            codec.decompress(data, raw_size, raw, max_size)?;
+            if raw.len() > max_size {
+                return Err(Error::BlobDataToLarge);
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Replaces a payload that is encoded with a registered codec by the
+    /// decompressed raw payload. Other blobs are not changed.
+    pub fn decompress_in_place(&self, blob: &mut PbfBlob, max_size: usize) -> Result<()> {
+        let mut raw = Vec::new();
+        if !self.decompress_blob_into(blob, &mut raw, max_size)? {
+            return Ok(());
+        }
+        for field in self.fields() {
+            blob.special_fields.mut_unknown_fields().remove(field);
+        }
+        blob.raw_size = Some(raw.len() as i32);
+        blob.data = Some(Data::Raw(Bytes::from(raw)));
+        Ok(())
+    }
+}
+
+/// The payload of `blob` in `field`.
+fn payload(blob: &PbfBlob, field: u32) -> Option<&[u8]> {
+    match (&blob.data, field) {
+        (Some(Data::ZlibData(d)), 3)
+        | (Some(Data::LzmaData(d)), 4)
+        | (Some(Data::OBSOLETEBzip2Data(d)), 5)
+        | (Some(Data::Lz4Data(d)), 6)
+        | (Some(Data::ZstdData(d)), 7) => Some(d),
+        (_, 1..=7) => None,
+        _ => match blob.special_fields.unknown_fields().get(field)? {
+            UnknownValueRef::LengthDelimited(d) => Some(d),
+            _ => None,
+        },
+    }
+}
+
+impl<R> Blobs<R> {
+    /// Registers `codec` for the payloads in `field` (see [`Codecs`]).
+    ///
+    /// Payloads of registered codecs are decompressed when their blob is
+    /// read, so the blobs returned by [`Blobs::next_primitive_block`] are
+    /// raw and can be decoded anywhere. The header block is read when the
+    /// reader is created, with the built-in decoders.
+    #[inline]
+    pub fn register_codec(&mut self, field: u32, codec: impl BlobCodec + 'static) {
+        self.codecs.register(field, codec);
+    }
+
+    #[inline]
+    pub fn with_codec(mut self, field: u32, codec: impl BlobCodec + 'static) -> Self {
+        self.register_codec(field, codec);
+        self
+    }
+
+    #[inline]
+    pub fn codecs(&self) -> &Codecs {
+        &self.codecs
+    }
+}
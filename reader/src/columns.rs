@@ -0,0 +1,212 @@
+//! Columnar extraction of tag values.
+
+use std::collections::HashMap;
+use std::io;
+
+use osm_pbf_proto::osmformat::PrimitiveBlock;
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Blobs;
+use crate::error::Result;
+
+/// The values of a tag key, one row per element with the key.
+///
+/// Values are dictionary encoded: `values[row]` is an index into
+/// `dictionary`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagColumn {
+    pub key: String,
+    pub types: Vec<PrimitiveType>,
+    pub ids: Vec<i64>,
+    pub values: Vec<u32>,
+    /// The distinct values, in the order of their first occurrence.
+    pub dictionary: Vec<String>,
+    lookup: HashMap<String, u32>,
+}
+
+impl TagColumn {
+    fn new(key: &str) -> Self {
+        Self {
+            key: key.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The value of the given row.
+    #[inline]
+    pub fn value(&self, row: usize) -> &str {
+        &self.dictionary[self.values[row] as usize]
+    }
+
+    /// The rows as `(type, id, value)`.
+    pub fn rows(&self) -> impl Iterator<Item = (PrimitiveType, i64, &str)> + '_ {
+        self.types
+            .iter()
+            .zip(&self.ids)
+            .zip(&self.values)
+            .map(|((&t, &id), &v)| (t, id, self.dictionary[v as usize].as_str()))
+    }
+
+    fn intern(&mut self, value: &[u8]) -> u32 {
+        let value = String::from_utf8_lossy(value);
+        if let Some(&index) = self.lookup.get(value.as_ref()) {
+            return index;
+        }
+        let index = self.dictionary.len() as u32;
+        self.dictionary.push(value.clone().into_owned());
+        self.lookup.insert(value.into_owned(), index);
+        index
+    }
+}
+
+/// Tag values of selected keys in columns; see [`Blobs::extract_columns`].
+///
+/// Elements are matched by comparing string table indexes, so only the
+/// strings of the selected keys and their values are looked at; elements are
+/// never materialized. Changesets are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagColumns {
+    columns: Vec<TagColumn>,
+    /// Per block: column of the string table indexes of the keys.
+    key_columns: HashMap<u32, usize>,
+    /// Per block: dictionary index of `(column, value string index)`.
+    value_cache: HashMap<(usize, u32), u32>,
+}
+
+impl TagColumns {
+    pub fn new(keys: &[&str]) -> Self {
+        Self {
+            columns: keys.iter().map(|k| TagColumn::new(k)).collect(),
+            ..Self::default()
+        }
+    }
+
+    #[inline]
+    pub fn columns(&self) -> &[TagColumn] {
+        &self.columns
+    }
+
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&TagColumn> {
+        self.columns.iter().find(|c| c.key == key)
+    }
+
+    #[inline]
+    pub fn into_columns(self) -> Vec<TagColumn> {
+        self.columns
+    }
+
+    /// Appends the rows of the elements of `block`.
+    pub fn push_block(&mut self, block: &PrimitiveBlock) {
+        let strings = &block.stringtable.s;
+        self.key_columns.clear();
+        self.value_cache.clear();
+        for (index, s) in strings.iter().enumerate() {
+            if let Some(column) = self.columns.iter().position(|c| c.key.as_bytes() == s) {
+                self.key_columns.entry(index as u32).or_insert(column);
+            }
+        }
+        if self.key_columns.is_empty() {
+            return;
+        }
+        for group in &block.primitivegroup {
+            for node in &group.nodes {
+                self.push_tags(
+                    block,
+                    PrimitiveType::NODE,
+                    node.id(),
+                    &node.keys,
+                    &node.vals,
+                );
+            }
+            let dense = &group.dense;
+            let mut id = 0i64;
+            let mut kv = dense.keys_vals.iter();
+            for delta in &dense.id {
+                id = id.wrapping_add(*delta);
+                // key/value pairs of the node, terminated by 0
+                while let Some(&key) = kv.next() {
+                    if key == 0 {
+                        break;
+                    }
+                    let Some(&value) = kv.next() else {
+                        break;
+                    };
+                    self.push_tag(block, PrimitiveType::NODE, id, key as u32, value as u32);
+                }
+            }
+            for way in &group.ways {
+                self.push_tags(block, PrimitiveType::WAY, way.id(), &way.keys, &way.vals);
+            }
+            for relation in &group.relations {
+                self.push_tags(
+                    block,
+                    PrimitiveType::RELATION,
+                    relation.id(),
+                    &relation.keys,
+                    &relation.vals,
+                );
+            }
+        }
+    }
+
+    fn push_tags(
+        &mut self,
+        block: &PrimitiveBlock,
+        t: PrimitiveType,
+        id: i64,
+        keys: &[u32],
+        vals: &[u32],
+    ) {
+        for (&key, &value) in keys.iter().zip(vals) {
+            self.push_tag(block, t, id, key, value);
+        }
+    }
+
+    #[inline]
+    fn push_tag(
+        &mut self,
+        block: &PrimitiveBlock,
+        t: PrimitiveType,
+        id: i64,
+        key: u32,
+        value: u32,
+    ) {
+        let Some(&column) = self.key_columns.get(&key) else {
+            return;
+        };
+        let Some(s) = block.stringtable.s.get(value as usize) else {
+            return;
+        };
+        let col = &mut self.columns[column];
+        let value = *self
+            .value_cache
+            .entry((column, value))
+            .or_insert_with(|| col.intern(s));
+        col.types.push(t);
+        col.ids.push(id);
+        col.values.push(value);
+    }
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Reads the remaining blocks and collects the values of the tags with
+    /// the given `keys` into columns, in a single pass.
+    pub fn extract_columns(&mut self, keys: &[&str]) -> Result<TagColumns> {
+        let mut columns = TagColumns::new(keys);
+        while let Some(block) = self.next_primitive_block_decoded()? {
+            columns.push_block(&block);
+        }
+        Ok(columns)
+    }
+}
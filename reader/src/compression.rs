@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::blob::{Blob, Blobs, Codec, CompressionOptions, PbfBlob};
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+use crate::writer::{blob_header, write_frame};
+
+/// Sizes of a set of blobs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlobStats {
+    pub blobs: u64,
+    /// Uncompressed bytes (from `raw_size`, or measured when sampled).
+    pub raw_size: u64,
+    /// Bytes of the encoded payloads.
+    pub encoded_size: u64,
+}
+
+impl BlobStats {
+    #[inline]
+    fn add(&mut self, raw_size: u64, encoded_size: u64) {
+        self.blobs += 1;
+        self.raw_size += raw_size;
+        self.encoded_size += encoded_size;
+    }
+
+    /// Encoded size relative to the uncompressed size (`1.0` when nothing
+    /// is known).
+    pub fn ratio(&self) -> f64 {
+        if self.raw_size == 0 {
+            1.0
+        } else {
+            self.encoded_size as f64 / self.raw_size as f64
+        }
+    }
+}
+
+/// Result of recompressing the sampled blobs with another codec and level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Recompression {
+    pub codec: Codec,
+    pub level: Option<u32>,
+    /// The sampled blobs as they are stored in the file.
+    pub original: BlobStats,
+    /// The sampled blobs recompressed with `codec`.
+    pub recompressed: BlobStats,
+}
+
+impl Recompression {
+    /// Estimated relative reduction of the encoded size (negative when the
+    /// file would grow).
+    pub fn savings(&self) -> f64 {
+        if self.original.encoded_size == 0 {
+            0.0
+        } else {
+            1.0 - self.recompressed.encoded_size as f64 / self.original.encoded_size as f64
+        }
+    }
+}
+
+/// Codec usage and compression ratios of a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompressionReport {
+    /// Blobs per codec.
+    pub codecs: BTreeMap<Codec, BlobStats>,
+    /// Blobs per blob type (`OSMData`, ...).
+    pub blob_types: BTreeMap<String, BlobStats>,
+    /// Blobs with an unknown or unsupported encoding.
+    pub unknown_encodings: u64,
+    /// Blobs selected for sampling that could not be decompressed, e.g.
+    /// because they are larger than the [`Limits`] of the reader.
+    pub unsampled: u64,
+    /// Estimates for the candidates of the [`CompressionSurvey`].
+    pub recompression: Vec<Recompression>,
+}
+
+impl CompressionReport {
+    /// The candidate with the smallest estimated size, if it is smaller than
+    /// the file.
+    pub fn best(&self) -> Option<&Recompression> {
+        self.recompression
+            .iter()
+            .filter(|r| r.savings() > 0.0)
+            .min_by_key(|r| r.recompressed.encoded_size)
+    }
+}
+
+/// Collects a [`CompressionReport`] of a file.
+///
+/// Every `sample_every`-th data blob is decompressed and recompressed with
+/// each candidate to estimate the savings.
+#[derive(Clone, Debug)]
+pub struct CompressionSurvey {
+    sample_every: u64,
+    candidates: Vec<(Codec, Option<u32>)>,
+}
+
+impl Default for CompressionSurvey {
+    fn default() -> Self {
+        let candidates = [
+            (Codec::Zlib, Some(6)),
+            (Codec::Zlib, Some(9)),
+            (Codec::Lzma, Some(6)),
+            (Codec::Zstd, Some(3)),
+            (Codec::Zstd, Some(19)),
+            (Codec::Lz4, None),
+        ];
+        Self {
+            sample_every: 10,
+            candidates: candidates
+                .into_iter()
+                .filter(|(c, _)| c.is_supported())
+                .collect(),
+        }
+    }
+}
+
+impl CompressionSurvey {
+    /// Samples every 10th data blob with all supported codecs.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Samples every `n`-th data blob (`0` disables sampling).
+    #[inline]
+    pub fn sample_every(mut self, n: u64) -> Self {
+        self.sample_every = n;
+        self
+    }
+
+    /// Codecs and levels to estimate.
+    #[inline]
+    pub fn candidates(mut self, candidates: Vec<(Codec, Option<u32>)>) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// Reports the remaining blobs of `blobs` (the header blob was already
+    /// read when `blobs` was created).
+    ///
+    /// Sampled blobs are decompressed within the [`Limits`] of `blobs`.
+    pub fn run<R: io::BufRead>(&self, blobs: &mut Blobs<R>) -> Result<CompressionReport> {
+        let mut report = CompressionReport {
+            recompression: self
+                .candidates
+                .iter()
+                .map(|&(codec, level)| Recompression {
+                    codec,
+                    level,
+                    original: BlobStats::default(),
+                    recompressed: BlobStats::default(),
+                })
+                .collect(),
+            ..CompressionReport::default()
+        };
+        let mut data_blobs = 0u64;
+        while let Some((header, blob)) = blobs.next_blob()? {
+            let is_data = header.type_() == "OSMData";
+            let sample =
+                is_data && self.sample_every > 0 && data_blobs.is_multiple_of(self.sample_every);
+            if is_data {
+                data_blobs += 1;
+            }
+            let Some(stats) = self.add_blob(&mut report, &blob, sample, blobs.limits())? else {
+                report.unknown_encodings += 1;
+                continue;
+            };
+            report
+                .blob_types
+                .entry(header.type_().to_string())
+                .or_default()
+                .add(stats.0, stats.1);
+        }
+        Ok(report)
+    }
+
+    /// Adds a blob to the codec statistics and returns its raw and encoded
+    /// size (`None` for unknown encodings).
+    fn add_blob(
+        &self,
+        report: &mut CompressionReport,
+        blob: &PbfBlob,
+        sample: bool,
+        limits: &Limits,
+    ) -> Result<Option<(u64, u64)>> {
+        let Some(data) = &blob.data else {
+            return Ok(Some((0, 0)));
+        };
+        let Some(codec) = Codec::of(data) else {
+            return Ok(None);
+        };
+        let encoded_size = Codec::encoded_len(data) as u64;
+        let mut raw_size = blob.raw_size.map(|s| s as u64);
+        // blobs that can't be decompressed are not sampled
+        let mut raw = Vec::new();
+        let decompressed = sample
+            && limits.check_blob(blob).is_ok()
+            && Codec::decompress_blob_into(blob, &mut raw, limits.max_decompressed_size).is_ok();
+        if sample && !decompressed {
+            report.unsampled += 1;
+        }
+        if decompressed {
+            raw_size = Some(raw.len() as u64);
+            for r in &mut report.recompression {
+                let recompressed = r.codec.compress(raw.clone(), r.level)?;
+                r.original.add(raw.len() as u64, encoded_size);
+                r.recompressed
+                    .add(raw.len() as u64, Codec::encoded_len(&recompressed) as u64);
+            }
+        }
+        let raw_size = raw_size.unwrap_or(if codec == Codec::Raw { encoded_size } else { 0 });
+        report
+            .codecs
+            .entry(codec)
+            .or_default()
+            .add(raw_size, encoded_size);
+        Ok(Some((raw_size, encoded_size)))
+    }
+}
+
+/// Recompresses the payload of `blob` with `codec`. The uncompressed content
+/// is not changed.
+///
+/// Payloads that are larger than the `limits` fail with
+/// [`Error::BlobDataToLarge`].
+pub fn recompress(
+    blob: &PbfBlob,
+    codec: Codec,
+    options: &CompressionOptions,
+    limits: &Limits,
+) -> Result<PbfBlob> {
+    if blob.data.is_none() {
+        return Ok(blob.clone());
+    }
+    limits.check_blob(blob)?;
+    let mut raw = Vec::new();
+    Codec::decompress_blob_into(blob, &mut raw, limits.max_decompressed_size)
+        .map_err(|e| limits.map_size_error(e))?;
+    let raw_size = i32::try_from(raw.len()).map_err(|_| Error::BlobDataToLarge)?;
+    let codec = if raw.len() < options.min_size_to_compress {
+        Codec::Raw
+    } else {
+        codec
+    };
+    let mut result = PbfBlob::new();
+    result.raw_size = Some(raw_size);
+    result.data = Some(codec.compress(raw, options.level)?);
+    Ok(result)
+}
+
+/// Writes the file of `input` to `output` with all blobs recompressed with
+/// `codec` (see [`recompress`]). Blob headers (e.g. `indexdata`) are kept.
+///
+/// The blobs are decompressed within the [`Limits`] of `input`.
+///
+/// Returns `output`.
+pub fn transcode<R, W>(
+    input: &mut Blobs<R>,
+    mut output: W,
+    codec: Codec,
+    options: &CompressionOptions,
+) -> Result<W>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    let header = Blob::encode_message_with(input.header(), codec, options)?;
+    write_frame(&mut output, &blob_header("OSMHeader"), &header)?;
+    let limits = input.limits().clone();
+    while let Some((header, blob)) = input.next_blob()? {
+        let blob = recompress(&blob, codec, options, &limits)?;
+        write_frame(&mut output, &header, &blob)?;
+    }
+    output.flush()?;
+    Ok(output)
+}
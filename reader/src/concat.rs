@@ -0,0 +1,119 @@
+use std::io;
+
+use osm_pbf_proto::osmformat::HeaderBBox;
+use osm_pbf_proto::protobuf::MessageField;
+
+use crate::blob::{Blob, Blobs};
+use crate::error::Result;
+use crate::header::{Feature, HeaderBlock, HeaderFeatures, HeaderReplication, Replication};
+use crate::writer::{PbfWriter, WriterOptions};
+
+/// Header of a concatenated file.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ConcatHeader {
+    /// The header of the first input is used as it is.
+    #[default]
+    First,
+    /// The headers of all inputs are merged (see [`merge_headers`]).
+    Merge,
+}
+
+/// Merges the headers of files that are concatenated.
+///
+/// The result contains the required features of all headers, the optional
+/// features that are common to all headers and the union of the bboxes (if
+/// all headers have one). Sort features are removed, as concatenated files
+/// are generally not sorted. The replication fields are only kept when they
+/// are equal in all headers.
+pub fn merge_headers<'h>(headers: impl IntoIterator<Item = &'h HeaderBlock>) -> HeaderBlock {
+    let mut merged = HeaderBlock::new();
+    for (i, header) in headers.into_iter().enumerate() {
+        for feature in header.required() {
+            merged.add_required(feature);
+        }
+        if i == 0 {
+            merged.optional_features = header.optional_features.clone();
+            merged.bbox = header.bbox.clone();
+            merged.source = header.source.clone();
+            merged.set_replication(&header.replication());
+            continue;
+        }
+        if merged.replication() != header.replication() {
+            // the inputs are at different positions of the replication
+            merged.set_replication(&Replication::new());
+        }
+        merged
+            .optional_features
+            .retain(|f| header.optional_features.contains(f));
+        merged.bbox = match (merged.bbox.as_ref(), header.bbox.as_ref()) {
+            (Some(a), Some(b)) => {
+                let mut bbox = HeaderBBox::new();
+                bbox.set_left(a.left().min(b.left()));
+                bbox.set_right(a.right().max(b.right()));
+                bbox.set_top(a.top().max(b.top()));
+                bbox.set_bottom(a.bottom().min(b.bottom()));
+                MessageField::some(bbox)
+            }
+            _ => MessageField::none(),
+        };
+    }
+    merged.remove_feature(&Feature::SortTypeThenId);
+    merged.remove_feature(&Feature::SortGeographic);
+    merged
+}
+
+impl<W: io::Write> PbfWriter<W> {
+    /// Appends the remaining data blocks of `blobs` and returns their number.
+    /// The pending block is flushed before.
+    ///
+    /// The blobs are copied without decoding, unless the options of the
+    /// writer require re-encoding the blocks (sorting, order checks,
+    /// stripping metadata or writing node locations on ways).
+    pub fn append_blobs<R: io::BufRead>(&mut self, blobs: &mut Blobs<R>) -> Result<u64> {
+        self.flush_block()?;
+        let reencode = self.reencodes_blocks();
+        let mut count = 0;
+        for blob in blobs {
+            let blob = blob?;
+            if reencode {
+                self.write_primitive_block(&blob.decode_into()?)?;
+            } else if let Blob::Encoded(blob) = &blob {
+                self.write_blob("OSMData", blob)?;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Concatenates the data blocks of `inputs` into `output`, see
+/// [`PbfWriter::append_blobs`].
+///
+/// Concatenating files that are sorted by type and id only results in a
+/// sorted file when the element ranges of the inputs don't overlap.
+///
+/// Returns `output` after the file was finished.
+pub fn concat<R, W>(
+    inputs: impl IntoIterator<Item = Blobs<R>>,
+    output: W,
+    options: WriterOptions,
+    header: ConcatHeader,
+) -> Result<W>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    let mut inputs: Vec<_> = inputs.into_iter().collect();
+    let header = match header {
+        ConcatHeader::First => inputs
+            .first()
+            .map(|i| i.header().clone())
+            .unwrap_or_default(),
+        ConcatHeader::Merge => merge_headers(inputs.iter().map(Blobs::header)),
+    };
+    let mut writer = PbfWriter::with_options(output, header, options);
+    for input in &mut inputs {
+        writer.append_blobs(input)?;
+    }
+    writer.finish()
+}
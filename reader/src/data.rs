@@ -1,8 +1,12 @@
+pub use osm_pbf_proto::element::{
+    Element, MemberType, OwnedChangeSet, OwnedComment, OwnedInfo, OwnedMember, OwnedMembers,
+    OwnedNode, OwnedRefs, OwnedRelation, OwnedTags, OwnedWay,
+};
 pub use osm_pbf_proto::osmformat::{
-    ChangeSet, Info, Node, PrimitiveBlock, PrimitiveGroup, Relation, Way,
+    ChangeSet, ChangeSetComment, Info, Node, PrimitiveBlock, PrimitiveGroup, Relation, Way,
 };
 pub mod primitives {
-    pub use osm_pbf_proto::osmformat::{ChangeSet, Info, Node, Relation, Way};
+    pub use osm_pbf_proto::osmformat::{ChangeSet, ChangeSetComment, Info, Node, Relation, Way};
     pub use osm_pbf_proto::primitives::*;
 }
 
@@ -0,0 +1,109 @@
+//! Iteration over the elements of a whole file.
+
+use std::io;
+use std::iter;
+use std::vec;
+
+use crate::blob::Blobs;
+use crate::data::primitives::Primitive;
+use crate::data::{Element, PrimitiveBlock};
+use crate::error::Result;
+use crate::filter::ElementFilter;
+use crate::probe::KindProbe;
+
+/// The nodes, ways and relations of a file as owned [`Element`]s; see
+/// [`Blobs::elements`] and [`Blobs::elements_matching`].
+#[derive(Debug)]
+pub struct Elements<R, F = ()> {
+    blobs: Blobs<R>,
+    filter: F,
+    probe: KindProbe,
+    current: vec::IntoIter<Element>,
+    done: bool,
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Yields the nodes, ways and relations of the remaining blocks, in file
+    /// order. The iterator stops after the first error.
+    ///
+    /// Each block is decoded when its first element is needed; see
+    /// [`Blobs::par_elements`] for decoding on worker threads.
+    #[inline]
+    pub fn elements(self) -> Elements<R> {
+        self.elements_matching(())
+    }
+
+    /// Like [`Blobs::elements`], but only yields the elements that match
+    /// `filter`; blocks rejected by [`ElementFilter::matches_block`] are not
+    /// iterated. Yields an error first when the filter is not meant for the
+    /// kind of the file (see [`ElementFilter::ensure_file_kind`]).
+    #[inline]
+    pub fn elements_matching<F: ElementFilter>(self, filter: F) -> Elements<R, F> {
+        Elements {
+            probe: KindProbe::new(self.header(), 1),
+            blobs: self,
+            filter,
+            current: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+
+    /// Calls `f` with all primitives of the remaining blocks, in file order.
+    ///
+    /// The primitives borrow from the current block, which is decoded into
+    /// the same buffer for all blocks (see
+    /// [`Blobs::next_primitive_block_into`]).
+    pub fn for_each_element(&mut self, mut f: impl FnMut(Primitive<'_>)) -> Result<()> {
+        let mut block = PrimitiveBlock::new();
+        while self.next_primitive_block_into(&mut block)? {
+            block.primitives().for_each(&mut f);
+        }
+        Ok(())
+    }
+}
+
+impl<R, F> Elements<R, F> {
+    #[inline]
+    pub fn into_blobs(self) -> Blobs<R> {
+        self.blobs
+    }
+}
+
+impl<R: io::BufRead, F: ElementFilter> Iterator for Elements<R, F> {
+    type Item = Result<Element>;
+
+    fn next(&mut self) -> Option<Result<Element>> {
+        loop {
+            if let Some(element) = self.current.next() {
+                return Some(Ok(element));
+            }
+            if self.done {
+                return None;
+            }
+            let block = self.blobs.next_primitive_block_decoded().and_then(|block| {
+                if let Some(kind) = block.as_ref().and_then(|b| self.probe.sample(b)) {
+                    self.filter.ensure_file_kind(kind)?;
+                }
+                Ok(block)
+            });
+            match block {
+                Ok(Some(block)) if self.filter.matches_block(&block) => {
+                    let elements: Vec<_> = block
+                        .primitives()
+                        .filter(|p| self.filter.matches(p))
+                        .filter_map(|p| p.to_element())
+                        .collect();
+                    self.current = elements.into_iter();
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl<R: io::BufRead, F: ElementFilter> iter::FusedIterator for Elements<R, F> {}
@@ -2,6 +2,10 @@ use std::str::Utf8Error;
 
 use thiserror::Error;
 
+use crate::header::Feature;
+use crate::probe::FileKind;
+use crate::qa::TagProblem;
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
@@ -24,11 +28,53 @@ pub enum Error {
     #[error("Invalid Format: The size of the `Blob` is to large")]
     BlobDataToLarge,
 
+    #[error("A single blob exceeds the memory budget of {0} bytes")]
+    MemoryBudgetExceeded(usize),
+
+    #[error("Invalid wire format at offset {0}: {1}")]
+    InvalidWireFormat(usize, &'static str),
+
     #[error("The encoding of the Blob is not supported")]
     UnsupportedEncoding,
 
     #[error("Unexpected Blob-Type {0}")]
     UnexpectedBlobType(String),
+
+    #[error("Unexpected file kind: the operation is not supported on a {0} file")]
+    UnexpectedFileKind(FileKind),
+
+    #[error("Quota exceeded: block contains {0} elements (limit is {1})")]
+    TooManyElements(usize, usize),
+
+    #[error("Quota exceeded: string table contains {0} entries (limit is {1})")]
+    TooManyStrings(usize, usize),
+
+    #[error("Quota exceeded: file contains more than {0} blobs")]
+    TooManyBlobs(u64),
+
+    #[error("Invalid order: {0} {1} follows {2} {3} (expected `Sort.Type_then_ID`)")]
+    UnsortedElement(&'static str, i64, &'static str, i64),
+
+    #[error("Invalid order: {0} {1} (blob at offset {2}) follows {3} {4} (blob at offset {5})")]
+    UnsortedBlob(&'static str, i64, u64, &'static str, i64, u64),
+
+    #[error("Missing location of node {0} (referenced by way {1})")]
+    MissingNodeLocation(i64, i64),
+
+    #[error("Invalid tag {2:?} on {0} {1}: {3}")]
+    InvalidTag(&'static str, i64, String, TagProblem),
+
+    #[error("Unsupported required feature {0}")]
+    UnsupportedFeature(Feature),
+
+    #[error("Invalid polygon file at line {0}: {1}")]
+    InvalidPoly(usize, &'static str),
+
+    #[error("The operation was cancelled")]
+    Cancelled,
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Blobs;
+use crate::bounds::Bounds;
+use crate::data::{Element, MemberType, OwnedMember, OwnedNode, OwnedRelation, OwnedWay};
+use crate::error::Result;
+use crate::header::HeaderBlock;
+use crate::probe::{FileKind, KindProbe, Probe};
+use crate::writer::{ElementOrdering, PbfWriter, WriterOptions};
+
+mod osc;
+
+/// Kind of a change made to an [`Extract`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Create,
+    Modify,
+    Delete,
+}
+
+/// Sort key of an element: type, then id.
+type Key = (u32, i64);
+
+#[inline]
+fn key_of(element: &Element) -> Key {
+    (element.primitive_type().bits(), element.id())
+}
+
+/// A reference to an element of an [`Extract`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ElementRef<'l> {
+    Node(&'l OwnedNode),
+    Way(&'l OwnedWay),
+    Relation(&'l OwnedRelation),
+}
+
+impl ElementRef<'_> {
+    #[inline]
+    pub fn id(&self) -> i64 {
+        match self {
+            Self::Node(n) => n.id,
+            Self::Way(w) => w.id,
+            Self::Relation(r) => r.id,
+        }
+    }
+
+    #[inline]
+    pub fn to_element(&self) -> Element {
+        match *self {
+            Self::Node(n) => Element::Node(n.clone()),
+            Self::Way(w) => Element::Way(w.clone()),
+            Self::Relation(r) => Element::Relation(r.clone()),
+        }
+    }
+}
+
+/// All elements of a file, held in memory and indexed by id.
+///
+/// Meant for small and medium sized extracts. When a file contains multiple
+/// versions of an element, the last one is kept.
+///
+/// Changes made with [`Extract::create`], [`Extract::modify`] and
+/// [`Extract::delete`] are tracked and can be exported as OsmChange with
+/// [`Extract::write_osm_change`].
+#[derive(Clone, Debug, Default)]
+pub struct Extract {
+    pub header: HeaderBlock,
+    pub nodes: BTreeMap<i64, OwnedNode>,
+    pub ways: BTreeMap<i64, OwnedWay>,
+    pub relations: BTreeMap<i64, OwnedRelation>,
+    changes: BTreeMap<Key, ChangeKind>,
+    deleted: BTreeMap<Key, Element>,
+    last_new_id: i64,
+}
+
+impl Extract {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut blobs = Blobs::from_path(path)?;
+        Self::from_blobs(&mut blobs)
+    }
+
+    /// Reads all remaining blocks of `blobs`, which must be a snapshot or a
+    /// history file.
+    pub fn from_blobs<R: io::BufRead>(blobs: &mut Blobs<R>) -> Result<Self> {
+        const KINDS: &[FileKind] = &[FileKind::Snapshot, FileKind::History];
+        let mut extract = Self {
+            header: blobs.header().clone(),
+            ..Self::default()
+        };
+        let mut probe = KindProbe::new(blobs.header(), Probe::DEFAULT_SAMPLE_BLOCKS);
+        while let Some(block) = blobs.next_primitive_block_decoded()? {
+            if let Some(kind) = probe.sample(&block) {
+                kind.ensure(KINDS)?;
+            }
+            for element in block.primitives().filter_map(|p| p.to_element()) {
+                extract.insert(element);
+            }
+        }
+        if let Some(kind) = probe.finish() {
+            kind.ensure(KINDS)?;
+        }
+        Ok(extract)
+    }
+
+    /// Adds an element, returning the replaced element with the same type
+    /// and id. The change is not tracked.
+    pub fn insert(&mut self, element: Element) -> Option<Element> {
+        match element {
+            Element::Node(n) => self.nodes.insert(n.id, n).map(Element::Node),
+            Element::Way(w) => self.ways.insert(w.id, w).map(Element::Way),
+            Element::Relation(r) => self.relations.insert(r.id, r).map(Element::Relation),
+        }
+    }
+
+    /// Total number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len() + self.ways.len() + self.relations.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes an element without tracking the change.
+    pub fn remove(&mut self, t: PrimitiveType, id: i64) -> Option<Element> {
+        if t == PrimitiveType::NODE {
+            self.nodes.remove(&id).map(Element::Node)
+        } else if t == PrimitiveType::WAY {
+            self.ways.remove(&id).map(Element::Way)
+        } else if t == PrimitiveType::RELATION {
+            self.relations.remove(&id).map(Element::Relation)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, t: PrimitiveType, id: i64) -> Option<ElementRef<'_>> {
+        if t == PrimitiveType::NODE {
+            self.node(id).map(ElementRef::Node)
+        } else if t == PrimitiveType::WAY {
+            self.way(id).map(ElementRef::Way)
+        } else if t == PrimitiveType::RELATION {
+            self.relation(id).map(ElementRef::Relation)
+        } else {
+            None
+        }
+    }
+
+    /// Adds a new element with a newly allocated negative id (the id of
+    /// `element` is ignored) and returns the id.
+    pub fn create(&mut self, element: impl Into<Element>) -> i64 {
+        let mut element = element.into();
+        self.last_new_id -= 1;
+        let id = self.last_new_id;
+        match &mut element {
+            Element::Node(n) => n.id = id,
+            Element::Way(w) => w.id = id,
+            Element::Relation(r) => r.id = id,
+        }
+        self.changes.insert(key_of(&element), ChangeKind::Create);
+        self.insert(element);
+        id
+    }
+
+    /// Replaces an existing element with the same type and id. The version
+    /// is set to the version of the replaced element plus one.
+    ///
+    /// Returns `false` (and changes nothing) when the element does not exist.
+    pub fn modify(&mut self, element: impl Into<Element>) -> bool {
+        let mut element = element.into();
+        let key = key_of(&element);
+        let Some(old) = self.get(element.primitive_type(), element.id()) else {
+            return false;
+        };
+        let old_version = old.to_element().info().and_then(|i| i.version);
+        if let Some(version) = old_version {
+            element
+                .info_mut()
+                .get_or_insert_with(Default::default)
+                .version = Some(version + 1);
+        }
+        self.changes.entry(key).or_insert(ChangeKind::Modify);
+        self.insert(element);
+        true
+    }
+
+    /// Deletes an element and returns it.
+    pub fn delete(&mut self, t: PrimitiveType, id: i64) -> Option<Element> {
+        let element = self.remove(t, id)?;
+        let key = key_of(&element);
+        if self.changes.insert(key, ChangeKind::Delete) == Some(ChangeKind::Create) {
+            // created and deleted again: nothing to report
+            self.changes.remove(&key);
+        } else {
+            self.deleted.insert(key, element.clone());
+        }
+        Some(element)
+    }
+
+    #[inline]
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// The tracked changes in type-then-id order. Deleted elements are
+    /// returned as they were before the deletion.
+    pub fn changes(&self) -> impl Iterator<Item = (ChangeKind, ElementRef<'_>)> + '_ {
+        self.changes.iter().filter_map(|(&(t, id), &kind)| {
+            let element = match kind {
+                ChangeKind::Delete => match self.deleted.get(&(t, id))? {
+                    Element::Node(n) => ElementRef::Node(n),
+                    Element::Way(w) => ElementRef::Way(w),
+                    Element::Relation(r) => ElementRef::Relation(r),
+                },
+                _ => self.get(PrimitiveType::from_bits_truncate(t), id)?,
+            };
+            Some((kind, element))
+        })
+    }
+
+    /// Forgets the tracked changes (e.g. after they were uploaded).
+    pub fn clear_changes(&mut self) {
+        self.changes.clear();
+        self.deleted.clear();
+    }
+
+    /// Writes all elements (sorted by type and id) to a PBF file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.save_with_options(path, WriterOptions::new())
+    }
+
+    pub fn save_with_options(&self, path: impl AsRef<Path>, options: WriterOptions) -> Result<()> {
+        let options = options.ordering(ElementOrdering::RequireTypeThenId);
+        let writer = PbfWriter::create_with_options(path, self.header.clone(), options)?;
+        self.write_elements(writer)?.commit()
+    }
+
+    /// Writes all elements (sorted by type and id) as PBF to `out`.
+    pub fn write_to<W: io::Write>(&self, out: W) -> Result<W> {
+        let options = WriterOptions::new().ordering(ElementOrdering::RequireTypeThenId);
+        self.write_elements(PbfWriter::with_options(out, self.header.clone(), options))
+    }
+
+    fn write_elements<W: io::Write>(&self, mut writer: PbfWriter<W>) -> Result<W> {
+        for element in self.elements() {
+            writer.write_element(&element.to_element())?;
+        }
+        writer.finish()
+    }
+
+    /// Writes the tracked changes as OsmChange XML.
+    pub fn write_osm_change<W: io::Write>(&self, out: W) -> io::Result<()> {
+        osc::write_osm_change(out, self.changes())
+    }
+
+    #[inline]
+    pub fn node(&self, id: i64) -> Option<&OwnedNode> {
+        self.nodes.get(&id)
+    }
+
+    #[inline]
+    pub fn way(&self, id: i64) -> Option<&OwnedWay> {
+        self.ways.get(&id)
+    }
+
+    #[inline]
+    pub fn relation(&self, id: i64) -> Option<&OwnedRelation> {
+        self.relations.get(&id)
+    }
+
+    /// The element referenced by a relation member.
+    pub fn member(&self, member: &OwnedMember) -> Option<ElementRef<'_>> {
+        match member.member_type {
+            MemberType::NODE => self.node(member.id).map(ElementRef::Node),
+            MemberType::WAY => self.way(member.id).map(ElementRef::Way),
+            MemberType::RELATION => self.relation(member.id).map(ElementRef::Relation),
+        }
+    }
+
+    /// All elements in type-then-id order.
+    pub fn elements(&self) -> impl Iterator<Item = ElementRef<'_>> + '_ {
+        let nodes = self.nodes.values().map(ElementRef::Node);
+        let ways = self.ways.values().map(ElementRef::Way);
+        let relations = self.relations.values().map(ElementRef::Relation);
+        nodes.chain(ways).chain(relations)
+    }
+
+    /// Bounds of an element, from the locations of all of its nodes and
+    /// (for relations, recursively) members. Elements missing in the extract
+    /// are ignored; `None` when no location is found.
+    pub fn bounds(&self, element: ElementRef<'_>) -> Option<Bounds> {
+        self.bounds_visited(element, &mut HashSet::new())
+    }
+
+    fn bounds_visited(
+        &self,
+        element: ElementRef<'_>,
+        visited: &mut HashSet<i64>,
+    ) -> Option<Bounds> {
+        match element {
+            ElementRef::Node(n) => Some(Bounds::point(n.nano_lat, n.nano_lon)),
+            ElementRef::Way(w) => {
+                Bounds::from_locations(w.nodes(self).flatten().map(|n| (n.nano_lat, n.nano_lon)))
+            }
+            ElementRef::Relation(r) => {
+                // relations may contain themselves (directly or indirectly)
+                if !visited.insert(r.id) {
+                    return None;
+                }
+                r.resolve_members(self)
+                    .filter_map(|(_, e)| self.bounds_visited(e?, visited))
+                    .reduce(Bounds::union)
+            }
+        }
+    }
+
+    /// Ways that reference `node_id` (scans all ways).
+    pub fn ways_of_node(&self, node_id: i64) -> impl Iterator<Item = &OwnedWay> + '_ {
+        self.ways
+            .values()
+            .filter(move |w| w.refs.contains(&node_id))
+    }
+}
+
+/// Resolves the node references of a way in an [`Extract`].
+pub trait WayNodes {
+    /// The nodes of the way (`None` for nodes missing in the extract).
+    fn nodes<'e>(&'e self, extract: &'e Extract) -> impl Iterator<Item = Option<&'e OwnedNode>>;
+
+    /// Bounds of the nodes of the way; see [`Extract::bounds`].
+    fn bounds(&self, extract: &Extract) -> Option<Bounds>;
+}
+
+impl WayNodes for OwnedWay {
+    fn nodes<'e>(&'e self, extract: &'e Extract) -> impl Iterator<Item = Option<&'e OwnedNode>> {
+        self.refs.iter().map(|id| extract.node(*id))
+    }
+
+    #[inline]
+    fn bounds(&self, extract: &Extract) -> Option<Bounds> {
+        extract.bounds(ElementRef::Way(self))
+    }
+}
+
+/// Resolves the members of a relation in an [`Extract`].
+pub trait RelationMembers {
+    /// The members with the referenced elements (`None` for elements
+    /// missing in the extract).
+    fn resolve_members<'e>(
+        &'e self,
+        extract: &'e Extract,
+    ) -> impl Iterator<Item = (&'e OwnedMember, Option<ElementRef<'e>>)>;
+
+    /// Bounds of all (nested) members; see [`Extract::bounds`].
+    fn bounds(&self, extract: &Extract) -> Option<Bounds>;
+}
+
+impl RelationMembers for OwnedRelation {
+    fn resolve_members<'e>(
+        &'e self,
+        extract: &'e Extract,
+    ) -> impl Iterator<Item = (&'e OwnedMember, Option<ElementRef<'e>>)> {
+        self.members.iter().map(|m| (m, extract.member(m)))
+    }
+
+    #[inline]
+    fn bounds(&self, extract: &Extract) -> Option<Bounds> {
+        extract.bounds(ElementRef::Relation(self))
+    }
+}
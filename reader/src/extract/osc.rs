@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use super::{ChangeKind, ElementRef};
+use crate::data::{MemberType, OwnedInfo, OwnedTags};
+use crate::writer::WRITING_PROGRAM;
+
+/// Writes `changes` as an OsmChange document.
+pub fn write_osm_change<'l>(
+    out: impl Write,
+    changes: impl Iterator<Item = (ChangeKind, ElementRef<'l>)>,
+) -> io::Result<()> {
+    let mut out = io::BufWriter::new(out);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<osmChange version="0.6" generator="{}">"#,
+        Escaped(WRITING_PROGRAM)
+    )?;
+    let mut sections: [Vec<ElementRef<'l>>; 3] = Default::default();
+    for (kind, element) in changes {
+        sections[kind as usize].push(element);
+    }
+    // deletions go in reverse order, so referencing elements come first
+    sections[ChangeKind::Delete as usize].reverse();
+    for (kind, elements) in [ChangeKind::Create, ChangeKind::Modify, ChangeKind::Delete]
+        .into_iter()
+        .zip(sections)
+    {
+        if elements.is_empty() {
+            continue;
+        }
+        writeln!(out, "  <{}>", section(kind))?;
+        for element in elements {
+            write_element(&mut out, element)?;
+        }
+        writeln!(out, "  </{}>", section(kind))?;
+    }
+    writeln!(out, "</osmChange>")?;
+    out.flush()
+}
+
+fn section(kind: ChangeKind) -> &'static str {
+    match kind {
+        ChangeKind::Create => "create",
+        ChangeKind::Modify => "modify",
+        ChangeKind::Delete => "delete",
+    }
+}
+
+fn write_element(out: &mut impl Write, element: ElementRef<'_>) -> io::Result<()> {
+    match element {
+        ElementRef::Node(n) => {
+            write!(out, r#"    <node id="{}""#, n.id)?;
+            write_info(out, n.info.as_ref())?;
+            write!(
+                out,
+                r#" lat="{}" lon="{}""#,
+                Coordinate(n.nano_lat),
+                Coordinate(n.nano_lon)
+            )?;
+            if n.tags.is_empty() {
+                return writeln!(out, "/>");
+            }
+            writeln!(out, ">")?;
+            write_tags(out, &n.tags)?;
+            writeln!(out, "    </node>")
+        }
+        ElementRef::Way(w) => {
+            write!(out, r#"    <way id="{}""#, w.id)?;
+            write_info(out, w.info.as_ref())?;
+            writeln!(out, ">")?;
+            for r in &w.refs {
+                writeln!(out, r#"      <nd ref="{r}"/>"#)?;
+            }
+            write_tags(out, &w.tags)?;
+            writeln!(out, "    </way>")
+        }
+        ElementRef::Relation(r) => {
+            write!(out, r#"    <relation id="{}""#, r.id)?;
+            write_info(out, r.info.as_ref())?;
+            writeln!(out, ">")?;
+            for m in &r.members {
+                let member_type = match m.member_type {
+                    MemberType::NODE => "node",
+                    MemberType::WAY => "way",
+                    MemberType::RELATION => "relation",
+                };
+                writeln!(
+                    out,
+                    r#"      <member type="{member_type}" ref="{}" role="{}"/>"#,
+                    m.id,
+                    Escaped(&m.role)
+                )?;
+            }
+            write_tags(out, &r.tags)?;
+            writeln!(out, "    </relation>")
+        }
+    }
+}
+
+fn write_info(out: &mut impl Write, info: Option<&OwnedInfo>) -> io::Result<()> {
+    let Some(info) = info else {
+        return Ok(());
+    };
+    if let Some(version) = info.version {
+        write!(out, r#" version="{version}""#)?;
+    }
+    if let Some(changeset) = info.changeset {
+        write!(out, r#" changeset="{changeset}""#)?;
+    }
+    if let Some(uid) = info.uid {
+        write!(out, r#" uid="{uid}""#)?;
+    }
+    if let Some(user) = &info.user {
+        write!(out, r#" user="{}""#, Escaped(user))?;
+    }
+    if let Some(timestamp) = info.timestamp {
+        write!(out, r#" timestamp="{}""#, Timestamp(timestamp))?;
+    }
+    Ok(())
+}
+
+fn write_tags(out: &mut impl Write, tags: &OwnedTags) -> io::Result<()> {
+    for (k, v) in tags {
+        writeln!(out, r#"      <tag k="{}" v="{}"/>"#, Escaped(k), Escaped(v))?;
+    }
+    Ok(())
+}
+
+/// Nanodegrees formatted as degrees with 7 decimal places.
+struct Coordinate(i64);
+
+impl std::fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.0 / 100;
+        let sign = if value < 0 { "-" } else { "" };
+        let value = value.unsigned_abs();
+        write!(f, "{sign}{}.{:07}", value / 10_000_000, value % 10_000_000)
+    }
+}
+
+/// Milliseconds since the epoch formatted as RFC 3339 (UTC).
+struct Timestamp(i64);
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.0.div_euclid(1000);
+        let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+        // civil date from days since 1970-01-01 (H. Hinnant)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    }
+}
+
+/// XML attribute value escaping.
+struct Escaped<'l>(&'l str);
+
+impl std::fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\n' => f.write_str("&#10;")?,
+                '\t' => f.write_str("&#9;")?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+//! Filters on the elements of a file.
+//!
+//! An [`ElementFilter`] decides on single primitives, and may reject whole
+//! blocks before their elements are iterated (e.g. [`TagKeyFilter`], that
+//! looks at the string table). Filters that depend on other elements (e.g.
+//! [`AreaFilter`] for ways) collect their ids in a first pass, into an
+//! [`IdSet`]. Filters are applied with [`Blobs::for_each_matching`] and
+//! [`Blobs::elements_matching`]; a pair of filters matches the elements that
+//! match both.
+
+use std::borrow::Cow;
+use std::io;
+
+use osm_pbf_proto::primitives::{Primitive, Tags};
+
+use crate::blob::Blobs;
+use crate::data::{Info, PrimitiveBlock};
+use crate::error::Result;
+use crate::probe::{FileKind, KindProbe};
+
+mod area;
+mod ids;
+mod meta;
+
+pub use self::area::{Area, AreaFilter, BboxFilter, PolyFilter};
+pub use self::ids::{IdFilter, IdSet};
+pub use self::meta::{MetaFilter, TimeFilter};
+
+/// A predicate on the primitives of a file.
+pub trait ElementFilter {
+    /// `false` when no primitive of `block` can match; the block is then
+    /// skipped without iterating its elements.
+    #[inline]
+    fn matches_block(&self, _block: &PrimitiveBlock) -> bool {
+        true
+    }
+
+    fn matches(&self, primitive: &Primitive<'_>) -> bool;
+
+    /// Fails when the filter is not meant for files of `kind`. The kind is
+    /// determined from the header and the first block.
+    #[inline]
+    fn ensure_file_kind(&self, _kind: FileKind) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Matches all elements.
+impl ElementFilter for () {
+    #[inline]
+    fn matches(&self, _primitive: &Primitive<'_>) -> bool {
+        true
+    }
+}
+
+impl<F: Fn(&Primitive<'_>) -> bool> ElementFilter for F {
+    #[inline]
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        self(primitive)
+    }
+}
+
+impl<A: ElementFilter, B: ElementFilter> ElementFilter for (A, B) {
+    #[inline]
+    fn matches_block(&self, block: &PrimitiveBlock) -> bool {
+        self.0.matches_block(block) && self.1.matches_block(block)
+    }
+
+    #[inline]
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        self.0.matches(primitive) && self.1.matches(primitive)
+    }
+
+    #[inline]
+    fn ensure_file_kind(&self, kind: FileKind) -> Result<()> {
+        self.0.ensure_file_kind(kind)?;
+        self.1.ensure_file_kind(kind)
+    }
+}
+
+/// The tags of `primitive`.
+pub(crate) fn tags<'a>(primitive: &'a Primitive<'_>) -> Option<Tags<'a>> {
+    match primitive {
+        Primitive::Node(n) => Some(n.tags()),
+        Primitive::Way(w) => Some(w.tags()),
+        Primitive::Relation(r) => Some(r.tags()),
+        Primitive::ChangeSet(c) => Some(c.tags()),
+        _ => None,
+    }
+}
+
+/// The metadata of `primitive`, with the block of its string table.
+pub(crate) fn info<'a>(
+    primitive: &'a Primitive<'_>,
+) -> Option<(Cow<'a, Info>, &'a PrimitiveBlock)> {
+    match primitive {
+        Primitive::Node(n) => Some((Cow::Owned(n.info()), n.block())),
+        Primitive::Way(w) => Some((Cow::Borrowed(w.info.as_ref()?), w.block())),
+        Primitive::Relation(r) => Some((Cow::Borrowed(r.info.as_ref()?), r.block())),
+        Primitive::ChangeSet(c) => Some((Cow::Borrowed(c.info.as_ref()?), c.block())),
+        _ => None,
+    }
+}
+
+/// Matches the elements with a tag of one of the given keys.
+///
+/// Blocks whose string table contains none of the keys are skipped, which
+/// makes selective extracts (e.g. all `highway`s) much cheaper.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagKeyFilter {
+    keys: Vec<String>,
+}
+
+impl TagKeyFilter {
+    pub fn new<K: Into<String>>(keys: impl IntoIterator<Item = K>) -> Self {
+        Self {
+            keys: keys.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+}
+
+impl ElementFilter for TagKeyFilter {
+    fn matches_block(&self, block: &PrimitiveBlock) -> bool {
+        block
+            .stringtable
+            .s
+            .iter()
+            .any(|s| self.keys.iter().any(|k| k.as_bytes() == &s[..]))
+    }
+
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        tags(primitive)
+            .is_some_and(|mut tags| tags.any(|(k, _)| self.keys.iter().any(|key| key == k)))
+    }
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Calls `f` with the primitives of the remaining blocks that match
+    /// `filter`, in file order (see [`Blobs::for_each_element`]). Returns
+    /// the number of blocks that were skipped by
+    /// [`ElementFilter::matches_block`].
+    ///
+    /// Fails before calling `f` when the filter is not meant for the kind
+    /// of the file (see [`ElementFilter::ensure_file_kind`]).
+    pub fn for_each_matching(
+        &mut self,
+        filter: &impl ElementFilter,
+        mut f: impl FnMut(Primitive<'_>),
+    ) -> Result<u64> {
+        let mut block = PrimitiveBlock::new();
+        let mut skipped = 0;
+        let mut probe = KindProbe::new(self.header(), 1);
+        while self.next_primitive_block_into(&mut block)? {
+            if let Some(kind) = probe.sample(&block) {
+                filter.ensure_file_kind(kind)?;
+            }
+            if !filter.matches_block(&block) {
+                skipped += 1;
+                continue;
+            }
+            for p in block.primitives() {
+                if filter.matches(&p) {
+                    f(p);
+                }
+            }
+        }
+        Ok(skipped)
+    }
+}
@@ -0,0 +1,116 @@
+use std::io;
+
+use osm_pbf_proto::primitives::Primitive;
+
+use super::{ElementFilter, IdSet};
+use crate::blob::Blobs;
+use crate::bounds::Bounds;
+use crate::data::MemberType;
+use crate::error::Result;
+use crate::poly::Polygon;
+
+/// A region of an [`AreaFilter`].
+pub trait Area {
+    fn contains(&self, nano_lat: i64, nano_lon: i64) -> bool;
+}
+
+impl Area for Bounds {
+    #[inline]
+    fn contains(&self, nano_lat: i64, nano_lon: i64) -> bool {
+        Self::contains(self, nano_lat, nano_lon)
+    }
+}
+
+impl Area for Polygon {
+    #[inline]
+    fn contains(&self, nano_lat: i64, nano_lon: i64) -> bool {
+        Self::contains(self, nano_lat, nano_lon)
+    }
+}
+
+/// Matches the nodes inside an [`Area`], and the ways and relations that
+/// reference them.
+///
+/// Ways and relations are only matched after a first pass over the file
+/// with [`AreaFilter::collect`], which records their ids. Elements are then
+/// read in a second pass, e.g. with [`Blobs::elements_matching`] after
+/// [`Blobs::rewind`].
+#[derive(Clone, Debug)]
+pub struct AreaFilter<A> {
+    area: A,
+    nodes: IdSet,
+    ways: IdSet,
+    relations: IdSet,
+}
+
+/// An [`AreaFilter`] for a bounding box.
+pub type BboxFilter = AreaFilter<Bounds>;
+
+/// An [`AreaFilter`] for the polygons of a `.poly` file.
+pub type PolyFilter = AreaFilter<Polygon>;
+
+impl<A: Area> AreaFilter<A> {
+    pub fn new(area: A) -> Self {
+        Self {
+            area,
+            nodes: IdSet::new(),
+            ways: IdSet::new(),
+            relations: IdSet::new(),
+        }
+    }
+
+    #[inline]
+    pub fn area(&self) -> &A {
+        &self.area
+    }
+
+    /// Reads the remaining blocks of `blobs` and records the nodes inside
+    /// the area, the ways with one of these nodes, and the relations with one
+    /// of these nodes or ways (or a relation read before) as member.
+    ///
+    /// Nodes have to come before ways, and ways before relations, as in all
+    /// files sorted by type.
+    pub fn collect<R: io::BufRead>(&mut self, blobs: &mut Blobs<R>) -> Result<()> {
+        blobs.for_each_element(|p| match &p {
+            Primitive::Node(n) if self.area.contains(n.nano_lat, n.nano_lon) => {
+                self.nodes.insert(n.id);
+            }
+            Primitive::Way(w) if w.refs().any(|id| self.nodes.contains(id)) => {
+                self.ways.insert(w.id());
+            }
+            Primitive::Relation(r) => {
+                let matches = r.members().any(|m| match m.member_type {
+                    MemberType::NODE => self.nodes.contains(m.id),
+                    MemberType::WAY => self.ways.contains(m.id),
+                    MemberType::RELATION => self.relations.contains(m.id),
+                });
+                if matches {
+                    self.relations.insert(r.id());
+                }
+            }
+            _ => {}
+        })
+    }
+
+    /// Number of the recorded nodes, ways and relations.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.nodes.len() + self.ways.len() + self.relations.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<A: Area> ElementFilter for AreaFilter<A> {
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        match primitive {
+            Primitive::Node(n) => self.area.contains(n.nano_lat, n.nano_lon),
+            Primitive::Way(w) => self.ways.contains(w.id()),
+            Primitive::Relation(r) => self.relations.contains(r.id()),
+            _ => false,
+        }
+    }
+}
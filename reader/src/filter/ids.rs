@@ -0,0 +1,144 @@
+use osm_pbf_proto::primitives::Primitive;
+
+use super::ElementFilter;
+
+/// A set of element ids.
+///
+/// Stored as a sorted vector, or as a roaring bitmap with the `roaring`
+/// feature, which needs much less memory for large sets of dense ids (e.g.
+/// millions of nodes of a region).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdSet {
+    #[cfg(feature = "roaring")]
+    ids: roaring::RoaringTreemap,
+    #[cfg(not(feature = "roaring"))]
+    ids: Vec<i64>,
+}
+
+/// Maps ids to bitmap keys, keeping their order.
+#[cfg(feature = "roaring")]
+#[inline]
+const fn key(id: i64) -> u64 {
+    (id as u64) ^ (1 << 63)
+}
+
+#[cfg(not(feature = "roaring"))]
+fn insert_sorted(ids: &mut Vec<i64>, id: i64) -> bool {
+    if ids.last().is_none_or(|&last| last < id) {
+        ids.push(id);
+        return true;
+    }
+    match ids.binary_search(&id) {
+        Ok(_) => false,
+        Err(i) => {
+            ids.insert(i, id);
+            true
+        }
+    }
+}
+
+impl IdSet {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `id`; returns `false` when it was already in the set.
+    ///
+    /// Without the `roaring` feature, inserting ids in ascending order is
+    /// much faster than in any other order.
+    #[inline]
+    pub fn insert(&mut self, id: i64) -> bool {
+        #[cfg(feature = "roaring")]
+        return self.ids.insert(key(id));
+        #[cfg(not(feature = "roaring"))]
+        return insert_sorted(&mut self.ids, id);
+    }
+
+    #[inline]
+    pub fn contains(&self, id: i64) -> bool {
+        #[cfg(feature = "roaring")]
+        return self.ids.contains(key(id));
+        #[cfg(not(feature = "roaring"))]
+        return self.ids.binary_search(&id).is_ok();
+    }
+
+    #[inline]
+    pub fn len(&self) -> u64 {
+        #[cfg(feature = "roaring")]
+        return self.ids.len();
+        #[cfg(not(feature = "roaring"))]
+        return self.ids.len() as u64;
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl Extend<i64> for IdSet {
+    fn extend<I: IntoIterator<Item = i64>>(&mut self, ids: I) {
+        #[cfg(feature = "roaring")]
+        self.ids.extend(ids.into_iter().map(key));
+        #[cfg(not(feature = "roaring"))]
+        {
+            self.ids.extend(ids);
+            self.ids.sort_unstable();
+            self.ids.dedup();
+        }
+    }
+}
+
+impl FromIterator<i64> for IdSet {
+    fn from_iter<I: IntoIterator<Item = i64>>(ids: I) -> Self {
+        let mut set = Self::new();
+        set.extend(ids);
+        set
+    }
+}
+
+/// Matches the nodes, ways and relations with the given ids, e.g. to
+/// extract millions of elements from a planet file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdFilter {
+    pub nodes: IdSet,
+    pub ways: IdSet,
+    pub relations: IdSet,
+}
+
+impl IdFilter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with_nodes(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.nodes.extend(ids);
+        self
+    }
+
+    #[inline]
+    pub fn with_ways(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.ways.extend(ids);
+        self
+    }
+
+    #[inline]
+    pub fn with_relations(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.relations.extend(ids);
+        self
+    }
+}
+
+impl ElementFilter for IdFilter {
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        match primitive {
+            Primitive::Node(n) => self.nodes.contains(n.id),
+            Primitive::Way(w) => self.ways.contains(w.id()),
+            Primitive::Relation(r) => self.relations.contains(r.id()),
+            _ => false,
+        }
+    }
+}
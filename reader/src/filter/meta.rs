@@ -0,0 +1,190 @@
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use osm_pbf_proto::primitives::Primitive;
+
+use super::{info, ElementFilter, IdSet};
+use crate::data::PrimitiveBlock;
+use crate::error::Result;
+use crate::probe::FileKind;
+
+/// Kinds of files with element metadata.
+const ELEMENT_FILES: &[FileKind] = &[FileKind::Snapshot, FileKind::History, FileKind::Change];
+
+/// Matches the elements by the user and changeset of their metadata, e.g. to
+/// pull the edits of a contributor out of a history file.
+///
+/// An element has to match all criteria that are set; elements without
+/// metadata never match unless no criteria are set. Changeset dumps are
+/// rejected.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetaFilter {
+    uids: Option<IdSet>,
+    users: Option<Vec<String>>,
+    changesets: Option<RangeInclusive<i64>>,
+}
+
+impl MetaFilter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches the elements last edited by one of these user ids.
+    pub fn with_uids(mut self, uids: impl IntoIterator<Item = i32>) -> Self {
+        self.uids
+            .get_or_insert_with(IdSet::new)
+            .extend(uids.into_iter().map(i64::from));
+        self
+    }
+
+    /// Only matches the elements last edited by one of these user names.
+    ///
+    /// Blocks whose string table contains none of the names are skipped.
+    pub fn with_users<U: Into<String>>(mut self, users: impl IntoIterator<Item = U>) -> Self {
+        self.users
+            .get_or_insert_with(Vec::new)
+            .extend(users.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only matches the elements of a changeset in `changesets`.
+    pub fn with_changesets(mut self, changesets: impl RangeBounds<i64>) -> Self {
+        let start = match changesets.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => i64::MIN,
+        };
+        let end = match changesets.end_bound() {
+            Bound::Included(&end) => end,
+            Bound::Excluded(&end) => end.saturating_sub(1),
+            Bound::Unbounded => i64::MAX,
+        };
+        self.changesets = Some(start..=end);
+        self
+    }
+
+    #[inline]
+    pub fn uids(&self) -> Option<&IdSet> {
+        self.uids.as_ref()
+    }
+
+    #[inline]
+    pub fn users(&self) -> Option<&[String]> {
+        self.users.as_deref()
+    }
+
+    #[inline]
+    pub fn changesets(&self) -> Option<&RangeInclusive<i64>> {
+        self.changesets.as_ref()
+    }
+}
+
+impl ElementFilter for MetaFilter {
+    fn matches_block(&self, block: &PrimitiveBlock) -> bool {
+        let Some(users) = &self.users else {
+            return true;
+        };
+        block
+            .stringtable
+            .s
+            .iter()
+            .any(|s| users.iter().any(|u| u.as_bytes() == &s[..]))
+    }
+
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        if self.uids.is_none() && self.users.is_none() && self.changesets.is_none() {
+            return true;
+        }
+        let Some((info, block)) = info(primitive) else {
+            return false;
+        };
+        if let Some(uids) = &self.uids {
+            if !info.uid.is_some_and(|uid| uids.contains(uid.into())) {
+                return false;
+            }
+        }
+        if let Some(users) = &self.users {
+            let user = info.user_sid.and_then(|sid| block.string(sid as usize));
+            if !user.is_some_and(|user| users.iter().any(|u| u == user)) {
+                return false;
+            }
+        }
+        if let Some(changesets) = &self.changesets {
+            if !info.changeset.is_some_and(|c| changesets.contains(&c)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    #[inline]
+    fn ensure_file_kind(&self, kind: FileKind) -> Result<()> {
+        kind.ensure(ELEMENT_FILES)
+    }
+}
+
+/// Matches the elements whose timestamp is in `since..until`, e.g. to stream
+/// the elements touched in a given period.
+///
+/// Times are milliseconds since the unix epoch; the timestamps of the
+/// elements are scaled by the `date_granularity` of their block. Elements
+/// without timestamp never match unless no bound is set. Changeset dumps are
+/// rejected.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeFilter {
+    /// Inclusive lower bound.
+    pub since: Option<i64>,
+    /// Exclusive upper bound.
+    pub until: Option<i64>,
+}
+
+impl TimeFilter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn since(mut self, time: SystemTime) -> Self {
+        self.since = Some(millis(time));
+        self
+    }
+
+    #[inline]
+    pub fn until(mut self, time: SystemTime) -> Self {
+        self.until = Some(millis(time));
+        self
+    }
+
+    /// Whether a timestamp in milliseconds is in the range.
+    #[inline]
+    pub fn contains(&self, millis: i64) -> bool {
+        self.since.is_none_or(|since| millis >= since)
+            && self.until.is_none_or(|until| millis < until)
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+impl ElementFilter for TimeFilter {
+    fn matches(&self, primitive: &Primitive<'_>) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        info(primitive).is_some_and(|(info, block)| {
+            info.timestamp
+                .is_some_and(|t| self.contains(block.millis(t)))
+        })
+    }
+
+    #[inline]
+    fn ensure_file_kind(&self, kind: FileKind) -> Result<()> {
+        kind.ensure(ELEMENT_FILES)
+    }
+}
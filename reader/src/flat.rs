@@ -0,0 +1,534 @@
+//! A specialized decoder for `PrimitiveBlock`s.
+//!
+//! [`FlatBlock`] reads the varints of a decompressed block directly into
+//! flat arrays, without going through the generated protobuf messages: ids,
+//! coordinates, refs and member ids are delta-decoded (and coordinates
+//! scaled to nanodegrees) while parsing, tags and roles are kept as string
+//! table indexes. Metadata (`Info`, `DenseInfo`) and changesets are skipped.
+//!
+//! The arrays are reused for the following blocks, so decoding a file with
+//! [`Blobs::next_flat_block`] allocates almost nothing after the first
+//! blocks.
+
+use std::io;
+use std::ops::Range;
+
+use osm_pbf_proto::fileformat::Blob as PbfBlob;
+use osm_pbf_proto::osmformat::relation::MemberType;
+
+use crate::blob::{Blobs, Codec};
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+use crate::wire::{zigzag, Field, Fields, Packed, Value};
+
+/// Nodes (including dense nodes) of a [`FlatBlock`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlatNodes {
+    pub ids: Vec<i64>,
+    pub nano_lats: Vec<i64>,
+    pub nano_lons: Vec<i64>,
+    /// `tags[tag_offsets[i]..tag_offsets[i + 1]]` are the tags of node `i`.
+    pub tag_offsets: Vec<usize>,
+    /// Tags as `(key, value)` string table indexes.
+    pub tags: Vec<(u32, u32)>,
+}
+
+impl FlatNodes {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The tags of the node at `index`.
+    #[inline]
+    pub fn tags(&self, index: usize) -> &[(u32, u32)] {
+        &self.tags[self.tag_offsets[index]..self.tag_offsets[index + 1]]
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+        self.nano_lats.clear();
+        self.nano_lons.clear();
+        self.tag_offsets.clear();
+        self.tag_offsets.push(0);
+        self.tags.clear();
+    }
+}
+
+/// Ways of a [`FlatBlock`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlatWays {
+    pub ids: Vec<i64>,
+    /// `tags[tag_offsets[i]..tag_offsets[i + 1]]` are the tags of way `i`.
+    pub tag_offsets: Vec<usize>,
+    pub tags: Vec<(u32, u32)>,
+    /// `refs[ref_offsets[i]..ref_offsets[i + 1]]` are the nodes of way `i`.
+    pub ref_offsets: Vec<usize>,
+    pub refs: Vec<i64>,
+}
+
+impl FlatWays {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    #[inline]
+    pub fn tags(&self, index: usize) -> &[(u32, u32)] {
+        &self.tags[self.tag_offsets[index]..self.tag_offsets[index + 1]]
+    }
+
+    #[inline]
+    pub fn refs(&self, index: usize) -> &[i64] {
+        &self.refs[self.ref_offsets[index]..self.ref_offsets[index + 1]]
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+        self.tag_offsets.clear();
+        self.tag_offsets.push(0);
+        self.tags.clear();
+        self.ref_offsets.clear();
+        self.ref_offsets.push(0);
+        self.refs.clear();
+    }
+}
+
+/// A relation member of a [`FlatBlock`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlatMember {
+    pub member_type: MemberType,
+    pub id: i64,
+    /// String table index of the role.
+    pub role: u32,
+}
+
+/// Relations of a [`FlatBlock`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlatRelations {
+    pub ids: Vec<i64>,
+    /// `tags[tag_offsets[i]..tag_offsets[i + 1]]` are the tags of relation
+    /// `i`.
+    pub tag_offsets: Vec<usize>,
+    pub tags: Vec<(u32, u32)>,
+    /// `members[member_offsets[i]..member_offsets[i + 1]]` are the members
+    /// of relation `i`.
+    pub member_offsets: Vec<usize>,
+    pub members: Vec<FlatMember>,
+}
+
+impl FlatRelations {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    #[inline]
+    pub fn tags(&self, index: usize) -> &[(u32, u32)] {
+        &self.tags[self.tag_offsets[index]..self.tag_offsets[index + 1]]
+    }
+
+    #[inline]
+    pub fn members(&self, index: usize) -> &[FlatMember] {
+        &self.members[self.member_offsets[index]..self.member_offsets[index + 1]]
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+        self.tag_offsets.clear();
+        self.tag_offsets.push(0);
+        self.tags.clear();
+        self.member_offsets.clear();
+        self.member_offsets.push(0);
+        self.members.clear();
+    }
+}
+
+/// A `PrimitiveBlock` decoded into flat arrays; see the [module
+/// documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct FlatBlock {
+    /// The decompressed payload.
+    buffer: Vec<u8>,
+    arrays: Arrays,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Arrays {
+    /// Ranges of the strings in the buffer.
+    strings: Vec<Range<usize>>,
+    nodes: FlatNodes,
+    ways: FlatWays,
+    relations: FlatRelations,
+    // columns of the current dense group or relation
+    lats: Vec<i64>,
+    lons: Vec<i64>,
+    memids: Vec<i64>,
+    keys: Vec<u32>,
+    vals: Vec<u32>,
+    keys_vals: Vec<u32>,
+    roles: Vec<u32>,
+    types: Vec<u32>,
+}
+
+/// Coordinate encoding of a block.
+#[derive(Copy, Clone)]
+struct Scale {
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+impl FlatBlock {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn nodes(&self) -> &FlatNodes {
+        &self.arrays.nodes
+    }
+
+    #[inline]
+    pub fn ways(&self) -> &FlatWays {
+        &self.arrays.ways
+    }
+
+    #[inline]
+    pub fn relations(&self) -> &FlatRelations {
+        &self.arrays.relations
+    }
+
+    /// Number of entries in the string table.
+    #[inline]
+    pub fn string_count(&self) -> usize {
+        self.arrays.strings.len()
+    }
+
+    /// The string table entry at `index`.
+    #[inline]
+    pub fn string(&self, index: u32) -> Option<&[u8]> {
+        let range = self.arrays.strings.get(index as usize)?;
+        Some(&self.buffer[range.clone()])
+    }
+
+    /// The string table entry at `index`, if it is valid UTF-8.
+    #[inline]
+    pub fn str(&self, index: u32) -> Option<&str> {
+        std::str::from_utf8(self.string(index)?).ok()
+    }
+
+    /// Number of nodes, ways and relations.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes().len() + self.ways().len() + self.relations().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Decompresses and decodes `blob`.
+    pub fn decode_blob(&mut self, blob: &PbfBlob, limits: &Limits) -> Result<()> {
+        limits.check_blob(blob)?;
+        Codec::decompress_blob_into(blob, &mut self.buffer, limits.max_decompressed_size)
+            .map_err(|e| limits.map_size_error(e))?;
+        self.arrays.decode_block(&self.buffer)?;
+        self.check_limits(limits)
+    }
+
+    /// Decodes an uncompressed `PrimitiveBlock`.
+    pub fn decode(&mut self, payload: &[u8]) -> Result<()> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(payload);
+        self.arrays.decode_block(&self.buffer)
+    }
+
+    fn check_limits(&self, limits: &Limits) -> Result<()> {
+        if let Some(max) = limits.max_string_table_entries {
+            if self.string_count() > max {
+                return Err(Error::TooManyStrings(self.string_count(), max));
+            }
+        }
+        if let Some(max) = limits.max_elements_per_block {
+            if self.len() > max {
+                return Err(Error::TooManyElements(self.len(), max));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Arrays {
+    fn decode_block(&mut self, data: &[u8]) -> Result<()> {
+        self.strings.clear();
+        self.nodes.clear();
+        self.ways.clear();
+        self.relations.clear();
+        let mut scale = Scale {
+            granularity: 100,
+            lat_offset: 0,
+            lon_offset: 0,
+        };
+        // the coordinate encoding may follow the groups
+        for field in Fields::new(data) {
+            let field = field?;
+            match (field.number, field.value) {
+                (1, Value::Bytes(table)) => self.decode_strings(data, table)?,
+                (17, Value::Varint(v)) => scale.granularity = v as i32 as i64,
+                (19, Value::Varint(v)) => scale.lat_offset = v as i64,
+                (20, Value::Varint(v)) => scale.lon_offset = v as i64,
+                _ => {}
+            }
+        }
+        for field in Fields::new(data) {
+            let field = field?;
+            if let (2, Value::Bytes(group)) = (field.number, field.value) {
+                self.decode_group(data, group, scale)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_strings(&mut self, data: &[u8], table: &[u8]) -> Result<()> {
+        for field in Fields::new(table) {
+            let field = field?;
+            if let (1, Value::Bytes(s)) = (field.number, field.value) {
+                let start = offset(data, s);
+                self.strings.push(start..start + s.len());
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_group(&mut self, data: &[u8], group: &[u8], scale: Scale) -> Result<()> {
+        for field in Fields::new(group) {
+            let field = field?;
+            match (field.number, field.value) {
+                (1, Value::Bytes(node)) => self.decode_node(node, scale)?,
+                (2, Value::Bytes(dense)) => self.decode_dense(data, dense, scale)?,
+                (3, Value::Bytes(way)) => self.decode_way(way)?,
+                (4, Value::Bytes(relation)) => self.decode_relation(relation)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_node(&mut self, node: &[u8], scale: Scale) -> Result<()> {
+        let (mut id, mut lat, mut lon) = (0, 0, 0);
+        self.keys.clear();
+        self.vals.clear();
+        for field in Fields::new(node) {
+            let field = field?;
+            match field.number {
+                1 => id = field.sint().unwrap_or(id),
+                2 => push_varints(&field, &mut self.keys, |v| v as u32)?,
+                3 => push_varints(&field, &mut self.vals, |v| v as u32)?,
+                8 => lat = field.sint().unwrap_or(lat),
+                9 => lon = field.sint().unwrap_or(lon),
+                _ => {}
+            }
+        }
+        let nodes = &mut self.nodes;
+        nodes.ids.push(id);
+        nodes.nano_lats.push(scale.lat(lat));
+        nodes.nano_lons.push(scale.lon(lon));
+        push_tags(&self.keys, &self.vals, &mut nodes.tags);
+        nodes.tag_offsets.push(nodes.tags.len());
+        Ok(())
+    }
+
+    fn decode_dense(&mut self, data: &[u8], dense: &[u8], scale: Scale) -> Result<()> {
+        let start = self.nodes.ids.len();
+        self.lats.clear();
+        self.lons.clear();
+        self.keys_vals.clear();
+        for field in Fields::new(dense) {
+            let field = field?;
+            match field.number {
+                1 => push_deltas(&field, &mut self.nodes.ids, start)?,
+                8 => push_deltas(&field, &mut self.lats, 0)?,
+                9 => push_deltas(&field, &mut self.lons, 0)?,
+                10 => push_varints(&field, &mut self.keys_vals, |v| v as u32)?,
+                _ => {}
+            }
+        }
+        let nodes = &mut self.nodes;
+        let count = nodes.ids.len() - start;
+        if self.lats.len() != count || self.lons.len() != count {
+            return Err(Error::InvalidWireFormat(
+                offset(data, dense),
+                "dense nodes with different numbers of ids and coordinates",
+            ));
+        }
+        nodes
+            .nano_lats
+            .extend(self.lats.iter().map(|&lat| scale.lat(lat)));
+        nodes
+            .nano_lons
+            .extend(self.lons.iter().map(|&lon| scale.lon(lon)));
+        // ( (<keyid> <valid>)* '0' )* for all nodes, or empty without tags
+        let mut kv = self.keys_vals.iter().copied();
+        for _ in 0..count {
+            while let Some(key) = kv.next() {
+                if key == 0 {
+                    break;
+                }
+                let value = kv.next().unwrap_or(0);
+                nodes.tags.push((key, value));
+            }
+            nodes.tag_offsets.push(nodes.tags.len());
+        }
+        Ok(())
+    }
+
+    fn decode_way(&mut self, way: &[u8]) -> Result<()> {
+        let mut id = 0;
+        let start = self.ways.refs.len();
+        self.keys.clear();
+        self.vals.clear();
+        for field in Fields::new(way) {
+            let field = field?;
+            match field.number {
+                1 => id = field.varint().map_or(id, |v| v as i64),
+                2 => push_varints(&field, &mut self.keys, |v| v as u32)?,
+                3 => push_varints(&field, &mut self.vals, |v| v as u32)?,
+                8 => push_deltas(&field, &mut self.ways.refs, start)?,
+                _ => {}
+            }
+        }
+        let ways = &mut self.ways;
+        ways.ids.push(id);
+        push_tags(&self.keys, &self.vals, &mut ways.tags);
+        ways.tag_offsets.push(ways.tags.len());
+        ways.ref_offsets.push(ways.refs.len());
+        Ok(())
+    }
+
+    fn decode_relation(&mut self, relation: &[u8]) -> Result<()> {
+        let mut id = 0;
+        self.keys.clear();
+        self.vals.clear();
+        self.roles.clear();
+        self.memids.clear();
+        self.types.clear();
+        for field in Fields::new(relation) {
+            let field = field?;
+            match field.number {
+                1 => id = field.varint().map_or(id, |v| v as i64),
+                2 => push_varints(&field, &mut self.keys, |v| v as u32)?,
+                3 => push_varints(&field, &mut self.vals, |v| v as u32)?,
+                8 => push_varints(&field, &mut self.roles, |v| v as u32)?,
+                9 => push_deltas(&field, &mut self.memids, 0)?,
+                10 => push_varints(&field, &mut self.types, |v| v as u32)?,
+                _ => {}
+            }
+        }
+        let relations = &mut self.relations;
+        relations.ids.push(id);
+        push_tags(&self.keys, &self.vals, &mut relations.tags);
+        relations.tag_offsets.push(relations.tags.len());
+        for (i, &member_id) in self.memids.iter().enumerate() {
+            let member_type = match self.types.get(i) {
+                Some(1) => MemberType::WAY,
+                Some(2) => MemberType::RELATION,
+                _ => MemberType::NODE,
+            };
+            relations.members.push(FlatMember {
+                member_type,
+                id: member_id,
+                role: self.roles.get(i).copied().unwrap_or(0),
+            });
+        }
+        relations.member_offsets.push(relations.members.len());
+        Ok(())
+    }
+}
+
+impl Scale {
+    #[inline]
+    fn lat(self, lat: i64) -> i64 {
+        self.lat_offset
+            .wrapping_add(lat.wrapping_mul(self.granularity))
+    }
+
+    #[inline]
+    fn lon(self, lon: i64) -> i64 {
+        self.lon_offset
+            .wrapping_add(lon.wrapping_mul(self.granularity))
+    }
+}
+
+/// Offset of `slice` in `data`.
+#[inline]
+fn offset(data: &[u8], slice: &[u8]) -> usize {
+    slice.as_ptr() as usize - data.as_ptr() as usize
+}
+
+#[inline]
+fn push_tags(keys: &[u32], vals: &[u32], tags: &mut Vec<(u32, u32)>) {
+    tags.extend(keys.iter().copied().zip(vals.iter().copied()));
+}
+
+/// Appends the values of a repeated varint field (packed or not); values
+/// with other wire types are ignored.
+fn push_varints<T>(
+    field: &Field<'_>,
+    out: &mut Vec<T>,
+    mut map: impl FnMut(u64) -> T,
+) -> Result<()> {
+    match field.value {
+        Value::Varint(v) => out.push(map(v)),
+        Value::Bytes(packed) => {
+            for v in Packed::new(packed) {
+                out.push(map(v?));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Appends the running sum of a delta coded `sint64` field to the column
+/// that starts at `out[start]`.
+fn push_deltas(field: &Field<'_>, out: &mut Vec<i64>, start: usize) -> Result<()> {
+    let mut sum = if out.len() > start {
+        out[out.len() - 1]
+    } else {
+        0
+    };
+    push_varints(field, out, |v| {
+        sum = sum.wrapping_add(zigzag(v));
+        sum
+    })
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Reads the next block and decodes it into `block` with the specialized
+    /// decoder (see [`FlatBlock`]). Returns `false` at the end of the input.
+    pub fn next_flat_block(&mut self, block: &mut FlatBlock) -> Result<bool> {
+        let Some(blob) = self.next_data_blob()? else {
+            return Ok(false);
+        };
+        block.decode_blob(&blob, self.limits())?;
+        Ok(true)
+    }
+}
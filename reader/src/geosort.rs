@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::io;
+
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Blobs;
+use crate::data::{Element, MemberType};
+use crate::error::Result;
+use crate::header::{Feature, HeaderFeatures};
+use crate::writer::{ElementOrdering, PbfWriter, WriterOptions};
+
+/// Position of a location on a Hilbert curve over a `2^32 x 2^32` grid.
+pub fn hilbert_index(nano_lat: i64, nano_lon: i64) -> u64 {
+    let x = to_grid(nano_lon, 180_000_000_000);
+    let y = to_grid(nano_lat, 90_000_000_000);
+    hilbert_xy(x, y)
+}
+
+/// Maps `-max..=max` to the full `u32` range.
+#[inline]
+fn to_grid(value: i64, max: i64) -> u32 {
+    let value = value.clamp(-max, max) + max;
+    ((value as u128 * u32::MAX as u128) / (2 * max) as u128) as u32
+}
+
+fn hilbert_xy(mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0u64;
+    let mut s = 1u32 << 31;
+    while s > 0 {
+        let rx = x & s != 0;
+        let ry = y & s != 0;
+        d += (s as u64) * (s as u64) * ((3 * rx as u64) ^ ry as u64);
+        if !ry {
+            if rx {
+                x = !x;
+                y = !y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    d
+}
+
+/// Geographic sort keys of elements.
+///
+/// Nodes are keyed by the Hilbert index of their location, ways by the key
+/// of their first known node and relations by the key of their first known
+/// node or way member. Elements without a known location are sorted last.
+#[derive(Clone, Debug, Default)]
+pub struct GeographicKeys {
+    nodes: HashMap<i64, u64>,
+    ways: HashMap<i64, u64>,
+}
+
+impl GeographicKeys {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Computes the key of `element` and remembers the keys of nodes and
+    /// ways. Keys of ways and relations depend on the members added before.
+    pub fn add(&mut self, element: &Element) -> u64 {
+        match element {
+            Element::Node(n) => {
+                let key = hilbert_index(n.nano_lat, n.nano_lon);
+                self.nodes.insert(n.id, key);
+                key
+            }
+            Element::Way(w) => {
+                let key = w
+                    .refs
+                    .iter()
+                    .find_map(|id| self.nodes.get(id).copied())
+                    .unwrap_or(u64::MAX);
+                self.ways.insert(w.id, key);
+                key
+            }
+            Element::Relation(r) => {
+                let key = r
+                    .members
+                    .iter()
+                    .find_map(|m| match m.member_type {
+                        MemberType::NODE => self.nodes.get(&m.id).copied(),
+                        MemberType::WAY => self.ways.get(&m.id).copied(),
+                        MemberType::RELATION => None,
+                    })
+                    .unwrap_or(u64::MAX);
+                key
+            }
+        }
+    }
+}
+
+/// Sorts the remaining elements of `input` geographically (see
+/// [`GeographicKeys`]) and writes them with the header of `input` to
+/// `output`. Nodes are written before ways and ways before relations; the
+/// header declares `Sort.Geographic`.
+///
+/// All elements are held in memory. Returns `output` after the file was
+/// finished.
+pub fn sort_geographic<R, W>(input: &mut Blobs<R>, output: W, options: WriterOptions) -> Result<W>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    let mut by_type: [Vec<(u64, Element)>; 3] = Default::default();
+    while let Some(block) = input.next_primitive_block_decoded()? {
+        for element in block.primitives().filter_map(|p| p.to_element()) {
+            let index = match element {
+                Element::Node(_) => 0,
+                Element::Way(_) => 1,
+                Element::Relation(_) => 2,
+            };
+            by_type[index].push((0, element));
+        }
+    }
+    // keys of ways and relations need the keys of their members
+    let mut keys = GeographicKeys::new();
+    for elements in &mut by_type {
+        for (key, element) in elements.iter_mut() {
+            *key = keys.add(element);
+        }
+    }
+
+    let mut header = input.header().clone();
+    header.remove_feature(&Feature::SortTypeThenId);
+    header.add_optional(Feature::SortGeographic);
+    let options = options.ordering(ElementOrdering::Input);
+    let mut writer = PbfWriter::with_options(output, header, options);
+    for mut elements in by_type {
+        // stable: keeps multiple versions of an element in input order
+        elements.sort_by_key(|(key, element)| (*key, element.id()));
+        for (_, element) in &elements {
+            writer.write_element(element)?;
+        }
+    }
+    writer.finish()
+}
+
+/// An element that is not in geographic order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnsortedElement {
+    pub element_type: PrimitiveType,
+    pub id: i64,
+    /// Id of the preceding element of the same type.
+    pub previous_id: i64,
+}
+
+/// Verifies that the remaining elements of `blobs` are sorted as written by
+/// [`sort_geographic`] and returns the first element that is out of order.
+pub fn check_geographic<R: io::BufRead>(blobs: &mut Blobs<R>) -> Result<Option<UnsortedElement>> {
+    let mut keys = GeographicKeys::new();
+    let mut last: Option<(u32, u64, i64)> = None;
+    while let Some(block) = blobs.next_primitive_block_decoded()? {
+        for element in block.primitives().filter_map(|p| p.to_element()) {
+            let element_type = element.primitive_type();
+            let current = (element_type.bits(), keys.add(&element), element.id());
+            if let Some(last) = last {
+                if current < last {
+                    return Ok(Some(UnsortedElement {
+                        element_type,
+                        id: current.2,
+                        previous_id: last.2,
+                    }));
+                }
+            }
+            last = Some(current);
+        }
+    }
+    Ok(None)
+}
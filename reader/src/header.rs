@@ -1,13 +1,215 @@
-pub use osm_pbf_proto::osmformat::HeaderBlock;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
 
-// REQUIRED FEATURES
-pub const DENSE_NODES: &str = "DenseNodes";
-pub const HISTORICAL_INFORMATION: &str = "HistoricalInformation";
+use osm_pbf_proto::protobuf::Chars;
 
-// OPTIONAL FEATURES
-pub const HAS_METADATA: &str = "Has_Metadata";
-pub const SORT_TYPE_THEN_ID: &str = "Sort.Type_then_ID";
-pub const SORT_GEOGRAPHIC: &str = "Sort.Geographic";
-pub const LOCATIONS_ON_WAYS: &str = "LocationsOnWays";
+pub use osm_pbf_proto::osmformat::{HeaderBBox, HeaderBlock};
+
+use crate::error::{Error, Result};
+
+/// A required or optional feature declared in the header.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    // required features
+    OsmSchemaV06,
+    DenseNodes,
+    HistoricalInformation,
+    // optional features
+    HasMetadata,
+    SortTypeThenId,
+    SortGeographic,
+    LocationsOnWays,
+    /// A feature unknown to this crate.
+    Unknown(String),
+}
+
+impl Feature {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::OsmSchemaV06 => "OsmSchema-V0.6",
+            Self::DenseNodes => "DenseNodes",
+            Self::HistoricalInformation => "HistoricalInformation",
+            Self::HasMetadata => "Has_Metadata",
+            Self::SortTypeThenId => "Sort.Type_then_ID",
+            Self::SortGeographic => "Sort.Geographic",
+            Self::LocationsOnWays => "LocationsOnWays",
+            Self::Unknown(s) => s,
+        }
+    }
+
+    /// `true` when files requiring this feature can be read.
+    #[inline]
+    pub const fn is_supported(&self) -> bool {
+        !matches!(self, Self::Unknown(_))
+    }
+}
+
+impl fmt::Display for Feature {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Feature {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Infallible> {
+        Ok(match s {
+            "OsmSchema-V0.6" => Self::OsmSchemaV06,
+            "DenseNodes" => Self::DenseNodes,
+            "HistoricalInformation" => Self::HistoricalInformation,
+            "Has_Metadata" => Self::HasMetadata,
+            "Sort.Type_then_ID" => Self::SortTypeThenId,
+            "Sort.Geographic" => Self::SortGeographic,
+            "LocationsOnWays" => Self::LocationsOnWays,
+            s => Self::Unknown(s.to_string()),
+        })
+    }
+}
+
+impl From<&str> for Feature {
+    #[inline]
+    fn from(s: &str) -> Self {
+        let Ok(feature) = s.parse();
+        feature
+    }
+}
+
+/// Typed access to the features of a [`HeaderBlock`].
+pub trait HeaderFeatures {
+    fn required(&self) -> impl Iterator<Item = Feature> + '_;
+
+    fn optional(&self) -> impl Iterator<Item = Feature> + '_;
+
+    /// `true` when `feature` is declared as required or optional.
+    fn has_feature(&self, feature: &Feature) -> bool;
+
+    /// Adds a required feature (unless already declared).
+    fn add_required(&mut self, feature: Feature);
+
+    /// Adds an optional feature (unless already declared).
+    fn add_optional(&mut self, feature: Feature);
+
+    /// Removes `feature` from the required and optional features.
+    fn remove_feature(&mut self, feature: &Feature);
+
+    /// Returns [`Error::UnsupportedFeature`] for the first required feature
+    /// that is not supported by this crate.
+    fn check_supported(&self) -> Result<()>;
+}
+
+#[inline]
+fn contains(features: &[Chars], feature: &Feature) -> bool {
+    features.iter().any(|f| &**f == feature.as_str())
+}
+
+impl HeaderFeatures for HeaderBlock {
+    fn required(&self) -> impl Iterator<Item = Feature> + '_ {
+        self.required_features.iter().map(|f| Feature::from(&**f))
+    }
+
+    fn optional(&self) -> impl Iterator<Item = Feature> + '_ {
+        self.optional_features.iter().map(|f| Feature::from(&**f))
+    }
+
+    fn has_feature(&self, feature: &Feature) -> bool {
+        contains(&self.required_features, feature) || contains(&self.optional_features, feature)
+    }
+
+    fn add_required(&mut self, feature: Feature) {
+        if !contains(&self.required_features, &feature) {
+            self.required_features.push(feature.as_str().into());
+        }
+    }
+
+    fn add_optional(&mut self, feature: Feature) {
+        if !contains(&self.optional_features, &feature) {
+            self.optional_features.push(feature.as_str().into());
+        }
+    }
+
+    fn remove_feature(&mut self, feature: &Feature) {
+        self.required_features.retain(|f| &**f != feature.as_str());
+        self.optional_features.retain(|f| &**f != feature.as_str());
+    }
+
+    fn check_supported(&self) -> Result<()> {
+        match self.required().find(|f| !f.is_supported()) {
+            Some(feature) => Err(Error::UnsupportedFeature(feature)),
+            None => Ok(()),
+        }
+    }
+}
 
 pub type OSMHeaderBlob = crate::blob::Blob<HeaderBlock>;
+
+/// Position of a file in an osmosis replication stream, used to update the
+/// file with later diffs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Replication {
+    /// Seconds since the unix epoch.
+    pub timestamp: Option<i64>,
+    pub sequence_number: Option<i64>,
+    /// URL of the replication directory, e.g.
+    /// `https://planet.openstreetmap.org/replication/minute`.
+    pub base_url: Option<String>,
+}
+
+impl Replication {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn timestamp(mut self, seconds: i64) -> Self {
+        self.timestamp = Some(seconds);
+        self
+    }
+
+    #[inline]
+    pub fn sequence_number(mut self, sequence_number: i64) -> Self {
+        self.sequence_number = Some(sequence_number);
+        self
+    }
+
+    #[inline]
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Access to the osmosis replication fields of a header.
+pub trait HeaderReplication {
+    fn replication(&self) -> Replication;
+
+    /// Replaces all replication fields (unset fields are cleared).
+    fn set_replication(&mut self, replication: &Replication);
+}
+
+impl HeaderReplication for HeaderBlock {
+    fn replication(&self) -> Replication {
+        Replication {
+            timestamp: self.osmosis_replication_timestamp,
+            sequence_number: self.osmosis_replication_sequence_number,
+            base_url: self
+                .osmosis_replication_base_url
+                .as_ref()
+                .map(|u| u.to_string()),
+        }
+    }
+
+    fn set_replication(&mut self, replication: &Replication) {
+        self.osmosis_replication_timestamp = replication.timestamp;
+        self.osmosis_replication_sequence_number = replication.sequence_number;
+        self.osmosis_replication_base_url = replication.base_url.as_deref().map(Chars::from);
+    }
+}
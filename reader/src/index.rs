@@ -0,0 +1,410 @@
+//! Index of the blobs of a file, for seeking to the relevant blobs.
+
+use std::io;
+use std::sync::Mutex;
+
+use osm_pbf_proto::primitives::{Primitive, PrimitiveType};
+
+use crate::blob::{Blob, Blobs, Codec};
+use crate::bounds::Bounds;
+use crate::data::{OwnedNode, OwnedRelation, OwnedWay, PrimitiveBlock};
+use crate::error::Result;
+use crate::header::{Feature, HeaderFeatures};
+use crate::parallel::{map_reduce_blobs, worker_count};
+use crate::wire::block_types;
+
+mod sidecar;
+
+pub use self::sidecar::Fingerprint;
+
+/// Smallest and largest id of the elements of a type (inclusive).
+pub type IdRange = (i64, i64);
+
+/// Entry of a [`BlobIndex`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobEntry {
+    /// Offset of the blob (of its header length) in the file.
+    pub offset: u64,
+    /// Size of the (compressed) blob, without its header.
+    pub data_size: u32,
+    /// Type of the blob, e.g. `OSMData`.
+    pub blob_type: String,
+    /// Element types in the block; empty for other blobs.
+    pub types: PrimitiveType,
+    pub node_ids: Option<IdRange>,
+    pub way_ids: Option<IdRange>,
+    pub relation_ids: Option<IdRange>,
+    pub changeset_ids: Option<IdRange>,
+    /// Bounds of the nodes of the block, and of the node locations of its
+    /// ways (`LocationsOnWays`).
+    pub bbox: Option<Bounds>,
+}
+
+impl BlobEntry {
+    fn new(offset: u64, data_size: u32, blob_type: String) -> Self {
+        Self {
+            offset,
+            data_size,
+            blob_type,
+            types: PrimitiveType::empty(),
+            node_ids: None,
+            way_ids: None,
+            relation_ids: None,
+            changeset_ids: None,
+            bbox: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_data(&self) -> bool {
+        self.blob_type == "OSMData"
+    }
+
+    /// Id range of the elements of type `t` (a single type).
+    pub fn ids(&self, t: PrimitiveType) -> Option<IdRange> {
+        match t {
+            PrimitiveType::NODE => self.node_ids,
+            PrimitiveType::WAY => self.way_ids,
+            PrimitiveType::RELATION => self.relation_ids,
+            PrimitiveType::CHANGE_SET => self.changeset_ids,
+            _ => None,
+        }
+    }
+
+    /// `true` when `id` is in the id range of `t` (a single type).
+    #[inline]
+    pub fn may_contain(&self, t: PrimitiveType, id: i64) -> bool {
+        self.ids(t).is_some_and(|(min, max)| min <= id && id <= max)
+    }
+
+    /// Records the elements of `block`.
+    fn add_block(&mut self, block: &PrimitiveBlock) {
+        fn extend(range: &mut Option<IdRange>, id: i64) {
+            *range = Some(match *range {
+                Some((min, max)) => (min.min(id), max.max(id)),
+                None => (id, id),
+            });
+        }
+        let mut bbox: Option<Bounds> = None;
+        let mut locate = |lat: i64, lon: i64| match &mut bbox {
+            Some(bbox) => bbox.extend(lat, lon),
+            None => bbox = Some(Bounds::point(lat, lon)),
+        };
+        for group in &block.primitivegroup {
+            for node in &group.nodes {
+                extend(&mut self.node_ids, node.id());
+                locate(block.nano_lat(node.lat()), block.nano_lon(node.lon()));
+            }
+            if let Some(dense) = group.dense.as_ref() {
+                let (mut id, mut lat, mut lon) = (0i64, 0i64, 0i64);
+                for (i, delta) in dense.id.iter().enumerate() {
+                    id = id.wrapping_add(*delta);
+                    lat = lat.wrapping_add(dense.lat.get(i).copied().unwrap_or(0));
+                    lon = lon.wrapping_add(dense.lon.get(i).copied().unwrap_or(0));
+                    extend(&mut self.node_ids, id);
+                    locate(block.nano_lat(lat), block.nano_lon(lon));
+                }
+            }
+            for way in &group.ways {
+                extend(&mut self.way_ids, way.id());
+                let (mut lat, mut lon) = (0i64, 0i64);
+                for (dlat, dlon) in way.lat.iter().zip(&way.lon) {
+                    lat = lat.wrapping_add(*dlat);
+                    lon = lon.wrapping_add(*dlon);
+                    locate(block.nano_lat(lat), block.nano_lon(lon));
+                }
+            }
+            for relation in &group.relations {
+                extend(&mut self.relation_ids, relation.id());
+            }
+            for changeset in &group.changesets {
+                extend(&mut self.changeset_ids, changeset.id());
+            }
+        }
+        self.bbox = bbox;
+        for (t, ids) in [
+            (PrimitiveType::NODE, self.node_ids),
+            (PrimitiveType::WAY, self.way_ids),
+            (PrimitiveType::RELATION, self.relation_ids),
+            (PrimitiveType::CHANGE_SET, self.changeset_ids),
+        ] {
+            if ids.is_some() {
+                self.types |= t;
+            }
+        }
+    }
+}
+
+/// Offsets and contents of the blobs of a file; see [`Blobs::build_index`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlobIndex {
+    entries: Vec<BlobEntry>,
+    fingerprint: Option<Fingerprint>,
+    /// The data blobs follow the other blobs, and their elements are in
+    /// `Sort.Type_then_ID` order (across blobs).
+    sorted: bool,
+}
+
+impl BlobIndex {
+    pub fn new(entries: Vec<BlobEntry>) -> Self {
+        let sorted = is_sorted(&entries);
+        Self {
+            entries,
+            fingerprint: None,
+            sorted,
+        }
+    }
+
+    /// Fingerprint of the indexed file; `None` when it is not known.
+    #[inline]
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
+    }
+
+    #[inline]
+    pub fn with_fingerprint(mut self, fingerprint: Fingerprint) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// All blobs, in the order of the file.
+    #[inline]
+    pub fn entries(&self) -> &[BlobEntry] {
+        &self.entries
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, BlobEntry> {
+        self.entries.iter()
+    }
+
+    /// The data blobs that contain elements of `types`.
+    pub fn data_blobs_with(&self, types: PrimitiveType) -> impl Iterator<Item = &BlobEntry> + '_ {
+        self.entries
+            .iter()
+            .filter(move |e| e.is_data() && e.types.intersects(types))
+    }
+
+    /// The data blobs whose bounds intersect `bbox`, e.g. for region queries
+    /// on geographically sorted files. Blobs without locations (relations,
+    /// or ways without `LocationsOnWays`) are not returned.
+    pub fn blobs_intersecting(&self, bbox: Bounds) -> impl Iterator<Item = &BlobEntry> + '_ {
+        self.entries
+            .iter()
+            .filter(move |e| e.bbox.is_some_and(|b| b.intersects(&bbox)))
+    }
+
+    /// The data blobs whose id range of `t` (a single type) contains `id`.
+    ///
+    /// For sorted files this is at most one blob, that is found by a binary
+    /// search; otherwise all entries are checked.
+    pub fn blobs_with_id(
+        &self,
+        t: PrimitiveType,
+        id: i64,
+    ) -> impl Iterator<Item = &BlobEntry> + '_ {
+        let candidates = if self.sorted {
+            let data = self.entries.partition_point(|e| !e.is_data());
+            let data = &self.entries[data..];
+            // blobs of earlier types, or with smaller ids of `t`
+            let i = data.partition_point(|e| match e.ids(t) {
+                Some((_, max)) => max < id,
+                None => e.types.bits() < t.bits(),
+            });
+            data.get(i..=i).unwrap_or_default()
+        } else {
+            &self.entries[..]
+        };
+        candidates.iter().filter(move |e| e.may_contain(t, id))
+    }
+}
+
+/// Checks the order of the blobs for [`BlobIndex::blobs_with_id`].
+fn is_sorted(entries: &[BlobEntry]) -> bool {
+    let data = entries.partition_point(|e| !e.is_data());
+    let mut last = None;
+    for entry in &entries[data..] {
+        if !entry.is_data() || entry.types.is_empty() {
+            return false;
+        }
+        for t in [
+            PrimitiveType::NODE,
+            PrimitiveType::WAY,
+            PrimitiveType::RELATION,
+        ] {
+            let Some((min, max)) = entry.ids(t) else {
+                continue;
+            };
+            if last.is_some_and(|last| (t.bits(), min) <= last) {
+                return false;
+            }
+            last = Some((t.bits(), max));
+        }
+    }
+    true
+}
+
+impl<'a> IntoIterator for &'a BlobIndex {
+    type Item = &'a BlobEntry;
+    type IntoIter = std::slice::Iter<'a, BlobEntry>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl<R: io::BufRead + io::Seek> Blobs<R> {
+    /// Reads the whole file (from its start) and records the offset, size
+    /// and type of each blob, and the element types, id ranges and bounds
+    /// of each block.
+    ///
+    /// The blocks are decoded on the worker threads of
+    /// [`Blobs::map_reduce`]. The reader is left at the end of the file.
+    /// See [`Blobs::load_or_build_index`] for keeping the index in a sidecar
+    /// file.
+    pub fn build_index(&mut self) -> Result<BlobIndex> {
+        let fingerprint = self.fingerprint()?;
+        self.seek_to(0)?;
+        let limits = self.limits().clone();
+        let mut entries = Vec::new();
+        let source = || loop {
+            let offset = self.current_offset();
+            let Some((header, mut blob)) = self.next_blob()? else {
+                return Ok(None);
+            };
+            let seq = entries.len() as u64;
+            let is_data = header.type_() == "OSMData";
+            entries.push(BlobEntry::new(
+                offset,
+                header.datasize() as u32,
+                header.type_().to_string(),
+            ));
+            if is_data {
+                limits.check_blob(&blob)?;
+                self.codecs
+                    .decompress_in_place(&mut blob, limits.max_decompressed_size)
+                    .map_err(|e| limits.map_size_error(e))?;
+                return Ok(Some((seq, Blob::new(blob))));
+            }
+        };
+        let blocks = Mutex::new(Vec::new());
+        map_reduce_blobs(
+            worker_count(),
+            &limits,
+            source,
+            |seq, block| {
+                let mut entry = BlobEntry::new(0, 0, String::new());
+                entry.add_block(block);
+                blocks.lock().unwrap().push((seq, entry));
+            },
+            |(), ()| (),
+        )?;
+        for (seq, block) in blocks.into_inner().unwrap() {
+            let entry = &mut entries[seq as usize];
+            *entry = BlobEntry {
+                offset: entry.offset,
+                data_size: entry.data_size,
+                blob_type: std::mem::take(&mut entry.blob_type),
+                ..block
+            };
+        }
+        Ok(BlobIndex::new(entries).with_fingerprint(fingerprint))
+    }
+
+    /// Skips the data blobs before the first one with elements of type `t`
+    /// or of a later type (in the order nodes, ways, relations), e.g. past
+    /// all node blobs with `skip_to(PrimitiveType::WAY)`. At the end of the
+    /// input when there is no such blob.
+    ///
+    /// Only the blob headers of the rest of the file are read. In files
+    /// declaring `Sort.Type_then_ID` the blob is found by a binary search,
+    /// that decompresses a few blobs, otherwise every blob is checked in
+    /// turn. The blob at the boundary may also contain elements of earlier
+    /// types.
+    pub fn skip_to(&mut self, t: PrimitiveType) -> Result<()> {
+        let Some(first) = t.iter().next() else {
+            return Ok(());
+        };
+        let later = PrimitiveType::from_bits_truncate(!(first.bits() - 1));
+        let offsets = self.data_blob_offsets()?;
+        let end = self.current_offset();
+        let mut raw = Vec::new();
+        let mut reaches = |blobs: &mut Self, offset: u64| -> Result<bool> {
+            blobs.seek_to(offset)?;
+            let Some(blob) = blobs.next_data_blob()? else {
+                return Ok(false);
+            };
+            let limits = blobs.limits();
+            Codec::decompress_blob_into(&blob, &mut raw, limits.max_decompressed_size)
+                .map_err(|e| limits.map_size_error(e))?;
+            Ok(block_types(&raw)?.intersects(later))
+        };
+        let sorted = self.header().has_feature(&Feature::SortTypeThenId);
+        let (mut lo, mut hi) = (0, offsets.len());
+        while lo < hi {
+            let i = if sorted { lo + (hi - lo) / 2 } else { lo };
+            if reaches(self, offsets[i])? {
+                hi = i;
+            } else {
+                lo = i + 1;
+            }
+        }
+        self.seek_to(offsets.get(lo).copied().unwrap_or(end))
+    }
+
+    /// The node `id`, read from the blob found with `index` (see
+    /// [`BlobIndex::blobs_with_id`]). Only the nodes of the blob are
+    /// decoded; the reader is left after the blob.
+    pub fn find_node(&mut self, index: &BlobIndex, id: i64) -> Result<Option<OwnedNode>> {
+        self.find_element(index, PrimitiveType::NODE, id, |p| match p {
+            Primitive::Node(n) if n.id == id => Some(OwnedNode::from(&n)),
+            _ => None,
+        })
+    }
+
+    /// The way `id`; see [`Blobs::find_node`].
+    pub fn find_way(&mut self, index: &BlobIndex, id: i64) -> Result<Option<OwnedWay>> {
+        self.find_element(index, PrimitiveType::WAY, id, |p| match p {
+            Primitive::Way(w) if w.id() == id => Some(OwnedWay::from(&w)),
+            _ => None,
+        })
+    }
+
+    /// The relation `id`; see [`Blobs::find_node`].
+    pub fn find_relation(&mut self, index: &BlobIndex, id: i64) -> Result<Option<OwnedRelation>> {
+        self.find_element(index, PrimitiveType::RELATION, id, |p| match p {
+            Primitive::Relation(r) if r.id() == id => Some(OwnedRelation::from(&r)),
+            _ => None,
+        })
+    }
+
+    fn find_element<T>(
+        &mut self,
+        index: &BlobIndex,
+        t: PrimitiveType,
+        id: i64,
+        find: impl Fn(Primitive<'_>) -> Option<T>,
+    ) -> Result<Option<T>> {
+        for entry in index.blobs_with_id(t, id) {
+            self.seek_to(entry.offset)?;
+            let Some(block) = self.next_primitive_block_of_types(t)? else {
+                return Ok(None);
+            };
+            if let Some(element) = block.primitives().find_map(&find) {
+                return Ok(Some(element));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use super::{BlobEntry, BlobIndex, IdRange};
+use crate::atomic::AtomicFile;
+use crate::blob::Blobs;
+use crate::bounds::Bounds;
+use crate::error::Result;
+use crate::wire::{read_varint, write_varint, zigzag};
+
+const MAGIC: &[u8; 8] = b"PBFINDEX";
+const VERSION: u8 = 2;
+
+/// Bytes at the start and at the end of a file that are hashed by
+/// [`Fingerprint::compute`].
+const FINGERPRINT_SAMPLE: u64 = 64 * 1024;
+
+/// Identifies the content of a file, to detect stale indexes.
+///
+/// Consists of the size of the file and a hash of its first and last
+/// 64 KiB, which contain the header and the last blobs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub hash: u64,
+}
+
+impl Fingerprint {
+    /// Computes the fingerprint of `input`; the position is not restored.
+    pub fn compute<R: Read + Seek>(input: &mut R) -> Result<Self> {
+        let size = input.seek(SeekFrom::End(0))?;
+        // FNV-1a, stable across platforms and releases
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut update = |data: &[u8]| {
+            for &b in data {
+                hash = (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        let mut buf = Vec::new();
+        input.rewind()?;
+        input
+            .by_ref()
+            .take(FINGERPRINT_SAMPLE)
+            .read_to_end(&mut buf)?;
+        update(&buf);
+        let tail = size
+            .saturating_sub(FINGERPRINT_SAMPLE)
+            .max(buf.len() as u64);
+        buf.clear();
+        input.seek(SeekFrom::Start(tail))?;
+        input.read_to_end(&mut buf)?;
+        update(&buf);
+        Ok(Self { size, hash })
+    }
+}
+
+impl<R: io::BufRead + Seek> Blobs<R> {
+    /// The [`Fingerprint`] of the input; the position of the reader is
+    /// restored.
+    pub fn fingerprint(&mut self) -> Result<Fingerprint> {
+        let position = self.current_offset();
+        let fingerprint = Fingerprint::compute(self.reader_mut());
+        self.seek_to(position)?;
+        fingerprint
+    }
+
+    /// Loads the index from the sidecar file at `path` if it matches the
+    /// input, otherwise builds the index (see [`Blobs::build_index`]) and
+    /// writes it to `path`. The index is also returned when it cannot be
+    /// written, e.g. next to a file in a read-only directory.
+    ///
+    /// The reader is left at the first data blob.
+    pub fn load_or_build_index(&mut self, path: impl AsRef<Path>) -> Result<BlobIndex> {
+        let path = path.as_ref();
+        let fingerprint = self.fingerprint()?;
+        // a missing, corrupt or stale index is rebuilt
+        let index = match BlobIndex::open(path) {
+            Ok(index) if index.fingerprint() == Some(fingerprint) => index,
+            _ => {
+                let index = self.build_index()?;
+                // the sidecar is only a cache
+                let _ = index.write_to_path(path);
+                index
+            }
+        };
+        let first_data = index.iter().find(|e| e.is_data()).map(|e| e.offset);
+        self.seek_to(first_data.unwrap_or(fingerprint.size))?;
+        Ok(index)
+    }
+}
+
+/// Flags of an entry in the sidecar file.
+const HAS_BBOX: u8 = 0x10;
+const TYPES: [PrimitiveType; 4] = [
+    PrimitiveType::NODE,
+    PrimitiveType::WAY,
+    PrimitiveType::RELATION,
+    PrimitiveType::CHANGE_SET,
+];
+
+#[inline]
+fn encode_zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads the entries of a sidecar file.
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn varint(&mut self) -> io::Result<u64> {
+        let rest = self.data.get(self.pos..).unwrap_or_default();
+        let (value, len) = read_varint(rest).ok_or_else(|| invalid("truncated blob index"))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    #[inline]
+    fn sint(&mut self) -> io::Result<i64> {
+        self.varint().map(zigzag)
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| invalid("truncated blob index"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+impl BlobIndex {
+    /// Path of the sidecar index of the file at `path` (`<path>.idx`).
+    pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+        let mut path = path.as_ref().as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Writes the index in a compact binary format, that is read by
+    /// [`BlobIndex::read_from`].
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<()> {
+        let fingerprint = self.fingerprint.unwrap_or(Fingerprint { size: 0, hash: 0 });
+        out.write_all(MAGIC)?;
+        out.write_u8(VERSION)?;
+        out.write_u64::<BigEndian>(fingerprint.size)?;
+        out.write_u64::<BigEndian>(fingerprint.hash)?;
+        let mut buf = Vec::new();
+        write_varint(self.entries.len() as u64, &mut buf);
+        let mut last_offset = 0;
+        for entry in &self.entries {
+            write_varint(entry.offset.wrapping_sub(last_offset), &mut buf);
+            last_offset = entry.offset;
+            write_varint(entry.data_size as u64, &mut buf);
+            write_varint(entry.blob_type.len() as u64, &mut buf);
+            buf.extend_from_slice(entry.blob_type.as_bytes());
+            let mut flags = 0;
+            for (i, t) in TYPES.into_iter().enumerate() {
+                if entry.ids(t).is_some() {
+                    flags |= 1 << i;
+                }
+            }
+            if entry.bbox.is_some() {
+                flags |= HAS_BBOX;
+            }
+            buf.push(flags);
+            for (min, max) in TYPES.into_iter().filter_map(|t| entry.ids(t)) {
+                write_varint(encode_zigzag(min), &mut buf);
+                write_varint(max.wrapping_sub(min) as u64, &mut buf);
+            }
+            if let Some(b) = entry.bbox {
+                for v in [b.min_lat, b.min_lon, b.max_lat, b.max_lon] {
+                    write_varint(encode_zigzag(v), &mut buf);
+                }
+            }
+        }
+        out.write_all(&buf)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes the index to `path`, atomically.
+    pub fn write_to_path(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut file = AtomicFile::create(path)?;
+        self.write_to(io::BufWriter::new(&mut file))?;
+        file.commit()
+    }
+
+    /// Reads an index written by [`BlobIndex::write_to`].
+    pub fn read_from<R: Read>(mut input: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a blob index").into());
+        }
+        if input.read_u8()? != VERSION {
+            return Err(invalid("unsupported blob index version").into());
+        }
+        let size = input.read_u64::<BigEndian>()?;
+        let hash = input.read_u64::<BigEndian>()?;
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+
+        let mut input = Input {
+            data: &data,
+            pos: 0,
+        };
+        let count = input.varint()?;
+        let mut entries = Vec::with_capacity(count.min(1 << 20) as usize);
+        let mut offset = 0u64;
+        for _ in 0..count {
+            offset = offset.wrapping_add(input.varint()?);
+            let data_size = input.varint()? as u32;
+            let type_len = input.varint()? as usize;
+            let blob_type = String::from_utf8(input.bytes(type_len)?.to_vec())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let mut entry = BlobEntry::new(offset, data_size, blob_type);
+            let flags = input.bytes(1)?[0];
+            for (i, t) in TYPES.into_iter().enumerate() {
+                if flags & (1 << i) == 0 {
+                    continue;
+                }
+                let min = input.sint()?;
+                let range: IdRange = (min, min.wrapping_add(input.varint()? as i64));
+                entry.types |= t;
+                match t {
+                    PrimitiveType::NODE => entry.node_ids = Some(range),
+                    PrimitiveType::WAY => entry.way_ids = Some(range),
+                    PrimitiveType::RELATION => entry.relation_ids = Some(range),
+                    _ => entry.changeset_ids = Some(range),
+                }
+            }
+            if flags & HAS_BBOX != 0 {
+                entry.bbox = Some(Bounds {
+                    min_lat: input.sint()?,
+                    min_lon: input.sint()?,
+                    max_lat: input.sint()?,
+                    max_lon: input.sint()?,
+                });
+            }
+            entries.push(entry);
+        }
+        Ok(Self::new(entries).with_fingerprint(Fingerprint { size, hash }))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read_from(io::BufReader::new(File::open(path)?))
+    }
+}
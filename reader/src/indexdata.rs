@@ -0,0 +1,47 @@
+//! Skipping of blobs by the `indexdata` of their headers.
+//!
+//! The specification leaves the content of `indexdata` to the producer, so
+//! the reader needs an interpreter that knows the producer's format (e.g. the
+//! element types or the bbox of the blob). Blobs that the interpreter rejects
+//! are skipped before their payload is parsed or decompressed.
+
+use std::io;
+
+use crate::blob::{Blobs, PbfBlobHeader};
+use crate::data::OSMDataBlob;
+use crate::error::Result;
+
+/// Interprets the `indexdata` of blob headers; see
+/// [`Blobs::next_primitive_block_indexed`].
+pub trait IndexData {
+    /// Whether the blob with the given `indexdata` may contain wanted
+    /// elements. Blobs for which this returns `false` are skipped.
+    fn accept(&self, indexdata: &[u8]) -> bool;
+
+    /// Whether blobs without `indexdata` are read (`true` by default).
+    fn accept_missing(&self) -> bool {
+        true
+    }
+}
+
+impl<F: Fn(&[u8]) -> bool> IndexData for F {
+    #[inline]
+    fn accept(&self, indexdata: &[u8]) -> bool {
+        self(indexdata)
+    }
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Reads the next `OSMData` blob that `index` accepts, together with its
+    /// header. The payload of the other blobs is skipped without being
+    /// parsed.
+    pub fn next_primitive_block_indexed(
+        &mut self,
+        index: &impl IndexData,
+    ) -> Result<Option<(PbfBlobHeader, OSMDataBlob)>> {
+        self.next_data_blob_where(|header| match &header.indexdata {
+            Some(indexdata) => index.accept(indexdata),
+            None => index.accept_missing(),
+        })
+    }
+}
@@ -0,0 +1,255 @@
+//! Background ingestion with join handles and statistics.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::blob::{Blob, Blobs, Codec};
+use crate::data::{OSMDataBlob, PrimitiveBlock};
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+
+/// Counters of a single worker thread.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// Decoded blocks (including blocks that failed to decode).
+    pub blocks: u64,
+    /// Encoded (on-disk) size of the blob payloads.
+    pub bytes: u64,
+    pub errors: u64,
+}
+
+/// Snapshot of the counters of an ingestion.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Data blobs read from the input.
+    pub blobs_read: u64,
+    pub workers: Vec<WorkerStats>,
+}
+
+impl IngestStats {
+    /// Sum of the counters of all workers.
+    pub fn total(&self) -> WorkerStats {
+        self.workers
+            .iter()
+            .fold(WorkerStats::default(), |acc, w| WorkerStats {
+                blocks: acc.blocks + w.blocks,
+                bytes: acc.bytes + w.bytes,
+                errors: acc.errors + w.errors,
+            })
+    }
+}
+
+/// Result of a joined ingestion.
+#[derive(Debug)]
+pub struct IngestReport<R> {
+    /// The input, positioned after the last read blob.
+    pub blobs: Blobs<R>,
+    pub stats: IngestStats,
+    /// Whether the ingestion was stopped by [`IngestHandle::abort`].
+    pub aborted: bool,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    blocks: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    aborted: AtomicBool,
+    failed: AtomicBool,
+    error: Mutex<Option<Error>>,
+    blobs_read: AtomicU64,
+    workers: Vec<Counters>,
+}
+
+impl Shared {
+    #[inline]
+    fn stopped(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed) || self.failed.load(Ordering::Relaxed)
+    }
+
+    fn fail(&self, error: Error) {
+        self.failed.store(true, Ordering::Relaxed);
+        self.error.lock().unwrap().get_or_insert(error);
+    }
+
+    fn stats(&self) -> IngestStats {
+        IngestStats {
+            blobs_read: self.blobs_read.load(Ordering::Relaxed),
+            workers: self
+                .workers
+                .iter()
+                .map(|c| WorkerStats {
+                    blocks: c.blocks.load(Ordering::Relaxed),
+                    bytes: c.bytes.load(Ordering::Relaxed),
+                    errors: c.errors.load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Handle to an ingestion running on background threads; see
+/// [`Blobs::spawn`].
+///
+/// Dropping the handle detaches the threads; they keep running until the
+/// input is exhausted.
+#[derive(Debug)]
+pub struct IngestHandle<R> {
+    reader: JoinHandle<Blobs<R>>,
+    workers: Vec<JoinHandle<()>>,
+    shared: Arc<Shared>,
+}
+
+impl<R> IngestHandle<R> {
+    /// Current counters; the values are updated while the ingestion runs.
+    #[inline]
+    pub fn stats(&self) -> IngestStats {
+        self.shared.stats()
+    }
+
+    /// Whether all threads have terminated.
+    pub fn is_finished(&self) -> bool {
+        self.reader.is_finished() && self.workers.iter().all(JoinHandle::is_finished)
+    }
+
+    /// Requests the ingestion to stop.
+    ///
+    /// No further blobs are read and queued blobs are discarded. Blocks that
+    /// are already being processed are completed; use
+    /// [`join`](Self::join) to wait for them.
+    #[inline]
+    pub fn abort(&self) {
+        self.shared.aborted.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits for all threads to terminate.
+    ///
+    /// Returns the first read or decode error. A panic of the handler is
+    /// propagated.
+    pub fn join(self) -> Result<IngestReport<R>> {
+        let mut panic = None;
+        for worker in self.workers {
+            if let Err(p) = worker.join() {
+                panic.get_or_insert(p);
+            }
+        }
+        let blobs = match self.reader.join() {
+            Ok(blobs) => blobs,
+            Err(p) => std::panic::resume_unwind(p),
+        };
+        if let Some(p) = panic {
+            std::panic::resume_unwind(p);
+        }
+        if let Some(e) = self.shared.error.lock().unwrap().take() {
+            return Err(e);
+        }
+        Ok(IngestReport {
+            blobs,
+            stats: self.shared.stats(),
+            aborted: self.shared.aborted.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl<R: io::BufRead + Send + 'static> Blobs<R> {
+    /// Reads and decodes all remaining blocks on background threads and
+    /// calls `handler` for each of them.
+    ///
+    /// One thread reads the blobs, `workers` threads decode them and run
+    /// `handler` (in no particular order). The first read or decode error
+    /// stops the ingestion and is returned by [`IngestHandle::join`].
+    pub fn spawn<F>(self, workers: usize, handler: F) -> IngestHandle<R>
+    where
+        F: Fn(&PrimitiveBlock) + Send + Sync + 'static,
+    {
+        let (workers, queue) = self.limits().split_in_flight(workers.max(1), workers * 2);
+        let shared = Arc::new(Shared {
+            aborted: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            error: Mutex::new(None),
+            blobs_read: AtomicU64::new(0),
+            workers: (0..workers).map(|_| Counters::default()).collect(),
+        });
+        let (sender, receiver) = mpsc::sync_channel::<OSMDataBlob>(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+        let limits = Arc::new(self.limits().clone());
+
+        let workers = (0..workers)
+            .map(|index| {
+                let shared = shared.clone();
+                let receiver = receiver.clone();
+                let handler = handler.clone();
+                let limits = limits.clone();
+                thread::spawn(move || work(index, &shared, &receiver, &limits, &*handler))
+            })
+            .collect();
+
+        let reader = {
+            let shared = shared.clone();
+            let mut blobs = self;
+            thread::spawn(move || {
+                while !shared.stopped() {
+                    match blobs.next_primitive_block() {
+                        Ok(Some(blob)) => {
+                            shared.blobs_read.fetch_add(1, Ordering::Relaxed);
+                            if sender.send(blob).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            shared.fail(e);
+                            break;
+                        }
+                    }
+                }
+                blobs
+            })
+        };
+
+        IngestHandle {
+            reader,
+            workers,
+            shared,
+        }
+    }
+}
+
+fn work(
+    index: usize,
+    shared: &Shared,
+    receiver: &Mutex<mpsc::Receiver<OSMDataBlob>>,
+    limits: &Limits,
+    handler: &impl Fn(&PrimitiveBlock),
+) {
+    let counters = &shared.workers[index];
+    loop {
+        let next = receiver.lock().map(|r| r.recv());
+        let Ok(Ok(mut blob)) = next else {
+            return;
+        };
+        if shared.stopped() {
+            // keep draining the channel, so the reader is not blocked
+            continue;
+        }
+        if let Blob::Encoded(pbf) = &blob {
+            let bytes = pbf.data.as_ref().map_or(0, Codec::encoded_len);
+            counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+        counters.blocks.fetch_add(1, Ordering::Relaxed);
+        match blob.decode_with_limits(limits) {
+            Ok(block) => handler(block),
+            Err(e) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                shared.fail(e);
+            }
+        }
+    }
+}
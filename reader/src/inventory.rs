@@ -0,0 +1,312 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+use osm_pbf_proto::primitives::Primitive;
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::Result;
+
+/// Default memory budget of a [`KeyInventory`].
+pub const DEFAULT_INVENTORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Default number of example values kept per key.
+pub const DEFAULT_MAX_EXAMPLES: usize = 3;
+
+/// A tag key with the number of its occurrences and a few example values.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyCount {
+    pub key: String,
+    pub count: u64,
+    pub examples: Vec<String>,
+}
+
+impl KeyCount {
+    /// Approximate heap + inline size while aggregated in memory.
+    fn memory_size(&self) -> usize {
+        96 + self.key.len() + self.examples.iter().map(|e| 24 + e.len()).sum::<usize>()
+    }
+
+    fn merge(&mut self, other: Self, max_examples: usize) {
+        self.count += other.count;
+        for example in other.examples {
+            if self.examples.len() >= max_examples {
+                break;
+            }
+            if !self.examples.contains(&example) {
+                self.examples.push(example);
+            }
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write_str(out, &self.key)?;
+        out.write_u64::<BigEndian>(self.count)?;
+        out.write_u8(self.examples.len().min(u8::MAX as usize) as u8)?;
+        for example in self.examples.iter().take(u8::MAX as usize) {
+            write_str(out, example)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the next record; `None` at the end of the table.
+    fn read_from(input: &mut impl Read) -> Result<Option<Self>> {
+        let key = match read_str(input) {
+            Ok(key) => key,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let count = input.read_u64::<BigEndian>()?;
+        let num_examples = input.read_u8()?;
+        let examples = (0..num_examples)
+            .map(|_| read_str(input))
+            .collect::<io::Result<_>>()?;
+        Ok(Some(Self {
+            key,
+            count,
+            examples,
+        }))
+    }
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    out.write_u32::<BigEndian>(s.len() as u32)?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_str(input: &mut impl Read) -> io::Result<String> {
+    let len = input.read_u32::<BigEndian>()? as usize;
+    let mut buf = vec![0; len];
+    input.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Counts the tag keys of a file with bounded memory.
+///
+/// Keys are aggregated in memory up to a budget. When the budget is
+/// exceeded, the aggregated keys are spilled as a sorted run into a
+/// temporary file; the runs are merged by [`KeyInventory::write_to`] into a
+/// table that is sorted by key and can be read back with [`KeyTable`].
+#[derive(Debug)]
+pub struct KeyInventory {
+    keys: HashMap<String, KeyCount>,
+    memory: usize,
+    budget: usize,
+    max_examples: usize,
+    runs: Vec<File>,
+}
+
+impl Default for KeyInventory {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+            memory: 0,
+            budget: DEFAULT_INVENTORY_BUDGET,
+            max_examples: DEFAULT_MAX_EXAMPLES,
+            runs: Vec::new(),
+        }
+    }
+}
+
+impl KeyInventory {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Approximate amount of memory used before spilling to disk.
+    #[inline]
+    pub fn budget(mut self, bytes: usize) -> Self {
+        self.budget = bytes;
+        self
+    }
+
+    /// Maximum number of distinct example values kept per key (at most 255).
+    #[inline]
+    pub fn max_examples(mut self, max_examples: usize) -> Self {
+        self.max_examples = max_examples.min(u8::MAX as usize);
+        self
+    }
+
+    /// Counts the keys of all remaining blocks of `blobs`.
+    pub fn from_blobs<R: io::BufRead>(blobs: &mut Blobs<R>) -> Result<Self> {
+        let mut inventory = Self::new();
+        inventory.add_blobs(blobs)?;
+        Ok(inventory)
+    }
+
+    pub fn add_blobs<R: io::BufRead>(&mut self, blobs: &mut Blobs<R>) -> Result<()> {
+        while let Some(block) = blobs.next_primitive_block_decoded()? {
+            self.add_block(&block)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_block(&mut self, block: &PrimitiveBlock) -> Result<()> {
+        for p in block.primitives() {
+            match &p {
+                Primitive::Node(n) => {
+                    for (k, v) in n.tags() {
+                        self.add_tag(k, v)?;
+                    }
+                }
+                Primitive::Way(w) => {
+                    for (k, v) in w.tags() {
+                        self.add_tag(k, v)?;
+                    }
+                }
+                Primitive::Relation(r) => {
+                    for (k, v) in r.tags() {
+                        self.add_tag(k, v)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_tag(&mut self, key: &str, value: &str) -> Result<()> {
+        if let Some(entry) = self.keys.get_mut(key) {
+            entry.count += 1;
+            if entry.examples.len() < self.max_examples
+                && !entry.examples.iter().any(|e| e == value)
+            {
+                entry.examples.push(value.to_string());
+                self.memory += 24 + value.len();
+            }
+        } else {
+            let mut entry = KeyCount {
+                key: key.to_string(),
+                count: 1,
+                examples: Vec::new(),
+            };
+            if self.max_examples > 0 {
+                entry.examples.push(value.to_string());
+            }
+            self.memory += entry.memory_size();
+            self.keys.insert(entry.key.clone(), entry);
+        }
+        if self.memory > self.budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn sorted_keys(&mut self) -> Vec<KeyCount> {
+        let mut keys: Vec<_> = self.keys.drain().map(|(_, v)| v).collect();
+        keys.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        self.memory = 0;
+        keys
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let mut out = io::BufWriter::new(tempfile::tempfile()?);
+        for entry in self.sorted_keys() {
+            entry.write_to(&mut out)?;
+        }
+        let mut file = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+        file.rewind()?;
+        self.runs.push(file);
+        Ok(())
+    }
+
+    /// Writes the table of all keys, sorted by key, and returns the number of
+    /// distinct keys.
+    pub fn write_to<W: Write>(mut self, out: W) -> Result<u64> {
+        let mut out = io::BufWriter::new(out);
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<KeyCount>>>> = Vec::new();
+        sources.push(Box::new(self.sorted_keys().into_iter().map(Ok)));
+        for run in self.runs.drain(..) {
+            sources.push(Box::new(KeyTable::new(io::BufReader::new(run))));
+        }
+
+        // k-way merge of the sorted sources; `heads` holds the next entry of each
+        let mut heads: Vec<Option<KeyCount>> = Vec::with_capacity(sources.len());
+        let mut heap = BinaryHeap::new();
+        for (i, source) in sources.iter_mut().enumerate() {
+            let head = source.next().transpose()?;
+            if let Some(entry) = &head {
+                heap.push(Reverse((entry.key.clone(), i)));
+            }
+            heads.push(head);
+        }
+
+        let mut num_keys = 0;
+        let mut current: Option<KeyCount> = None;
+        while let Some(Reverse((_, i))) = heap.pop() {
+            let entry = heads[i].take().expect("head of a merged source");
+            heads[i] = sources[i].next().transpose()?;
+            if let Some(next) = &heads[i] {
+                heap.push(Reverse((next.key.clone(), i)));
+            }
+            match &mut current {
+                Some(c) if c.key == entry.key => c.merge(entry, self.max_examples),
+                _ => {
+                    if let Some(c) = current.replace(entry) {
+                        c.write_to(&mut out)?;
+                        num_keys += 1;
+                    }
+                }
+            }
+        }
+        if let Some(c) = current {
+            c.write_to(&mut out)?;
+            num_keys += 1;
+        }
+        out.flush()?;
+        Ok(num_keys)
+    }
+
+    pub fn write_to_path(self, path: impl AsRef<Path>) -> Result<u64> {
+        self.write_to(File::create(path)?)
+    }
+}
+
+/// Reads a key table written by [`KeyInventory::write_to`].
+#[derive(Debug)]
+pub struct KeyTable<R> {
+    reader: R,
+    done: bool,
+}
+
+impl KeyTable<io::BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(io::BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> KeyTable<R> {
+    #[inline]
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Iterator for KeyTable<R> {
+    type Item = Result<KeyCount>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match KeyCount::read_from(&mut self.reader) {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -21,9 +21,96 @@
     // clippy::missing_panics_doc,
     clippy::wildcard_imports
 )]
+// only used by the benchmarks
+#[cfg(test)]
+use criterion as _;
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub mod async_blobs;
+pub mod atomic;
 pub mod blob;
+pub mod bounds;
+pub mod cancel;
+pub mod changes;
+pub mod cli;
+pub mod codec;
+pub mod columns;
+pub mod compression;
+pub mod concat;
 pub mod data;
+pub mod elements;
 pub mod error;
+pub mod extract;
+pub mod filter;
+pub mod flat;
+pub mod geosort;
 pub mod header;
+pub mod index;
+pub mod indexdata;
+pub mod ingest;
+pub mod inventory;
+pub mod limits;
+pub mod multipass;
+pub mod ordered;
+mod parallel;
+pub mod pipeline;
+pub mod poly;
+pub mod prefetch;
+pub mod probe;
+pub mod qa;
+pub mod shard;
+pub mod source;
+pub mod split;
+pub mod synthetic;
+pub mod visitor;
+pub mod wire;
+pub mod writer;
 
-pub use blob::{Blob, Blobs};
+#[cfg(feature = "futures-io")]
+pub use async_blobs::FuturesIo;
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub use async_blobs::{AsyncBlobs, AsyncInput, BlobStream, BlockingSpawner, DecodedBlocks};
+pub use atomic::AtomicFile;
+pub use blob::{Blob, BlobInfo, Blobs, Codec, CompressionOptions, FramedBlobs};
+pub use bounds::{ApproxBounds, Bounds};
+pub use cancel::CancellationToken;
+pub use changes::{apply_changes, Changes};
+pub use codec::{BlobCodec, Codecs};
+pub use columns::{TagColumn, TagColumns};
+pub use compression::{transcode, CompressionReport, CompressionSurvey};
+pub use concat::{concat, ConcatHeader};
+pub use elements::Elements;
+pub use extract::{ChangeKind, Extract};
+pub use filter::{
+    AreaFilter, BboxFilter, ElementFilter, IdFilter, IdSet, MetaFilter, PolyFilter, TagKeyFilter,
+    TimeFilter,
+};
+pub use flat::FlatBlock;
+pub use geosort::sort_geographic;
+pub use index::{BlobEntry, BlobIndex, Fingerprint};
+pub use indexdata::IndexData;
+pub use ingest::{IngestHandle, IngestReport, IngestStats, WorkerStats};
+pub use inventory::{KeyCount, KeyInventory, KeyTable};
+pub use limits::Limits;
+pub use multipass::MultiPass;
+pub use ordered::OrderedElements;
+#[cfg(feature = "rayon")]
+pub use parallel::ParDecode;
+pub use pipeline::transform;
+pub use poly::Polygon;
+pub use prefetch::Prefetch;
+pub use probe::{FileKind, Probe};
+pub use shard::{shard_by_id, Partitioning, ShardById, ShardedWriter};
+#[cfg(feature = "http")]
+pub use source::HttpSource;
+#[cfg(feature = "object_store")]
+pub use source::ObjectStoreSource;
+pub use source::{BlobSource, SourceReader};
+pub use split::SplitTarget;
+pub use synthetic::SyntheticPlanet;
+pub use visitor::Visitor;
+#[cfg(feature = "tokio")]
+pub use writer::AsyncPbfWriter;
+pub use writer::{
+    BlockEncoding, Durable, ElementOrdering, NodeLocations, PbfWriter, QueueOptions, QueueStats,
+    QueuedWriter, StringTableStrategy, StripMetadata, WriterOptions, WriterStats,
+};
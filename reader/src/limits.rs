@@ -0,0 +1,238 @@
+use osm_pbf_proto::osmformat::PrimitiveBlock;
+
+use crate::blob::{PbfBlob, MAX_HEADER_SIZE, MAX_UNCOMPRESSED_DATA_SIZE};
+use crate::error::{Error, Result};
+
+/// Memory of the decompression buffer and the decoded messages of a block,
+/// relative to its decompressed size; see [`Limits::memory_budget`].
+pub const BLOCK_MEMORY_FACTOR: usize = 3;
+
+/// Caps enforced while reading, to protect against resource exhaustion by
+/// untrusted inputs.
+///
+/// By default, the sizes are limited as required by the specification and
+/// all other limits are disabled (`None`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of a `BlobHeader` (64 KiB by default).
+    pub max_header_size: u32,
+    /// Maximum size of an encoded `Blob` (32 MiB by default).
+    pub max_blob_size: usize,
+    /// Maximum size of the decompressed payload of a `Blob` (32 MiB by
+    /// default).
+    pub max_decompressed_size: usize,
+    /// Maximum number of elements (nodes, ways, relations and changesets) in
+    /// a single `PrimitiveBlock`.
+    pub max_elements_per_block: Option<usize>,
+    /// Maximum number of entries in the string table of a `PrimitiveBlock`.
+    pub max_string_table_entries: Option<usize>,
+    /// Maximum number of blobs in a file (including the `OSMHeader` blob).
+    pub max_blob_count: Option<u64>,
+    /// Maximum number of blocks that the parallel readers keep in memory at
+    /// the same time (read ahead, being decoded or waiting to be yielded).
+    pub max_blocks_in_flight: Option<usize>,
+    /// The memory budget the other limits were derived from, see
+    /// [`Limits::memory_budget`].
+    pub memory_budget: Option<usize>,
+}
+
+impl Default for Limits {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Limits {
+    /// The limits of the specification.
+    pub const DEFAULT: Self = Self {
+        max_header_size: MAX_HEADER_SIZE,
+        max_blob_size: MAX_UNCOMPRESSED_DATA_SIZE,
+        max_decompressed_size: MAX_UNCOMPRESSED_DATA_SIZE,
+        max_elements_per_block: None,
+        max_string_table_entries: None,
+        max_blob_count: None,
+        max_blocks_in_flight: None,
+        memory_budget: None,
+    };
+
+    /// No limits at all, e.g. for non-standard files with huge blobs.
+    pub const UNLIMITED: Self = Self {
+        max_header_size: u32::MAX,
+        max_blob_size: usize::MAX,
+        max_decompressed_size: usize::MAX,
+        max_elements_per_block: None,
+        max_string_table_entries: None,
+        max_blob_count: None,
+        max_blocks_in_flight: None,
+        memory_budget: None,
+    };
+
+    /// Conservative limits for user-uploaded files.
+    ///
+    /// Reference writers (osmium, osmosis) emit at most 8000 elements per
+    /// block; these limits leave plenty of headroom for other producers.
+    pub const UNTRUSTED: Self = Self {
+        max_header_size: MAX_HEADER_SIZE,
+        max_blob_size: MAX_UNCOMPRESSED_DATA_SIZE,
+        max_decompressed_size: MAX_UNCOMPRESSED_DATA_SIZE,
+        max_elements_per_block: Some(256 * 1024),
+        max_string_table_entries: Some(256 * 1024),
+        max_blob_count: Some(1024 * 1024),
+        max_blocks_in_flight: None,
+        memory_budget: None,
+    };
+
+    #[inline]
+    pub fn new() -> Self {
+        Self::DEFAULT
+    }
+
+    #[inline]
+    pub fn max_header_size(mut self, max: u32) -> Self {
+        self.max_header_size = max;
+        self
+    }
+
+    #[inline]
+    pub fn max_blob_size(mut self, max: usize) -> Self {
+        self.max_blob_size = max;
+        self
+    }
+
+    #[inline]
+    pub fn max_decompressed_size(mut self, max: usize) -> Self {
+        self.max_decompressed_size = max;
+        self
+    }
+
+    #[inline]
+    pub fn max_elements_per_block(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.max_elements_per_block = max.into();
+        self
+    }
+
+    #[inline]
+    pub fn max_string_table_entries(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.max_string_table_entries = max.into();
+        self
+    }
+
+    #[inline]
+    pub fn max_blob_count(mut self, max: impl Into<Option<u64>>) -> Self {
+        self.max_blob_count = max.into();
+        self
+    }
+
+    #[inline]
+    pub fn max_blocks_in_flight(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.max_blocks_in_flight = max.into().map(|n| n.max(1));
+        self
+    }
+
+    /// Keeps the memory used for blocks below `bytes`.
+    ///
+    /// A block in memory is accounted with its encoded size plus
+    /// [`BLOCK_MEMORY_FACTOR`] times its decompressed size (for the
+    /// decompression buffer and the decoded messages). The sizes of single
+    /// blobs are capped so that at least one block fits into the budget, and
+    /// [`Limits::max_blocks_in_flight`] is set to the number of blocks of the
+    /// maximal size that fit. Blobs that are larger (as stated by their
+    /// header, or while decompressing) fail with
+    /// [`Error::MemoryBudgetExceeded`] before they are decoded.
+    ///
+    /// Set a smaller [`Limits::max_decompressed_size`] before, to allow
+    /// more blocks in flight (typical blocks are much smaller than the 32 MiB
+    /// allowed by the specification). Memory used outside of the reader (e.g.
+    /// for elements converted from the blocks) is not accounted.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        let per_block = bytes / (BLOCK_MEMORY_FACTOR + 1);
+        let max_decompressed_size = self.max_decompressed_size.min(per_block).max(1);
+        self.max_decompressed_size = max_decompressed_size;
+        self.max_blob_size = self.max_blob_size.min(max_decompressed_size);
+        let block = self.max_blob_size + BLOCK_MEMORY_FACTOR * max_decompressed_size;
+        let blocks = (bytes / block).max(1);
+        self.max_blocks_in_flight =
+            Some(self.max_blocks_in_flight.map_or(blocks, |n| n.min(blocks)));
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Number of blocks to keep in memory, when `wanted` are asked for.
+    #[inline]
+    pub fn blocks_in_flight(&self, wanted: usize) -> usize {
+        self.max_blocks_in_flight
+            .map_or(wanted, |max| wanted.min(max))
+            .max(1)
+    }
+
+    /// Splits the blocks in flight between `workers` threads that decode a
+    /// block each and a queue of `queue` blocks that are read ahead.
+    pub(crate) fn split_in_flight(&self, workers: usize, queue: usize) -> (usize, usize) {
+        let workers = self.blocks_in_flight(workers);
+        match self.max_blocks_in_flight {
+            Some(max) => (workers, queue.min(max.saturating_sub(workers))),
+            None => (workers, queue),
+        }
+    }
+
+    /// The error for a blob that is larger than the limits.
+    pub(crate) fn size_error(&self) -> Error {
+        match self.memory_budget {
+            Some(budget) => Error::MemoryBudgetExceeded(budget),
+            None => Error::BlobDataToLarge,
+        }
+    }
+
+    /// Reports [`Error::BlobDataToLarge`] as the budget error when a memory
+    /// budget is set.
+    pub(crate) fn map_size_error(&self, e: Error) -> Error {
+        match e {
+            Error::BlobDataToLarge => self.size_error(),
+            e => e,
+        }
+    }
+
+    /// Checks the sizes stated by a blob before it is decompressed.
+    pub(crate) fn check_blob(&self, blob: &PbfBlob) -> Result<()> {
+        match blob.raw_size {
+            Some(size) if size > 0 && size as usize > self.max_decompressed_size => {
+                Err(self.size_error())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_primitive_block(&self, block: &PrimitiveBlock) -> Result<()> {
+        if let Some(max) = self.max_string_table_entries {
+            let count = block.stringtable.s.len();
+            if count > max {
+                return Err(Error::TooManyStrings(count, max));
+            }
+        }
+        if let Some(max) = self.max_elements_per_block {
+            let count: usize = block
+                .primitivegroup
+                .iter()
+                .map(|g| {
+                    g.nodes.len()
+                        + g.dense.id.len()
+                        + g.ways.len()
+                        + g.relations.len()
+                        + g.changesets.len()
+                })
+                .sum();
+            if count > max {
+                return Err(Error::TooManyElements(count, max));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_blob_count(&self, count: u64) -> Result<()> {
+        match self.max_blob_count {
+            Some(max) if count > max => Err(Error::TooManyBlobs(max)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::Result;
+use crate::header::HeaderBlock;
+use crate::parallel::{map_reduce_blobs, worker_count};
+
+/// Progress of a pass of [`MultiPass`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PassProgress {
+    /// Number of the pass, starting at 0.
+    pub pass: usize,
+    /// Element types of the pass.
+    pub types: PrimitiveType,
+    /// Blobs read so far in this pass.
+    pub blobs: u64,
+    /// Number of blobs of this pass, when known from the index.
+    pub total_blobs: Option<u64>,
+}
+
+/// Element types contained in a block.
+pub fn block_types(block: &PrimitiveBlock) -> PrimitiveType {
+    let mut types = PrimitiveType::empty();
+    for group in &block.primitivegroup {
+        if !group.nodes.is_empty() || group.dense.as_ref().is_some_and(|d| !d.id.is_empty()) {
+            types |= PrimitiveType::NODE;
+        }
+        if !group.ways.is_empty() {
+            types |= PrimitiveType::WAY;
+        }
+        if !group.relations.is_empty() {
+            types |= PrimitiveType::RELATION;
+        }
+        if !group.changesets.is_empty() {
+            types |= PrimitiveType::CHANGE_SET;
+        }
+    }
+    types
+}
+
+/// Runs multiple parallel passes over a file, e.g. a node pass, then a way
+/// pass, then a relation pass.
+///
+/// The first pass reads the whole file and records the offset and the
+/// element types of each block; later passes only read the blocks that
+/// contain the requested types. All passes use the same number of worker
+/// threads and report to the same progress callback.
+pub struct MultiPass<'p, R> {
+    blobs: Blobs<R>,
+    data_start: u64,
+    threads: usize,
+    passes: usize,
+    index: Option<Vec<(u64, PrimitiveType)>>,
+    progress: Option<ProgressFn<'p>>,
+}
+
+type ProgressFn<'p> = Box<dyn FnMut(&PassProgress) + 'p>;
+
+impl<R> fmt::Debug for MultiPass<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiPass")
+            .field("threads", &self.threads)
+            .field("passes", &self.passes)
+            .field("indexed", &self.index.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'p, R: io::BufRead + io::Seek> MultiPass<'p, R> {
+    /// Starts at the current position of `blobs` (usually the first data
+    /// blob).
+    pub fn new(blobs: Blobs<R>) -> Result<Self> {
+        let data_start = blobs.current_offset();
+        Ok(Self {
+            blobs,
+            data_start,
+            threads: worker_count(),
+            passes: 0,
+            index: None,
+            progress: None,
+        })
+    }
+
+    /// Number of worker threads (default: available parallelism).
+    #[inline]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Calls `progress` after each blob that was read.
+    #[inline]
+    pub fn on_progress(mut self, progress: impl FnMut(&PassProgress) + 'p) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    #[inline]
+    pub fn header(&self) -> &HeaderBlock {
+        self.blobs.header()
+    }
+
+    #[inline]
+    pub fn into_blobs(self) -> Blobs<R> {
+        self.blobs
+    }
+
+    /// Decodes the blocks that contain elements of `types` on the worker
+    /// threads, applies `map` to each of them and combines the results with
+    /// `reduce` (see [`Blobs::map_reduce`]).
+    ///
+    /// `map` gets the whole block and has to filter the elements itself.
+    /// Returns `None` when no block contains `types`.
+    pub fn pass<T, M, F>(&mut self, types: PrimitiveType, map: M, reduce: F) -> Result<Option<T>>
+    where
+        T: Send,
+        M: Fn(&PrimitiveBlock) -> T + Sync,
+        F: Fn(T, T) -> T + Sync,
+    {
+        let mut progress = PassProgress {
+            pass: self.passes,
+            types,
+            blobs: 0,
+            total_blobs: None,
+        };
+        self.passes += 1;
+        let limits = self.blobs.limits().clone();
+        let blobs = &mut self.blobs;
+        let callback = &mut self.progress;
+        let mut report = move |progress: &PassProgress| {
+            if let Some(callback) = callback {
+                callback(progress);
+            }
+        };
+
+        if let Some(index) = &self.index {
+            let offsets: Vec<u64> = index
+                .iter()
+                .filter(|(_, t)| t.intersects(types))
+                .map(|(offset, _)| *offset)
+                .collect();
+            progress.total_blobs = Some(offsets.len() as u64);
+            let mut offsets = offsets.into_iter();
+            let source = || {
+                let Some(offset) = offsets.next() else {
+                    return Ok(None);
+                };
+                blobs.seek_to(offset)?;
+                let blob = blobs.next_primitive_block()?;
+                progress.blobs += 1;
+                report(&progress);
+                Ok(blob.map(|b| (progress.blobs, b)))
+            };
+            return map_reduce_blobs(self.threads, &limits, source, |_, b| map(b), reduce);
+        }
+
+        // first pass: read everything and build the index
+        blobs.seek_to(self.data_start)?;
+        let mut offsets = Vec::new();
+        let block_types_by_seq = Mutex::new(Vec::new());
+        let source = || {
+            let offset = blobs.current_offset();
+            let Some(blob) = blobs.next_primitive_block()? else {
+                return Ok(None);
+            };
+            offsets.push(offset);
+            progress.blobs += 1;
+            report(&progress);
+            Ok(Some((offsets.len() as u64 - 1, blob)))
+        };
+        let result = map_reduce_blobs(
+            self.threads,
+            &limits,
+            source,
+            |seq, block| {
+                let t = block_types(block);
+                block_types_by_seq.lock().unwrap().push((seq, t));
+                t.intersects(types).then(|| map(block))
+            },
+            |a, b| match (a, b) {
+                (Some(a), Some(b)) => Some(reduce(a, b)),
+                (a, b) => a.or(b),
+            },
+        )?;
+        let mut block_types_by_seq = block_types_by_seq.into_inner().unwrap();
+        block_types_by_seq.sort_unstable_by_key(|(seq, _)| *seq);
+        self.index = Some(
+            offsets
+                .into_iter()
+                .zip(block_types_by_seq)
+                .map(|(offset, (_, t))| (offset, t))
+                .collect(),
+        );
+        Ok(result.flatten())
+    }
+}
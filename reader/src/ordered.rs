@@ -0,0 +1,179 @@
+//! Parallel decoding that keeps the file order.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::vec;
+
+use crate::blob::Blobs;
+use crate::data::{Element, OSMDataBlob};
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+
+/// A blob with its sequence number.
+type Job = (u64, OSMDataBlob);
+/// The elements of a decoded blob with its sequence number.
+type Decoded = (u64, Result<Vec<Element>>);
+
+/// Elements decoded on worker threads, in file order; see
+/// [`Blobs::par_elements`].
+#[derive(Debug)]
+pub struct OrderedElements<R> {
+    blobs: Blobs<R>,
+    jobs: Option<mpsc::SyncSender<Job>>,
+    results: mpsc::Receiver<Decoded>,
+    workers: Vec<JoinHandle<()>>,
+    window: u64,
+    /// Sequence number of the next read blob.
+    next_read: u64,
+    /// Sequence number of the next yielded blob.
+    next_yield: u64,
+    /// Blobs that were decoded ahead of the next yielded blob.
+    reordered: BTreeMap<u64, Result<Vec<Element>>>,
+    current: vec::IntoIter<Element>,
+    exhausted: bool,
+    failed: bool,
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Decodes the remaining blocks on `workers` threads and yields their
+    /// nodes, ways and relations in file order.
+    ///
+    /// Blobs are read by the calling thread; at most two blobs per worker
+    /// are decoded ahead. Decoded blocks are converted to owned [`Element`]s
+    /// on the workers as well. The iterator stops after the first error.
+    pub fn par_elements(self, workers: usize) -> OrderedElements<R> {
+        let window = self.limits().blocks_in_flight(workers.max(1) * 2);
+        let workers = workers.clamp(1, window);
+        let (jobs, job_receiver) = mpsc::sync_channel::<Job>(window);
+        let (result_sender, results) = mpsc::sync_channel::<Decoded>(window);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let limits = Arc::new(self.limits().clone());
+        let workers = (0..workers)
+            .map(|_| {
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+                let limits = limits.clone();
+                thread::spawn(move || work(&jobs, &results, &limits))
+            })
+            .collect();
+        OrderedElements {
+            blobs: self,
+            jobs: Some(jobs),
+            results,
+            workers,
+            window: window as u64,
+            next_read: 0,
+            next_yield: 0,
+            reordered: BTreeMap::new(),
+            current: Vec::new().into_iter(),
+            exhausted: false,
+            failed: false,
+        }
+    }
+}
+
+fn work(jobs: &Mutex<mpsc::Receiver<Job>>, results: &mpsc::SyncSender<Decoded>, limits: &Limits) {
+    loop {
+        let next = jobs.lock().map(|r| r.recv());
+        let Ok(Ok((seq, mut blob))) = next else {
+            return;
+        };
+        let elements = blob
+            .decode_with_limits(limits)
+            .map(|block| block.primitives().filter_map(|p| p.to_element()).collect());
+        if results.send((seq, elements)).is_err() {
+            return;
+        }
+    }
+}
+
+impl<R: io::BufRead> OrderedElements<R> {
+    /// Reads blobs until the window is full.
+    fn fill(&mut self) {
+        while !self.exhausted && self.next_read - self.next_yield < self.window {
+            let seq = self.next_read;
+            match self.blobs.next_primitive_block() {
+                Ok(Some(blob)) => {
+                    let sent = self
+                        .jobs
+                        .as_ref()
+                        .is_some_and(|j| j.send((seq, blob)).is_ok());
+                    if !sent {
+                        self.exhausted = true;
+                        self.reordered.insert(seq, Err(worker_gone()));
+                    }
+                }
+                Ok(None) => {
+                    self.exhausted = true;
+                    return;
+                }
+                Err(e) => {
+                    // reported after the blobs that were read before
+                    self.exhausted = true;
+                    self.reordered.insert(seq, Err(e));
+                }
+            }
+            self.next_read += 1;
+        }
+    }
+
+    /// Waits for the blob with the next sequence number.
+    fn next_decoded(&mut self) -> Result<Vec<Element>> {
+        let seq = self.next_yield;
+        self.next_yield += 1;
+        loop {
+            if let Some(decoded) = self.reordered.remove(&seq) {
+                return decoded;
+            }
+            match self.results.recv() {
+                Ok((s, decoded)) if s == seq => return decoded,
+                Ok((s, decoded)) => {
+                    self.reordered.insert(s, decoded);
+                }
+                Err(_) => return Err(worker_gone()),
+            }
+        }
+    }
+}
+
+fn worker_gone() -> Error {
+    io::Error::other("decoding worker panicked").into()
+}
+
+impl<R: io::BufRead> Iterator for OrderedElements<R> {
+    type Item = Result<Element>;
+
+    fn next(&mut self) -> Option<Result<Element>> {
+        loop {
+            if let Some(element) = self.current.next() {
+                return Some(Ok(element));
+            }
+            if self.failed {
+                return None;
+            }
+            self.fill();
+            if self.next_yield == self.next_read {
+                return None;
+            }
+            match self.next_decoded() {
+                Ok(elements) => self.current = elements.into_iter(),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl<R> Drop for OrderedElements<R> {
+    fn drop(&mut self) {
+        // stops the workers once they finished their current blob
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
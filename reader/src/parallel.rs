@@ -0,0 +1,163 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+#[cfg(feature = "rayon")]
+mod par_decode;
+
+use osm_pbf_proto::primitives::Primitive;
+
+use crate::blob::Blobs;
+use crate::data::{OSMDataBlob, PrimitiveBlock};
+use crate::error::Result;
+use crate::limits::Limits;
+
+#[cfg(feature = "rayon")]
+pub use self::par_decode::ParDecode;
+
+/// Number of worker threads used by the parallel helpers.
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Decodes all remaining blocks on a pool of worker threads, applies `map`
+    /// to each of them and combines the results with `reduce`.
+    ///
+    /// Blobs are read sequentially from the underlying reader; decoding and
+    /// `map` run in parallel, so `reduce` must be associative and
+    /// commutative. Returns `None` when there are no blocks left.
+    pub fn map_reduce<T, M, F>(&mut self, map: M, reduce: F) -> Result<Option<T>>
+    where
+        T: Send,
+        M: Fn(&PrimitiveBlock) -> T + Sync,
+        F: Fn(T, T) -> T + Sync,
+    {
+        let limits = self.limits().clone();
+        let mut seq = 0;
+        let source = || {
+            let blob = self.next_primitive_block()?;
+            seq += 1;
+            Ok(blob.map(|b| (seq, b)))
+        };
+        map_reduce_blobs(
+            worker_count(),
+            &limits,
+            source,
+            |_, block| map(block),
+            reduce,
+        )
+    }
+
+    /// Applies `map` to each remaining element (nodes, ways and relations)
+    /// on a pool of worker threads and combines the results with `reduce`,
+    /// starting from `identity()` (similar to the `osmpbf` crate).
+    ///
+    /// As with [`Blobs::map_reduce`], `reduce` must be associative and
+    /// commutative.
+    pub fn par_map_reduce<T, M, I, F>(&mut self, map: M, identity: I, reduce: F) -> Result<T>
+    where
+        T: Send,
+        M: Fn(Primitive<'_>) -> T + Sync,
+        I: Fn() -> T + Sync,
+        F: Fn(T, T) -> T + Sync,
+    {
+        let folded = self.map_reduce(
+            |block| block.primitives().map(&map).fold(identity(), &reduce),
+            &reduce,
+        )?;
+        Ok(folded.unwrap_or_else(identity))
+    }
+}
+
+/// Reads blobs from `source`, decodes them on `workers` threads, applies
+/// `map` (with the sequence number given by `source`) and combines the
+/// results with `reduce`.
+pub fn map_reduce_blobs<T, S, M, F>(
+    workers: usize,
+    limits: &Limits,
+    mut source: S,
+    map: M,
+    reduce: F,
+) -> Result<Option<T>>
+where
+    T: Send,
+    S: FnMut() -> Result<Option<(u64, OSMDataBlob)>>,
+    M: Fn(u64, &PrimitiveBlock) -> T + Sync,
+    F: Fn(T, T) -> T + Sync,
+{
+    let (workers, queue) = limits.split_in_flight(workers.max(1), workers * 2);
+    let failed = AtomicBool::new(false);
+    let error = Mutex::new(None);
+    let (sender, receiver) = mpsc::sync_channel::<(u64, OSMDataBlob)>(queue);
+    let receiver = Mutex::new(receiver);
+
+    let worker = || -> Option<T> {
+        let mut acc: Option<T> = None;
+        loop {
+            let next = receiver.lock().map(|r| r.recv());
+            let Ok(Ok((seq, mut blob))) = next else {
+                return acc;
+            };
+            if failed.load(Ordering::Relaxed) {
+                // keep draining the channel, so the reader is not blocked
+                continue;
+            }
+            let value = match blob.decode_with_limits(limits) {
+                Ok(block) => map(seq, block),
+                Err(e) => {
+                    failed.store(true, Ordering::Relaxed);
+                    error.lock().unwrap().get_or_insert(e);
+                    continue;
+                }
+            };
+            acc = Some(match acc {
+                Some(acc) => reduce(acc, value),
+                None => value,
+            });
+        }
+    };
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|_| scope.spawn(worker)).collect();
+
+        let mut read_result = Ok(());
+        while !failed.load(Ordering::Relaxed) {
+            match source() {
+                Ok(Some(blob)) => {
+                    if sender.send(blob).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    // queued blobs are discarded
+                    failed.store(true, Ordering::Relaxed);
+                    read_result = Err(e);
+                    break;
+                }
+            }
+        }
+        drop(sender);
+
+        let mut acc: Option<T> = None;
+        for handle in handles {
+            match handle.join() {
+                Ok(Some(value)) => {
+                    acc = Some(match acc {
+                        Some(acc) => reduce(acc, value),
+                        None => value,
+                    });
+                }
+                Ok(None) => {}
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        read_result?;
+        if let Some(e) = error.lock().unwrap().take() {
+            return Err(e);
+        }
+        Ok(acc)
+    })
+}
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::{mpsc, Arc};
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::Result;
+use crate::limits::Limits;
+
+/// Blocks decoded on the rayon thread pool; see [`Blobs::par_decode`].
+#[derive(Debug)]
+pub struct ParDecode<R> {
+    blobs: Blobs<R>,
+    limits: Arc<Limits>,
+    pending: VecDeque<mpsc::Receiver<Result<PrimitiveBlock>>>,
+    window: usize,
+    done: bool,
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Decodes the remaining blocks on the rayon thread pool.
+    ///
+    /// Blobs are read sequentially by the calling thread; decompression and
+    /// parsing run ahead on the pool (two blocks per thread by default). The
+    /// blocks are yielded in file order.
+    pub fn par_decode(self) -> ParDecode<R> {
+        let window = self
+            .limits()
+            .blocks_in_flight(rayon::current_num_threads() * 2);
+        ParDecode {
+            limits: Arc::new(self.limits().clone()),
+            blobs: self,
+            pending: VecDeque::new(),
+            window,
+            done: false,
+        }
+    }
+}
+
+impl<R> ParDecode<R> {
+    /// Maximum number of blocks that are decoded ahead (capped by
+    /// [`Limits::max_blocks_in_flight`]).
+    #[inline]
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = self.limits.blocks_in_flight(window);
+        self
+    }
+
+    /// Returns the input, positioned after the last read blob. Blocks that
+    /// were decoded ahead are discarded.
+    #[inline]
+    pub fn into_blobs(self) -> Blobs<R> {
+        self.blobs
+    }
+}
+
+impl<R: io::BufRead> Iterator for ParDecode<R> {
+    type Item = Result<PrimitiveBlock>;
+
+    fn next(&mut self) -> Option<Result<PrimitiveBlock>> {
+        while !self.done && self.pending.len() < self.window {
+            let (sender, receiver) = mpsc::sync_channel(1);
+            match self.blobs.next_primitive_block() {
+                Ok(Some(mut blob)) => {
+                    let limits = self.limits.clone();
+                    rayon::spawn(move || {
+                        let block = blob.decode_with_limits(&limits).map(mem::take);
+                        // the iterator may have been dropped
+                        let _ = sender.send(block);
+                    });
+                }
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                Err(e) => {
+                    // reported after the blocks that were read before
+                    self.done = true;
+                    let _ = sender.send(Err(e));
+                }
+            }
+            self.pending.push_back(receiver);
+        }
+        let receiver = self.pending.pop_front()?;
+        Some(receiver.recv().unwrap_or_else(|_| {
+            self.done = true;
+            Err(io::Error::other("decoding task panicked").into())
+        }))
+    }
+}
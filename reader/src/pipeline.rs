@@ -0,0 +1,61 @@
+use std::io;
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::{Error, Result};
+use crate::writer::{PbfWriter, WriterOptions};
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Decodes the remaining blocks, passes each of them to `f` and writes
+    /// the blocks for which `f` returns `true` to `writer`.
+    ///
+    /// `f` may modify the block in place. Blocks that are left without any
+    /// group are dropped as well.
+    pub fn transform_into<W, F>(&mut self, writer: &mut PbfWriter<W>, mut f: F) -> Result<()>
+    where
+        W: io::Write,
+        F: FnMut(&mut PrimitiveBlock) -> Result<bool>,
+    {
+        while let Some(mut block) = self.next_primitive_block_decoded()? {
+            if f(&mut block)? && !block.primitivegroup.is_empty() {
+                writer.write_primitive_block(&block)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the remaining blobs (of all types) to `writer` without
+    /// decoding them and returns their number.
+    ///
+    /// The blobs are written without recompressing them, see
+    /// [`PbfWriter::write_raw_blob`]: their order is checked, and writers
+    /// that sort or re-encode elements are rejected. The pending block of
+    /// `writer` is flushed before.
+    pub fn copy_into<W: io::Write>(&mut self, writer: &mut PbfWriter<W>) -> Result<u64> {
+        let mut count = 0;
+        while let Some((header, blob)) = self.next_blob()? {
+            if header.type_() == "OSMHeader" {
+                return Err(Error::UnexpectedBlobType(header.type_().to_string()));
+            }
+            writer.write_raw_blob(&header, &blob)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Reads all blocks of `input`, transforms them with `f` (see
+/// [`Blobs::transform_into`]) and writes the result with the header of
+/// `input` to `output`.
+///
+/// Returns `output` after the file was finished.
+pub fn transform<R, W, F>(mut input: Blobs<R>, output: W, options: WriterOptions, f: F) -> Result<W>
+where
+    R: io::BufRead,
+    W: io::Write,
+    F: FnMut(&mut PrimitiveBlock) -> Result<bool>,
+{
+    let mut writer = PbfWriter::with_options(output, input.header().clone(), options);
+    input.transform_into(&mut writer, f)?;
+    writer.finish()
+}
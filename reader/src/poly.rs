@@ -0,0 +1,162 @@
+//! Polygons of Osmosis `.poly` files, as used to define regional extracts.
+//!
+//! A file has a name, followed by sections of `lon lat` lines that each end
+//! with `END`, and a final `END`. Sections whose name starts with `!` are
+//! holes.
+
+use std::fs;
+use std::path::Path;
+
+use crate::bounds::Bounds;
+use crate::error::{Error, Result};
+
+/// A ring of a [`Polygon`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ring {
+    pub name: String,
+    /// Locations are inside the polygon unless they are in a hole.
+    pub hole: bool,
+    /// `(nano_lat, nano_lon)` locations; the ring is closed implicitly.
+    pub points: Vec<(i64, i64)>,
+}
+
+impl Ring {
+    /// Even-odd test of a location against the ring.
+    pub fn contains(&self, nano_lat: i64, nano_lon: i64) -> bool {
+        let (lat, lon) = (nano_lat as i128, nano_lon as i128);
+        let mut inside = false;
+        let mut prev = match self.points.last() {
+            Some(&p) => p,
+            None => return false,
+        };
+        for &point in &self.points {
+            let (a_lat, a_lon) = (prev.0 as i128, prev.1 as i128);
+            let (b_lat, b_lon) = (point.0 as i128, point.1 as i128);
+            prev = point;
+            if (a_lat > lat) == (b_lat > lat) {
+                continue;
+            }
+            // whether the edge crosses the parallel of the location east of it
+            let lhs = (lon - a_lon) * (b_lat - a_lat);
+            let rhs = (lat - a_lat) * (b_lon - a_lon);
+            if (b_lat > a_lat && lhs < rhs) || (b_lat < a_lat && lhs > rhs) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+/// The rings of a `.poly` file; see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polygon {
+    pub name: String,
+    rings: Vec<Ring>,
+    bounds: Option<Bounds>,
+}
+
+impl Polygon {
+    pub fn new(name: impl Into<String>, rings: Vec<Ring>) -> Self {
+        let bounds = Bounds::from_locations(
+            rings
+                .iter()
+                .filter(|r| !r.hole)
+                .flat_map(|r| r.points.iter().copied()),
+        );
+        Self {
+            name: name.into(),
+            rings,
+            bounds,
+        }
+    }
+
+    /// Parses the content of a `.poly` file.
+    pub fn parse(poly: &str) -> Result<Self> {
+        let mut lines = poly
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let Some((_, name)) = lines.next() else {
+            return Err(Error::InvalidPoly(1, "missing name"));
+        };
+        let mut rings = Vec::new();
+        loop {
+            let Some((_, section)) = lines.next() else {
+                return Err(Error::InvalidPoly(line_count(poly), "missing END"));
+            };
+            if section == "END" {
+                break;
+            }
+            let (hole, ring_name) = match section.strip_prefix('!') {
+                Some(name) => (true, name),
+                None => (false, section),
+            };
+            let mut points = Vec::new();
+            loop {
+                let Some((line, coords)) = lines.next() else {
+                    return Err(Error::InvalidPoly(
+                        line_count(poly),
+                        "missing END of section",
+                    ));
+                };
+                if coords == "END" {
+                    break;
+                }
+                let mut coords = coords.split_whitespace().map(parse_degrees);
+                match (coords.next(), coords.next(), coords.next()) {
+                    (Some(Some(lon)), Some(Some(lat)), None) => points.push((lat, lon)),
+                    _ => return Err(Error::InvalidPoly(line, "expected `lon lat`")),
+                }
+            }
+            rings.push(Ring {
+                name: ring_name.trim().to_string(),
+                hole,
+                points,
+            });
+        }
+        Ok(Self::new(name, rings))
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    #[inline]
+    pub fn rings(&self) -> &[Ring] {
+        &self.rings
+    }
+
+    /// Bounds of the rings that are not holes.
+    #[inline]
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// `true` when the location is inside a ring and not inside a hole.
+    pub fn contains(&self, nano_lat: i64, nano_lon: i64) -> bool {
+        if !self.bounds.is_some_and(|b| b.contains(nano_lat, nano_lon)) {
+            return false;
+        }
+        let mut inside = false;
+        for ring in &self.rings {
+            if ring.contains(nano_lat, nano_lon) {
+                if ring.hole {
+                    return false;
+                }
+                inside = true;
+            }
+        }
+        inside
+    }
+}
+
+/// Parses degrees (e.g. `8.25` or `1.5E+01`) into nanodegrees.
+fn parse_degrees(s: &str) -> Option<i64> {
+    let degrees: f64 = s.parse().ok()?;
+    degrees.is_finite().then(|| (degrees * 1e9).round() as i64)
+}
+
+fn line_count(s: &str) -> usize {
+    s.lines().count().max(1)
+}
@@ -0,0 +1,110 @@
+//! Reading of blobs ahead on a background thread.
+
+use std::io;
+use std::iter;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+
+use crate::blob::Blobs;
+use crate::data::{OSMDataBlob, PrimitiveBlock};
+use crate::error::Result;
+use crate::limits::Limits;
+
+/// Blobs that are read ahead on an IO thread; see [`Blobs::prefetch`].
+#[derive(Debug)]
+pub struct Prefetch {
+    header: HeaderBlock,
+    limits: Limits,
+    blobs: Option<mpsc::Receiver<Result<OSMDataBlob>>>,
+    worker: Option<JoinHandle<()>>,
+    done: bool,
+}
+
+impl<R: io::BufRead + Send + 'static> Blobs<R> {
+    /// Reads and frames the next `n` blobs on a dedicated IO thread, while
+    /// the caller decodes the current one.
+    ///
+    /// Overlaps the latency of slow inputs (spinning disks, network file
+    /// systems) with the decoding. Blobs are not decompressed by the IO
+    /// thread. `n` is capped by [`Limits::max_blocks_in_flight`].
+    pub fn prefetch(mut self, n: usize) -> Prefetch {
+        let header = self.header().clone();
+        let limits = self.limits().clone();
+        let (sender, blobs) = mpsc::sync_channel(limits.blocks_in_flight(n));
+        let worker = thread::spawn(move || loop {
+            let next = self.next_primitive_block();
+            let stop = !matches!(next, Ok(Some(_)));
+            let Some(next) = next.transpose() else {
+                break;
+            };
+            if sender.send(next).is_err() || stop {
+                break;
+            }
+        });
+        Prefetch {
+            header,
+            limits,
+            blobs: Some(blobs),
+            worker: Some(worker),
+            done: false,
+        }
+    }
+}
+
+impl Prefetch {
+    #[inline]
+    pub fn header(&self) -> &HeaderBlock {
+        &self.header
+    }
+
+    /// The next `OSMData` blob; reading stops after the first error.
+    pub fn next_primitive_block(&mut self) -> Result<Option<OSMDataBlob>> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.blobs.as_ref().map(mpsc::Receiver::recv) {
+            Some(Ok(Ok(blob))) => Ok(Some(blob)),
+            Some(Ok(Err(e))) => {
+                self.done = true;
+                Err(e)
+            }
+            _ => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// The next block, decoded and checked against the limits of the
+    /// reader.
+    pub fn next_primitive_block_decoded(&mut self) -> Result<Option<PrimitiveBlock>> {
+        let Some(mut blob) = self.next_primitive_block()? else {
+            return Ok(None);
+        };
+        blob.decode_with_limits(&self.limits)?;
+        blob.decode_into().map(Some)
+    }
+}
+
+impl Iterator for Prefetch {
+    type Item = Result<OSMDataBlob>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_primitive_block().transpose()
+    }
+}
+
+impl iter::FusedIterator for Prefetch {}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        // lets a worker that waits for space in the channel stop
+        self.blobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
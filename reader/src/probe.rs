@@ -0,0 +1,184 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use osm_pbf_proto::primitives::{Primitive, PrimitiveType};
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::{Error, Result};
+use crate::header::{Feature, HeaderBlock, HeaderFeatures};
+
+/// Kind of data contained in a PBF file.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum FileKind {
+    /// Current state of the data (a planet or an extract).
+    Snapshot,
+    /// Full history, with multiple versions of the same element.
+    History,
+    /// Changes (creations, modifications and deletions) between two states.
+    Change,
+    /// A dump of changesets.
+    ChangesetDump,
+}
+
+impl FileKind {
+    /// Returns an error if the kind is not one of `expected`.
+    pub fn ensure(self, expected: &[Self]) -> Result<()> {
+        if expected.contains(&self) {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedFileKind(self))
+        }
+    }
+
+    #[inline]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Snapshot => "snapshot",
+            Self::History => "history",
+            Self::Change => "change",
+            Self::ChangesetDump => "changeset dump",
+        }
+    }
+}
+
+impl fmt::Display for FileKind {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Summary of the header and the first few data blocks of a file.
+#[derive(Clone, Debug)]
+pub struct Probe {
+    header: HeaderBlock,
+    sampled_blocks: usize,
+    types: PrimitiveType,
+    has_invisible: bool,
+    has_repeated_ids: bool,
+}
+
+impl Probe {
+    /// Number of data blocks that are sampled by default.
+    pub const DEFAULT_SAMPLE_BLOCKS: usize = 4;
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let mut blobs = Blobs::from_path(path)?;
+        Self::from_blobs(&mut blobs, Self::DEFAULT_SAMPLE_BLOCKS)
+    }
+
+    /// Inspects the header and up to `sample_blocks` data blocks.
+    ///
+    /// The sampled blocks are consumed from `blobs`.
+    pub fn from_blobs<R: io::BufRead>(blobs: &mut Blobs<R>, sample_blocks: usize) -> Result<Self> {
+        let mut probe = Self::new(blobs.header().clone());
+        while probe.sampled_blocks < sample_blocks {
+            let Some(block) = blobs.next_primitive_block_decoded()? else {
+                break;
+            };
+            probe.add_block(&block);
+        }
+        Ok(probe)
+    }
+
+    fn new(header: HeaderBlock) -> Self {
+        Self {
+            header,
+            sampled_blocks: 0,
+            types: PrimitiveType::empty(),
+            has_invisible: false,
+            has_repeated_ids: false,
+        }
+    }
+
+    fn add_block(&mut self, block: &PrimitiveBlock) {
+        self.sampled_blocks += 1;
+        let mut last = None;
+        for p in block.primitives().filter_types(PrimitiveType::all()) {
+            let (t, id, visible) = match &p {
+                Primitive::Node(n) => (PrimitiveType::NODE, n.id, n.info().visible),
+                Primitive::Way(w) => (PrimitiveType::WAY, w.id(), w.info.visible),
+                Primitive::Relation(r) => (PrimitiveType::RELATION, r.id(), r.info.visible),
+                Primitive::ChangeSet(c) => (PrimitiveType::CHANGE_SET, c.id(), None),
+                _ => continue,
+            };
+            self.types |= t;
+            self.has_invisible |= visible == Some(false);
+            self.has_repeated_ids |= last == Some((t, id));
+            last = Some((t, id));
+        }
+    }
+
+    #[inline]
+    pub fn header(&self) -> &HeaderBlock {
+        &self.header
+    }
+
+    /// Number of data blocks that were inspected.
+    #[inline]
+    pub fn sampled_blocks(&self) -> usize {
+        self.sampled_blocks
+    }
+
+    /// Element types found in the sampled blocks.
+    #[inline]
+    pub fn primitive_types(&self) -> PrimitiveType {
+        self.types
+    }
+
+    #[inline]
+    pub fn has_locations_on_ways(&self) -> bool {
+        self.header.has_feature(&Feature::LocationsOnWays)
+    }
+
+    pub fn file_kind(&self) -> FileKind {
+        let historical = self.header.has_feature(&Feature::HistoricalInformation);
+        if self.types == PrimitiveType::CHANGE_SET {
+            FileKind::ChangesetDump
+        } else if historical || self.has_repeated_ids {
+            FileKind::History
+        } else if self.has_invisible {
+            FileKind::Change
+        } else {
+            FileKind::Snapshot
+        }
+    }
+}
+
+/// Determines the kind of a file from the blocks that are read anyway, for
+/// APIs that only support some kinds (see [`FileKind::ensure`]).
+#[derive(Clone, Debug)]
+pub(crate) struct KindProbe {
+    probe: Option<Probe>,
+    sample_blocks: usize,
+}
+
+impl KindProbe {
+    pub(crate) fn new(header: &HeaderBlock, sample_blocks: usize) -> Self {
+        Self {
+            probe: Some(Probe::new(header.clone())),
+            sample_blocks,
+        }
+    }
+
+    /// Samples `block`; returns the kind once `sample_blocks` blocks were
+    /// sampled.
+    pub(crate) fn sample(&mut self, block: &PrimitiveBlock) -> Option<FileKind> {
+        let probe = self.probe.as_mut()?;
+        probe.add_block(block);
+        if probe.sampled_blocks < self.sample_blocks {
+            return None;
+        }
+        self.finish()
+    }
+
+    /// The kind from the blocks sampled so far, at the end of a file with
+    /// fewer blocks; `None` when no block was sampled or the kind was
+    /// already returned.
+    pub(crate) fn finish(&mut self) -> Option<FileKind> {
+        let probe = self.probe.take()?;
+        (probe.sampled_blocks > 0).then(|| probe.file_kind())
+    }
+}
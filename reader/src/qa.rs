@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::{fmt, io};
+
+use osm_pbf_proto::primitives::{Primitive, PrimitiveType};
+
+use crate::blob::Blobs;
+use crate::data::{OwnedTags, PrimitiveBlock};
+use crate::error::{Error, Result};
+use crate::writer::type_name;
+
+/// A way location that differs from the location of its node.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LocationDrift {
+    pub way_id: i64,
+    pub node_id: i64,
+    /// `(nano_lat, nano_lon)` stored on the way.
+    pub way_location: (i64, i64),
+    /// `(nano_lat, nano_lon)` of the node.
+    pub node_location: (i64, i64),
+}
+
+impl LocationDrift {
+    /// Largest difference of latitude or longitude in nanodegrees.
+    #[inline]
+    pub fn distance(&self) -> i64 {
+        let lat = (self.way_location.0 - self.node_location.0).abs();
+        let lon = (self.way_location.1 - self.node_location.1).abs();
+        lat.max(lon)
+    }
+}
+
+/// Result of a [`LocationsOnWaysCheck`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LocationReport {
+    /// Ways with locations.
+    pub ways_checked: u64,
+    /// Compared way locations.
+    pub locations_checked: u64,
+    /// Ways without locations, or with a different number of locations and
+    /// node refs.
+    pub ways_without_locations: u64,
+    /// Node refs that were not found (before the way) in the file.
+    pub missing_nodes: u64,
+    /// Locations that differ by more than the tolerance.
+    pub drifted: Vec<LocationDrift>,
+}
+
+impl LocationReport {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.drifted.is_empty() && self.missing_nodes == 0 && self.ways_without_locations == 0
+    }
+}
+
+/// Compares the node locations stored on ways (`LocationsOnWays`) with the
+/// locations of the nodes in the same file.
+///
+/// Node locations are kept in memory. Nodes are expected before the ways
+/// referencing them (as in files sorted by type and id).
+#[derive(Clone, Debug, Default)]
+pub struct LocationsOnWaysCheck {
+    tolerance: i64,
+    nodes: HashMap<i64, (i64, i64)>,
+    report: LocationReport,
+}
+
+impl LocationsOnWaysCheck {
+    /// Reports locations that differ by more than `tolerance` nanodegrees
+    /// (in latitude or longitude).
+    pub fn new(tolerance: i64) -> Self {
+        Self {
+            tolerance,
+            ..Self::default()
+        }
+    }
+
+    /// Checks all remaining blocks of `blobs`.
+    pub fn check<R: io::BufRead>(mut self, blobs: &mut Blobs<R>) -> Result<LocationReport> {
+        while let Some(block) = blobs.next_primitive_block_decoded()? {
+            self.add_block(&block);
+        }
+        Ok(self.finish())
+    }
+
+    pub fn add_block(&mut self, block: &PrimitiveBlock) {
+        for p in block.primitives() {
+            match &p {
+                Primitive::Node(n) => {
+                    self.nodes.insert(n.id, (n.nano_lat, n.nano_lon));
+                }
+                Primitive::Way(w) => {
+                    let locations = w.locations();
+                    if locations.len() == 0 || locations.len() != w.refs.len() {
+                        self.report.ways_without_locations += 1;
+                        continue;
+                    }
+                    self.report.ways_checked += 1;
+                    for (node_id, way_location) in w.refs().zip(locations) {
+                        self.report.locations_checked += 1;
+                        let Some(&node_location) = self.nodes.get(&node_id) else {
+                            self.report.missing_nodes += 1;
+                            continue;
+                        };
+                        let drift = LocationDrift {
+                            way_id: w.id(),
+                            node_id,
+                            way_location,
+                            node_location,
+                        };
+                        if drift.distance() > self.tolerance {
+                            self.report.drifted.push(drift);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[inline]
+    pub fn finish(self) -> LocationReport {
+        self.report
+    }
+}
+
+/// Maximum length (in characters) of tag keys and values accepted by the OSM
+/// API.
+pub const MAX_TAG_LENGTH: usize = 255;
+
+/// A violation of the tag limits of the OSM API.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TagProblem {
+    KeyTooLong,
+    ValueTooLong,
+    ControlCharacter,
+}
+
+impl TagProblem {
+    /// The first problem of a tag, if any.
+    pub fn of(key: &str, value: &str) -> Option<Self> {
+        if too_long(key) {
+            Some(Self::KeyTooLong)
+        } else if too_long(value) {
+            Some(Self::ValueTooLong)
+        } else if key.chars().chain(value.chars()).any(char::is_control) {
+            Some(Self::ControlCharacter)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for TagProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyTooLong => write!(f, "key is longer than {MAX_TAG_LENGTH} characters"),
+            Self::ValueTooLong => write!(f, "value is longer than {MAX_TAG_LENGTH} characters"),
+            Self::ControlCharacter => f.write_str("contains control characters"),
+        }
+    }
+}
+
+#[inline]
+fn too_long(s: &str) -> bool {
+    // byte length is an upper bound of the number of characters
+    s.len() > MAX_TAG_LENGTH && s.chars().count() > MAX_TAG_LENGTH
+}
+
+fn sanitize(s: &mut String) {
+    if s.chars().any(char::is_control) {
+        s.retain(|c| !c.is_control());
+    }
+    if let Some((end, _)) = s.char_indices().nth(MAX_TAG_LENGTH) {
+        s.truncate(end);
+    }
+}
+
+/// How tags that violate the limits of the OSM API are handled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TagPolicy {
+    /// Tags are not checked.
+    #[default]
+    Allow,
+    /// Control characters are removed and keys and values are truncated to
+    /// [`MAX_TAG_LENGTH`] characters.
+    Truncate,
+    /// Invalid tags are removed.
+    Skip,
+    /// Invalid tags are reported as [`Error::InvalidTag`].
+    Error,
+}
+
+impl TagPolicy {
+    /// Applies the policy to the tags of an element.
+    pub fn apply(self, t: PrimitiveType, id: i64, tags: &mut OwnedTags) -> Result<()> {
+        match self {
+            Self::Allow => {}
+            Self::Truncate => {
+                for (k, v) in tags.iter_mut() {
+                    if TagProblem::of(k, v).is_some() {
+                        sanitize(k);
+                        sanitize(v);
+                    }
+                }
+            }
+            Self::Skip => tags.retain(|(k, v)| TagProblem::of(k, v).is_none()),
+            Self::Error => {
+                if let Some((k, problem)) = tags
+                    .iter()
+                    .find_map(|(k, v)| Some((k, TagProblem::of(k, v)?)))
+                {
+                    return Err(Error::InvalidTag(type_name(t), id, k.clone(), problem));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A tag that violates the limits of the OSM API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagViolation {
+    pub element_type: PrimitiveType,
+    pub id: i64,
+    pub key: String,
+    pub problem: TagProblem,
+}
+
+/// Checks the tags of nodes, ways and relations against the limits of the
+/// OSM API.
+#[derive(Clone, Debug, Default)]
+pub struct TagCheck {
+    violations: Vec<TagViolation>,
+}
+
+impl TagCheck {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks all remaining blocks of `blobs`.
+    pub fn check<R: io::BufRead>(mut self, blobs: &mut Blobs<R>) -> Result<Vec<TagViolation>> {
+        while let Some(block) = blobs.next_primitive_block_decoded()? {
+            self.add_block(&block);
+        }
+        Ok(self.finish())
+    }
+
+    pub fn add_block(&mut self, block: &PrimitiveBlock) {
+        for p in block.primitives() {
+            let (element_type, id, tags) = match &p {
+                Primitive::Node(n) => (PrimitiveType::NODE, n.id, n.tags()),
+                Primitive::Way(w) => (PrimitiveType::WAY, w.id(), w.tags()),
+                Primitive::Relation(r) => (PrimitiveType::RELATION, r.id(), r.tags()),
+                _ => continue,
+            };
+            for (k, v) in tags {
+                if let Some(problem) = TagProblem::of(k, v) {
+                    self.violations.push(TagViolation {
+                        element_type,
+                        id,
+                        key: k.to_string(),
+                        problem,
+                    });
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn finish(self) -> Vec<TagViolation> {
+        self.violations
+    }
+}
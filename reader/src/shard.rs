@@ -0,0 +1,236 @@
+//! Partitioning of elements by id for sharded processing.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::Path;
+
+use osm_pbf_proto::element::{Element, OwnedChangeSet};
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::Result;
+use crate::writer::{FileSink, PbfWriter, WriterOptions};
+
+/// How ids are assigned to shards.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Partitioning {
+    /// Stable hash of the element type and id. Balanced for any id
+    /// distribution and independent of the platform and crate version.
+    #[default]
+    Hash,
+    /// Consecutive id ranges of equal width within `min..=max`; ids outside
+    /// go to the first or last shard. Sorted inputs give sorted shards.
+    Range { min: i64, max: i64 },
+}
+
+/// Assigns elements to one of `shards` partitions by their id.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShardById {
+    shards: usize,
+    partitioning: Partitioning,
+}
+
+/// Partitions elements into `shards` shards by a stable hash of their id.
+#[inline]
+pub fn shard_by_id(shards: usize) -> ShardById {
+    ShardById::new(shards)
+}
+
+impl ShardById {
+    /// `shards` below `1` are treated as `1`.
+    #[inline]
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: shards.max(1),
+            partitioning: Partitioning::Hash,
+        }
+    }
+
+    #[inline]
+    pub fn partitioning(mut self, partitioning: Partitioning) -> Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.shards
+    }
+
+    /// Shard of the element with type `t` and `id`.
+    pub fn shard_of_id(&self, t: PrimitiveType, id: i64) -> usize {
+        let shards = self.shards as u64;
+        match self.partitioning {
+            Partitioning::Hash => (mix(id as u64 ^ ((t.bits() as u64) << 56)) % shards) as usize,
+            Partitioning::Range { min, max } => {
+                if id <= min || max <= min {
+                    return 0;
+                }
+                let width = (max as i128 - min as i128 + 1) as u128;
+                let offset = (id.min(max) as i128 - min as i128) as u128;
+                (offset * shards as u128 / width) as usize
+            }
+        }
+    }
+
+    #[inline]
+    pub fn shard_of(&self, element: &Element) -> usize {
+        self.shard_of_id(element.primitive_type(), element.id())
+    }
+}
+
+/// `splitmix64` finalizer.
+#[inline]
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The elements of one shard of a file; see [`Blobs::shard`].
+#[derive(Debug)]
+pub struct ShardElements<'a, R> {
+    blobs: &'a mut Blobs<R>,
+    sharding: ShardById,
+    shard: usize,
+    pending: VecDeque<Element>,
+}
+
+impl<R: io::BufRead> Iterator for ShardElements<'_, R> {
+    type Item = Result<Element>;
+
+    fn next(&mut self) -> Option<Result<Element>> {
+        loop {
+            if let Some(element) = self.pending.pop_front() {
+                return Some(Ok(element));
+            }
+            let block = match self.blobs.next_primitive_block_decoded() {
+                Ok(Some(block)) => block,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            let (sharding, shard) = (self.sharding, self.shard);
+            self.pending.extend(
+                block
+                    .primitives()
+                    .filter_map(|p| p.to_element())
+                    .filter(|e| sharding.shard_of(e) == shard),
+            );
+        }
+    }
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Iterates the remaining elements that belong to `shard`.
+    ///
+    /// Each consumer reads the whole file and keeps its own partition, so
+    /// independent workers can process the shards of a file in parallel.
+    pub fn shard(&mut self, sharding: ShardById, shard: usize) -> ShardElements<'_, R> {
+        ShardElements {
+            blobs: self,
+            sharding,
+            shard,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// Writes one output per shard.
+#[derive(Debug)]
+pub struct ShardedWriter<W> {
+    sharding: ShardById,
+    writers: Vec<PbfWriter<W>>,
+}
+
+impl ShardedWriter<FileSink> {
+    /// Creates the output of each shard at `path(shard)`.
+    pub fn create<P: AsRef<Path>>(
+        sharding: ShardById,
+        path: impl Fn(usize) -> P,
+        header: &HeaderBlock,
+        options: &WriterOptions,
+    ) -> Result<Self> {
+        let writers = (0..sharding.shards())
+            .map(|shard| {
+                PbfWriter::create_with_options(path(shard), header.clone(), options.clone())
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { sharding, writers })
+    }
+
+    /// Finishes and commits the outputs of all shards (see
+    /// [`PbfWriter::finish_and_commit`]).
+    pub fn finish_and_commit(self) -> Result<()> {
+        self.writers
+            .into_iter()
+            .try_for_each(PbfWriter::finish_and_commit)
+    }
+}
+
+impl<W: Write> ShardedWriter<W> {
+    /// Creates a writer for each of the `outputs` (one per shard).
+    ///
+    /// # Panics
+    ///
+    /// When the number of outputs differs from the number of shards.
+    pub fn new(
+        sharding: ShardById,
+        outputs: impl IntoIterator<Item = W>,
+        header: &HeaderBlock,
+        options: &WriterOptions,
+    ) -> Self {
+        let writers: Vec<_> = outputs
+            .into_iter()
+            .map(|w| PbfWriter::with_options(w, header.clone(), options.clone()))
+            .collect();
+        assert_eq!(writers.len(), sharding.shards(), "one output per shard");
+        Self { sharding, writers }
+    }
+
+    #[inline]
+    pub fn sharding(&self) -> &ShardById {
+        &self.sharding
+    }
+
+    /// The writer of `shard`.
+    #[inline]
+    pub fn shard_mut(&mut self, shard: usize) -> Option<&mut PbfWriter<W>> {
+        self.writers.get_mut(shard)
+    }
+
+    #[inline]
+    pub fn write_element(&mut self, element: &Element) -> Result<()> {
+        let shard = self.sharding.shard_of(element);
+        self.writers[shard].write_element(element)
+    }
+
+    #[inline]
+    pub fn write_changeset(&mut self, changeset: &OwnedChangeSet) -> Result<()> {
+        let shard = self
+            .sharding
+            .shard_of_id(PrimitiveType::CHANGE_SET, changeset.id);
+        self.writers[shard].write_changeset(changeset)
+    }
+
+    /// Distributes the elements of `block` to the shards.
+    pub fn write_primitive_block(&mut self, block: &PrimitiveBlock) -> Result<()> {
+        for p in block.primitives().filter_types(PrimitiveType::all()) {
+            match p.to_element() {
+                Some(element) => self.write_element(&element)?,
+                None => {
+                    if let Some(changeset) = p.to_changeset() {
+                        self.write_changeset(&changeset)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes all shards and returns their outputs in shard order.
+    pub fn finish(self) -> Result<Vec<W>> {
+        self.writers.into_iter().map(PbfWriter::finish).collect()
+    }
+}
@@ -0,0 +1,219 @@
+//! Random access to inputs that are not local files.
+//!
+//! A [`BlobSource`] reads byte ranges of an input (e.g. a remote file over
+//! HTTP with the `http` feature, or in a cloud bucket with the
+//! `object_store` feature). Single blobs are read with
+//! [`BlobSource::read_blob_at`], e.g. at the offsets of an index, and
+//! [`SourceReader`] turns a source into a [`Blobs`] input.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use bytes::Bytes;
+use osm_pbf_proto::protobuf::CodedInputStream;
+
+use crate::blob::{merge_lenient, Blobs, PbfBlob, PbfBlobHeader};
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "object_store")]
+mod object_store;
+
+#[cfg(feature = "http")]
+pub use self::http::HttpSource;
+#[cfg(feature = "object_store")]
+pub use self::object_store::ObjectStoreSource;
+
+/// Bytes read with the size of a blob header, so that the header of most
+/// blobs needs a single read.
+const HEADER_PROBE: usize = 256;
+
+/// Default number of bytes fetched at once by a [`SourceReader`].
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// An input that can be read at arbitrary offsets.
+pub trait BlobSource {
+    /// Length of the input in bytes.
+    fn size(&self) -> Result<u64>;
+
+    /// Reads `len` bytes at `offset`; less only at the end of the input.
+    fn read_at(&self, offset: u64, len: usize) -> Result<Bytes>;
+
+    /// Reads the blob that starts at `offset`. Returns the header, the blob
+    /// and the offset of the next blob.
+    fn read_blob_at(&self, offset: u64, limits: &Limits) -> Result<(PbfBlobHeader, PbfBlob, u64)> {
+        let probe = self.read_at(offset, HEADER_PROBE)?;
+        let Some(size) = probe.get(..4) else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        let header_size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]);
+        if header_size > limits.max_header_size {
+            return Err(Error::BlobHeaderToLarge);
+        }
+        let header_end = 4 + header_size as usize;
+        let header = match probe.get(4..header_end) {
+            Some(_) => probe.slice(4..header_end),
+            None => self.read_exact_at(offset + 4, header_size as usize)?,
+        };
+        let header: PbfBlobHeader =
+            merge_lenient(&mut CodedInputStream::from_tokio_bytes(&header))?;
+        let data_size = header.datasize() as usize;
+        if data_size > limits.max_blob_size {
+            return Err(limits.size_error());
+        }
+        let data_offset = offset + header_end as u64;
+        let data = self.read_exact_at(data_offset, data_size)?;
+        let blob: PbfBlob = merge_lenient(&mut CodedInputStream::from_tokio_bytes(&data))?;
+        limits.check_blob(&blob)?;
+        Ok((header, blob, data_offset + data_size as u64))
+    }
+
+    /// Reads exactly `len` bytes at `offset`.
+    fn read_exact_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        let bytes = self.read_at(offset, len)?;
+        if bytes.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+}
+
+impl BlobSource for Bytes {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.len());
+        let end = start.saturating_add(len).min(self.len());
+        Ok(self.slice(start..end))
+    }
+}
+
+impl<S: BlobSource + ?Sized> BlobSource for &S {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+
+    #[inline]
+    fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        (**self).read_at(offset, len)
+    }
+}
+
+/// A buffered reader over a [`BlobSource`], that fetches chunks of
+/// [`DEFAULT_CHUNK_SIZE`] bytes.
+#[derive(Debug)]
+pub struct SourceReader<S> {
+    source: S,
+    chunk_size: usize,
+    /// Position of the start of `buf` in the input.
+    pos: u64,
+    buf: Bytes,
+}
+
+impl<S: BlobSource> SourceReader<S> {
+    #[inline]
+    pub fn new(source: S) -> Self {
+        Self::with_chunk_size(source, DEFAULT_CHUNK_SIZE)
+    }
+
+    #[inline]
+    pub fn with_chunk_size(source: S, chunk_size: usize) -> Self {
+        Self {
+            source,
+            chunk_size: chunk_size.max(1),
+            pos: 0,
+            buf: Bytes::new(),
+        }
+    }
+
+    #[inline]
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    #[inline]
+    pub fn into_source(self) -> S {
+        self.source
+    }
+}
+
+fn into_io_error(e: Error) -> io::Error {
+    match e {
+        Error::IoError(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+impl<S: BlobSource> Read for SourceReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<S: BlobSource> BufRead for SourceReader<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buf.is_empty() {
+            self.buf = self
+                .source
+                .read_at(self.pos, self.chunk_size)
+                .map_err(into_io_error)?;
+        }
+        Ok(&self.buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.buf.len());
+        self.pos += amt as u64;
+        let _ = self.buf.split_to(amt);
+    }
+}
+
+impl<S: BlobSource> Seek for SourceReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                let size = self.source.size().map_err(into_io_error)?;
+                size.checked_add_signed(delta)
+            }
+        };
+        let Some(target) = target else {
+            return Err(io::ErrorKind::InvalidInput.into());
+        };
+        // keeps the buffer when seeking within it
+        match target.checked_sub(self.pos) {
+            Some(skip) if skip < self.buf.len() as u64 => self.consume(skip as usize),
+            _ => {
+                self.pos = target;
+                self.buf = Bytes::new();
+            }
+        }
+        Ok(target)
+    }
+
+    #[inline]
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+impl<S: BlobSource> Blobs<SourceReader<S>> {
+    /// Reads the blobs of `source` in chunks of [`DEFAULT_CHUNK_SIZE`]
+    /// bytes.
+    #[inline]
+    pub fn from_source(source: S) -> Result<Self> {
+        Self::from_buf_read(SourceReader::new(source))
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+use std::sync::OnceLock;
+
+use bytes::Bytes;
+
+use super::BlobSource;
+use crate::error::Result;
+
+/// A remote file that is read with HTTP range requests.
+///
+/// The server must answer range requests with `206 Partial Content`;
+/// servers that send the whole file instead are reported as an error.
+#[derive(Debug)]
+pub struct HttpSource {
+    url: String,
+    agent: ureq::Agent,
+    size: OnceLock<u64>,
+}
+
+fn http_error(e: ureq::Error) -> io::Error {
+    e.into_io()
+}
+
+impl HttpSource {
+    #[inline]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_agent(url, ureq::Agent::new_with_defaults())
+    }
+
+    /// Uses `agent` for the requests, e.g. for proxies or timeouts.
+    #[inline]
+    pub fn with_agent(url: impl Into<String>, agent: ureq::Agent) -> Self {
+        Self {
+            url: url.into(),
+            agent,
+            size: OnceLock::new(),
+        }
+    }
+
+    #[inline]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl BlobSource for HttpSource {
+    fn size(&self) -> Result<u64> {
+        if let Some(size) = self.size.get() {
+            return Ok(*size);
+        }
+        let response = self.agent.head(&self.url).call().map_err(http_error)?;
+        let size = response
+            .headers()
+            .get("content-length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| io::Error::other("missing Content-Length"))?;
+        Ok(*self.size.get_or_init(|| size))
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+        let last = offset.saturating_add(len as u64 - 1);
+        let response = self
+            .agent
+            .get(&self.url)
+            .header("Range", format!("bytes={offset}-{last}"))
+            .call();
+        let mut response = match response {
+            Ok(response) => response,
+            // the range starts at or after the end of the file
+            Err(ureq::Error::StatusCode(416)) => return Ok(Bytes::new()),
+            Err(e) => return Err(http_error(e).into()),
+        };
+        if response.status() != 206 {
+            return Err(io::Error::other("the server does not support range requests").into());
+        }
+        let data = response
+            .body_mut()
+            .with_config()
+            .limit(len as u64 + 1)
+            .read_to_vec()
+            .map_err(http_error)?;
+        Ok(Bytes::from(data))
+    }
+}
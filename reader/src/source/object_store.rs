@@ -0,0 +1,76 @@
+use std::io;
+use std::sync::{Arc, OnceLock};
+
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::{ObjectStore, ObjectStoreExt};
+use tokio::runtime::Handle;
+
+use super::BlobSource;
+use crate::error::Result;
+
+/// An object of an [`ObjectStore`] (e.g. S3, GCS or Azure), read with range
+/// requests.
+///
+/// The requests are run on the tokio runtime of `handle`; the source must
+/// be read outside of that runtime, e.g. in `spawn_blocking` or on a
+/// [`Prefetch`](crate::Prefetch) thread.
+#[derive(Debug)]
+pub struct ObjectStoreSource {
+    store: Arc<dyn ObjectStore>,
+    location: Path,
+    handle: Handle,
+    size: OnceLock<u64>,
+}
+
+fn store_error(e: object_store::Error) -> io::Error {
+    match e {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, e),
+        e => io::Error::other(e),
+    }
+}
+
+impl ObjectStoreSource {
+    #[inline]
+    pub fn new(store: Arc<dyn ObjectStore>, location: impl Into<Path>, handle: Handle) -> Self {
+        Self {
+            store,
+            location: location.into(),
+            handle,
+            size: OnceLock::new(),
+        }
+    }
+
+    #[inline]
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+}
+
+impl BlobSource for ObjectStoreSource {
+    fn size(&self) -> Result<u64> {
+        if let Some(size) = self.size.get() {
+            return Ok(*size);
+        }
+        let meta = self
+            .handle
+            .block_on(self.store.head(&self.location))
+            .map_err(store_error)?;
+        Ok(*self.size.get_or_init(|| meta.size))
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        // stores reject ranges that start after the end of the object
+        let size = self.size()?;
+        let start = offset.min(size);
+        let end = offset.saturating_add(len as u64).min(size);
+        if start == end {
+            return Ok(Bytes::new());
+        }
+        let bytes = self
+            .handle
+            .block_on(self.store.get_range(&self.location, start..end))
+            .map_err(store_error)?;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,86 @@
+//! Splitting of files into blob-aligned chunks for distributed processing.
+
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::blob::Blobs;
+use crate::error::Result;
+use crate::writer::PbfWriter;
+
+/// Size of the chunks of [`Blobs::split`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SplitTarget {
+    /// About this number of chunks of equal size; less when the file has
+    /// fewer blobs.
+    Chunks(usize),
+    /// Chunks of at least this number of bytes (except the last one).
+    Size(u64),
+}
+
+impl<R: io::BufRead + io::Seek> Blobs<R> {
+    /// Splits the data blobs of the file into byte ranges that start and end
+    /// at blob boundaries.
+    ///
+    /// A range is read by seeking a reader of the same file to its start
+    /// (see [`Blobs::seek_to`]) and reading while [`Blobs::current_offset`]
+    /// is before its end. Only the blob headers are read; the reader is left
+    /// at the end of the file.
+    pub fn split(&mut self, target: SplitTarget) -> Result<Vec<Range<u64>>> {
+        self.rewind()?;
+        let start = self.current_offset();
+        let offsets = self.data_blob_offsets()?;
+        let end = self.current_offset();
+        if offsets.is_empty() {
+            return Ok(Vec::new());
+        }
+        // blobs before the first data blob belong to the first chunk
+        let mut cuts = vec![start];
+        match target {
+            SplitTarget::Size(size) => {
+                for &offset in &offsets[1..] {
+                    if offset - cuts[cuts.len() - 1] >= size {
+                        cuts.push(offset);
+                    }
+                }
+            }
+            SplitTarget::Chunks(chunks) => {
+                let chunks = chunks.max(1) as u128;
+                let total = (end - start) as u128;
+                for i in 1..chunks {
+                    let ideal = start + (total * i / chunks) as u64;
+                    let next = offsets.partition_point(|&o| o < ideal);
+                    match offsets.get(next) {
+                        Some(&offset) if offset > cuts[cuts.len() - 1] => cuts.push(offset),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        cuts.push(end);
+        Ok(cuts.windows(2).map(|w| w[0]..w[1]).collect())
+    }
+
+    /// Writes the chunks of [`Blobs::split`] as complete files at
+    /// `path(chunk)`, each with the header block of this file. The blobs
+    /// are copied without decoding. Returns the byte ranges of the chunks.
+    pub fn split_into_files<P: AsRef<Path>>(
+        &mut self,
+        target: SplitTarget,
+        path: impl Fn(usize) -> P,
+    ) -> Result<Vec<Range<u64>>> {
+        let ranges = self.split(target)?;
+        for (chunk, range) in ranges.iter().enumerate() {
+            let mut writer = PbfWriter::create(path(chunk), self.header().clone())?;
+            self.seek_to(range.start)?;
+            while self.current_offset() < range.end {
+                let Some((header, blob)) = self.next_blob()? else {
+                    break;
+                };
+                writer.write_raw_blob(&header, &blob)?;
+            }
+            writer.finish_and_commit()?;
+        }
+        Ok(ranges)
+    }
+}
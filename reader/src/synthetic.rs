@@ -0,0 +1,551 @@
+//! Generator of synthetic OSM data for tests and benchmarks.
+//!
+//! The data mimics the structure of real files: nodes are clustered around
+//! "cities" of different sizes, ways are short paths or building outlines
+//! of consecutive nodes, some ways share junction nodes, buildings are
+//! grouped into multipolygon relations, and tag keys and values follow zipf
+//! distributions.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+
+use crate::data::{
+    Element, MemberType, OwnedInfo, OwnedMember, OwnedNode, OwnedRelation, OwnedTags, OwnedWay,
+};
+use crate::error::Result;
+use crate::header::HeaderBlock;
+use crate::writer::{PbfWriter, WriterOptions};
+
+/// Keys of way tags with their values, in order of decreasing frequency.
+const WAY_TAGS: &[(&str, &[&str])] = &[
+    (
+        "highway",
+        &[
+            "residential",
+            "service",
+            "track",
+            "footway",
+            "unclassified",
+            "path",
+            "tertiary",
+            "secondary",
+            "primary",
+        ],
+    ),
+    ("waterway", &["stream", "ditch", "river", "drain", "canal"]),
+    ("railway", &["rail", "abandoned", "tram", "disused"]),
+    ("barrier", &["fence", "wall", "hedge", "retaining_wall"]),
+    ("power", &["line", "minor_line", "cable"]),
+];
+
+/// Additional tags of any element, in order of decreasing frequency.
+const EXTRA_TAGS: &[(&str, &[&str])] = &[
+    (
+        "source",
+        &["survey", "bing", "landsat", "gps", "local_knowledge"],
+    ),
+    (
+        "surface",
+        &["asphalt", "unpaved", "paved", "ground", "gravel"],
+    ),
+    ("oneway", &["yes", "no", "-1"]),
+    (
+        "access",
+        &["private", "yes", "no", "permissive", "destination"],
+    ),
+    ("maxspeed", &["50", "30", "60", "40", "80", "100"]),
+    ("lit", &["yes", "no"]),
+    ("lanes", &["2", "1", "3", "4"]),
+    ("layer", &["1", "-1", "2"]),
+];
+
+/// Tags of points of interest, in order of decreasing frequency.
+const POI_TAGS: &[(&str, &[&str])] = &[
+    (
+        "amenity",
+        &[
+            "parking",
+            "bench",
+            "restaurant",
+            "place_of_worship",
+            "school",
+            "cafe",
+            "fast_food",
+            "bank",
+        ],
+    ),
+    (
+        "shop",
+        &[
+            "convenience",
+            "supermarket",
+            "clothes",
+            "hairdresser",
+            "bakery",
+        ],
+    ),
+    ("natural", &["tree", "peak", "spring", "rock"]),
+    (
+        "tourism",
+        &["information", "hotel", "viewpoint", "attraction"],
+    ),
+];
+
+const LANDUSE: &[&str] = &[
+    "residential",
+    "farmland",
+    "grass",
+    "meadow",
+    "forest",
+    "industrial",
+    "retail",
+];
+
+/// Settings of a synthetic file. All outputs are deterministic for a seed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntheticPlanet {
+    seed: u64,
+    nodes: u64,
+    clusters: usize,
+    poi_ratio: f64,
+    building_ratio: f64,
+    relation_ratio: f64,
+    metadata: bool,
+}
+
+impl Default for SyntheticPlanet {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            nodes: 100_000,
+            clusters: 100,
+            poi_ratio: 0.05,
+            building_ratio: 0.5,
+            relation_ratio: 0.05,
+            metadata: true,
+        }
+    }
+}
+
+impl SyntheticPlanet {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Approximate number of nodes (100 000 by default). There are about 6
+    /// nodes per way and one relation per 40 ways.
+    #[inline]
+    pub fn nodes(mut self, nodes: u64) -> Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Number of node clusters ("cities"); their sizes follow a zipf
+    /// distribution.
+    #[inline]
+    pub fn clusters(mut self, clusters: usize) -> Self {
+        self.clusters = clusters.max(1);
+        self
+    }
+
+    /// Share of tagged standalone nodes (points of interest).
+    #[inline]
+    pub fn poi_ratio(mut self, ratio: f64) -> Self {
+        self.poi_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Share of ways that are closed building outlines.
+    #[inline]
+    pub fn building_ratio(mut self, ratio: f64) -> Self {
+        self.building_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Share of buildings that start a multipolygon relation.
+    #[inline]
+    pub fn relation_ratio(mut self, ratio: f64) -> Self {
+        self.relation_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Whether the elements have metadata (version, timestamp, changeset and
+    /// author).
+    #[inline]
+    pub fn metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// All elements, sorted by type and id.
+    ///
+    /// The generator is run once per element type, so the memory usage does
+    /// not depend on the number of elements.
+    pub fn elements(&self) -> SyntheticElements<'_> {
+        SyntheticElements {
+            planet: self,
+            pass: 0,
+            generator: Generator::new(self),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Writes all elements to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut PbfWriter<W>) -> Result<()> {
+        for element in self.elements() {
+            writer.write_element(&element)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a file with all elements to `path`.
+    pub fn write_file(&self, path: impl AsRef<Path>, options: WriterOptions) -> Result<()> {
+        let mut writer = PbfWriter::create_with_options(path, HeaderBlock::new(), options)?;
+        self.write_to(&mut writer)?;
+        writer.finish_and_commit()
+    }
+}
+
+/// Iterator over the elements of a [`SyntheticPlanet`].
+#[derive(Debug)]
+pub struct SyntheticElements<'a> {
+    planet: &'a SyntheticPlanet,
+    /// 0: nodes, 1: ways, 2: relations.
+    pass: u8,
+    generator: Generator,
+    pending: VecDeque<Element>,
+}
+
+impl Iterator for SyntheticElements<'_> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        loop {
+            if let Some(element) = self.pending.pop_front() {
+                return Some(element);
+            }
+            match self.generator.step() {
+                Some(step) => match self.pass {
+                    0 => self
+                        .pending
+                        .extend(step.nodes.into_iter().map(Element::Node)),
+                    1 => self.pending.extend(step.way.map(Element::Way)),
+                    _ => self.pending.extend(step.relation.map(Element::Relation)),
+                },
+                None if self.pass < 2 => {
+                    // replay the same sequence for the next type
+                    self.pass += 1;
+                    self.generator = Generator::new(self.planet);
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+/// `splitmix64` random number generator.
+#[derive(Clone, Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0.0..1.0`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    #[inline]
+    fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + self.unit() * (max - min)
+    }
+
+    /// Standard normal distribution (Box-Muller).
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.unit();
+        let v = self.unit();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    /// Geometric distribution with the given mean, at least 0.
+    fn geometric(&mut self, mean: f64) -> usize {
+        let u = 1.0 - self.unit();
+        (u.ln() / (mean / (mean + 1.0)).ln()) as usize
+    }
+
+    /// Zipf distribution (exponent 1) over `0..n`.
+    fn zipf(&mut self, n: usize) -> usize {
+        // inverse of the continuous approximation of the harmonic numbers
+        let h = ((n + 1) as f64).ln();
+        let index = (self.unit() * h).exp() - 1.0;
+        (index as usize).min(n - 1)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Cluster {
+    lat: f64,
+    lon: f64,
+    /// Standard deviation of the node locations, in degrees.
+    radius: f64,
+    /// Last node of a way in this cluster, for junctions.
+    last_node: Option<(i64, f64, f64)>,
+}
+
+/// Elements created by one step of the [`Generator`].
+#[derive(Debug, Default)]
+struct Step {
+    nodes: Vec<OwnedNode>,
+    way: Option<OwnedWay>,
+    relation: Option<OwnedRelation>,
+}
+
+#[derive(Clone, Debug)]
+struct Generator {
+    rng: Rng,
+    clusters: Vec<Cluster>,
+    target_nodes: u64,
+    poi_ratio: f64,
+    building_ratio: f64,
+    relation_ratio: f64,
+    metadata: bool,
+    next_node: i64,
+    next_way: i64,
+    next_relation: i64,
+    /// Recent buildings of each cluster, used as inner rings.
+    recent_buildings: Vec<Vec<i64>>,
+}
+
+impl Generator {
+    fn new(planet: &SyntheticPlanet) -> Self {
+        let mut rng = Rng(planet.seed);
+        let clusters = (0..planet.clusters)
+            .map(|i| Cluster {
+                lat: rng.range(-55.0, 70.0),
+                lon: rng.range(-180.0, 180.0),
+                // large clusters are also more spread out
+                radius: 0.5 / (i + 1) as f64 + 0.02,
+                last_node: None,
+            })
+            .collect();
+        Self {
+            rng,
+            clusters,
+            target_nodes: planet.nodes,
+            poi_ratio: planet.poi_ratio,
+            building_ratio: planet.building_ratio,
+            relation_ratio: planet.relation_ratio,
+            metadata: planet.metadata,
+            next_node: 1,
+            next_way: 1,
+            next_relation: 1,
+            recent_buildings: vec![Vec::new(); planet.clusters],
+        }
+    }
+
+    fn step(&mut self) -> Option<Step> {
+        if self.next_node as u64 > self.target_nodes {
+            return None;
+        }
+        let cluster = self.rng.zipf(self.clusters.len());
+        let (lat, lon) = self.location(cluster);
+        let mut step = Step::default();
+        if self.rng.chance(self.poi_ratio) {
+            let mut tags = self.tags(POI_TAGS, 1);
+            if self.rng.chance(0.5) {
+                tags.push(("name".to_owned(), self.name()));
+            }
+            let node = self.node(lat, lon, tags);
+            step.nodes.push(node);
+        } else if self.rng.chance(self.building_ratio) {
+            self.building(cluster, lat, lon, &mut step);
+        } else {
+            self.path(cluster, lat, lon, &mut step);
+        }
+        Some(step)
+    }
+
+    /// A random location in `cluster`.
+    fn location(&mut self, cluster: usize) -> (f64, f64) {
+        let c = &self.clusters[cluster];
+        let (lat, lon, radius) = (c.lat, c.lon, c.radius);
+        let lat = (lat + self.rng.normal() * radius).clamp(-90.0, 90.0);
+        let lon = (lon + self.rng.normal() * radius).clamp(-180.0, 180.0);
+        (lat, lon)
+    }
+
+    fn building(&mut self, cluster: usize, lat: f64, lon: f64, step: &mut Step) {
+        let size = self.rng.range(0.0001, 0.0005);
+        let corners = [(0.0, 0.0), (0.0, size), (size, size), (size, 0.0)];
+        let mut refs: Vec<i64> = corners
+            .iter()
+            .map(|(dlat, dlon)| {
+                let node = self.node(lat + dlat, lon + dlon, OwnedTags::new());
+                let id = node.id;
+                step.nodes.push(node);
+                id
+            })
+            .collect();
+        refs.push(refs[0]);
+        let mut tags = vec![("building".to_owned(), "yes".to_owned())];
+        if self.rng.chance(0.3) {
+            let number = self.rng.zipf(200) + 1;
+            tags.push(("addr:housenumber".to_owned(), number.to_string()));
+            tags.push(("addr:street".to_owned(), self.name()));
+        }
+        let way = self.way(refs, tags);
+
+        if self.rng.chance(self.relation_ratio) {
+            let mut members = vec![OwnedMember {
+                member_type: MemberType::WAY,
+                id: way.id,
+                role: "outer".to_owned(),
+            }];
+            let inner = self.rng.geometric(1.0);
+            let recent = &self.recent_buildings[cluster];
+            members.extend(recent.iter().rev().take(inner).map(|id| OwnedMember {
+                member_type: MemberType::WAY,
+                id: *id,
+                role: "inner".to_owned(),
+            }));
+            let landuse = LANDUSE[self.rng.zipf(LANDUSE.len())];
+            let tags = vec![
+                ("type".to_owned(), "multipolygon".to_owned()),
+                ("landuse".to_owned(), landuse.to_owned()),
+            ];
+            step.relation = Some(self.relation(members, tags));
+        }
+        let recent = &mut self.recent_buildings[cluster];
+        if recent.len() == 4 {
+            recent.remove(0);
+        }
+        recent.push(way.id);
+        step.way = Some(way);
+    }
+
+    /// An open way along a random walk; it may start at the end of the
+    /// previous way of the cluster.
+    fn path(&mut self, cluster: usize, lat: f64, lon: f64, step: &mut Step) {
+        let len = 2 + self.rng.geometric(6.0);
+        let mut refs = Vec::with_capacity(len);
+        let (mut lat, mut lon) = (lat, lon);
+        if let Some((id, junction_lat, junction_lon)) = self.clusters[cluster].last_node {
+            if self.rng.chance(0.4) {
+                refs.push(id);
+                (lat, lon) = (junction_lat, junction_lon);
+            }
+        }
+        while refs.len() < len {
+            lat = (lat + self.rng.normal() * 0.0005).clamp(-90.0, 90.0);
+            lon = (lon + self.rng.normal() * 0.0005).clamp(-180.0, 180.0);
+            let node = self.node(lat, lon, OwnedTags::new());
+            refs.push(node.id);
+            step.nodes.push(node);
+        }
+        self.clusters[cluster].last_node = refs.last().map(|id| (*id, lat, lon));
+
+        let (key, values) = WAY_TAGS[self.rng.zipf(WAY_TAGS.len())];
+        let mut tags = vec![(
+            key.to_owned(),
+            values[self.rng.zipf(values.len())].to_owned(),
+        )];
+        if key == "highway" && self.rng.chance(0.4) {
+            tags.push(("name".to_owned(), self.name()));
+        }
+        let extra = self.rng.geometric(1.5);
+        tags.extend(self.tags(EXTRA_TAGS, extra));
+        step.way = Some(self.way(refs, tags));
+    }
+
+    /// Up to `count` distinct tags with zipf distributed keys and values.
+    fn tags(&mut self, table: &[(&str, &[&str])], count: usize) -> OwnedTags {
+        let mut tags = OwnedTags::new();
+        for _ in 0..count.min(table.len()) {
+            let (key, values) = table[self.rng.zipf(table.len())];
+            if tags.iter().any(|(k, _)| k == key) {
+                continue;
+            }
+            let value = values[self.rng.zipf(values.len())];
+            tags.push((key.to_owned(), value.to_owned()));
+        }
+        tags
+    }
+
+    /// A name out of a long tail of distinct names.
+    fn name(&mut self) -> String {
+        format!("Name {}", self.rng.zipf(100_000))
+    }
+
+    fn info(&mut self) -> Option<OwnedInfo> {
+        if !self.metadata {
+            return None;
+        }
+        let uid = self.rng.zipf(10_000) as i32 + 1;
+        // 2008 to 2024
+        let timestamp = self.rng.range(1.2e12, 1.7e12) as i64 / 1000 * 1000;
+        Some(OwnedInfo {
+            version: Some(self.rng.geometric(1.0) as i32 + 1),
+            timestamp: Some(timestamp),
+            changeset: Some(((timestamp - 1_200_000_000_000) / 10_000) + uid as i64 % 97),
+            uid: Some(uid),
+            user: Some(format!("user{uid}")),
+            visible: None,
+        })
+    }
+
+    fn node(&mut self, lat: f64, lon: f64, tags: OwnedTags) -> OwnedNode {
+        let id = self.next_node;
+        self.next_node += 1;
+        OwnedNode {
+            id,
+            // granularity 100
+            nano_lat: (lat * 1e7).round() as i64 * 100,
+            nano_lon: (lon * 1e7).round() as i64 * 100,
+            tags,
+            info: self.info(),
+            ..OwnedNode::default()
+        }
+    }
+
+    fn way(&mut self, refs: Vec<i64>, tags: OwnedTags) -> OwnedWay {
+        let id = self.next_way;
+        self.next_way += 1;
+        OwnedWay {
+            id,
+            refs: refs.into_iter().collect(),
+            tags,
+            info: self.info(),
+            ..OwnedWay::default()
+        }
+    }
+
+    fn relation(&mut self, members: Vec<OwnedMember>, tags: OwnedTags) -> OwnedRelation {
+        let id = self.next_relation;
+        self.next_relation += 1;
+        OwnedRelation {
+            id,
+            members: members.into_iter().collect(),
+            tags,
+            info: self.info(),
+            ..OwnedRelation::default()
+        }
+    }
+}
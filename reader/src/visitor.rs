@@ -0,0 +1,108 @@
+//! Callback based traversal of blocks.
+
+use std::io;
+
+use osm_pbf_proto::primitives::{
+    ChangeSetRef, NodeRef, Primitive, PrimitiveType, RelationRef, WayRef,
+};
+
+use crate::blob::Blobs;
+use crate::data::PrimitiveBlock;
+use crate::error::Result;
+use crate::probe::{FileKind, KindProbe};
+
+/// Receives the primitives of a file block by block; see [`Blobs::visit`].
+///
+/// All references passed to the hooks (including tag strings) borrow from
+/// the current block. They stay valid until [`Visitor::block_will_drop`]
+/// returns; caches keyed by them must be cleared there. Override
+/// [`Visitor::block`] to keep such references in locals that live as long as
+/// the block, without copying.
+pub trait Visitor {
+    /// Types passed to the hooks (nodes, ways and relations by default).
+    ///
+    /// [`Blobs::visit`] only decodes the primitives of these types; the
+    /// blocks passed to [`Visitor::block`] contain no others.
+    fn types(&self) -> PrimitiveType {
+        PrimitiveType::DEFAULT
+    }
+
+    /// Visits a complete block; the default calls the other hooks in order.
+    fn block(&mut self, block: &PrimitiveBlock) {
+        self.block_start(block);
+        for p in block.primitives().filter_types(self.types()) {
+            match &p {
+                Primitive::Node(n) => self.node(n),
+                Primitive::Way(w) => self.way(w),
+                Primitive::Relation(r) => self.relation(r),
+                Primitive::ChangeSet(c) => self.changeset(c),
+                _ => {}
+            }
+        }
+        self.block_will_drop(block);
+    }
+
+    /// Called before the first primitive of `block`.
+    fn block_start(&mut self, _block: &PrimitiveBlock) {}
+
+    fn node(&mut self, _node: &NodeRef<'_>) {}
+
+    fn way(&mut self, _way: &WayRef<'_>) {}
+
+    fn relation(&mut self, _relation: &RelationRef<'_>) {}
+
+    fn changeset(&mut self, _changeset: &ChangeSetRef<'_>) {}
+
+    /// Called after the last primitive of `block`, right before it is
+    /// dropped. Data borrowed from the block becomes invalid afterwards.
+    fn block_will_drop(&mut self, _block: &PrimitiveBlock) {}
+
+    /// Fails when the visitor is not meant for files of `kind`, which is
+    /// determined from the header and the first block (of the visited
+    /// types). By default, visitors of changesets only accept changeset
+    /// dumps.
+    fn ensure_file_kind(&self, kind: FileKind) -> Result<()> {
+        if self.types() == PrimitiveType::CHANGE_SET {
+            kind.ensure(&[FileKind::ChangesetDump])?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: Visitor + ?Sized> Visitor for &mut V {
+    #[inline]
+    fn types(&self) -> PrimitiveType {
+        (**self).types()
+    }
+
+    #[inline]
+    fn block(&mut self, block: &PrimitiveBlock) {
+        (**self).block(block)
+    }
+
+    #[inline]
+    fn ensure_file_kind(&self, kind: FileKind) -> Result<()> {
+        (**self).ensure_file_kind(kind)
+    }
+}
+
+impl<R: io::BufRead> Blobs<R> {
+    /// Decodes all remaining blocks and passes them to `visitor`.
+    ///
+    /// Returns the number of visited blocks. Fails before the first block is
+    /// visited when the visitor is not meant for the kind of the file (see
+    /// [`Visitor::ensure_file_kind`]).
+    pub fn visit(&mut self, mut visitor: impl Visitor) -> Result<u64> {
+        let types = visitor.types();
+        let mut blocks = 0;
+        let mut probe = KindProbe::new(self.header(), 1);
+        while let Some(block) = self.next_primitive_block_of_types(types)? {
+            if let Some(kind) = probe.sample(&block) {
+                visitor.ensure_file_kind(kind)?;
+            }
+            visitor.block(&block);
+            blocks += 1;
+        }
+        Ok(blocks)
+    }
+}
@@ -0,0 +1,344 @@
+//! Low-level access to the protobuf wire format.
+//!
+//! [`Fields`] walks the fields of an encoded message without decoding it:
+//! each [`Field`] is a field number with an integer or a slice of the input.
+//! Nested messages (e.g. the groups of a `PrimitiveBlock`) are walked by
+//! calling [`Field::message`] on their slices, packed repeated fields with
+//! [`Field::packed`]. Nothing is allocated, and fields that are not needed
+//! are skipped without being looked at.
+//!
+//! The walker works on the uncompressed payload of a blob, see [`payload`].
+
+use std::borrow::Cow;
+
+use osm_pbf_proto::fileformat::blob::Data;
+use osm_pbf_proto::fileformat::Blob as PbfBlob;
+use osm_pbf_proto::primitives::PrimitiveType;
+
+use crate::blob::Codec;
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+
+/// The value of a field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Value<'a> {
+    /// Wire type 0 (`int32`, `int64`, `uint*`, `sint*`, `bool`, enums).
+    Varint(u64),
+    /// Wire type 1 (`fixed64`, `sfixed64`, `double`).
+    Fixed64(u64),
+    /// Wire type 2 (`string`, `bytes`, messages and packed repeated fields).
+    Bytes(&'a [u8]),
+    /// Wire type 5 (`fixed32`, `sfixed32`, `float`).
+    Fixed32(u32),
+}
+
+/// A field of an encoded message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Field<'a> {
+    pub number: u32,
+    pub value: Value<'a>,
+}
+
+impl<'a> Field<'a> {
+    /// The value of a varint field (`None` for other wire types).
+    #[inline]
+    pub fn varint(&self) -> Option<u64> {
+        match self.value {
+            Value::Varint(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value of a `sint32`/`sint64` field.
+    #[inline]
+    pub fn sint(&self) -> Option<i64> {
+        self.varint().map(zigzag)
+    }
+
+    /// The value of a length-delimited field.
+    #[inline]
+    pub fn bytes(&self) -> Option<&'a [u8]> {
+        match self.value {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The fields of an embedded message.
+    #[inline]
+    pub fn message(&self) -> Option<Fields<'a>> {
+        self.bytes().map(Fields::new)
+    }
+
+    /// The values of a packed repeated varint field.
+    #[inline]
+    pub fn packed(&self) -> Option<Packed<'a>> {
+        self.bytes().map(Packed::new)
+    }
+}
+
+/// Iterator over the fields of an encoded message.
+///
+/// Stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Fields<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    #[inline]
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Offset of the next field in the message.
+    #[inline]
+    pub const fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// The first field with the given number.
+    pub fn find(self, number: u32) -> Result<Option<Field<'a>>> {
+        for field in self {
+            let field = field?;
+            if field.number == number {
+                return Ok(Some(field));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_field(&mut self) -> Result<Field<'a>> {
+        let start = self.pos;
+        let tag = self.read_varint()?;
+        let number = (tag >> 3) as u32;
+        if number == 0 || tag >> 32 != 0 {
+            return Err(Error::InvalidWireFormat(start, "invalid tag"));
+        }
+        let value = match tag & 7 {
+            0 => Value::Varint(self.read_varint()?),
+            1 => Value::Fixed64(u64::from_le_bytes(self.read_array()?)),
+            2 => {
+                let len = self.read_varint()?;
+                Value::Bytes(self.read_slice(len)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(self.read_array()?)),
+            _ => return Err(Error::InvalidWireFormat(start, "unsupported wire type")),
+        };
+        Ok(Field { number, value })
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        match read_varint(&self.data[self.pos..]) {
+            Some((value, len)) => {
+                self.pos += len;
+                Ok(value)
+            }
+            None => Err(Error::InvalidWireFormat(self.pos, "invalid varint")),
+        }
+    }
+
+    fn read_slice(&mut self, len: u64) -> Result<&'a [u8]> {
+        let remaining = self.data.len() - self.pos;
+        if len > remaining as u64 {
+            return Err(Error::InvalidWireFormat(self.pos, "truncated field"));
+        }
+        let slice = &self.data[self.pos..self.pos + len as usize];
+        self.pos += len as usize;
+        Ok(slice)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let slice = self.read_slice(N as u64)?;
+        let mut array = [0; N];
+        array.copy_from_slice(slice);
+        Ok(array)
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<Field<'a>>;
+
+    fn next(&mut self) -> Option<Result<Field<'a>>> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let field = self.read_field();
+        if field.is_err() {
+            self.pos = self.data.len();
+        }
+        Some(field)
+    }
+}
+
+/// Iterator over the values of a packed repeated varint field.
+///
+/// Use [`zigzag`] on the values of `sint*` fields (and [`delta`] on
+/// delta-coded fields, like the ids of `DenseNodes`). Stops after the first
+/// error.
+#[derive(Clone, Debug)]
+pub struct Packed<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Packed<'a> {
+    #[inline]
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl Iterator for Packed<'_> {
+    type Item = Result<u64>;
+
+    fn next(&mut self) -> Option<Result<u64>> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        match read_varint(&self.data[self.pos..]) {
+            Some((value, len)) => {
+                self.pos += len;
+                Some(Ok(value))
+            }
+            None => {
+                let pos = self.pos;
+                self.pos = self.data.len();
+                Some(Err(Error::InvalidWireFormat(pos, "invalid varint")))
+            }
+        }
+    }
+}
+
+/// Reads a varint from the start of `data`; returns the value and the number
+/// of bytes read.
+#[inline]
+pub fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().take(10).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte < 0x80 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Appends `value` as a varint to `out`.
+#[inline]
+pub fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decodes a zigzag encoded `sint32`/`sint64` value.
+#[inline]
+pub const fn zigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Running sum of delta-coded values.
+pub fn delta<I>(values: I) -> impl Iterator<Item = Result<i64>>
+where
+    I: IntoIterator<Item = Result<i64>>,
+{
+    let mut sum = 0i64;
+    values.into_iter().map(move |v| {
+        sum = sum.wrapping_add(v?);
+        Ok(sum)
+    })
+}
+
+/// The uncompressed payload of a blob (a `HeaderBlock` or `PrimitiveBlock`),
+/// borrowed for raw blobs.
+///
+/// Payloads that are larger than the `limits` fail with
+/// [`Error::BlobDataToLarge`].
+pub fn payload<'a>(blob: &'a PbfBlob, limits: &Limits) -> Result<Cow<'a, [u8]>> {
+    limits.check_blob(blob)?;
+    let max_size = limits.max_decompressed_size;
+    match &blob.data {
+        Some(Data::Raw(raw)) if raw.len() > max_size => Err(limits.size_error()),
+        Some(Data::Raw(raw)) => Ok(Cow::Borrowed(raw)),
+        Some(_) => {
+            let mut raw = Vec::new();
+            Codec::decompress_blob_into(blob, &mut raw, max_size)
+                .map_err(|e| limits.map_size_error(e))?;
+            Ok(Cow::Owned(raw))
+        }
+        None => Ok(Cow::Borrowed(&[])),
+    }
+}
+
+/// Copies the encoded `PrimitiveBlock` in `block` to `out`, without the
+/// primitives whose type is not in `types`.
+///
+/// The skipped primitives are not looked at; all other fields (including
+/// unknown ones) are copied unchanged.
+pub fn retain_types(block: &[u8], types: PrimitiveType, out: &mut Vec<u8>) -> Result<()> {
+    let mut group = Vec::new();
+    let mut fields = Fields::new(block);
+    loop {
+        let start = fields.offset();
+        let Some(field) = fields.next() else {
+            return Ok(());
+        };
+        match field? {
+            Field {
+                number: 2,
+                value: Value::Bytes(data),
+            } => {
+                group.clear();
+                retain_group_types(data, types, &mut group)?;
+                out.push(2 << 3 | 2);
+                write_varint(group.len() as u64, out);
+                out.extend_from_slice(&group);
+            }
+            _ => out.extend_from_slice(&block[start..fields.offset()]),
+        }
+    }
+}
+
+fn retain_group_types(group: &[u8], types: PrimitiveType, out: &mut Vec<u8>) -> Result<()> {
+    let mut fields = Fields::new(group);
+    loop {
+        let start = fields.offset();
+        let Some(field) = fields.next() else {
+            return Ok(());
+        };
+        let t = group_field_type(field?.number);
+        if types.contains(t) {
+            out.extend_from_slice(&group[start..fields.offset()]);
+        }
+    }
+}
+
+/// Type of the primitives in the field `number` of a `PrimitiveGroup`.
+#[inline]
+fn group_field_type(number: u32) -> PrimitiveType {
+    match number {
+        1 | 2 => PrimitiveType::NODE,
+        3 => PrimitiveType::WAY,
+        4 => PrimitiveType::RELATION,
+        5 => PrimitiveType::CHANGE_SET,
+        _ => PrimitiveType::empty(),
+    }
+}
+
+/// The types of the primitives in the encoded `PrimitiveBlock` in `block`;
+/// the primitives themselves are not decoded.
+pub fn block_types(block: &[u8]) -> Result<PrimitiveType> {
+    let mut types = PrimitiveType::empty();
+    for field in Fields::new(block) {
+        if let (2, Value::Bytes(group)) = field.map(|f| (f.number, f.value))? {
+            for field in Fields::new(group) {
+                types |= group_field_type(field?.number);
+            }
+        }
+    }
+    Ok(types)
+}
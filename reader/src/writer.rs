@@ -0,0 +1,1050 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use osm_pbf_proto::builder::{optimize_encoding, sort_string_table, PrimitiveBlockBuilder};
+use osm_pbf_proto::element::{Element, OwnedChangeSet, OwnedInfo};
+use osm_pbf_proto::osmformat::{HeaderBlock, PrimitiveBlock};
+use osm_pbf_proto::primitives::{Primitive, PrimitiveType};
+use osm_pbf_proto::protobuf::Message;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Seek, Write};
+use std::path::Path;
+
+use crate::atomic::AtomicFile;
+use crate::blob::{
+    Blob, Codec, CompressionOptions, PbfBlob, PbfBlobHeader, MAX_HEADER_SIZE,
+    MAX_UNCOMPRESSED_DATA_SIZE,
+};
+use crate::data::OSMDataBlob;
+use crate::error::{Error, Result};
+use crate::header::Replication;
+use crate::qa::{TagPolicy, TagProblem};
+
+#[cfg(feature = "tokio")]
+mod async_writer;
+mod header;
+mod pool;
+mod queue;
+mod sort;
+
+use self::header::{complete_header, ContentSummary};
+use self::pool::CompressionPool;
+use self::sort::ElementSorter;
+
+#[cfg(feature = "tokio")]
+pub use self::async_writer::AsyncPbfWriter;
+pub use self::header::WRITING_PROGRAM;
+pub use self::queue::{Durable, QueueOptions, QueueStats, QueuedWriter};
+pub use osm_pbf_proto::builder::{BlockEncoding, StringTableStrategy};
+
+/// Number of elements per block used by reference implementations.
+pub const DEFAULT_BLOCK_ELEMENTS: usize = 8000;
+
+/// Default budget for the estimated uncompressed size of a block.
+pub const DEFAULT_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Default memory budget for sorting elements.
+pub const DEFAULT_SORT_BUFFER_SIZE: usize = 512 * 1024 * 1024;
+
+/// A source of node locations for writing ways with `LocationsOnWays`.
+pub trait NodeLocations {
+    /// The location of node `id` as `(nano_lat, nano_lon)`.
+    fn location(&self, id: i64) -> Option<(i64, i64)>;
+}
+
+impl<F: Fn(i64) -> Option<(i64, i64)>> NodeLocations for F {
+    #[inline]
+    fn location(&self, id: i64) -> Option<(i64, i64)> {
+        self(id)
+    }
+}
+
+impl NodeLocations for HashMap<i64, (i64, i64)> {
+    #[inline]
+    fn location(&self, id: i64) -> Option<(i64, i64)> {
+        self.get(&id).copied()
+    }
+}
+
+impl NodeLocations for BTreeMap<i64, (i64, i64)> {
+    #[inline]
+    fn location(&self, id: i64) -> Option<(i64, i64)> {
+        self.get(&id).copied()
+    }
+}
+
+struct LocationSource(Box<dyn NodeLocations + Send>);
+
+impl fmt::Debug for LocationSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LocationSource")
+    }
+}
+
+struct ProgressCallback(Box<dyn FnMut(&WriterStats) + Send>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Counters of the data written by a [`PbfWriter`].
+///
+/// Elements are counted when their block is completed; elements of raw blobs
+/// (e.g. [`PbfWriter::write_blob`]) are not counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriterStats {
+    /// Written `OSMData` blobs.
+    pub blocks: u64,
+    pub nodes: u64,
+    pub ways: u64,
+    pub relations: u64,
+    pub changesets: u64,
+    /// Uncompressed size of the written `OSMData` blobs.
+    pub raw_bytes: u64,
+    /// Encoded size of the written `OSMData` blobs.
+    pub compressed_bytes: u64,
+}
+
+impl WriterStats {
+    #[inline]
+    pub fn elements(&self) -> u64 {
+        self.nodes + self.ways + self.relations + self.changesets
+    }
+
+    fn add_block(&mut self, block: &PrimitiveBlock) {
+        for group in &block.primitivegroup {
+            self.nodes += (group.nodes.len() + group.dense.id.len()) as u64;
+            self.ways += group.ways.len() as u64;
+            self.relations += group.relations.len() as u64;
+            self.changesets += group.changesets.len() as u64;
+        }
+    }
+
+    fn add_blob(&mut self, blob: &PbfBlob) {
+        let encoded = blob.data.as_ref().map_or(0, Codec::encoded_len) as u64;
+        self.blocks += 1;
+        self.raw_bytes += blob.raw_size.map_or(encoded, |s| s as u64);
+        self.compressed_bytes += encoded;
+    }
+}
+
+/// Order of the elements in the output.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ElementOrdering {
+    /// Elements are written in the order they are passed to the writer. The
+    /// `Sort.Type_then_ID` feature is not added to the header.
+    #[default]
+    Input,
+    /// Elements are sorted by type (nodes, ways, relations) and id before
+    /// writing, and `Sort.Type_then_ID` is declared in the header.
+    TypeThenId,
+    /// Elements must already be passed in type-then-id order (an error is
+    /// returned otherwise), and `Sort.Type_then_ID` is declared in the
+    /// header. Unlike [`ElementOrdering::TypeThenId`], nothing is buffered.
+    RequireTypeThenId,
+}
+
+impl ElementOrdering {
+    /// `true` when the output is sorted by type and id.
+    #[inline]
+    pub const fn is_type_then_id(self) -> bool {
+        matches!(self, Self::TypeThenId | Self::RequireTypeThenId)
+    }
+}
+
+/// Metadata that is removed from the written elements.
+///
+/// The default keeps all metadata.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StripMetadata {
+    /// Remove uid, user name and changeset.
+    pub author: bool,
+    pub timestamp: bool,
+    pub version: bool,
+}
+
+impl StripMetadata {
+    /// Keep all metadata.
+    pub const NONE: Self = Self {
+        author: false,
+        timestamp: false,
+        version: false,
+    };
+    /// Remove uid, user name and changeset.
+    pub const AUTHOR: Self = Self {
+        author: true,
+        timestamp: false,
+        version: false,
+    };
+    /// Remove all metadata except the `visible` flag.
+    pub const ALL: Self = Self {
+        author: true,
+        timestamp: true,
+        version: true,
+    };
+
+    #[inline]
+    pub const fn timestamp(mut self, timestamp: bool) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    #[inline]
+    pub const fn version(mut self, version: bool) -> Self {
+        self.version = version;
+        self
+    }
+
+    #[inline]
+    pub const fn is_none(&self) -> bool {
+        !self.author && !self.timestamp && !self.version
+    }
+
+    /// Removes the selected fields; `info` is cleared when nothing is left.
+    pub fn apply(&self, info: &mut Option<OwnedInfo>) {
+        let Some(i) = info else {
+            return;
+        };
+        if self.author {
+            i.uid = None;
+            i.user = None;
+            i.changeset = None;
+        }
+        if self.timestamp {
+            i.timestamp = None;
+        }
+        if self.version {
+            i.version = None;
+        }
+        if *i
+            == (OwnedInfo {
+                visible: i.visible,
+                ..OwnedInfo::default()
+            })
+            && i.visible.is_none()
+        {
+            *info = None;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WriterOptions {
+    atomic: bool,
+    codec: Codec,
+    compression: CompressionOptions,
+    ordering: ElementOrdering,
+    sort_buffer_size: usize,
+    compute_bbox: bool,
+    historical: bool,
+    encoding: BlockEncoding,
+    string_table: StringTableStrategy,
+    optimize: bool,
+    block_elements: usize,
+    block_size: usize,
+    compression_threads: usize,
+    strip_metadata: StripMetadata,
+    tag_policy: TagPolicy,
+    deterministic: bool,
+    replication: Option<Replication>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            atomic: false,
+            codec: Codec::default(),
+            compression: CompressionOptions::default(),
+            ordering: ElementOrdering::Input,
+            sort_buffer_size: DEFAULT_SORT_BUFFER_SIZE,
+            compute_bbox: false,
+            historical: false,
+            encoding: BlockEncoding::new(),
+            string_table: StringTableStrategy::FirstSeen,
+            optimize: false,
+            block_elements: DEFAULT_BLOCK_ELEMENTS,
+            block_size: DEFAULT_BLOCK_SIZE,
+            compression_threads: 0,
+            strip_metadata: StripMetadata::NONE,
+            tag_policy: TagPolicy::Allow,
+            deterministic: false,
+            replication: None,
+        }
+    }
+}
+
+impl WriterOptions {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write into a temporary file and rename it into place on
+    /// [`PbfWriter::finish_and_commit`]. Only used by
+    /// [`PbfWriter::create_with_options`].
+    ///
+    /// [`PbfWriter::finish`] alone does not move the file into place: the
+    /// returned [`FileSink`] has to be [committed](FileSink::commit), it is
+    /// removed when dropped.
+    #[inline]
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    /// Compression of the written blobs (zlib by default).
+    #[inline]
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Compression level and the minimum size of compressed blobs.
+    #[inline]
+    pub fn compression(mut self, compression: CompressionOptions) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    #[inline]
+    pub fn ordering(mut self, ordering: ElementOrdering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Approximate amount of memory used for buffering elements when sorting.
+    /// Larger inputs are sorted with temporary files.
+    #[inline]
+    pub fn sort_buffer_size(mut self, bytes: usize) -> Self {
+        self.sort_buffer_size = bytes;
+        self
+    }
+
+    /// Compute the header bbox from the written nodes (unless the header
+    /// already has a bbox).
+    ///
+    /// The data is buffered in a temporary file until [`PbfWriter::finish`],
+    /// so the header also reflects the features of all written data.
+    #[inline]
+    pub fn compute_bbox(mut self, compute_bbox: bool) -> Self {
+        self.compute_bbox = compute_bbox;
+        self
+    }
+
+    /// Declare the `HistoricalInformation` feature. It is also added when
+    /// deleted (invisible) elements are detected.
+    #[inline]
+    pub fn historical(mut self, historical: bool) -> Self {
+        self.historical = historical;
+        self
+    }
+
+    /// Granularity, offsets and date granularity of the written blocks. Can be
+    /// changed per block with [`PbfWriter::set_block_encoding`].
+    #[inline]
+    pub fn block_encoding(mut self, encoding: BlockEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    #[inline]
+    pub fn string_table(mut self, strategy: StringTableStrategy) -> Self {
+        self.string_table = strategy;
+        self
+    }
+
+    /// Re-encode each written block with the coarsest granularity and the
+    /// offsets that still represent its coordinates exactly (see
+    /// [`optimize_encoding`](osm_pbf_proto::builder::optimize_encoding)).
+    #[inline]
+    pub fn optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Maximum number of elements per block (`8000` by default). Use
+    /// `usize::MAX` to manage block boundaries with [`PbfWriter::flush_block`].
+    #[inline]
+    pub fn block_elements(mut self, elements: usize) -> Self {
+        self.block_elements = elements;
+        self
+    }
+
+    /// Budget for the estimated uncompressed size of a block in bytes (16 MiB
+    /// by default). Blocks must not exceed 32 MiB.
+    #[inline]
+    pub fn block_size(mut self, bytes: usize) -> Self {
+        self.block_size = bytes;
+        self
+    }
+
+    /// Serialize and compress blocks on this many worker threads (`0`, the
+    /// default, compresses on the calling thread). Blocks are still written
+    /// in order.
+    #[inline]
+    pub fn compression_threads(mut self, threads: usize) -> Self {
+        self.compression_threads = threads;
+        self
+    }
+
+    /// Remove metadata (e.g. the author information) from all written
+    /// elements. Blocks passed to [`PbfWriter::write_primitive_block`] are
+    /// re-encoded, so removed user names don't remain in the string tables.
+    #[inline]
+    pub fn strip_metadata(mut self, strip: StripMetadata) -> Self {
+        self.strip_metadata = strip;
+        self
+    }
+
+    /// How tags that violate the limits of the OSM API are handled (default:
+    /// [`TagPolicy::Allow`]). Blocks passed to
+    /// [`PbfWriter::write_primitive_block`] are re-encoded when a policy is
+    /// set.
+    #[inline]
+    pub fn tag_policy(mut self, policy: TagPolicy) -> Self {
+        self.tag_policy = policy;
+        self
+    }
+
+    /// Produce byte-identical output for identical input and options,
+    /// independent of the library version.
+    ///
+    /// The writer never injects timestamps and orders strings, groups and
+    /// blocks only by the input, also with compression threads. This mode
+    /// additionally pins the compression level (see
+    /// [`Codec::default_level`]) and omits the version from the default
+    /// writing program. Identical bytes still require the same compression
+    /// backend (e.g. `zlib` vs. `zlib-ng-compat`).
+    #[inline]
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Replaces the osmosis replication fields of the written header, e.g.
+    /// for headers that are derived from the inputs (see
+    /// [`concat`](crate::concat::concat)).
+    #[inline]
+    pub fn replication(mut self, replication: Replication) -> Self {
+        self.replication = Some(replication);
+        self
+    }
+}
+
+/// Output of a [`PbfWriter`] created from a path.
+#[derive(Debug)]
+#[must_use = "an atomic output is removed unless it is committed"]
+pub enum FileSink {
+    File(io::BufWriter<File>),
+    Atomic(io::BufWriter<AtomicFile>),
+}
+
+impl FileSink {
+    /// Creates the file at `path`. An atomic output only replaces `path`
+    /// when it is committed.
+    pub fn create(path: impl AsRef<Path>, atomic: bool) -> Result<Self> {
+        Ok(if atomic {
+            Self::Atomic(io::BufWriter::new(AtomicFile::create(path)?))
+        } else {
+            Self::File(io::BufWriter::new(File::create(path)?))
+        })
+    }
+
+    /// Flushes all data to disk. For atomic outputs, the temporary file is
+    /// renamed to the target path.
+    pub fn commit(self) -> Result<()> {
+        match self {
+            Self::File(w) => {
+                let file = w.into_inner().map_err(io::IntoInnerError::into_error)?;
+                file.sync_all()?;
+            }
+            Self::Atomic(w) => {
+                let file = w.into_inner().map_err(io::IntoInnerError::into_error)?;
+                file.commit()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for FileSink {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(w) => w.write(buf),
+            Self::Atomic(w) => w.write(buf),
+        }
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Self::File(w) => w.write_all(buf),
+            Self::Atomic(w) => w.write_all(buf),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(w) => w.flush(),
+            Self::Atomic(w) => w.flush(),
+        }
+    }
+}
+
+/// Writes `OSMHeader` and `OSMData` blobs to an OSM PBF file.
+///
+/// The header is written lazily before the first data block (or on
+/// [`PbfWriter::finish`]). Required and optional features, the writing
+/// program and (with [`WriterOptions::compute_bbox`]) the bbox are filled in
+/// from the written data.
+#[derive(Debug)]
+pub struct PbfWriter<W> {
+    writer: W,
+    header: Option<HeaderBlock>,
+    options: WriterOptions,
+    block: PrimitiveBlockBuilder,
+    block_size_estimate: usize,
+    sorter: Option<ElementSorter>,
+    /// Changesets written after the sorted elements.
+    sorted_changesets: Vec<OwnedChangeSet>,
+    summary: ContentSummary,
+    deferred: bool,
+    spool: Option<io::BufWriter<File>>,
+    pool: Option<CompressionPool>,
+    last_key: Option<(PrimitiveType, i64)>,
+    locations: Option<LocationSource>,
+    stats: WriterStats,
+    progress: Option<ProgressCallback>,
+}
+
+impl PbfWriter<FileSink> {
+    #[inline]
+    pub fn create(path: impl AsRef<Path>, header: HeaderBlock) -> Result<Self> {
+        Self::create_with_options(path, header, WriterOptions::default())
+    }
+
+    /// Creates the output file at `path`.
+    ///
+    /// Finish the file with [`PbfWriter::finish_and_commit`]; an uncommitted
+    /// atomic output is removed.
+    pub fn create_with_options(
+        path: impl AsRef<Path>,
+        header: HeaderBlock,
+        options: WriterOptions,
+    ) -> Result<Self> {
+        let sink = FileSink::create(path, options.atomic)?;
+        Ok(Self::with_options(sink, header, options))
+    }
+
+    /// [Finishes](PbfWriter::finish) the file and [commits](FileSink::commit)
+    /// it, which moves an atomic output into place.
+    #[inline]
+    pub fn finish_and_commit(self) -> Result<()> {
+        self.finish()?.commit()
+    }
+}
+
+impl<W: Write> PbfWriter<W> {
+    #[inline]
+    pub fn new(writer: W, header: HeaderBlock) -> Self {
+        Self::with_options(writer, header, WriterOptions::default())
+    }
+
+    #[inline]
+    pub fn with_options(writer: W, header: HeaderBlock, mut options: WriterOptions) -> Self {
+        if options.deterministic && options.compression.level.is_none() {
+            options.compression.level = options.codec.default_level();
+        }
+        let sorter = match options.ordering {
+            ElementOrdering::Input | ElementOrdering::RequireTypeThenId => None,
+            ElementOrdering::TypeThenId => Some(ElementSorter::new(options.sort_buffer_size)),
+        };
+        let deferred = options.compute_bbox && header.bbox.is_none();
+        let block = PrimitiveBlockBuilder::with_encoding(options.encoding);
+        let pool = (options.compression_threads > 0).then(|| {
+            CompressionPool::new(
+                options.compression_threads,
+                options.codec,
+                options.compression,
+            )
+        });
+        Self {
+            writer,
+            header: Some(header),
+            options,
+            block,
+            block_size_estimate: 0,
+            sorter,
+            sorted_changesets: Vec::new(),
+            summary: ContentSummary::default(),
+            deferred,
+            spool: None,
+            pool,
+            last_key: None,
+            locations: None,
+            stats: WriterStats::default(),
+            progress: None,
+        }
+    }
+
+    #[inline]
+    pub fn options(&self) -> &WriterOptions {
+        &self.options
+    }
+
+    /// Writes the node locations on all following ways (`LocationsOnWays`).
+    ///
+    /// Writing a way fails with [`Error::MissingNodeLocation`] when the
+    /// location of one of its nodes is unknown.
+    pub fn set_node_locations(&mut self, source: impl NodeLocations + Send + 'static) {
+        self.locations = Some(LocationSource(Box::new(source)));
+    }
+
+    /// Calls `progress` after each written `OSMData` blob.
+    pub fn set_progress(&mut self, progress: impl FnMut(&WriterStats) + Send + 'static) {
+        self.progress = Some(ProgressCallback(Box::new(progress)));
+    }
+
+    /// Counters of the data written so far.
+    #[inline]
+    pub fn stats(&self) -> &WriterStats {
+        &self.stats
+    }
+
+    /// The underlying output. Writing to it directly corrupts the file.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// The header, as long as it was not written yet.
+    #[inline]
+    pub fn header_mut(&mut self) -> Option<&mut HeaderBlock> {
+        self.header.as_mut()
+    }
+
+    fn write_header_if_pending(&mut self) -> Result<()> {
+        if self.deferred {
+            return Ok(());
+        }
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if let Some(mut header) = self.header.take() {
+            complete_header(&mut header, &self.summary, &self.options, self.deferred);
+            let blob =
+                Blob::encode_message_with(&header, self.options.codec, &self.options.compression)?;
+            write_frame(&mut self.writer, &blob_header("OSMHeader"), &blob)?;
+        }
+        Ok(())
+    }
+
+    /// Adds an element to the pending block.
+    ///
+    /// Nodes are packed into `DenseNodes`. With [`ElementOrdering::Input`],
+    /// elements are kept in input order; a new group is started whenever the
+    /// element type changes. The block is written automatically when it
+    /// reaches [`WriterOptions::block_elements`] or
+    /// [`WriterOptions::block_size`]; call [`PbfWriter::flush_block`] to end
+    /// it earlier.
+    ///
+    /// With [`ElementOrdering::TypeThenId`], elements are buffered and written
+    /// on [`PbfWriter::finish`].
+    pub fn write_element(&mut self, element: &Element) -> Result<()> {
+        if let Some(sorter) = &mut self.sorter {
+            return sorter.push(element.clone());
+        }
+        self.check_order(element.primitive_type(), element.id())?;
+        self.push_element(element)
+    }
+
+    /// Verifies the order for [`ElementOrdering::RequireTypeThenId`]. Equal
+    /// keys (multiple versions of an element) are allowed.
+    fn check_order(&mut self, t: PrimitiveType, id: i64) -> Result<()> {
+        if self.options.ordering != ElementOrdering::RequireTypeThenId {
+            return Ok(());
+        }
+        let key = (t, id);
+        if let Some(last) = self.last_key {
+            if (key.0.bits(), key.1) < (last.0.bits(), last.1) {
+                return Err(Error::UnsortedElement(
+                    type_name(t),
+                    id,
+                    type_name(last.0),
+                    last.1,
+                ));
+            }
+        }
+        self.last_key = Some(key);
+        Ok(())
+    }
+
+    fn push_element(&mut self, element: &Element) -> Result<()> {
+        let mut element = Cow::Borrowed(element);
+        if !self.options.strip_metadata.is_none() && element.info().is_some() {
+            self.options
+                .strip_metadata
+                .apply(element.to_mut().info_mut());
+        }
+        let policy = self.options.tag_policy;
+        if policy != TagPolicy::Allow
+            && element
+                .tags()
+                .iter()
+                .any(|(k, v)| TagProblem::of(k, v).is_some())
+        {
+            let (t, id) = (element.primitive_type(), element.id());
+            policy.apply(t, id, element.to_mut().tags_mut())?;
+        }
+        let element = &*element;
+        let locations = match (element, &self.locations) {
+            (Element::Way(w), Some(source)) => Some(
+                w.refs
+                    .iter()
+                    .map(|&id| {
+                        source
+                            .0
+                            .location(id)
+                            .ok_or(Error::MissingNodeLocation(id, w.id))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            _ => None,
+        };
+        let size = element.encoded_size_estimate() + locations.as_ref().map_or(0, |l| l.len() * 8);
+        self.reserve(size)?;
+        self.summary.add_element(element);
+        match element {
+            Element::Node(n) => self.block.push_node(n),
+            Element::Way(w) => {
+                self.summary.locations_on_ways |= locations.is_some();
+                match &locations {
+                    Some(locations) => self.block.push_way_with_locations(w, locations),
+                    None => self.block.push_way(w),
+                }
+            }
+            Element::Relation(r) => self.block.push_relation(r),
+        }
+        Ok(())
+    }
+
+    /// Flushes the pending block if an element of `size` bytes doesn't fit.
+    fn reserve(&mut self, size: usize) -> Result<()> {
+        if self.block.len() >= self.options.block_elements
+            || (!self.block.is_empty() && self.block_size_estimate + size > self.options.block_size)
+        {
+            self.flush_block()?;
+        }
+        self.block_size_estimate += size;
+        Ok(())
+    }
+
+    /// Adds a changeset to the pending block, in a group of changesets.
+    ///
+    /// Changesets sort after all other element types: with
+    /// [`ElementOrdering::TypeThenId`], they are buffered (in memory) and
+    /// written by id after the sorted elements on [`PbfWriter::finish`].
+    pub fn write_changeset(&mut self, changeset: &OwnedChangeSet) -> Result<()> {
+        if self.sorter.is_some() {
+            self.sorted_changesets.push(changeset.clone());
+            return Ok(());
+        }
+        self.check_order(PrimitiveType::CHANGE_SET, changeset.id)?;
+        self.push_changeset(changeset)
+    }
+
+    fn push_changeset(&mut self, changeset: &OwnedChangeSet) -> Result<()> {
+        let mut changeset = Cow::Borrowed(changeset);
+        if !self.options.strip_metadata.is_none() && changeset.info.is_some() {
+            self.options
+                .strip_metadata
+                .apply(&mut changeset.to_mut().info);
+        }
+        let policy = self.options.tag_policy;
+        if policy != TagPolicy::Allow
+            && changeset
+                .tags
+                .iter()
+                .any(|(k, v)| TagProblem::of(k, v).is_some())
+        {
+            let id = changeset.id;
+            policy.apply(PrimitiveType::CHANGE_SET, id, &mut changeset.to_mut().tags)?;
+        }
+        self.reserve(changeset.encoded_size_estimate())?;
+        self.block.push_changeset(&changeset);
+        Ok(())
+    }
+
+    /// Number of elements in the pending block.
+    #[inline]
+    pub fn pending_elements(&self) -> usize {
+        self.block.len()
+    }
+
+    /// Estimated uncompressed size of the pending block in bytes.
+    #[inline]
+    pub fn pending_size_estimate(&self) -> usize {
+        self.block_size_estimate
+    }
+
+    /// Changes the quantization of the following blocks. The pending block is
+    /// flushed before.
+    pub fn set_block_encoding(&mut self, encoding: BlockEncoding) -> Result<()> {
+        self.flush_block()?;
+        self.options.encoding = encoding;
+        self.block = PrimitiveBlockBuilder::with_encoding(encoding);
+        Ok(())
+    }
+
+    /// Writes the pending block (if any elements were added).
+    pub fn flush_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let encoding = self.options.encoding;
+        let mut block = std::mem::replace(
+            &mut self.block,
+            PrimitiveBlockBuilder::with_encoding(encoding),
+        )
+        .build_with(self.options.string_table);
+        self.block_size_estimate = 0;
+        if self.options.optimize {
+            optimize_encoding(&mut block);
+        }
+        self.write_encoded_block(Cow::Owned(block))
+    }
+
+    /// Writes a complete block. The pending block is flushed before.
+    ///
+    /// With [`ElementOrdering::TypeThenId`], the elements of the block are
+    /// buffered for sorting instead.
+    pub fn write_primitive_block(&mut self, block: &PrimitiveBlock) -> Result<()> {
+        if let Some(sorter) = &mut self.sorter {
+            for p in block.primitives().filter_types(PrimitiveType::all()) {
+                match p.to_element() {
+                    Some(element) => sorter.push(element)?,
+                    None => self.sorted_changesets.extend(p.to_changeset()),
+                }
+            }
+            return Ok(());
+        }
+        self.check_block_order(block)?;
+        self.flush_block()?;
+        if self.reencodes_elements() {
+            for p in block.primitives().filter_types(PrimitiveType::all()) {
+                match p.to_element() {
+                    Some(element) => self.push_element(&element)?,
+                    None => {
+                        if let Some(changeset) = p.to_changeset() {
+                            self.push_changeset(&changeset)?;
+                        }
+                    }
+                }
+            }
+            return self.flush_block();
+        }
+        self.summary.add_block(block);
+        if self.options.string_table == StringTableStrategy::Frequency || self.options.optimize {
+            let mut block = block.clone();
+            if self.options.string_table == StringTableStrategy::Frequency {
+                sort_string_table(&mut block);
+            }
+            if self.options.optimize {
+                optimize_encoding(&mut block);
+            }
+            return self.write_encoded_block(Cow::Owned(block));
+        }
+        self.write_encoded_block(Cow::Borrowed(block))
+    }
+
+    fn check_block_order(&mut self, block: &PrimitiveBlock) -> Result<()> {
+        if self.options.ordering != ElementOrdering::RequireTypeThenId {
+            return Ok(());
+        }
+        for p in block.primitives().filter_types(PrimitiveType::all()) {
+            match &p {
+                Primitive::Node(n) => self.check_order(PrimitiveType::NODE, n.id)?,
+                Primitive::Way(w) => self.check_order(PrimitiveType::WAY, w.id())?,
+                Primitive::Relation(r) => self.check_order(PrimitiveType::RELATION, r.id())?,
+                Primitive::ChangeSet(c) => self.check_order(PrimitiveType::CHANGE_SET, c.id())?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether [`PbfWriter::write_primitive_block`] has to look at the
+    /// elements instead of writing blocks as they are.
+    pub(crate) fn reencodes_blocks(&self) -> bool {
+        self.options.ordering != ElementOrdering::Input || self.reencodes_elements()
+    }
+
+    fn reencodes_elements(&self) -> bool {
+        !self.options.strip_metadata.is_none()
+            || self.options.tag_policy != TagPolicy::Allow
+            || self.locations.is_some()
+    }
+
+    fn write_encoded_block(&mut self, block: Cow<'_, PrimitiveBlock>) -> Result<()> {
+        self.stats.add_block(&block);
+        if let Some(pool) = &mut self.pool {
+            pool.submit(block.into_owned())?;
+            return self.write_compressed(false);
+        }
+        let blob =
+            Blob::encode_message_with(&*block, self.options.codec, &self.options.compression)?;
+        self.write_frame("OSMData", &blob)
+    }
+
+    /// Writes the blocks that were compressed by the pool (all of them with
+    /// `wait_all`).
+    fn write_compressed(&mut self, wait_all: bool) -> Result<()> {
+        let Some(pool) = &mut self.pool else {
+            return Ok(());
+        };
+        for blob in pool.take_ready(wait_all)? {
+            self.write_frame("OSMData", &blob)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a framed `BlobHeader`/`Blob` pair after the header. The pending
+    /// block is flushed before.
+    ///
+    /// `OSMData` blobs are written without recompressing them. They are
+    /// decoded once to check their order (for
+    /// [`ElementOrdering::RequireTypeThenId`]) and to derive the features of
+    /// the header while it is not written yet. Fails with
+    /// [`ElementOrdering::TypeThenId`], as the buffered elements are only
+    /// written on [`PbfWriter::finish`], and for `OSMData` blobs when the
+    /// elements have to be re-encoded (stripping metadata, a tag policy or
+    /// node locations on ways).
+    pub fn write_blob(&mut self, blob_type: &str, blob: &PbfBlob) -> Result<()> {
+        self.write_raw_blob(&blob_header(blob_type), blob)
+    }
+
+    /// Like [`PbfWriter::write_blob`], but keeps all fields of `header`
+    /// (e.g. `indexdata`); only `datasize` is recomputed.
+    pub fn write_raw_blob(&mut self, header: &PbfBlobHeader, blob: &PbfBlob) -> Result<()> {
+        let is_data = header.type_() == "OSMData";
+        if self.sorter.is_some() {
+            return Err(Error::InvalidArgument(
+                "blobs cannot be written while sorting elements".into(),
+            ));
+        }
+        if is_data && self.reencodes_elements() {
+            return Err(Error::InvalidArgument(
+                "blobs cannot be written when elements are re-encoded".into(),
+            ));
+        }
+        self.flush_block()?;
+        let check_order = self.options.ordering == ElementOrdering::RequireTypeThenId;
+        if is_data && (check_order || self.header.is_some()) {
+            let block = OSMDataBlob::new(blob.clone()).decode_into()?;
+            self.check_block_order(&block)?;
+            self.summary.add_block(&block);
+        }
+        self.write_compressed(true)?;
+        self.write_frame_with(header, blob)
+    }
+
+    #[inline]
+    fn write_frame(&mut self, blob_type: &str, blob: &PbfBlob) -> Result<()> {
+        self.write_frame_with(&blob_header(blob_type), blob)
+    }
+
+    fn write_frame_with(&mut self, header: &PbfBlobHeader, blob: &PbfBlob) -> Result<()> {
+        self.write_header_if_pending()?;
+        if self.deferred {
+            let spool = match &mut self.spool {
+                Some(spool) => spool,
+                None => self.spool.insert(io::BufWriter::new(tempfile::tempfile()?)),
+            };
+            write_frame(spool, header, blob)?;
+        } else {
+            write_frame(&mut self.writer, header, blob)?;
+        }
+        if header.type_() == "OSMData" {
+            self.stats.add_blob(blob);
+            if let Some(progress) = &mut self.progress {
+                (progress.0)(&self.stats);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_compressed(true)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the pending block and header and flushes the output.
+    pub fn finish(mut self) -> Result<W> {
+        if let Some(sorter) = self.sorter.take() {
+            sorter.finish(|element| self.push_element(&element))?;
+        }
+        let mut changesets = std::mem::take(&mut self.sorted_changesets);
+        changesets.sort_by_key(|c| c.id);
+        for changeset in &changesets {
+            self.push_changeset(changeset)?;
+        }
+        self.flush_block()?;
+        self.write_compressed(true)?;
+        self.write_header()?;
+        if let Some(spool) = self.spool.take() {
+            let mut spool = spool.into_inner().map_err(io::IntoInnerError::into_error)?;
+            spool.rewind()?;
+            io::copy(&mut spool, &mut self.writer)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub(crate) fn type_name(t: PrimitiveType) -> &'static str {
+    if t == PrimitiveType::NODE {
+        "node"
+    } else if t == PrimitiveType::WAY {
+        "way"
+    } else if t == PrimitiveType::RELATION {
+        "relation"
+    } else {
+        "changeset"
+    }
+}
+
+pub(crate) fn blob_header(blob_type: &str) -> PbfBlobHeader {
+    let mut header = PbfBlobHeader::new();
+    header.set_type(blob_type.into());
+    header
+}
+
+pub(crate) fn write_frame(
+    out: &mut impl Write,
+    header: &PbfBlobHeader,
+    blob: &PbfBlob,
+) -> Result<()> {
+    let data = blob.write_to_bytes()?;
+    // readers reject larger blobs
+    if data.len() > MAX_UNCOMPRESSED_DATA_SIZE {
+        return Err(Error::BlobDataToLarge);
+    }
+    let mut header = header.clone();
+    header.set_datasize(data.len() as i32);
+    let header = header.write_to_bytes()?;
+    if header.len() > MAX_HEADER_SIZE as usize {
+        return Err(Error::BlobHeaderToLarge);
+    }
+    out.write_u32::<BigEndian>(header.len() as u32)?;
+    out.write_all(&header)?;
+    out.write_all(&data)?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use osm_pbf_proto::element::{Element, OwnedChangeSet};
+use osm_pbf_proto::osmformat::{HeaderBlock, PrimitiveBlock};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::{PbfWriter, WriterOptions, WriterStats};
+use crate::blob::PbfBlob;
+use crate::error::Result;
+
+/// A [`PbfWriter`] that writes to a tokio [`AsyncWrite`].
+///
+/// Blocks are encoded (and compressed, unless
+/// [`WriterOptions::compression_threads`] is set) on the calling task; the
+/// encoded frames are buffered and written asynchronously.
+#[derive(Debug)]
+pub struct AsyncPbfWriter<W> {
+    writer: W,
+    inner: PbfWriter<Vec<u8>>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncPbfWriter<W> {
+    #[inline]
+    pub fn new(writer: W, header: HeaderBlock) -> Self {
+        Self::with_options(writer, header, WriterOptions::default())
+    }
+
+    #[inline]
+    pub fn with_options(writer: W, header: HeaderBlock, options: WriterOptions) -> Self {
+        Self {
+            writer,
+            inner: PbfWriter::with_options(Vec::new(), header, options),
+        }
+    }
+
+    #[inline]
+    pub fn options(&self) -> &WriterOptions {
+        self.inner.options()
+    }
+
+    /// The header, as long as it was not written yet.
+    #[inline]
+    pub fn header_mut(&mut self) -> Option<&mut HeaderBlock> {
+        self.inner.header_mut()
+    }
+
+    /// See [`PbfWriter::set_progress`].
+    #[inline]
+    pub fn set_progress(&mut self, progress: impl FnMut(&WriterStats) + Send + 'static) {
+        self.inner.set_progress(progress);
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &WriterStats {
+        self.inner.stats()
+    }
+
+    /// Writes the buffered frames.
+    async fn drain(&mut self) -> Result<()> {
+        let buffer = self.inner.get_mut();
+        if !buffer.is_empty() {
+            self.writer.write_all(buffer).await?;
+            buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// See [`PbfWriter::write_element`].
+    pub async fn write_element(&mut self, element: &Element) -> Result<()> {
+        self.inner.write_element(element)?;
+        self.drain().await
+    }
+
+    /// See [`PbfWriter::write_changeset`].
+    pub async fn write_changeset(&mut self, changeset: &OwnedChangeSet) -> Result<()> {
+        self.inner.write_changeset(changeset)?;
+        self.drain().await
+    }
+
+    /// See [`PbfWriter::write_primitive_block`].
+    pub async fn write_primitive_block(&mut self, block: &PrimitiveBlock) -> Result<()> {
+        self.inner.write_primitive_block(block)?;
+        self.drain().await
+    }
+
+    /// See [`PbfWriter::write_blob`].
+    pub async fn write_blob(&mut self, blob_type: &str, blob: &PbfBlob) -> Result<()> {
+        self.inner.write_blob(blob_type, blob)?;
+        self.drain().await
+    }
+
+    /// See [`PbfWriter::flush_block`].
+    pub async fn flush_block(&mut self) -> Result<()> {
+        self.inner.flush_block()?;
+        self.drain().await
+    }
+
+    pub async fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        self.drain().await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// See [`PbfWriter::finish`]. Returns the output after it was flushed.
+    pub async fn finish(mut self) -> Result<W> {
+        let rest = self.inner.finish()?;
+        self.writer.write_all(&rest).await?;
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,122 @@
+use osm_pbf_proto::element::{Element, OwnedInfo};
+use osm_pbf_proto::osmformat::{HeaderBBox, HeaderBlock, PrimitiveBlock};
+use osm_pbf_proto::primitives::Primitive;
+use osm_pbf_proto::protobuf::MessageField;
+
+use super::WriterOptions;
+use crate::header::{Feature, HeaderFeatures, HeaderReplication};
+
+/// Default value of `HeaderBlock::writingprogram`.
+pub const WRITING_PROGRAM: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Summary of the written data, used to complete the header.
+#[derive(Clone, Debug, Default)]
+pub struct ContentSummary {
+    pub dense_nodes: bool,
+    pub metadata: bool,
+    pub historical: bool,
+    pub locations_on_ways: bool,
+    /// `(left, right, top, bottom)` in nanodegrees
+    pub bbox: Option<(i64, i64, i64, i64)>,
+}
+
+impl ContentSummary {
+    #[inline]
+    pub fn add_location(&mut self, nano_lat: i64, nano_lon: i64) {
+        self.bbox = Some(match self.bbox {
+            None => (nano_lon, nano_lon, nano_lat, nano_lat),
+            Some((left, right, top, bottom)) => (
+                left.min(nano_lon),
+                right.max(nano_lon),
+                top.max(nano_lat),
+                bottom.min(nano_lat),
+            ),
+        });
+    }
+
+    #[inline]
+    fn add_info(&mut self, info: Option<&OwnedInfo>) {
+        if let Some(info) = info {
+            self.metadata = true;
+            self.historical |= info.visible == Some(false);
+        }
+    }
+
+    pub fn add_element(&mut self, element: &Element) {
+        if let Element::Node(n) = element {
+            self.dense_nodes = true;
+            self.add_location(n.nano_lat, n.nano_lon);
+        }
+        self.add_info(element.info());
+    }
+
+    pub fn add_block(&mut self, block: &PrimitiveBlock) {
+        for group in &block.primitivegroup {
+            self.dense_nodes |= group.dense.is_some();
+        }
+        for p in block.primitives() {
+            let info = match &p {
+                Primitive::Node(n) => {
+                    self.add_location(n.nano_lat, n.nano_lon);
+                    n.info()
+                }
+                Primitive::Way(w) => w.info.clone().unwrap_or_default(),
+                Primitive::Relation(r) => r.info.clone().unwrap_or_default(),
+                _ => continue,
+            };
+            if info.version.is_some() || info.timestamp.is_some() {
+                self.metadata = true;
+            }
+            self.historical |= info.visible == Some(false);
+        }
+    }
+}
+
+/// Adds the features, writing program and bbox derived from the written data.
+///
+/// When the header is not `deferred`, it is written before the data, so
+/// content-derived values only reflect the first block and the bbox is not
+/// computed.
+pub fn complete_header(
+    header: &mut HeaderBlock,
+    summary: &ContentSummary,
+    options: &WriterOptions,
+    deferred: bool,
+) {
+    if summary.dense_nodes || !deferred {
+        header.add_required(Feature::DenseNodes);
+    }
+    if summary.historical || options.historical {
+        header.add_required(Feature::HistoricalInformation);
+    }
+    if summary.metadata {
+        header.add_optional(Feature::HasMetadata);
+    }
+    if summary.locations_on_ways {
+        header.add_optional(Feature::LocationsOnWays);
+    }
+    if options.ordering.is_type_then_id() {
+        header.add_optional(Feature::SortTypeThenId);
+    }
+    if let Some(replication) = &options.replication {
+        header.set_replication(replication);
+    }
+    if header.writingprogram.is_none() {
+        let program = if options.deterministic {
+            env!("CARGO_PKG_NAME")
+        } else {
+            WRITING_PROGRAM
+        };
+        header.writingprogram = Some(program.into());
+    }
+    if deferred && header.bbox.is_none() {
+        if let Some((left, right, top, bottom)) = summary.bbox {
+            let mut bbox = HeaderBBox::new();
+            bbox.set_left(left);
+            bbox.set_right(right);
+            bbox.set_top(top);
+            bbox.set_bottom(bottom);
+            header.bbox = MessageField::some(bbox);
+        }
+    }
+}
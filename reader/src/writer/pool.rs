@@ -0,0 +1,123 @@
+use osm_pbf_proto::osmformat::PrimitiveBlock;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::blob::{Blob, Codec, CompressionOptions, PbfBlob};
+use crate::error::{Error, Result};
+
+type Job = (u64, PrimitiveBlock);
+type Done = (u64, Result<PbfBlob>);
+
+/// Serializes and compresses blocks on worker threads. The encoded blobs are
+/// returned in submission order.
+#[derive(Debug)]
+pub struct CompressionPool {
+    jobs: Option<mpsc::SyncSender<Job>>,
+    results: mpsc::Receiver<Done>,
+    workers: Vec<thread::JoinHandle<()>>,
+    max_in_flight: usize,
+    submitted: u64,
+    received: u64,
+    next: u64,
+    done: BTreeMap<u64, Result<PbfBlob>>,
+}
+
+impl CompressionPool {
+    pub fn new(threads: usize, codec: Codec, compression: CompressionOptions) -> Self {
+        let threads = threads.max(1);
+        let (jobs, job_receiver) = mpsc::sync_channel::<Job>(threads);
+        let (result_sender, results) = mpsc::channel::<Done>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..threads)
+            .map(|_| {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                thread::spawn(move || loop {
+                    let job = jobs.lock().map(|r| r.recv());
+                    let Ok(Ok((seq, block))) = job else {
+                        return;
+                    };
+                    let blob = Blob::encode_message_with(&block, codec, &compression);
+                    if results.send((seq, blob)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            results,
+            workers,
+            max_in_flight: threads * 2,
+            submitted: 0,
+            received: 0,
+            next: 0,
+            done: BTreeMap::new(),
+        }
+    }
+
+    #[inline]
+    fn in_flight(&self) -> usize {
+        (self.submitted - self.received) as usize
+    }
+
+    fn receive(&mut self, wait: bool) -> bool {
+        let result = if wait {
+            self.results.recv().ok()
+        } else {
+            self.results.try_recv().ok()
+        };
+        match result {
+            Some((seq, blob)) => {
+                self.received += 1;
+                self.done.insert(seq, blob);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Queues a block. Blocks while too many blocks are in flight.
+    pub fn submit(&mut self, block: PrimitiveBlock) -> Result<()> {
+        while self.in_flight() >= self.max_in_flight && self.receive(true) {}
+        let sent = self
+            .jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send((self.submitted, block)).is_ok());
+        if !sent {
+            return Err(worker_stopped());
+        }
+        self.submitted += 1;
+        Ok(())
+    }
+
+    /// Returns the blobs that are ready, in submission order. With
+    /// `wait_all`, waits for all submitted blocks.
+    pub fn take_ready(&mut self, wait_all: bool) -> Result<Vec<PbfBlob>> {
+        while self.in_flight() > 0 && self.receive(wait_all) {}
+        if wait_all && self.in_flight() > 0 {
+            return Err(worker_stopped());
+        }
+        let mut ready = Vec::new();
+        while let Some(blob) = self.done.remove(&self.next) {
+            self.next += 1;
+            ready.push(blob?);
+        }
+        Ok(ready)
+    }
+}
+
+fn worker_stopped() -> Error {
+    io::Error::other("compression worker stopped").into()
+}
+
+impl Drop for CompressionPool {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use osm_pbf_proto::element::Element;
+
+use super::{FileSink, PbfWriter, DEFAULT_BLOCK_ELEMENTS};
+use crate::error::{Error, Result};
+
+/// An output that can be flushed to durable storage.
+pub trait Durable: Write {
+    /// Flushes buffered data and waits until it reached the storage device
+    /// (`fsync`).
+    fn sync_data(&mut self) -> io::Result<()>;
+}
+
+impl Durable for File {
+    #[inline]
+    fn sync_data(&mut self) -> io::Result<()> {
+        let file: &Self = self;
+        file.sync_data()
+    }
+}
+
+impl<W: Durable> Durable for io::BufWriter<W> {
+    #[inline]
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync_data()
+    }
+}
+
+impl Durable for FileSink {
+    fn sync_data(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(w) => w.sync_data(),
+            Self::Atomic(w) => w.sync_data(),
+        }
+    }
+}
+
+/// In-memory outputs are never synced.
+impl Durable for Vec<u8> {
+    #[inline]
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Options of a [`QueuedWriter`].
+#[derive(Clone, Debug)]
+pub struct QueueOptions {
+    capacity: usize,
+    flush_interval: Option<Duration>,
+    flush_elements: Option<u64>,
+    sync: bool,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_BLOCK_ELEMENTS,
+            flush_interval: Some(Duration::from_secs(60)),
+            flush_elements: None,
+            sync: true,
+        }
+    }
+}
+
+impl QueueOptions {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of queued elements (8000 by default). Senders are
+    /// blocked while the queue is full.
+    #[inline]
+    pub fn capacity(mut self, elements: usize) -> Self {
+        self.capacity = elements.max(1);
+        self
+    }
+
+    /// Flush at most this long after an element was written (60s by
+    /// default); `None` disables periodic flushes.
+    #[inline]
+    pub fn flush_interval(mut self, interval: Option<Duration>) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Flush after this many elements were written (disabled by default).
+    #[inline]
+    pub fn flush_elements(mut self, elements: Option<u64>) -> Self {
+        self.flush_elements = elements.map(|n| n.max(1));
+        self
+    }
+
+    /// `fsync` the output after each flush (enabled by default).
+    #[inline]
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+}
+
+/// Snapshot of the counters of a [`QueuedWriter`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Elements that were sent to the queue.
+    pub sent: u64,
+    /// Elements that were passed to the writer.
+    pub written: u64,
+    /// Elements that were written before the last flush.
+    pub flushed: u64,
+    pub flushes: u64,
+    /// Number of flushes that were followed by an `fsync`.
+    pub syncs: u64,
+}
+
+impl QueueStats {
+    /// Number of elements in the queue.
+    #[inline]
+    pub fn queued(&self) -> u64 {
+        self.sent.saturating_sub(self.written)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    written: AtomicU64,
+    flushed: AtomicU64,
+    flushes: AtomicU64,
+    syncs: AtomicU64,
+}
+
+enum Command {
+    Element(Box<Element>),
+    /// Flush and sync; the sender is dropped on failure.
+    Sync(mpsc::Sender<()>),
+}
+
+/// A [`PbfWriter`] on a background thread behind a bounded queue.
+///
+/// Senders are blocked while the queue is full, so a fast producer (e.g. a
+/// stage that fetches and applies replication diffs) can't grow the memory
+/// usage. The pending block is flushed periodically (see [`QueueOptions`]),
+/// and optionally synced to disk, so the written elements are durable even
+/// if the process runs for a long time. Use [`QueuedWriter::sync`] before
+/// persisting the progress of the producer (e.g. the replication sequence
+/// number).
+///
+/// Flushes only make data durable when the writer writes directly to its
+/// output, i.e. not with [`WriterOptions::compute_bbox`] or sorting.
+///
+/// [`WriterOptions::compute_bbox`]: super::WriterOptions::compute_bbox
+#[derive(Debug)]
+pub struct QueuedWriter<W> {
+    sender: Option<mpsc::SyncSender<Command>>,
+    worker: Option<JoinHandle<Result<PbfWriter<W>>>>,
+    counters: Arc<Counters>,
+}
+
+impl<W: Durable + Send + 'static> QueuedWriter<W> {
+    pub fn new(writer: PbfWriter<W>, options: QueueOptions) -> Self {
+        let (sender, commands) = mpsc::sync_channel(options.capacity);
+        let counters = Arc::new(Counters::default());
+        let worker = {
+            let counters = counters.clone();
+            thread::spawn(move || run(writer, &commands, &options, &counters))
+        };
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+            counters,
+        }
+    }
+
+    /// Queues `element`, waiting while the queue is full.
+    pub fn send(&mut self, element: Element) -> Result<()> {
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|s| s.send(Command::Element(Box::new(element))).is_ok());
+        if !sent {
+            return Err(self.failed());
+        }
+        self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Queues `element` unless the queue is full; then it is returned.
+    pub fn try_send(&mut self, element: Element) -> Result<Option<Element>> {
+        let Some(sender) = &self.sender else {
+            return Err(self.failed());
+        };
+        match sender.try_send(Command::Element(Box::new(element))) {
+            Ok(()) => {
+                self.counters.sent.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(mpsc::TrySendError::Full(Command::Element(element))) => Ok(Some(*element)),
+            Err(_) => Err(self.failed()),
+        }
+    }
+
+    /// Waits until all queued elements are written, flushed and (unless
+    /// disabled) synced.
+    pub fn sync(&mut self) -> Result<()> {
+        let (done, wait) = mpsc::channel();
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|s| s.send(Command::Sync(done)).is_ok());
+        if !sent || wait.recv().is_err() {
+            return Err(self.failed());
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let c = &self.counters;
+        QueueStats {
+            sent: c.sent.load(Ordering::Relaxed),
+            written: c.written.load(Ordering::Relaxed),
+            flushed: c.flushed.load(Ordering::Relaxed),
+            flushes: c.flushes.load(Ordering::Relaxed),
+            syncs: c.syncs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Writes and flushes the queued elements and returns the writer; call
+    /// [`PbfWriter::finish`] on it to complete the file.
+    pub fn finish(mut self) -> Result<PbfWriter<W>> {
+        self.sender = None;
+        match self.worker.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Err(gone()),
+        }
+    }
+
+    /// Stops the worker and returns its error.
+    fn failed(&mut self) -> Error {
+        self.sender = None;
+        match self.worker.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => e,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            _ => gone(),
+        }
+    }
+}
+
+/// The error of a writer whose failure was already reported.
+fn gone() -> Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "queued writer has failed").into()
+}
+
+impl<W> Drop for QueuedWriter<W> {
+    fn drop(&mut self) {
+        // lets the worker write the queued elements
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run<W: Durable>(
+    mut writer: PbfWriter<W>,
+    commands: &mpsc::Receiver<Command>,
+    options: &QueueOptions,
+    counters: &Counters,
+) -> Result<PbfWriter<W>> {
+    let mut written = 0;
+    let mut flushed = 0;
+    // time of the first element after the last flush
+    let mut pending_since: Option<Instant> = None;
+    loop {
+        let deadline = pending_since
+            .zip(options.flush_interval)
+            .map(|(t, i)| t + i);
+        let command = match deadline {
+            Some(deadline) => {
+                match commands.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(command) => Some(command),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            },
+        };
+        let flush = match command {
+            Some(Command::Element(element)) => {
+                writer.write_element(&element)?;
+                written += 1;
+                counters.written.store(written, Ordering::Relaxed);
+                pending_since.get_or_insert_with(Instant::now);
+                options
+                    .flush_elements
+                    .is_some_and(|n| written - flushed >= n)
+            }
+            Some(Command::Sync(done)) => {
+                flush_writer(&mut writer, options.sync, counters, written)?;
+                flushed = written;
+                pending_since = None;
+                let _ = done.send(());
+                false
+            }
+            None => true,
+        };
+        if flush {
+            flush_writer(&mut writer, options.sync, counters, written)?;
+            flushed = written;
+            pending_since = None;
+        }
+    }
+    if flushed < written {
+        flush_writer(&mut writer, options.sync, counters, written)?;
+    }
+    Ok(writer)
+}
+
+fn flush_writer<W: Durable>(
+    writer: &mut PbfWriter<W>,
+    sync: bool,
+    counters: &Counters,
+    written: u64,
+) -> Result<()> {
+    writer.flush_block()?;
+    writer.flush()?;
+    counters.flushes.fetch_add(1, Ordering::Relaxed);
+    if sync {
+        writer.get_mut().sync_data()?;
+        counters.syncs.fetch_add(1, Ordering::Relaxed);
+    }
+    counters.flushed.store(written, Ordering::Relaxed);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use osm_pbf_proto::element::Element;
+use osm_pbf_proto::primitives::PrimitiveType;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::File;
+use std::io::{self, Seek};
+
+use super::{PbfWriter, WriterOptions};
+use crate::blob::Blobs;
+use crate::error::Result;
+use crate::header::HeaderBlock;
+
+/// Sort key for `Sort.Type_then_ID`: nodes, then ways, then relations, each
+/// ordered by id.
+#[inline]
+pub fn type_then_id_key(element: &Element) -> (u32, i64) {
+    (element.primitive_type().bits(), element.id())
+}
+
+/// Approximate heap + inline size of an element.
+fn memory_size(element: &Element) -> usize {
+    let tags: usize = element
+        .tags()
+        .iter()
+        .map(|(k, v)| 48 + k.len() + v.len())
+        .sum();
+    let info = element
+        .info()
+        .map_or(0, |i| 64 + i.user.as_ref().map_or(0, String::len));
+    let members = match element {
+        Element::Node(_) => 0,
+        Element::Way(w) => w.refs.len() * 8,
+        Element::Relation(r) => r.members.iter().map(|m| 48 + m.role.len()).sum(),
+    };
+    size_of::<Element>() + tags + info + members
+}
+
+/// External merge sort of elements in `Sort.Type_then_ID` order.
+///
+/// Elements are buffered in memory up to `budget` bytes. Whenever the budget
+/// is exceeded, the buffer is sorted and spilled as a temporary PBF file;
+/// the sorted runs are merged on [`ElementSorter::finish`].
+#[derive(Debug)]
+pub struct ElementSorter {
+    buffer: Vec<Element>,
+    buffer_size: usize,
+    budget: usize,
+    runs: Vec<File>,
+}
+
+impl ElementSorter {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            buffer_size: 0,
+            budget,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, element: Element) -> Result<()> {
+        self.buffer_size += memory_size(&element);
+        self.buffer.push(element);
+        if self.buffer_size > self.budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn sort_buffer(&mut self) {
+        // stable: keeps multiple versions of the same element in input order
+        self.buffer.sort_by_key(type_then_id_key);
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        self.sort_buffer();
+        let mut file = tempfile::tempfile()?;
+        {
+            let mut writer = PbfWriter::with_options(
+                io::BufWriter::new(&mut file),
+                HeaderBlock::new(),
+                WriterOptions::new(),
+            );
+            for element in self.buffer.drain(..) {
+                writer.write_element(&element)?;
+            }
+            writer.finish()?;
+        }
+        file.rewind()?;
+        self.runs.push(file);
+        self.buffer_size = 0;
+        Ok(())
+    }
+
+    /// Emits all elements in sorted order.
+    pub fn finish(mut self, mut emit: impl FnMut(Element) -> Result<()>) -> Result<()> {
+        if self.runs.is_empty() {
+            self.sort_buffer();
+            for element in self.buffer.drain(..) {
+                emit(element)?;
+            }
+            return Ok(());
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        let mut runs = Vec::with_capacity(self.runs.len());
+        for file in self.runs.drain(..) {
+            runs.push(RunReader {
+                blobs: Blobs::from_read(file)?,
+                pending: VecDeque::new(),
+            });
+        }
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some(e) = run.next_element()? {
+                heap.push(Reverse(HeapEntry(type_then_id_key(&e), i, e)));
+            }
+        }
+        while let Some(Reverse(HeapEntry(_, i, element))) = heap.pop() {
+            if let Some(e) = runs[i].next_element()? {
+                heap.push(Reverse(HeapEntry(type_then_id_key(&e), i, e)));
+            }
+            emit(element)?;
+        }
+        Ok(())
+    }
+}
+
+struct RunReader {
+    blobs: Blobs<io::BufReader<File>>,
+    pending: VecDeque<Element>,
+}
+
+impl RunReader {
+    fn next_element(&mut self) -> Result<Option<Element>> {
+        loop {
+            if let Some(e) = self.pending.pop_front() {
+                return Ok(Some(e));
+            }
+            let Some(block) = self.blobs.next_primitive_block_decoded()? else {
+                return Ok(None);
+            };
+            self.pending.extend(
+                block
+                    .primitives()
+                    .filter_types(PrimitiveType::DEFAULT)
+                    .filter_map(|p| p.to_element()),
+            );
+        }
+    }
+}
+
+/// Heap entry ordered by key, then by run index (keeps the merge stable).
+struct HeapEntry((u32, i64), usize, Element);
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        (self.0, self.1) == (other.0, other.1)
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.0, self.1).cmp(&(other.0, other.1))
+    }
+}
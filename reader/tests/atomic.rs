@@ -0,0 +1,40 @@
+//! Atomic outputs only appear at their path once they are committed.
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::{Element, OwnedNode};
+use osm_pbf_reader::{Blobs, PbfWriter, WriterOptions};
+
+fn write_node(writer: &mut PbfWriter<osm_pbf_reader::writer::FileSink>) {
+    let node = Element::Node(OwnedNode {
+        id: 1,
+        ..Default::default()
+    });
+    writer.write_element(&node).unwrap();
+}
+
+#[test]
+fn committed_on_finish_and_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.osm.pbf");
+    let options = WriterOptions::new().atomic(true);
+    let mut writer = PbfWriter::create_with_options(&path, HeaderBlock::new(), options).unwrap();
+    write_node(&mut writer);
+    assert!(!path.exists());
+    writer.finish_and_commit().unwrap();
+
+    let blobs = Blobs::from_path(&path).unwrap();
+    assert_eq!(blobs.elements().count(), 1);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn removed_without_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.osm.pbf");
+    let options = WriterOptions::new().atomic(true);
+    let mut writer = PbfWriter::create_with_options(&path, HeaderBlock::new(), options).unwrap();
+    write_node(&mut writer);
+    drop(writer.finish().unwrap());
+    assert!(!path.exists());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
@@ -0,0 +1,97 @@
+//! Applying change sets to a sorted snapshot.
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::primitives::PrimitiveType;
+use osm_pbf_reader::data::{Element, OwnedInfo, OwnedNode, OwnedRelation, OwnedWay};
+use osm_pbf_reader::writer::ElementOrdering;
+use osm_pbf_reader::{apply_changes, Blobs, Changes, PbfWriter, WriterOptions};
+
+fn info(version: i32) -> Option<OwnedInfo> {
+    Some(OwnedInfo {
+        version: Some(version),
+        ..Default::default()
+    })
+}
+
+fn node(id: i64, version: i32) -> Element {
+    Element::Node(OwnedNode {
+        id,
+        info: info(version),
+        ..Default::default()
+    })
+}
+
+/// Nodes 10, 20 and 30 and way 1.
+fn base() -> Vec<u8> {
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    for id in [10, 20, 30] {
+        writer.write_element(&node(id, 1)).unwrap();
+    }
+    let way = Element::Way(OwnedWay {
+        id: 1,
+        info: info(1),
+        ..Default::default()
+    });
+    writer.write_element(&way).unwrap();
+    writer.finish().unwrap()
+}
+
+fn summary(elements: &[Element]) -> Vec<(PrimitiveType, i64, Option<i32>)> {
+    elements
+        .iter()
+        .map(|e| (e.primitive_type(), e.id(), e.info().and_then(|i| i.version)))
+        .collect()
+}
+
+#[test]
+fn changes_are_merged_into_the_base() {
+    let mut first = Changes::new();
+    // before the first and after the last element of the base
+    first.upsert(node(5, 1));
+    first.upsert(Element::Relation(OwnedRelation {
+        id: 1,
+        info: info(1),
+        ..Default::default()
+    }));
+    first.upsert(node(20, 2));
+    first.delete(PrimitiveType::NODE, 30);
+    // not in the base
+    first.delete(PrimitiveType::NODE, 99);
+    first.upsert(node(15, 1));
+
+    let mut second = Changes::new();
+    second.upsert(node(20, 3));
+    second.delete(PrimitiveType::NODE, 15);
+
+    let mut blobs = Blobs::from_bytes(base()).unwrap();
+    let output = apply_changes(
+        &mut blobs,
+        [&first, &second],
+        Vec::new(),
+        WriterOptions::new(),
+    )
+    .unwrap();
+    let elements: Vec<_> = Blobs::from_bytes(output)
+        .unwrap()
+        .elements()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        summary(&elements),
+        [
+            (PrimitiveType::NODE, 5, Some(1)),
+            (PrimitiveType::NODE, 10, Some(1)),
+            (PrimitiveType::NODE, 20, Some(3)),
+            (PrimitiveType::WAY, 1, Some(1)),
+            (PrimitiveType::RELATION, 1, Some(1)),
+        ]
+    );
+
+    // the output is sorted by type and id
+    let options = WriterOptions::new().ordering(ElementOrdering::RequireTypeThenId);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    for element in &elements {
+        writer.write_element(element).unwrap();
+    }
+    writer.finish().unwrap();
+}
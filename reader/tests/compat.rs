@@ -0,0 +1,405 @@
+//! Test vectors that reproduce the layouts and quirks of files written by
+//! common producers (osmosis, osmium, imposm, go osmpbf).
+
+use byteorder::{BigEndian, WriteBytesExt};
+use bytes::Bytes;
+use osm_pbf_proto::fileformat::blob::Data;
+use osm_pbf_proto::osmformat::{DenseInfo, DenseNodes, HeaderBlock, StringTable};
+use osm_pbf_proto::protobuf::{Message, MessageField, UnknownValueRef};
+use osm_pbf_reader::blob::{PbfBlob, PbfBlobHeader};
+use osm_pbf_reader::data::primitives::{Primitive, PrimitiveType};
+use osm_pbf_reader::data::{
+    ChangeSet, ChangeSetComment, Element, Info, Node, PrimitiveBlock, PrimitiveGroup, Way,
+};
+use osm_pbf_reader::writer::WRITING_PROGRAM;
+use osm_pbf_reader::{Blob, Blobs, Codec, FlatBlock, Limits, PbfWriter};
+
+fn frame(out: &mut Vec<u8>, blob_type: &str, blob: &PbfBlob) {
+    let data = blob.write_to_bytes().unwrap();
+    let mut header = PbfBlobHeader::new();
+    header.set_type(blob_type.into());
+    header.set_datasize(data.len() as i32);
+    let header = header.write_to_bytes().unwrap();
+    out.write_u32::<BigEndian>(header.len() as u32).unwrap();
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&data);
+}
+
+fn raw_blob(msg: &impl Message, raw_size: bool) -> PbfBlob {
+    let raw = msg.write_to_bytes().unwrap();
+    let mut blob = PbfBlob::new();
+    if raw_size {
+        blob.raw_size = Some(raw.len() as i32);
+    }
+    blob.data = Some(Data::Raw(Bytes::from(raw)));
+    blob
+}
+
+fn file(program: &str, blocks: &[PbfBlob]) -> Vec<u8> {
+    let mut header = HeaderBlock::new();
+    header.required_features = vec!["OsmSchema-V0.6".into(), "DenseNodes".into()];
+    header.writingprogram = Some(program.into());
+    let mut out = Vec::new();
+    frame(&mut out, "OSMHeader", &raw_blob(&header, true));
+    for block in blocks {
+        frame(&mut out, "OSMData", block);
+    }
+    out
+}
+
+fn string_table(strings: &[&str]) -> MessageField<StringTable> {
+    let mut table = StringTable::new();
+    table.s = strings
+        .iter()
+        .map(|s| Bytes::copy_from_slice(s.as_bytes()))
+        .collect();
+    MessageField::some(table)
+}
+
+fn elements(data: Vec<u8>) -> Vec<Element> {
+    let mut blobs = Blobs::from_bytes(data).unwrap();
+    let mut elements = Vec::new();
+    while let Some(block) = blobs.next_primitive_block_decoded().unwrap() {
+        elements.extend(block.primitives().filter_map(|p| p.to_element()));
+    }
+    elements
+}
+
+fn dense_block() -> PrimitiveBlock {
+    let mut dense = DenseNodes::new();
+    dense.id = vec![1, 1, 1];
+    dense.lat = vec![515_000_000, 10, -20];
+    dense.lon = vec![-1_000_000, 10, 20];
+    dense.keys_vals = vec![1, 2, 0, 0, 0];
+    let mut info = DenseInfo::new();
+    info.version = vec![1, 2, 1];
+    info.timestamp = vec![1_600_000_000, 60, 60];
+    info.changeset = vec![100, 1, 1];
+    info.uid = vec![7, 0, 0];
+    info.user_sid = vec![3, 0, 0];
+    dense.denseinfo = MessageField::some(info);
+
+    let mut group = PrimitiveGroup::new();
+    group.dense = MessageField::some(dense);
+    let mut block = PrimitiveBlock::new();
+    block.stringtable = string_table(&["", "highway", "crossing", "mapper"]);
+    block.primitivegroup = vec![group];
+    block
+}
+
+#[test]
+fn osmosis_zlib_dense_with_metadata() {
+    let blob = osm_pbf_reader::Blob::encode_message(&dense_block(), Codec::Zlib).unwrap();
+    let elements = elements(file("Osmosis 0.48.3", &[blob]));
+    assert_eq!(elements.len(), 3);
+    let Element::Node(first) = &elements[0] else {
+        panic!("expected a node");
+    };
+    assert_eq!(first.id, 1);
+    assert_eq!(first.nano_lat, 51_500_000_000);
+    assert_eq!(first.tags, vec![("highway".into(), "crossing".into())]);
+    let info = first.info.as_ref().unwrap();
+    assert_eq!(info.timestamp, Some(1_600_000_000_000));
+    assert_eq!(info.user.as_deref(), Some("mapper"));
+    let Element::Node(last) = &elements[2] else {
+        panic!("expected a node");
+    };
+    assert_eq!(last.id, 3);
+    assert!(last.tags.is_empty());
+    assert_eq!(last.info.as_ref().unwrap().changeset, Some(102));
+}
+
+#[test]
+fn imposm_raw_blobs_without_raw_size() {
+    let blob = raw_blob(&dense_block(), false);
+    assert_eq!(elements(file("imposm", &[blob])).len(), 3);
+}
+
+#[test]
+fn empty_blobs() {
+    // neither data nor raw_size
+    let empty = PbfBlob::new();
+    // an empty block without the (required) string table
+    let mut raw_empty = PbfBlob::new();
+    raw_empty.raw_size = Some(0);
+    raw_empty.data = Some(Data::Raw(Bytes::new()));
+    let blocks = [empty, raw_empty, raw_blob(&dense_block(), true)];
+    assert_eq!(elements(file("go-osmpbf", &blocks)).len(), 3);
+}
+
+#[test]
+fn unknown_blob_types_are_skipped() {
+    let mut data = file("osmium/1.16.0", &[raw_blob(&dense_block(), true)]);
+    frame(&mut data, "OSMIndex", &raw_blob(&HeaderBlock::new(), true));
+    frame(&mut data, "OSMData", &raw_blob(&dense_block(), true));
+    assert_eq!(elements(data).len(), 6);
+}
+
+#[test]
+fn plain_nodes_and_mixed_groups() {
+    let mut node = Node::new();
+    node.set_id(10);
+    node.set_lat(100);
+    node.set_lon(200);
+    node.keys = vec![1];
+    node.vals = vec![2];
+    let mut info = Info::new();
+    info.set_version(3);
+    node.info = MessageField::some(info);
+    let mut way = Way::new();
+    way.set_id(20);
+    way.refs = [10, 1, 1].into_iter().collect();
+
+    // all kinds in a single group
+    let mut group = dense_block().primitivegroup.remove(0);
+    group.nodes = vec![node];
+    group.ways = vec![way];
+    let mut block = dense_block();
+    block.primitivegroup = vec![group];
+
+    let elements = elements(file("go-osmpbf", &[raw_blob(&block, true)]));
+    let ids: Vec<_> = elements
+        .iter()
+        .map(|e| (e.primitive_type().bits(), e.id()))
+        .collect();
+    assert_eq!(ids, vec![(1, 10), (1, 1), (1, 2), (1, 3), (2, 20)]);
+    let Element::Node(node) = &elements[0] else {
+        panic!("expected a node");
+    };
+    assert_eq!(node.nano_lat, 10_000);
+    assert_eq!(node.tags, vec![("highway".into(), "crossing".into())]);
+    let Element::Way(way) = &elements[4] else {
+        panic!("expected a way");
+    };
+    assert_eq!(way.refs[..], [10, 11, 12]);
+}
+
+#[test]
+fn repeated_groups_of_the_same_type() {
+    // dense nodes and ways split across several groups; every group is
+    // delta coded on its own
+    let dense = dense_block().primitivegroup.remove(0);
+    let mut second = dense.clone();
+    let nodes = second.dense.as_mut().unwrap();
+    nodes.id = vec![4, 1];
+    nodes.lat = vec![100, 1];
+    nodes.lon = vec![200, 2];
+    nodes.keys_vals = vec![0, 1, 2, 0];
+    let info = nodes.denseinfo.as_mut().unwrap();
+    info.version = vec![1, 1];
+    info.timestamp = vec![1_600_000_000, 1];
+    info.changeset = vec![200, 0];
+    info.uid = vec![7, 0];
+    info.user_sid = vec![3, 0];
+    let way_group = |id: i64| {
+        let mut way = Way::new();
+        way.set_id(id);
+        way.refs = [1, 1].into_iter().collect();
+        let mut group = PrimitiveGroup::new();
+        group.ways = vec![way];
+        group
+    };
+    let mut block = dense_block();
+    block.primitivegroup = vec![dense, second, way_group(20), way_group(21)];
+
+    let expected = vec![(1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (2, 20), (2, 21)];
+    let ids = |chunk: usize| -> Vec<_> {
+        block
+            .primitives()
+            .chunked(chunk)
+            .filter_map(|p| p.to_element())
+            .map(|e| (e.primitive_type().bits(), e.id()))
+            .collect()
+    };
+    for chunk in [0, 1, 2, 1024] {
+        assert_eq!(ids(chunk), expected, "chunk size {chunk}");
+    }
+    let per_group: Vec<usize> = (0..4)
+        .map(|i| block.primitivegroup(i).unwrap().primitives().count())
+        .collect();
+    assert_eq!(per_group, vec![3, 2, 1, 1]);
+    let ways = block.primitives().filter_types(PrimitiveType::WAY).count();
+    assert_eq!(ways, 2);
+
+    let elements = elements(file("go-osmpbf", &[raw_blob(&block, true)]));
+    assert_eq!(elements.len(), 7);
+    let Element::Node(node) = &elements[3] else {
+        panic!("expected a node");
+    };
+    assert_eq!((node.nano_lat, node.nano_lon), (10_000, 20_000));
+    assert!(node.tags.is_empty());
+    let info = node.info.as_ref().unwrap();
+    assert_eq!(info.changeset, Some(200));
+    assert_eq!(info.user.as_deref(), Some("mapper"));
+    let Element::Node(node) = &elements[4] else {
+        panic!("expected a node");
+    };
+    assert_eq!(node.tags, vec![("highway".into(), "crossing".into())]);
+    assert_eq!(
+        node.info.as_ref().unwrap().timestamp,
+        Some(1_600_000_001_000)
+    );
+
+    let limits = Limits::new().max_elements_per_block(6);
+    assert!(limits.check_primitive_block(&block).is_err());
+    assert!(limits
+        .max_elements_per_block(7)
+        .check_primitive_block(&block)
+        .is_ok());
+
+    // columns of all dense groups; the keys_vals are concatenated
+    let nano_lats = vec![
+        51_500_000_000,
+        51_500_001_000,
+        51_499_999_000,
+        10_000,
+        10_100,
+    ];
+    let view = block.dense_nodes();
+    assert_eq!(view.ids, vec![1, 2, 3, 4, 5]);
+    assert_eq!(view.nano_lats, nano_lats);
+    assert_eq!(view.nano_lons[3..], [20_000, 20_200]);
+    assert_eq!(view.keys_vals(), [1, 2, 0, 0, 0, 0, 1, 2, 0]);
+    let tags: Vec<Vec<_>> = (0..5).map(|i| view.tags(i).collect()).collect();
+    let crossing = vec![("highway", "crossing")];
+    assert_eq!(
+        tags,
+        vec![crossing.clone(), vec![], vec![], vec![], crossing]
+    );
+
+    // wire-level filtering keeps all groups of the requested type
+    let blob = raw_blob(&block, true);
+    let encoded = Blob::<PrimitiveBlock>::Encoded(blob.clone());
+    let ids = |types: PrimitiveType| -> Vec<i64> {
+        let block = encoded.decode_types(types, &Limits::new()).unwrap();
+        let ids = block.primitives().filter_map(|p| p.to_element());
+        ids.map(|e| e.id()).collect()
+    };
+    assert_eq!(ids(PrimitiveType::NODE), vec![1, 2, 3, 4, 5]);
+    assert_eq!(ids(PrimitiveType::WAY), vec![20, 21]);
+
+    let mut flat = FlatBlock::new();
+    flat.decode_blob(&blob, &Limits::new()).unwrap();
+    let nodes = flat.nodes();
+    assert_eq!(nodes.ids, vec![1, 2, 3, 4, 5]);
+    assert_eq!(nodes.nano_lats, nano_lats);
+    assert_eq!(nodes.nano_lons[3..], [20_000, 20_200]);
+    let tags: Vec<_> = (0..5).map(|i| nodes.tags(i).to_vec()).collect();
+    assert_eq!(
+        tags,
+        vec![vec![(1, 2)], vec![], vec![], vec![], vec![(1, 2)]]
+    );
+    assert_eq!(flat.ways().ids, vec![20, 21]);
+    assert_eq!(flat.ways().refs, vec![1, 2, 1, 2]);
+}
+
+#[test]
+fn dense_nodes_without_info_and_tags() {
+    let mut block = dense_block();
+    let dense = block.primitivegroup[0].dense.as_mut().unwrap();
+    dense.keys_vals.clear();
+    dense.denseinfo.clear();
+    let elements = elements(file("osmium/1.16.0", &[raw_blob(&block, true)]));
+    assert_eq!(elements.len(), 3);
+    assert!(elements
+        .iter()
+        .all(|e| e.tags().is_empty() && e.info().is_none()));
+}
+
+#[test]
+fn history_with_visible_flags() {
+    let mut block = dense_block();
+    let dense = block.primitivegroup[0].dense.as_mut().unwrap();
+    dense.denseinfo.as_mut().unwrap().visible = vec![true, true, false];
+    let elements = elements(file("osmium/1.16.0", &[raw_blob(&block, true)]));
+    let visible: Vec<_> = elements
+        .iter()
+        .map(|e| e.info().and_then(|i| i.visible))
+        .collect();
+    assert_eq!(visible, vec![Some(true), Some(true), Some(false)]);
+}
+
+/// The changeset extension of this crate (`proto/src/changeset.proto`); no
+/// other producer is known to write it.
+#[test]
+fn changeset_dump_with_discussion() {
+    let mut comment = ChangeSetComment::new();
+    comment.set_date(1_600_000_100);
+    comment.set_uid(8);
+    comment.set_user_sid(3);
+    comment.set_text_sid(4);
+    let mut changeset = ChangeSet::new();
+    changeset.set_id(42);
+    changeset.keys = vec![1];
+    changeset.vals = vec![2];
+    changeset.set_created_at(1_600_000_000);
+    changeset.set_closetime_delta(60);
+    changeset.set_comments_count(1);
+    changeset.discussion = vec![comment];
+    let mut group = PrimitiveGroup::new();
+    group.changesets = vec![changeset];
+    let mut block = PrimitiveBlock::new();
+    block.stringtable = string_table(&["", "comment", "fix", "mapper", "thanks"]);
+    block.primitivegroup = vec![group];
+
+    let data = file(WRITING_PROGRAM, &[raw_blob(&block, true)]);
+    let mut blobs = Blobs::from_bytes(data).unwrap();
+    let block = blobs.next_primitive_block_decoded().unwrap().unwrap();
+    let mut primitives = block.primitives().filter_types(PrimitiveType::CHANGE_SET);
+    let Some(Primitive::ChangeSet(changeset)) = primitives.next() else {
+        panic!("expected a changeset");
+    };
+    assert_eq!(changeset.id(), 42);
+    assert_eq!(
+        changeset.tags().collect::<Vec<_>>(),
+        vec![("comment", "fix")]
+    );
+    assert_eq!(changeset.closed_at(), Some(1_600_000_060_000));
+    assert_eq!(changeset.comments_count(), 1);
+    let comments: Vec<_> = changeset.discussion().collect();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].user, "mapper");
+    assert_eq!(comments[0].text, "thanks");
+    assert_eq!(comments[0].timestamp, Some(1_600_000_100_000));
+}
+
+#[test]
+fn vendor_extension_fields_round_trip() {
+    let mut way = Way::new();
+    way.set_id(20);
+    way.refs = [1, 1].into_iter().collect();
+    way.special_fields.mut_unknown_fields().add_varint(1000, 42);
+    let mut node = Node::new();
+    node.set_id(10);
+    node.set_lat(1);
+    node.set_lon(2);
+    node.special_fields
+        .mut_unknown_fields()
+        .add_length_delimited(1001, b"x".to_vec());
+    let mut block = dense_block();
+    let mut nodes = PrimitiveGroup::new();
+    nodes.nodes = vec![node];
+    let mut ways = PrimitiveGroup::new();
+    ways.ways = vec![way];
+    block.primitivegroup.push(nodes);
+    block.primitivegroup.push(ways);
+
+    let read = elements(file("vendor", &[raw_blob(&block, true)]));
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    for element in &read {
+        writer.write_element(element).unwrap();
+    }
+    let written = elements(writer.finish().unwrap());
+    assert_eq!(read, written);
+    let Element::Way(way) = &written[4] else {
+        panic!("expected a way");
+    };
+    assert_eq!(
+        way.unknown_fields.get(1000),
+        Some(UnknownValueRef::Varint(42))
+    );
+    let Element::Node(node) = &written[3] else {
+        panic!("expected a node");
+    };
+    assert!(node.unknown_fields.get(1001).is_some());
+}
@@ -0,0 +1,218 @@
+//! Blob codecs and recompression of whole files.
+
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "bzip2"))]
+use osm_pbf_proto::fileformat::blob::Data;
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::{Element, OwnedNode, OwnedWay};
+use osm_pbf_reader::error::Error;
+use osm_pbf_reader::wire;
+use osm_pbf_reader::{
+    transcode, Blobs, Codec, CompressionOptions, CompressionSurvey, Limits, PbfWriter,
+    WriterOptions,
+};
+
+/// Nodes and ways in several blocks compressed with `codec`.
+fn sample(codec: Codec) -> Vec<u8> {
+    let options = WriterOptions::new().codec(codec).block_elements(100);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    for id in 1..=250 {
+        let node = Element::Node(OwnedNode {
+            id,
+            nano_lat: id * 1_000_000,
+            nano_lon: -id * 2_000_000,
+            tags: vec![("amenity".into(), format!("bench {}", id % 7))],
+            ..Default::default()
+        });
+        writer.write_element(&node).unwrap();
+    }
+    for id in 1..=50 {
+        let way = Element::Way(OwnedWay {
+            id,
+            refs: (id..id + 5).collect(),
+            tags: vec![("highway".into(), "residential".into())],
+            ..Default::default()
+        });
+        writer.write_element(&way).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn blocks(data: Vec<u8>) -> Vec<osm_pbf_reader::data::PrimitiveBlock> {
+    let mut blobs = Blobs::from_bytes(data).unwrap();
+    let mut blocks = Vec::new();
+    while let Some(block) = blobs.next_primitive_block_decoded().unwrap() {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Codecs of the data blobs of a file.
+fn codecs(data: Vec<u8>) -> Vec<Option<Codec>> {
+    let mut blobs = Blobs::from_bytes(data).unwrap();
+    let mut codecs = Vec::new();
+    while let Some((_, blob)) = blobs.next_blob().unwrap() {
+        codecs.push(blob.data.as_ref().and_then(Codec::of));
+    }
+    codecs
+}
+
+fn transcoded(input: &[u8], codec: Codec) -> Vec<u8> {
+    let mut blobs = Blobs::from_bytes(input.to_vec()).unwrap();
+    transcode(
+        &mut blobs,
+        Vec::new(),
+        codec,
+        &CompressionOptions::default(),
+    )
+    .unwrap()
+}
+
+fn assert_transcodes_to(codec: Codec) {
+    let input = sample(Codec::Zlib);
+    let output = transcoded(&input, codec);
+    assert_eq!(codecs(output.clone()), vec![Some(codec); 3]);
+    let expected = blocks(input);
+    assert_eq!(expected.len(), 3);
+    assert_eq!(blocks(output), expected);
+}
+
+#[test]
+fn transcode_to_raw() {
+    assert_transcodes_to(Codec::Raw);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn transcode_to_zstd() {
+    assert_transcodes_to(Codec::Zstd);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn transcode_to_lz4() {
+    assert_transcodes_to(Codec::Lz4);
+}
+
+/// The data blobs of [`sample`] with their payloads replaced by `compress`.
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "bzip2"))]
+fn recompressed(compress: impl Fn(Vec<u8>) -> Data) -> Vec<u8> {
+    let mut blobs = Blobs::from_bytes(sample(Codec::Raw)).unwrap();
+    let mut writer = PbfWriter::new(Vec::new(), blobs.header().clone());
+    while let Some((header, mut blob)) = blobs.next_blob().unwrap() {
+        let raw = Codec::decompress_blob(&blob).unwrap();
+        blob.raw_size = Some(raw.len() as i32);
+        blob.data = Some(compress(raw));
+        writer.write_blob(header.type_(), &blob).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+/// Compresses the blobs with `codec` and reads them back.
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+fn assert_round_trips(codec: Codec) {
+    let output = recompressed(|raw| codec.compress(raw, None).unwrap());
+    assert_eq!(codecs(output.clone()), vec![Some(codec); 3]);
+    assert_eq!(blocks(output), blocks(sample(Codec::Raw)));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_round_trip() {
+    assert_round_trips(Codec::Zstd);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn lz4_round_trip() {
+    assert_round_trips(Codec::Lz4);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn lz4_respects_limits() {
+    let output = recompressed(|raw| Codec::Lz4.compress(raw, None).unwrap());
+    let mut blobs = Blobs::from_bytes(output.clone()).unwrap();
+    let (_, blob) = blobs.next_blob().unwrap().unwrap();
+    let mut raw = Vec::new();
+    assert!(matches!(
+        Codec::decompress_blob_into(&blob, &mut raw, 64),
+        Err(Error::BlobDataToLarge)
+    ));
+
+    let limits = Limits::new().max_decompressed_size(64);
+    let mut blobs = Blobs::from_bytes(output).unwrap().with_limits(limits);
+    assert!(matches!(
+        blobs.next_primitive_block_decoded(),
+        Err(Error::BlobDataToLarge)
+    ));
+}
+
+/// bzip2 is obsolete and only decoded, so the payloads are compressed here.
+#[cfg(feature = "bzip2")]
+#[test]
+fn bzip2_round_trip() {
+    use std::io::Write;
+
+    let output = recompressed(|raw| {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::best());
+        encoder.write_all(&raw).unwrap();
+        Data::OBSOLETEBzip2Data(encoder.finish().unwrap().into())
+    });
+    let mut blobs = Blobs::from_bytes(output.clone()).unwrap();
+    while let Some((_, blob)) = blobs.next_blob().unwrap() {
+        assert!(matches!(blob.data, Some(Data::OBSOLETEBzip2Data(_))));
+    }
+    assert_eq!(blocks(output), blocks(sample(Codec::Raw)));
+}
+
+#[test]
+fn transcode_respects_limits() {
+    let input = sample(Codec::Zlib);
+    let limits = Limits::new().max_decompressed_size(64);
+    let mut blobs = Blobs::from_bytes(input).unwrap().with_limits(limits);
+    let options = CompressionOptions::default();
+    match transcode(&mut blobs, Vec::new(), Codec::Raw, &options) {
+        Err(Error::BlobDataToLarge) => {}
+        other => panic!("expected BlobDataToLarge, got {:?}", other.map(|o| o.len())),
+    }
+}
+
+#[test]
+fn survey_skips_blobs_over_the_limits() {
+    let survey = CompressionSurvey::new()
+        .sample_every(1)
+        .candidates(vec![(Codec::Zlib, Some(9))]);
+    let mut blobs = Blobs::from_bytes(sample(Codec::Zlib)).unwrap();
+    let report = survey.run(&mut blobs).unwrap();
+    assert_eq!(report.unsampled, 0);
+    assert_eq!(report.recompression[0].original.blobs, 3);
+
+    let limits = Limits::new().max_decompressed_size(64);
+    let mut blobs = Blobs::from_bytes(sample(Codec::Zlib))
+        .unwrap()
+        .with_limits(limits);
+    let report = survey.run(&mut blobs).unwrap();
+    assert_eq!(report.unsampled, 3);
+    assert_eq!(report.recompression[0].original.blobs, 0);
+    assert_eq!(report.codecs[&Codec::Zlib].blobs, 3);
+}
+
+#[test]
+fn wire_payload_respects_limits() {
+    let mut blobs = Blobs::from_bytes(sample(Codec::Zlib)).unwrap();
+    let (_, blob) = blobs.next_blob().unwrap().unwrap();
+    let payload = wire::payload(&blob, &Limits::new()).unwrap();
+    assert_eq!(payload.len(), blob.raw_size.unwrap() as usize);
+
+    let limits = Limits::new().max_decompressed_size(64);
+    assert!(matches!(
+        wire::payload(&blob, &limits),
+        Err(Error::BlobDataToLarge)
+    ));
+    let mut unchecked = blob.clone();
+    unchecked.raw_size = None;
+    assert!(matches!(
+        wire::payload(&unchecked, &limits),
+        Err(Error::BlobDataToLarge)
+    ));
+}
@@ -0,0 +1,191 @@
+//! Corrupted input must be reported as an error, never as a panic.
+//!
+//! A small valid file is truncated at every offset and has bits flipped at
+//! every offset; every variant, and every input of the regression corpus in
+//! `tests/corpus`, is read through the decoding paths of the crate. The
+//! same paths are exercised by the fuzz target in `fuzz/`.
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use bytes::Bytes;
+use osm_pbf_proto::fileformat::blob::Data;
+use osm_pbf_proto::osmformat::{DenseInfo, DenseNodes, HeaderBlock, Relation, StringTable};
+use osm_pbf_proto::primitives::PrimitiveType;
+use osm_pbf_proto::protobuf::{Message, MessageField};
+use osm_pbf_reader::blob::{PbfBlob, PbfBlobHeader};
+use osm_pbf_reader::data::{PrimitiveBlock, PrimitiveGroup, Way};
+use osm_pbf_reader::wire::{self, Fields};
+use osm_pbf_reader::{Blobs, FlatBlock, Limits};
+
+fn frame(out: &mut Vec<u8>, blob_type: &str, msg: &impl Message) {
+    let mut blob = PbfBlob::new();
+    blob.data = Some(Data::Raw(Bytes::from(msg.write_to_bytes().unwrap())));
+    let data = blob.write_to_bytes().unwrap();
+    let mut header = PbfBlobHeader::new();
+    header.set_type(blob_type.into());
+    header.set_datasize(data.len() as i32);
+    let header = header.write_to_bytes().unwrap();
+    out.write_u32::<BigEndian>(header.len() as u32).unwrap();
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&data);
+}
+
+fn sample() -> Vec<u8> {
+    let mut dense = DenseNodes::new();
+    dense.id = vec![1, 1, 1];
+    dense.lat = vec![515_000_000, 10, -20];
+    dense.lon = vec![-1_000_000, 10, 20];
+    dense.keys_vals = vec![1, 2, 0, 0, 1, 2, 0];
+    let mut info = DenseInfo::new();
+    info.version = vec![1, 2, 1];
+    info.timestamp = vec![1_600_000_000, 60, 60];
+    info.changeset = vec![100, 1, 1];
+    info.uid = vec![7, 0, 0];
+    info.user_sid = vec![3, 0, 0];
+    dense.denseinfo = MessageField::some(info);
+    let mut nodes = PrimitiveGroup::new();
+    nodes.dense = MessageField::some(dense);
+
+    let mut way = Way::new();
+    way.set_id(10);
+    way.keys = vec![1];
+    way.vals = vec![2];
+    way.refs = vec![1, 1, 1];
+    let mut ways = PrimitiveGroup::new();
+    ways.ways = vec![way];
+
+    let mut relation = Relation::new();
+    relation.set_id(20);
+    relation.roles_sid = vec![3];
+    relation.memids = vec![10];
+    relation.types = vec![Default::default()];
+    let mut relations = PrimitiveGroup::new();
+    relations.relations = vec![relation];
+
+    let mut table = StringTable::new();
+    table.s = ["", "highway", "crossing", "mapper"]
+        .iter()
+        .map(|s| Bytes::from_static(s.as_bytes()))
+        .collect();
+    let mut block = PrimitiveBlock::new();
+    block.stringtable = MessageField::some(table);
+    block.primitivegroup = vec![nodes, ways, relations];
+
+    let mut header = HeaderBlock::new();
+    header.required_features = vec!["OsmSchema-V0.6".into(), "DenseNodes".into()];
+    let mut out = Vec::new();
+    frame(&mut out, "OSMHeader", &header);
+    frame(&mut out, "OSMData", &block);
+    out
+}
+
+/// Reads `data` through all decoding paths, ignoring errors.
+fn read_all(data: &[u8]) {
+    let data = Bytes::copy_from_slice(data);
+    if let Ok(mut blobs) = Blobs::from_bytes(data.clone()) {
+        while let Ok(Some(block)) = blobs.next_primitive_block_decoded() {
+            for primitive in block.primitives() {
+                let _ = primitive.to_element();
+            }
+        }
+    }
+    if let Ok(mut blobs) = Blobs::from_bytes(data.clone()) {
+        let mut block = FlatBlock::new();
+        while let Ok(true) = blobs.next_flat_block(&mut block) {
+            let ways = block.ways();
+            for i in 0..ways.len() {
+                for &(k, v) in ways.tags(i) {
+                    let _ = (block.str(k), block.str(v));
+                }
+                let _ = ways.refs(i);
+            }
+            let relations = block.relations();
+            for i in 0..relations.len() {
+                let _ = relations.members(i);
+            }
+            for i in 0..block.nodes().len() {
+                let _ = block.nodes().tags(i);
+            }
+        }
+    }
+    if let Ok(mut blobs) = Blobs::from_bytes(data) {
+        let mut block = FlatBlock::new();
+        let mut out = Vec::new();
+        while let Ok(Some((_, blob))) = blobs.next_blob() {
+            let _ = block.decode_blob(&blob, &Limits::new());
+            if let Ok(payload) = wire::payload(&blob, &Limits::new()) {
+                walk(Fields::new(&payload), 0);
+                for types in [
+                    PrimitiveType::NODE,
+                    PrimitiveType::WAY | PrimitiveType::RELATION,
+                ] {
+                    out.clear();
+                    let _ = wire::retain_types(&payload, types, &mut out);
+                }
+            }
+        }
+    }
+}
+
+fn walk(fields: Fields<'_>, depth: usize) {
+    for field in fields {
+        let Ok(field) = field else {
+            return;
+        };
+        if depth < 4 {
+            if let Some(message) = field.message() {
+                walk(message, depth + 1);
+            }
+        }
+        if let Some(packed) = field.packed() {
+            let _ = wire::delta(packed.map(|v| v.map(wire::zigzag))).count();
+        }
+    }
+}
+
+fn assert_no_panic(data: &[u8], what: &str) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| read_all(data)));
+    assert!(result.is_ok(), "panicked on {what}");
+}
+
+#[test]
+fn sample_is_valid() {
+    let mut blobs = Blobs::from_bytes(sample()).unwrap();
+    let block = blobs.next_primitive_block_decoded().unwrap().unwrap();
+    assert_eq!(block.primitives().count(), 5);
+}
+
+#[test]
+fn truncated_input() {
+    let data = sample();
+    for len in 0..data.len() {
+        assert_no_panic(&data[..len], &format!("input truncated to {len} bytes"));
+    }
+}
+
+#[test]
+fn flipped_bits() {
+    let data = sample();
+    for offset in 0..data.len() {
+        for mask in [0x01, 0x10, 0x80, 0xff] {
+            let mut corrupt = data.clone();
+            corrupt[offset] ^= mask;
+            assert_no_panic(&corrupt, &format!("byte {offset} xor {mask:#x}"));
+        }
+    }
+}
+
+#[test]
+fn regression_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        assert_no_panic(&fs::read(&path).unwrap(), &path.display().to_string());
+        count += 1;
+    }
+    assert!(count > 0, "empty corpus");
+}
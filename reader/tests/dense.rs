@@ -0,0 +1,80 @@
+//! Delta decoding of dense nodes, compared with a naive running sum.
+
+use osm_pbf_proto::osmformat::DenseNodes;
+use osm_pbf_proto::protobuf::MessageField;
+use osm_pbf_reader::data::primitives::Primitive;
+use osm_pbf_reader::data::{PrimitiveBlock, PrimitiveGroup};
+
+/// Deltas with both signs and no pattern of period 4.
+fn deltas(len: usize, seed: i64) -> Vec<i64> {
+    (0..len as i64)
+        .map(|i| (i * 7919 + seed) % 1000 - 500)
+        .collect()
+}
+
+/// Running sums of `deltas`; missing deltas count as `0`.
+fn running_sum(deltas: &[i64], len: usize) -> Vec<i64> {
+    let mut acc = 0;
+    (0..len)
+        .map(|i| {
+            acc += deltas.get(i).copied().unwrap_or(0);
+            acc
+        })
+        .collect()
+}
+
+fn block(id: Vec<i64>, lat: Vec<i64>, lon: Vec<i64>) -> PrimitiveBlock {
+    let mut dense = DenseNodes::new();
+    dense.id = id;
+    dense.lat = lat;
+    dense.lon = lon;
+    let mut group = PrimitiveGroup::new();
+    group.dense = MessageField::some(dense);
+    let mut block = PrimitiveBlock::new();
+    block.primitivegroup = vec![group];
+    block
+}
+
+fn assert_decoded(len: usize, lat_len: usize, lon_len: usize) {
+    let (id, lat, lon) = (deltas(len, 1), deltas(lat_len, 2), deltas(lon_len, 3));
+    let ids = running_sum(&id, len);
+    let scale = |v: Vec<i64>| -> Vec<i64> { v.into_iter().map(|v| v * 100).collect() };
+    let nano_lats = scale(running_sum(&lat, len));
+    let nano_lons = scale(running_sum(&lon, len));
+    let block = block(id, lat, lon);
+    let what = format!("{len} nodes, {lat_len} lats, {lon_len} lons");
+
+    let view = block.dense_nodes();
+    assert_eq!(view.ids, ids, "{what}");
+    assert_eq!(view.nano_lats, nano_lats, "{what}");
+    assert_eq!(view.nano_lons, nano_lons, "{what}");
+
+    let nodes: Vec<_> = block
+        .primitives()
+        .filter_map(|p| match p {
+            Primitive::Node(n) => Some((n.id, n.nano_lat, n.nano_lon)),
+            _ => None,
+        })
+        .collect();
+    let expected: Vec<_> = (0..len)
+        .map(|i| (ids[i], nano_lats[i], nano_lons[i]))
+        .collect();
+    assert_eq!(nodes, expected, "{what}");
+}
+
+#[test]
+fn chunks_and_remainders() {
+    for len in 0..=9 {
+        assert_decoded(len, len, len);
+    }
+    assert_decoded(10_000, 10_000, 10_000);
+}
+
+#[test]
+fn truncated_columns_repeat_the_last_value() {
+    for len in 0..=9 {
+        assert_decoded(len, len / 2, 0);
+        assert_decoded(len, len.saturating_sub(1), len / 3);
+    }
+    assert_decoded(10_000, 4_099, 5);
+}
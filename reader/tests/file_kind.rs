@@ -0,0 +1,130 @@
+//! APIs that only support some kinds of files reject the others.
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::primitives::{ChangeSetRef, PrimitiveType};
+use osm_pbf_reader::data::{Element, OwnedChangeSet, OwnedInfo, OwnedNode};
+use osm_pbf_reader::error::Error;
+use osm_pbf_reader::{
+    Blobs, Changes, Extract, FileKind, MetaFilter, PbfWriter, TimeFilter, Visitor,
+};
+
+fn node(id: i64, version: i32) -> Element {
+    Element::Node(OwnedNode {
+        id,
+        info: Some(OwnedInfo {
+            version: Some(version),
+            timestamp: Some(1_600_000_000_000),
+            changeset: Some(100),
+            uid: Some(7),
+            user: Some("mapper".into()),
+            visible: Some(true),
+        }),
+        ..Default::default()
+    })
+}
+
+fn snapshot() -> Vec<u8> {
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    writer.write_element(&node(1, 1)).unwrap();
+    writer.write_element(&node(2, 1)).unwrap();
+    writer.finish().unwrap()
+}
+
+/// Two versions of the same node.
+fn history() -> Vec<u8> {
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    writer.write_element(&node(1, 1)).unwrap();
+    writer.write_element(&node(1, 2)).unwrap();
+    writer.finish().unwrap()
+}
+
+/// A modification and a deletion.
+fn change() -> Vec<u8> {
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    writer.write_element(&node(1, 2)).unwrap();
+    let mut deleted = node(2, 2);
+    deleted.info_mut().as_mut().unwrap().visible = Some(false);
+    writer.write_element(&deleted).unwrap();
+    writer.finish().unwrap()
+}
+
+fn changeset_dump() -> Vec<u8> {
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    let changeset = OwnedChangeSet {
+        id: 100,
+        ..Default::default()
+    };
+    writer.write_changeset(&changeset).unwrap();
+    writer.finish().unwrap()
+}
+
+fn assert_kind<T>(result: Result<T, Error>, expected: FileKind) {
+    match result {
+        Err(Error::UnexpectedFileKind(kind)) => assert_eq!(kind, expected),
+        Err(e) => panic!("unexpected error {e}"),
+        Ok(_) => panic!("expected an error for a {expected} file"),
+    }
+}
+
+#[test]
+fn extract_rejects_changeset_dump() {
+    let mut blobs = Blobs::from_bytes(changeset_dump()).unwrap();
+    assert_kind(Extract::from_blobs(&mut blobs), FileKind::ChangesetDump);
+
+    let mut blobs = Blobs::from_bytes(history()).unwrap();
+    assert_eq!(Extract::from_blobs(&mut blobs).unwrap().len(), 1);
+}
+
+#[test]
+fn changes_reject_snapshot() {
+    let mut blobs = Blobs::from_bytes(snapshot()).unwrap();
+    assert_kind(Changes::from_blobs(&mut blobs), FileKind::Snapshot);
+
+    let mut blobs = Blobs::from_bytes(change()).unwrap();
+    assert_eq!(Changes::from_blobs(&mut blobs).unwrap().len(), 2);
+}
+
+#[test]
+fn metadata_filters_reject_changeset_dump() {
+    let filter = MetaFilter::new().with_uids([7]);
+    let mut blobs = Blobs::from_bytes(changeset_dump()).unwrap();
+    let mut called = false;
+    assert_kind(
+        blobs.for_each_matching(&filter, |_| called = true),
+        FileKind::ChangesetDump,
+    );
+    assert!(!called);
+
+    let blobs = Blobs::from_bytes(changeset_dump()).unwrap();
+    let mut elements = blobs.elements_matching(TimeFilter::new().since(std::time::UNIX_EPOCH));
+    assert_kind(elements.next().unwrap(), FileKind::ChangesetDump);
+    assert!(elements.next().is_none());
+
+    let blobs = Blobs::from_bytes(history()).unwrap();
+    let elements = blobs.elements_matching((filter, TimeFilter::new()));
+    assert_eq!(elements.map(Result::unwrap).count(), 2);
+}
+
+#[derive(Default)]
+struct ChangeSetCounter(usize);
+
+impl Visitor for ChangeSetCounter {
+    fn types(&self) -> PrimitiveType {
+        PrimitiveType::CHANGE_SET
+    }
+
+    fn changeset(&mut self, _changeset: &ChangeSetRef<'_>) {
+        self.0 += 1;
+    }
+}
+
+#[test]
+fn changeset_visitor_rejects_snapshot() {
+    let mut counter = ChangeSetCounter::default();
+    let mut blobs = Blobs::from_bytes(snapshot()).unwrap();
+    assert_kind(blobs.visit(&mut counter), FileKind::Snapshot);
+
+    let mut blobs = Blobs::from_bytes(changeset_dump()).unwrap();
+    assert_eq!(blobs.visit(&mut counter).unwrap(), 1);
+    assert_eq!(counter.0, 1);
+}
@@ -0,0 +1,109 @@
+//! Geographic sorting and its verification.
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::primitives::PrimitiveType;
+use osm_pbf_reader::data::{Element, MemberType, OwnedMember, OwnedNode, OwnedRelation, OwnedWay};
+use osm_pbf_reader::geosort::{check_geographic, hilbert_index, UnsortedElement};
+use osm_pbf_reader::header::{Feature, HeaderFeatures};
+use osm_pbf_reader::writer::ElementOrdering;
+use osm_pbf_reader::{sort_geographic, Blobs, PbfWriter, WriterOptions};
+
+/// Nodes scattered over the globe, ways over consecutive nodes and a
+/// relation of two ways; sorted by type and id.
+fn elements() -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut state = 12345u64;
+    for id in 1..=40 {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        elements.push(Element::Node(OwnedNode {
+            id,
+            nano_lat: ((state >> 33) % 180_000) as i64 * 1_000_000 - 90_000_000_000,
+            nano_lon: ((state >> 13) % 360_000) as i64 * 1_000_000 - 180_000_000_000,
+            ..Default::default()
+        }));
+    }
+    for id in 1..=10 {
+        elements.push(Element::Way(OwnedWay {
+            id,
+            refs: (id * 4 - 3..=id * 4).rev().collect(),
+            ..Default::default()
+        }));
+    }
+    elements.push(Element::Relation(OwnedRelation {
+        id: 1,
+        members: [7, 2]
+            .into_iter()
+            .map(|id| OwnedMember {
+                member_type: MemberType::WAY,
+                id,
+                role: "outer".into(),
+            })
+            .collect(),
+        ..Default::default()
+    }));
+    elements
+}
+
+fn write(elements: &[Element], ordering: ElementOrdering) -> Vec<u8> {
+    let options = WriterOptions::new().ordering(ordering);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    for element in elements {
+        writer.write_element(element).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn read(data: Vec<u8>) -> Vec<Element> {
+    Blobs::from_bytes(data)
+        .unwrap()
+        .elements()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn node_key(element: &Element) -> u64 {
+    let Element::Node(node) = element else {
+        panic!("not a node: {element:?}");
+    };
+    hilbert_index(node.nano_lat, node.nano_lon)
+}
+
+#[test]
+fn sorted_output_passes_the_check() {
+    let input = write(&elements(), ElementOrdering::RequireTypeThenId);
+    let mut blobs = Blobs::from_bytes(input).unwrap();
+    assert!(blobs.header().has_feature(&Feature::SortTypeThenId));
+    let output = sort_geographic(&mut blobs, Vec::new(), WriterOptions::new()).unwrap();
+
+    let mut blobs = Blobs::from_bytes(output.clone()).unwrap();
+    assert!(blobs.header().has_feature(&Feature::SortGeographic));
+    assert!(!blobs.header().has_feature(&Feature::SortTypeThenId));
+    assert_eq!(check_geographic(&mut blobs).unwrap(), None);
+
+    let sorted = read(output);
+    assert_eq!(sorted.len(), elements().len());
+    let nodes = &sorted[..40];
+    assert!(nodes.windows(2).all(|w| node_key(&w[0]) <= node_key(&w[1])));
+}
+
+#[test]
+fn check_reports_swapped_nodes() {
+    let input = write(&elements(), ElementOrdering::RequireTypeThenId);
+    let mut blobs = Blobs::from_bytes(input).unwrap();
+    let mut sorted = read(sort_geographic(&mut blobs, Vec::new(), WriterOptions::new()).unwrap());
+    let i = sorted[..40]
+        .windows(2)
+        .position(|w| node_key(&w[0]) < node_key(&w[1]))
+        .unwrap();
+    sorted.swap(i, i + 1);
+
+    let mut blobs = Blobs::from_bytes(write(&sorted, ElementOrdering::Input)).unwrap();
+    assert_eq!(
+        check_geographic(&mut blobs).unwrap(),
+        Some(UnsortedElement {
+            element_type: PrimitiveType::NODE,
+            id: sorted[i + 1].id(),
+            previous_id: sorted[i].id(),
+        })
+    );
+}
@@ -0,0 +1,72 @@
+//! Sidecar files of the blob index.
+
+use std::fs;
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::{Element, OwnedNode};
+use osm_pbf_reader::{BlobIndex, Blobs, Codec, PbfWriter, WriterOptions};
+
+/// A file with a single node, whose blob is not compressed.
+fn file_with_node(id: i64) -> Vec<u8> {
+    let options = WriterOptions::new().codec(Codec::Raw);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    let node = Element::Node(OwnedNode {
+        id,
+        ..Default::default()
+    });
+    writer.write_element(&node).unwrap();
+    writer.finish().unwrap()
+}
+
+#[test]
+fn sidecar_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.osm.pbf");
+    fs::write(&path, file_with_node(1)).unwrap();
+    let sidecar = BlobIndex::sidecar_path(&path);
+
+    let built = Blobs::from_path(&path)
+        .unwrap()
+        .load_or_build_index(&sidecar)
+        .unwrap();
+    assert_eq!(BlobIndex::open(&sidecar).unwrap(), built);
+
+    let mut blobs = Blobs::from_path(&path).unwrap();
+    assert_eq!(blobs.load_or_build_index(&sidecar).unwrap(), built);
+    assert_eq!(blobs.elements().count(), 1);
+}
+
+#[test]
+fn stale_sidecar_is_rebuilt() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.osm.pbf");
+    let sidecar = BlobIndex::sidecar_path(&path);
+    let (old, new) = (file_with_node(1), file_with_node(2));
+    assert_eq!(old.len(), new.len());
+    assert_eq!(old.iter().zip(&new).filter(|(a, b)| a != b).count(), 1);
+
+    fs::write(&path, old).unwrap();
+    let mut blobs = Blobs::from_path(&path).unwrap();
+    let index = blobs.load_or_build_index(&sidecar).unwrap();
+    assert_eq!(index.entries()[1].node_ids, Some((1, 1)));
+
+    fs::write(&path, new).unwrap();
+    let mut blobs = Blobs::from_path(&path).unwrap();
+    let index = blobs.load_or_build_index(&sidecar).unwrap();
+    assert_eq!(index.entries()[1].node_ids, Some((2, 2)));
+    assert_eq!(BlobIndex::open(&sidecar).unwrap(), index);
+}
+
+#[test]
+fn unwritable_sidecar_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.osm.pbf");
+    fs::write(&path, file_with_node(1)).unwrap();
+    let sidecar = dir.path().join("missing").join("a.osm.pbf.idx");
+
+    let mut blobs = Blobs::from_path(&path).unwrap();
+    let index = blobs.load_or_build_index(&sidecar).unwrap();
+    assert_eq!(index.len(), 2);
+    assert!(!sidecar.exists());
+    assert_eq!(blobs.elements().count(), 1);
+}
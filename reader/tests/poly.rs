@@ -0,0 +1,73 @@
+//! Parsing of Osmosis `.poly` files and point-in-polygon tests.
+
+use osm_pbf_reader::error::Error;
+use osm_pbf_reader::Polygon;
+
+/// Two outer rings, the first with a hole, as written by Osmosis.
+const TWO_ISLANDS: &str = include_str!("data/two-islands.poly");
+
+fn nano(degrees: f64) -> i64 {
+    (degrees * 1e9).round() as i64
+}
+
+fn contains(polygon: &Polygon, lon: f64, lat: f64) -> bool {
+    polygon.contains(nano(lat), nano(lon))
+}
+
+#[test]
+fn osmosis_file_with_hole() {
+    let polygon = Polygon::parse(TWO_ISLANDS).unwrap();
+    assert_eq!(polygon.name, "two_islands");
+    let rings: Vec<_> = polygon
+        .rings()
+        .iter()
+        .map(|r| (r.name.as_str(), r.hole, r.points.len()))
+        .collect();
+    assert_eq!(rings, [("1", false, 5), ("2", true, 5), ("3", false, 4)]);
+    assert_eq!(polygon.rings()[2].points[2], (nano(10.0), nano(25.0)));
+    let bounds = polygon.bounds().unwrap();
+    assert_eq!((bounds.min_lon, bounds.max_lon), (0, nano(30.0)));
+
+    assert!(contains(&polygon, 2.0, 2.0));
+    assert!(contains(&polygon, 22.0, 2.0));
+    assert!(!contains(&polygon, 5.0, 5.0));
+    assert!(!contains(&polygon, 15.0, 5.0));
+    assert!(!contains(&polygon, 5.0, 11.0));
+}
+
+#[test]
+fn points_on_either_side_of_an_edge() {
+    let polygon = Polygon::parse(TWO_ISLANDS).unwrap();
+    let eps = 1e-6;
+    // vertical edge of the first ring
+    assert!(contains(&polygon, 10.0 - eps, 5.0));
+    assert!(!contains(&polygon, 10.0 + eps, 5.0));
+    // edge of the hole
+    assert!(contains(&polygon, 4.0 - eps, 5.0));
+    assert!(!contains(&polygon, 4.0 + eps, 5.0));
+    // slanted edge of the triangle, at lon 27.5 for lat 5
+    assert!(contains(&polygon, 27.5 - eps, 5.0));
+    assert!(!contains(&polygon, 27.5 + eps, 5.0));
+    // horizontal edge of the triangle
+    assert!(contains(&polygon, 22.0, eps));
+    assert!(!contains(&polygon, 22.0, -eps));
+}
+
+fn error_line(poly: &str) -> usize {
+    match Polygon::parse(poly) {
+        Err(Error::InvalidPoly(line, _)) => line,
+        Err(e) => panic!("unexpected error {e}"),
+        Ok(_) => panic!("expected an error for {poly:?}"),
+    }
+}
+
+#[test]
+fn errors_report_the_line() {
+    assert_eq!(error_line(""), 1);
+    assert_eq!(error_line("name\n1\n  0 0\nEND\n"), 4);
+    assert_eq!(error_line("name\n1\n  0 0\n  1 1\n"), 4);
+    // blank lines are counted
+    assert_eq!(error_line("name\n\n1\n  0 0\n  1 x\nEND\nEND\n"), 5);
+    assert_eq!(error_line("name\n1\n  0 0 0\nEND\nEND\n"), 3);
+    assert_eq!(error_line("name\n1\n  0\nEND\nEND\n"), 3);
+}
@@ -0,0 +1,100 @@
+//! Sorting of elements by the writer, with temporary files for large inputs.
+
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::data::{Element, OwnedInfo, OwnedNode, OwnedRelation, OwnedWay};
+use osm_pbf_reader::writer::ElementOrdering;
+use osm_pbf_reader::{Blobs, PbfWriter, WriterOptions};
+
+fn info(version: i32) -> Option<OwnedInfo> {
+    Some(OwnedInfo {
+        version: Some(version),
+        ..Default::default()
+    })
+}
+
+/// Nodes, ways and relations in shuffled order; every tenth id has three
+/// versions, which are not in version order.
+fn shuffled_elements() -> Vec<Element> {
+    let mut elements = Vec::new();
+    for id in 0..300 {
+        let versions: &[i32] = if id % 10 == 0 { &[3, 1, 2] } else { &[1] };
+        for &version in versions {
+            elements.push(match id % 3 {
+                0 => Element::Node(OwnedNode {
+                    id,
+                    info: info(version),
+                    ..Default::default()
+                }),
+                1 => Element::Way(OwnedWay {
+                    id,
+                    info: info(version),
+                    ..Default::default()
+                }),
+                _ => Element::Relation(OwnedRelation {
+                    id,
+                    info: info(version),
+                    ..Default::default()
+                }),
+            });
+        }
+    }
+    // deterministic shuffle that keeps the versions of an element in order
+    let mut state = 12345u64;
+    let mut keyed: Vec<_> = elements
+        .into_iter()
+        .map(|e| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let group = if e.id() % 10 == 0 { 0 } else { state >> 33 };
+            (group, e)
+        })
+        .collect();
+    keyed.sort_by_key(|(group, _)| *group);
+    keyed.into_iter().map(|(_, e)| e).collect()
+}
+
+fn sorted(elements: &[Element], sort_buffer_size: usize) -> Vec<Element> {
+    let options = WriterOptions::new()
+        .ordering(ElementOrdering::TypeThenId)
+        .sort_buffer_size(sort_buffer_size);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    for element in elements {
+        writer.write_element(element).unwrap();
+    }
+    let data = writer.finish().unwrap();
+    Blobs::from_bytes(data)
+        .unwrap()
+        .elements()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn key(element: &Element) -> (u32, i64) {
+    (element.primitive_type().bits(), element.id())
+}
+
+fn version(element: &Element) -> Option<i32> {
+    element.info().and_then(|i| i.version)
+}
+
+#[test]
+fn spilled_runs_are_merged_in_type_then_id_order() {
+    let input = shuffled_elements();
+    // in memory, with a few spills, and with a spill for every element
+    for sort_buffer_size in [usize::MAX, 20_000, 1] {
+        let output = sorted(&input, sort_buffer_size);
+        assert_eq!(output.len(), input.len(), "{sort_buffer_size}");
+        assert!(
+            output.windows(2).all(|w| key(&w[0]) <= key(&w[1])),
+            "{sort_buffer_size}"
+        );
+        // repeated ids keep their input order
+        for element in output.iter().filter(|e| e.id() % 10 == 0) {
+            let versions: Vec<_> = output
+                .iter()
+                .filter(|e| key(e) == key(element))
+                .map(version)
+                .collect();
+            assert_eq!(versions, [Some(3), Some(1), Some(2)], "{sort_buffer_size}");
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! The writer rejects blobs that readers would reject.
+
+use osm_pbf_proto::fileformat::blob::Data;
+use osm_pbf_proto::osmformat::HeaderBlock;
+use osm_pbf_reader::blob::PbfBlob;
+use osm_pbf_reader::data::{Element, OwnedNode};
+use osm_pbf_reader::error::Error;
+use osm_pbf_reader::{Blobs, Codec, PbfWriter, WriterOptions};
+
+/// Larger than the 32 MiB readers accept for a blob.
+const TOO_LARGE: usize = 33 * 1024 * 1024;
+
+fn node(id: i64, value: String) -> Element {
+    Element::Node(OwnedNode {
+        id,
+        tags: vec![("note".into(), value)],
+        ..Default::default()
+    })
+}
+
+#[test]
+fn oversized_block_is_rejected() {
+    let options = WriterOptions::new().codec(Codec::Raw);
+    let mut writer = PbfWriter::with_options(Vec::new(), HeaderBlock::new(), options);
+    writer
+        .write_element(&node(1, "x".repeat(TOO_LARGE)))
+        .unwrap();
+    assert!(matches!(writer.flush_block(), Err(Error::BlobDataToLarge)));
+}
+
+#[test]
+fn oversized_blob_is_not_written() {
+    let mut writer = PbfWriter::new(Vec::new(), HeaderBlock::new());
+    writer.write_element(&node(1, "small".into())).unwrap();
+    writer.flush_block().unwrap();
+    let len = writer.get_mut().len();
+
+    let mut blob = PbfBlob::new();
+    blob.data = Some(Data::Raw(vec![0; TOO_LARGE].into()));
+    assert!(matches!(
+        writer.write_blob("Example", &blob),
+        Err(Error::BlobDataToLarge)
+    ));
+    assert_eq!(writer.get_mut().len(), len);
+
+    let data = writer.finish().unwrap();
+    let elements = Blobs::from_bytes(data).unwrap().elements().count();
+    assert_eq!(elements, 1);
+}